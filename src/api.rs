@@ -0,0 +1,557 @@
+//! Requests to the REST API of the forge the repository is on, shared by everything that reads
+//! from GitHub or GitLab. [`Client::fetch`] runs requests on a worker thread so the TUI keeps
+//! drawing while they are under way.
+//!
+//! - Requests run the `curl` executable, which has to be on the `PATH`. It brings TLS, proxies
+//!   and HTTP/2 without gix linking an HTTP stack, and gives up after [`TIMEOUT_SECONDS`].
+//! - Tokens come from `GITHUB_TOKEN`, `GH_TOKEN` or `GITLAB_TOKEN`, otherwise from git's
+//!   credential helpers for the forge's host, where `git push` over https keeps them. A token
+//!   from a helper is approved back to it once it works and rejected when it is refused.
+//! - Responses are kept in `gix-api` in the git directory with their ETag and asked for again
+//!   with `If-None-Match`, which the forges don't count against the rate limit when unchanged.
+//! - Once the rate limit is used up, requests are answered from the cache until it resets.
+//! - When the forge can't be reached, the cached response is used.
+
+use crate::{date::now, json::Json};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Arc, Mutex, OnceLock,
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const CACHE_DIR: &str = "gix-api";
+/// Longest a request may take, so an unreachable forge falls back to the cache quickly.
+const TIMEOUT_SECONDS: &str = "15";
+/// Longest a credential helper may take before it is given up on, e.g. when it waits for an
+/// unlock prompt nobody sees.
+const CREDENTIAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A response, possibly from the cache.
+pub struct Response {
+    pub body: String,
+    /// Why the forge wasn't asked and the cached body is shown instead, e.g. `offline`.
+    pub stale: Option<String>,
+}
+
+/// Token for the API and where it came from.
+struct Credential {
+    username: String,
+    password: String,
+    /// Whether a credential helper gave it, which is then told whether it worked.
+    from_helper: bool,
+}
+
+/// Sends the requests, [`Curl`] outside of tests.
+trait Transport: Send + Sync {
+    /// GETs `url` with `headers` such as `Accept: application/json`.
+    fn get(&self, url: &str, headers: &[String]) -> Result<Reply, String>;
+}
+
+/// Where tokens come from, [`GitCredentials`] outside of tests.
+trait Tokens: Send + Sync {
+    fn token(&self, host: &str) -> Option<Credential>;
+
+    /// Tells the source whether `credential` was accepted by `host`.
+    fn report(&self, host: &str, credential: &Credential, worked: bool);
+}
+
+/// Client for the API of one forge. Clones share the token and the rate limit.
+#[derive(Clone)]
+pub struct Client {
+    /// Root of the API, e.g. `https://api.github.com`.
+    root: String,
+    /// Host the web UI and the credentials are on, e.g. `github.com`.
+    host: String,
+    cache_dir: PathBuf,
+    /// Looked up on the first request.
+    credential: Arc<OnceLock<Option<Credential>>>,
+    /// When the used up rate limit resets, in seconds since the epoch.
+    limited_until: Arc<Mutex<Option<i64>>>,
+    transport: Arc<dyn Transport>,
+    tokens: Arc<dyn Tokens>,
+}
+
+impl Client {
+    pub fn new(root: String, host: String, git_dir: &Path) -> Self {
+        Self::with(
+            root,
+            host,
+            git_dir,
+            Arc::new(Curl),
+            Arc::new(GitCredentials),
+        )
+    }
+
+    fn with(
+        root: String,
+        host: String,
+        git_dir: &Path,
+        transport: Arc<dyn Transport>,
+        tokens: Arc<dyn Tokens>,
+    ) -> Self {
+        Self {
+            root,
+            host,
+            cache_dir: git_dir.join(CACHE_DIR),
+            credential: Arc::default(),
+            limited_until: Arc::default(),
+            transport,
+            tokens,
+        }
+    }
+
+    /// Runs [`Client::get`] on a worker thread, the receiver gets its result.
+    pub fn fetch(&self, path: &str) -> Receiver<Result<Response, String>> {
+        let (sender, receiver) = mpsc::channel();
+        let client = self.clone();
+        let path = path.to_string();
+        thread::spawn(move || sender.send(client.get(&path)));
+        receiver
+    }
+
+    /// GETs `path` below the root of the API, answering from the cache when the rate limit is
+    /// used up or the forge can't be reached. Blocks on the token lookup and the request.
+    fn get(&self, path: &str) -> Result<Response, String> {
+        let url = format!("{}{path}", self.root);
+        let cache = self.cache_path(&url);
+        // Cache files have the ETag on their first line and the body after it.
+        let cached = fs::read_to_string(&cache).ok().and_then(|content| {
+            let (etag, body) = content.split_once('\n')?;
+            Some((etag.to_string(), body.to_string()))
+        });
+        let stale = |reason: String| match &cached {
+            Some((_, body)) => Ok(Response {
+                body: body.clone(),
+                stale: Some(reason),
+            }),
+            None => Err(format!("{} is {reason}", self.host)),
+        };
+
+        let limited_until = *self.limited_until.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(until) = limited_until
+            && until > now()
+        {
+            return stale(rate_limited(until));
+        }
+
+        let credential = self
+            .credential
+            .get_or_init(|| self.tokens.token(&self.host));
+        let mut headers = vec![
+            "Accept: application/json".to_string(),
+            "User-Agent: gix".to_string(),
+        ];
+        if let Some(credential) = credential {
+            headers.push(format!("Authorization: Bearer {}", credential.password));
+        }
+        if let Some((etag, _)) = &cached {
+            headers.push(format!("If-None-Match: {etag}"));
+        }
+        let reply = match self.transport.get(&url, &headers) {
+            Ok(reply) => reply,
+            Err(_) if cached.is_some() => return stale("offline".to_string()),
+            Err(e) => return Err(format!("couldn't reach {}: {e}", self.host)),
+        };
+
+        let remaining = reply.header("x-ratelimit-remaining", "ratelimit-remaining");
+        let reset = reply.header("x-ratelimit-reset", "ratelimit-reset");
+        let limited_until = match (remaining.as_deref(), reset) {
+            (Some("0"), Some(reset)) => reset.parse().ok(),
+            _ => None,
+        };
+        *self.limited_until.lock().unwrap_or_else(|e| e.into_inner()) = limited_until;
+
+        match (reply.status, limited_until) {
+            // Unchanged, so the cached body is as good as a fresh one.
+            (304, _) if cached.is_some() => Ok(Response {
+                body: cached.map(|(_, body)| body).unwrap_or_default(),
+                stale: None,
+            }),
+            (200..=299, _) => {
+                if let Some(credential) = credential {
+                    self.tokens.report(&self.host, credential, true);
+                }
+                if let Some(etag) = reply.header("etag", "etag") {
+                    // A cache that can't be written only costs a request next time.
+                    let _ = fs::create_dir_all(&self.cache_dir);
+                    let _ = fs::write(&cache, format!("{etag}\n{}", reply.body));
+                }
+                Ok(Response {
+                    body: reply.body,
+                    stale: None,
+                })
+            }
+            (401, _) => {
+                if let Some(credential) = credential {
+                    self.tokens.report(&self.host, credential, false);
+                }
+                Err(format!("{} refused the token", self.host))
+            }
+            (403 | 429, Some(until)) => stale(rate_limited(until)),
+            (status, _) => Err(format!(
+                "{} answered {status}{}",
+                self.host,
+                error_message(&reply.body)
+                    .map(|m| format!(": {m}"))
+                    .unwrap_or_default()
+            )),
+        }
+    }
+
+    fn cache_path(&self, url: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        self.cache_dir.join(format!("{:016x}", hasher.finish()))
+    }
+}
+
+fn rate_limited(until: i64) -> String {
+    let minutes = ((until - now()).max(0) + 59) / 60;
+    format!("rate limited for another {minutes} min")
+}
+
+/// `message` of a JSON error body, which both forges send.
+fn error_message(body: &str) -> Option<String> {
+    let json = Json::parse(body).ok()?;
+    Some(json.get("message")?.as_str()?.to_string())
+}
+
+/// Tokens from the environment, then from git's credential helpers.
+struct GitCredentials;
+
+impl Tokens for GitCredentials {
+    fn token(&self, host: &str) -> Option<Credential> {
+        token_from_env(host).or_else(|| fill(host))
+    }
+
+    fn report(&self, host: &str, credential: &Credential, worked: bool) {
+        if credential.from_helper {
+            let action = if worked { "approve" } else { "reject" };
+            credential_command(host, credential, action);
+        }
+    }
+}
+
+fn token_from_env(host: &str) -> Option<Credential> {
+    let names: &[&str] = if host.contains("gitlab") {
+        &["GITLAB_TOKEN"]
+    } else {
+        &["GITHUB_TOKEN", "GH_TOKEN"]
+    };
+    let password = names
+        .iter()
+        .find_map(|name| std::env::var(name).ok())
+        .filter(|token| !token.is_empty())?;
+    Some(Credential {
+        username: String::new(),
+        password,
+        from_helper: false,
+    })
+}
+
+/// Asks git's credential helpers for a token for `host`, never prompting.
+fn fill(host: &str) -> Option<Credential> {
+    let output = run_credential(&format!("protocol=https\nhost={host}\n\n"), "fill")?;
+    let field = |key: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    Some(Credential {
+        username: field("username").unwrap_or_default(),
+        password: field("password").filter(|p| !p.is_empty())?,
+        from_helper: true,
+    })
+}
+
+/// Tells the credential helpers that `credential` worked (`approve`) or didn't (`reject`).
+fn credential_command(host: &str, credential: &Credential, action: &str) {
+    let input = format!(
+        "protocol=https\nhost={host}\nusername={}\npassword={}\n\n",
+        credential.username, credential.password
+    );
+    run_credential(&input, action);
+}
+
+/// Runs `git credential <action>` with `input`, returning its output when it succeeded within
+/// [`CREDENTIAL_TIMEOUT`].
+fn run_credential(input: &str, action: &str) -> Option<String> {
+    let mut child = Command::new("git")
+        .args(["credential", action])
+        // Without a stored credential git would ask for one, on the terminal gix draws on or
+        // in an askpass window.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_ASKPASS", "true")
+        .env("SSH_ASKPASS", "true")
+        .env("GCM_INTERACTIVE", "never")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdin = child.stdin.take()?;
+    let input = input.to_string();
+    thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let mut stdout = child.stdout.take()?;
+    let output = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let deadline = Instant::now() + CREDENTIAL_TIMEOUT;
+    let status = loop {
+        match child.try_wait().ok()? {
+            Some(status) => break status,
+            None if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    let output = output.join().ok()?.ok()?;
+    status
+        .success()
+        .then(|| String::from_utf8_lossy(&output).into_owned())
+}
+
+/// Status, headers and body of a response.
+struct Reply {
+    status: u16,
+    /// Names lowercased.
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Reply {
+    /// Parses what `curl --include` prints: the header blocks of any redirects and proxies, then
+    /// those of the response and its body.
+    fn parse(output: &str) -> Option<Self> {
+        let mut rest = output;
+        let mut head = "";
+        while rest.starts_with("HTTP/") {
+            let (block, after) = rest
+                .split_once("\r\n\r\n")
+                .or_else(|| rest.split_once("\n\n"))
+                .unwrap_or((rest, ""));
+            head = block;
+            rest = after;
+        }
+        let mut lines = head.lines();
+        let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+        Some(Self {
+            status,
+            headers,
+            body: rest.to_string(),
+        })
+    }
+
+    /// Value of the header called `name`, or `other` as GitLab calls it.
+    fn header(&self, name: &str, other: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name || n == other)
+            .map(|(_, value)| value.clone())
+    }
+}
+
+/// Runs `curl` for each request. The headers go through stdin so the token doesn't show in the
+/// process list.
+struct Curl;
+
+impl Transport for Curl {
+    fn get(&self, url: &str, headers: &[String]) -> Result<Reply, String> {
+        curl(url, headers)
+    }
+}
+
+fn curl(url: &str, headers: &[String]) -> Result<Reply, String> {
+    let mut child = Command::new("curl")
+        .args([
+            "--silent",
+            "--show-error",
+            "--location",
+            "--include",
+            "--connect-timeout",
+            "5",
+            "--max-time",
+            TIMEOUT_SECONDS,
+            "--header",
+            "@-",
+            url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't run curl: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let input = headers.join("\n");
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Reply::parse(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| "unreadable response".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers requests with `replies` in turn and records what it was asked.
+    #[derive(Default)]
+    struct Stub {
+        replies: Mutex<Vec<Result<&'static str, String>>>,
+        requests: Mutex<Vec<Vec<String>>>,
+        reports: Mutex<Vec<bool>>,
+    }
+
+    impl Stub {
+        fn reply(&self, reply: Result<&'static str, String>) {
+            self.replies.lock().unwrap().push(reply);
+        }
+
+        fn requests(&self) -> usize {
+            self.requests.lock().unwrap().len()
+        }
+    }
+
+    impl Transport for Stub {
+        fn get(&self, _url: &str, headers: &[String]) -> Result<Reply, String> {
+            self.requests.lock().unwrap().push(headers.to_vec());
+            let reply = self.replies.lock().unwrap().remove(0)?;
+            Ok(Reply::parse(reply).unwrap())
+        }
+    }
+
+    impl Tokens for Stub {
+        fn token(&self, _host: &str) -> Option<Credential> {
+            Some(Credential {
+                username: "gix".to_string(),
+                password: "secret".to_string(),
+                from_helper: true,
+            })
+        }
+
+        fn report(&self, _host: &str, _credential: &Credential, worked: bool) {
+            self.reports.lock().unwrap().push(worked);
+        }
+    }
+
+    fn client(name: &str) -> (Client, Arc<Stub>) {
+        let dir = std::env::temp_dir().join(format!("gix-test-api-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let stub = Arc::new(Stub::default());
+        let client = Client::with(
+            "https://api.example.com".to_string(),
+            "example.com".to_string(),
+            &dir,
+            stub.clone(),
+            stub.clone(),
+        );
+        (client, stub)
+    }
+
+    #[test]
+    fn reads_the_last_response_of_curl() {
+        let reply = Reply::parse(
+            "HTTP/1.1 301 Moved\r\nLocation: /x\r\n\r\n\
+             HTTP/2 200\r\nETag: \"abc\"\r\nX-RateLimit-Remaining: 0\r\n\r\n[1]",
+        )
+        .unwrap();
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.header("etag", "etag").as_deref(), Some("\"abc\""));
+        assert_eq!(
+            reply
+                .header("x-ratelimit-remaining", "ratelimit-remaining")
+                .as_deref(),
+            Some("0")
+        );
+        assert_eq!(reply.body, "[1]");
+    }
+
+    #[test]
+    fn revalidates_cached_responses_with_their_etag() {
+        let (client, stub) = client("etag");
+        stub.reply(Ok("HTTP/2 200\nETag: \"abc\"\n\n[1]"));
+        stub.reply(Ok("HTTP/2 304\n\n"));
+        assert_eq!(client.get("/pulls").unwrap().body, "[1]");
+        let response = client.get("/pulls").unwrap();
+        assert_eq!(response.body, "[1]");
+        assert_eq!(response.stale, None);
+
+        let requests = stub.requests.lock().unwrap();
+        assert!(requests[0].contains(&"Authorization: Bearer secret".to_string()));
+        assert!(!requests[0].iter().any(|h| h.starts_with("If-None-Match")));
+        assert!(requests[1].contains(&"If-None-Match: \"abc\"".to_string()));
+        assert_eq!(*stub.reports.lock().unwrap(), [true]);
+    }
+
+    #[test]
+    fn answers_from_the_cache_while_rate_limited_or_offline() {
+        let (client, stub) = client("limit");
+        stub.reply(Err("curl: (6) Could not resolve host".to_string()));
+        assert_eq!(
+            client.get("/pulls").err().as_deref(),
+            Some("couldn't reach example.com: curl: (6) Could not resolve host")
+        );
+
+        let reset = now() + 600;
+        let limited: &'static str = format!(
+            "HTTP/2 200\nETag: \"abc\"\nX-RateLimit-Remaining: 0\nX-RateLimit-Reset: {reset}\n\n[1]"
+        )
+        .leak();
+        stub.reply(Ok(limited));
+        assert_eq!(client.get("/pulls").unwrap().stale, None);
+        let response = client.get("/pulls").unwrap();
+        assert_eq!(response.body, "[1]");
+        assert_eq!(
+            response.stale.as_deref(),
+            Some("rate limited for another 10 min")
+        );
+        assert_eq!(stub.requests(), 2);
+        assert_eq!(
+            client.get("/issues").err().as_deref(),
+            Some("example.com is rate limited for another 10 min")
+        );
+
+        *client.limited_until.lock().unwrap() = None;
+        stub.reply(Err("curl: (7) Failed to connect".to_string()));
+        let response = client.get("/pulls").unwrap();
+        assert_eq!(response.body, "[1]");
+        assert_eq!(response.stale.as_deref(), Some("offline"));
+    }
+
+    #[test]
+    fn rejects_refused_tokens() {
+        let (client, stub) = client("refused");
+        stub.reply(Ok("HTTP/2 401\n\n{\"message\": \"Bad credentials\"}"));
+        stub.reply(Ok("HTTP/2 404\n\n{\"message\": \"Not Found\"}"));
+        assert_eq!(
+            client.get("/pulls").err().as_deref(),
+            Some("example.com refused the token")
+        );
+        assert_eq!(*stub.reports.lock().unwrap(), [false]);
+        assert_eq!(
+            client.get("/pulls").err().as_deref(),
+            Some("example.com answered 404: Not Found")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    date::now,
+    lossy::lossy,
+    refs::{head_ref, read_refs},
+};
+use git2::{Oid, Repository};
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+const FILE_NAME: &str = "gix-audit.log";
+
+/// A ref an operation moved. A zero id means the ref didn't exist on that side.
+pub struct RefChange {
+    pub refname: String,
+    pub old: Oid,
+    pub new: Oid,
+}
+
+/// An operation gix performed that can change refs, with its outcome and the refs it moved.
+pub struct AuditEntry {
+    /// Seconds since the epoch.
+    pub time: i64,
+    /// What was done, e.g. `checkout main`.
+    pub operation: String,
+    pub failed: bool,
+    /// Message shown when the operation finished.
+    pub message: String,
+    pub changes: Vec<RefChange>,
+}
+
+impl AuditEntry {
+    pub fn new(operation: &str, outcome: &Result<String, String>, changes: Vec<RefChange>) -> Self {
+        let (failed, message) = match outcome {
+            Ok(message) => (false, message),
+            Err(error) => (true, error),
+        };
+        Self {
+            time: now(),
+            operation: operation.to_string(),
+            failed,
+            message: message.clone(),
+            changes,
+        }
+    }
+}
+
+/// Local branch tips and the branch HEAD is on, taken before and after an operation to find
+/// the refs it moved.
+pub struct RefTips {
+    tips: BTreeMap<String, Oid>,
+    head: Option<String>,
+}
+
+impl RefTips {
+    pub fn capture(repo: &Repository) -> Self {
+        let mut tips: BTreeMap<String, Oid> = match read_refs(repo, &["refs/heads/"]) {
+            Some(refs) => refs
+                .into_iter()
+                .filter_map(|r| Some((lossy(&r.name).0, r.target?)))
+                .collect(),
+            None => repo
+                .references_glob("refs/heads/*")
+                .map(|refs| {
+                    refs.flatten()
+                        .filter_map(|r| Some((lossy(r.name_bytes()).0, r.target()?)))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+        if let Some(oid) = repo.head().ok().and_then(|head| head.target()) {
+            tips.insert("HEAD".to_string(), oid);
+        }
+        Self {
+            tips,
+            head: head_ref(repo).map(|name| lossy(&name).0),
+        }
+    }
+
+    /// Refs that differ in `after`. HEAD counts as moved when it is on another branch, even
+    /// one at the same commit.
+    pub fn changes(&self, after: &RefTips) -> Vec<RefChange> {
+        let mut names: Vec<&String> = self.tips.keys().chain(after.tips.keys()).collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let old = self.tips.get(name).copied().unwrap_or(Oid::zero());
+                let new = after.tips.get(name).copied().unwrap_or(Oid::zero());
+                let moved = old != new || (name == "HEAD" && self.head != after.head);
+                moved.then(|| RefChange {
+                    refname: name.clone(),
+                    old,
+                    new,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Appends `entry` to the audit log of the repository with the common git directory
+/// `common_dir`, shared by all its worktrees.
+pub fn append(common_dir: &Path, entry: &AuditEntry) -> io::Result<()> {
+    let one_line = |text: &str| text.replace(['\t', '\n', '\r'], " ");
+    let mut record = format!(
+        "{}\t{}\t{}\t{}\n",
+        entry.time,
+        if entry.failed { "failed" } else { "ok" },
+        one_line(&entry.operation),
+        one_line(&entry.message)
+    );
+    for change in &entry.changes {
+        record.push_str(&format!(
+            "\t{} {} {}\n",
+            change.old, change.new, change.refname
+        ));
+    }
+    // A single write, so concurrent sessions don't interleave records.
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path(common_dir))?
+        .write_all(record.as_bytes())
+}
+
+/// Entries of the audit log, newest first. Lines that can't be parsed are skipped.
+pub fn load(common_dir: &Path) -> Vec<AuditEntry> {
+    let Ok(content) = fs::read_to_string(path(common_dir)) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<AuditEntry> = Vec::new();
+    for line in content.lines() {
+        if let Some(change) = line.strip_prefix('\t') {
+            let mut parts = change.splitn(3, ' ');
+            let (Some(old), Some(new), Some(refname)) = (parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            let (Ok(old), Ok(new), Some(entry)) =
+                (Oid::from_str(old), Oid::from_str(new), entries.last_mut())
+            else {
+                continue;
+            };
+            entry.changes.push(RefChange {
+                refname: refname.to_string(),
+                old,
+                new,
+            });
+            continue;
+        }
+        let mut fields = line.splitn(4, '\t');
+        let (Some(time), Some(status), Some(operation), Some(message)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Ok(time) = time.parse() else {
+            continue;
+        };
+        entries.push(AuditEntry {
+            time,
+            operation: operation.to_string(),
+            failed: status == "failed",
+            message: message.to_string(),
+            changes: Vec::new(),
+        });
+    }
+    entries.reverse();
+    entries
+}
+
+fn path(common_dir: &Path) -> PathBuf {
+    common_dir.join(FILE_NAME)
+}
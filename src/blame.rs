@@ -0,0 +1,100 @@
+use crate::lossy::lossy;
+use git2::{BlameOptions, Oid, Repository};
+use std::path::{Path, PathBuf};
+
+/// A line of a blamed file with the commit that last changed it.
+pub struct BlameLine {
+    pub oid: Oid,
+    pub author: String,
+    /// Path the file had in `oid`, differs from the blamed path if it was renamed since.
+    pub orig_path: PathBuf,
+    /// 1-based line number of the line in `oid`.
+    pub orig_line: usize,
+    pub content: String,
+}
+
+impl BlameLine {
+    pub fn short_oid(&self) -> String {
+        self.oid.to_string().chars().take(7).collect()
+    }
+}
+
+pub struct Blame {
+    pub path: PathBuf,
+    /// Commit the file is blamed at.
+    pub at: Oid,
+    /// 1-based line number of the first entry in `lines`, which only cover a range of the file
+    /// when the blame was limited to one.
+    pub first_line: usize,
+    /// Whether only a range of the file was blamed.
+    pub limited: bool,
+    pub lines: Vec<BlameLine>,
+}
+
+/// Blames `path` as of commit `at`, only lines `range` (1-based, inclusive) when given.
+pub fn blame_file(
+    repo: &Repository,
+    path: &Path,
+    at: Oid,
+    range: Option<(usize, usize)>,
+) -> Result<Blame, String> {
+    let commit = repo.find_commit(at).map_err(|e| e.message().to_string())?;
+    let blob = commit
+        .tree()
+        .and_then(|tree| tree.get_path(path))
+        .and_then(|entry| repo.find_blob(entry.id()))
+        .map_err(|_| format!("{} doesn't exist in {}", path.display(), short(at)))?;
+    if blob.is_binary() {
+        return Err(format!("{} is a binary file", path.display()));
+    }
+    let content = String::from_utf8_lossy(blob.content());
+    let n_lines = content.lines().count();
+    let (first, last) = range.unwrap_or((1, n_lines));
+    let last = last.min(n_lines);
+
+    let mut opts = BlameOptions::new();
+    opts.newest_commit(at);
+    if range.is_some() {
+        opts.min_line(first).max_line(last);
+    }
+    let blame = repo
+        .blame_file(path, Some(&mut opts))
+        .map_err(|e| e.message().to_string())?;
+
+    let lines = content
+        .lines()
+        .enumerate()
+        .skip(first.saturating_sub(1))
+        .take((last + 1).saturating_sub(first))
+        .filter_map(|(i, content)| {
+            let hunk = blame.get_line(i + 1)?;
+            Some(BlameLine {
+                oid: hunk.final_commit_id(),
+                author: lossy(hunk.final_signature().name_bytes()).0,
+                orig_path: hunk.path().unwrap_or(path).to_path_buf(),
+                orig_line: hunk.orig_start_line() + (i + 1 - hunk.final_start_line()),
+                content: content.to_string(),
+            })
+        })
+        .collect();
+
+    Ok(Blame {
+        path: path.to_path_buf(),
+        at,
+        first_line: first,
+        limited: range.is_some(),
+        lines,
+    })
+}
+
+/// First parent of `oid`, where blaming continues past the changes `oid` made.
+pub fn blame_parent(repo: &Repository, oid: Oid) -> Result<Oid, String> {
+    let commit = repo.find_commit(oid).map_err(|e| e.message().to_string())?;
+    commit
+        .parent_id(0)
+        .map_err(|_| format!("{} is a root commit, nothing before it", short(oid)))
+}
+
+fn short(oid: Oid) -> String {
+    oid.to_string().chars().take(7).collect()
+}
@@ -1,119 +1,1064 @@
-use git2::{BranchType, Branches, Error, ErrorCode, Repository, build::CheckoutBuilder};
+use crate::{
+    date::WEEK,
+    log::touches_path,
+    lossy::lossy,
+    refs::{head_ref, read_refs},
+    worktree::WorktreeHeads,
+};
+use git2::{
+    Branch, BranchType, Branches, Commit, Delta, Error, ErrorCode, Oid, Repository, Sort, Tree,
+    build::CheckoutBuilder,
+};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::{Arc, OnceLock, mpsc},
+    thread,
+};
 
+/// Branch count from which upstreams are looked up on several threads.
+const PARALLEL_UPSTREAMS_MIN: usize = 200;
+
+/// Parts of a branch's tip commit that are only read for rows being shown.
+#[derive(Default)]
+pub struct CommitDetails {
+    pub summary: String,
+    pub author_email: String,
+    /// Commit time in seconds since the epoch.
+    pub time: i64,
+}
+
+impl CommitDetails {
+    fn from_commit(commit: &Commit) -> Self {
+        Self {
+            summary: lossy(commit.summary_bytes().unwrap_or_default()).0,
+            author_email: lossy(commit.author().email_bytes()).0,
+            time: commit.time().seconds(),
+        }
+    }
+}
+
+/// Reads what [`BranchItem`] loads on first use for branches listed by another backend than
+/// libgit2, see oxide.rs.
+pub trait Loader: Send + Sync {
+    fn details(&self, oid: &str) -> CommitDetails;
+    /// Same as [`upstream_ahead_behind`].
+    fn ahead_behind(&self, branch: &BranchItem) -> Option<(usize, usize)>;
+}
+
+#[derive(Clone)]
 pub struct BranchItem {
+    /// Name for display, with replacement characters if it isn't valid UTF-8.
     pub name: String,
+    /// Name as stored, only kept when it isn't valid UTF-8 and `name` can't be used to find
+    /// the branch again.
+    pub raw_name: Option<Vec<u8>>,
     pub oid: String,
-    pub summary: String,
+    /// Loaded on first use and shared between clones, see [`BranchItem::details`].
+    details: Arc<OnceLock<CommitDetails>>,
+    /// Loaded on first use like the details, see [`BranchItem::ahead_behind`].
+    ahead_behind: Arc<OnceLock<Option<(usize, usize)>>>,
+    /// Loaded on first use like the details, see [`BranchItem::last_change`].
+    last_change: Arc<OnceLock<Option<i64>>>,
+    /// Counted on first use like the details, see [`BranchItem::activity`].
+    activity: Arc<OnceLock<Vec<usize>>>,
+    /// Backend the details and ahead/behind counts are read through, libgit2 when `None`.
+    loader: Option<Arc<dyn Loader>>,
     pub is_head: bool,
-    pub has_upstream: bool,
+    /// Short name of the upstream, e.g. `origin/main`, when it exists.
+    pub upstream: Option<String>,
     pub is_gone: bool,
+    pub is_remote: bool,
+    /// Indentation level when branches are ordered as stacks.
+    pub stack_depth: usize,
+    /// Set when time-travelling and the tip was estimated from history, not read from a reflog.
+    pub is_estimated: bool,
 }
 
 impl BranchItem {
+    /// A branch whose details and ahead/behind counts are read through `loader` on first use.
+    #[cfg(feature = "gitoxide")]
+    pub fn with_loader(name: String, oid: String, loader: Arc<dyn Loader>) -> Self {
+        Self {
+            name,
+            raw_name: None,
+            oid,
+            details: Arc::default(),
+            ahead_behind: Arc::default(),
+            last_change: Arc::default(),
+            activity: Arc::default(),
+            loader: Some(loader),
+            is_head: false,
+            upstream: None,
+            is_gone: false,
+            is_remote: false,
+            stack_depth: 0,
+            is_estimated: false,
+        }
+    }
+
+    /// A branch whose details are loaded on first use, see [`BranchItem::details`].
+    fn from_name_bytes(name_bytes: &[u8], oid: String, is_head: bool, is_remote: bool) -> Self {
+        let (name, invalid) = lossy(name_bytes);
+        Self {
+            name,
+            raw_name: invalid.then(|| name_bytes.to_vec()),
+            oid,
+            details: Arc::default(),
+            ahead_behind: Arc::default(),
+            last_change: Arc::default(),
+            activity: Arc::default(),
+            loader: None,
+            is_head,
+            upstream: None,
+            is_gone: false,
+            is_remote,
+            stack_depth: 0,
+            is_estimated: false,
+        }
+    }
+
+    pub fn refname(&self) -> String {
+        if self.is_remote {
+            format!("refs/remotes/{}", self.name)
+        } else {
+            format!("refs/heads/{}", self.name)
+        }
+    }
+
+    /// Name as stored, byte for byte.
+    pub fn name_bytes(&self) -> &[u8] {
+        self.raw_name.as_deref().unwrap_or(self.name.as_bytes())
+    }
+
     pub fn short_oid(&self) -> String {
         self.oid.chars().take(7).collect()
     }
+
+    /// Summary and author of the tip commit, read from the repository the first time they
+    /// are needed so listing branches doesn't have to load every commit.
+    pub fn details(&self, repo: &Repository) -> &CommitDetails {
+        self.details.get_or_init(|| {
+            if let Some(loader) = &self.loader {
+                return loader.details(&self.oid);
+            }
+            Oid::from_str(&self.oid)
+                .and_then(|oid| repo.find_commit(oid))
+                .map(|commit| CommitDetails::from_commit(&commit))
+                .unwrap_or_default()
+        })
+    }
+
+    /// The details if something already asked for them, without reading the commit.
+    pub fn loaded_details(&self) -> Option<&CommitDetails> {
+        self.details.get()
+    }
+
+    /// Time of the newest commit changing `path`, that of the tip without a path. Found the first
+    /// time it is needed, the path stays the same for the whole session.
+    pub fn last_change(&self, repo: &Repository, path: Option<&Path>) -> Option<i64> {
+        match path {
+            Some(path) => *self
+                .last_change
+                .get_or_init(|| last_commit_touching(repo, &self.oid, path)),
+            None => Some(self.details(repo).time),
+        }
+    }
+
+    /// [`commit_activity`] of the tip, counted the first time it is needed rather than on every
+    /// render. The weeks end at `now` of that first time, until the branches are listed again.
+    pub fn activity(
+        &self,
+        repo: &Repository,
+        path: Option<&Path>,
+        weeks: usize,
+        now: i64,
+    ) -> &[usize] {
+        self.activity
+            .get_or_init(|| commit_activity(repo, &self.oid, path, weeks, now))
+    }
+
+    /// [`upstream_ahead_behind`], counted the first time it is needed.
+    pub fn ahead_behind(&self, repo: &Repository) -> Option<(usize, usize)> {
+        *self.ahead_behind.get_or_init(|| match &self.loader {
+            Some(loader) => loader.ahead_behind(self),
+            None => upstream_ahead_behind(repo, self),
+        })
+    }
+}
+
+/// Outcome of [`fast_forward_branches`].
+#[derive(Default)]
+pub struct FastForwardSummary {
+    pub updated: Vec<String>,
+    pub diverged: Vec<String>,
+    pub failed: Vec<(String, Error)>,
 }
 
+#[derive(Clone, PartialEq, Eq)]
 pub enum BranchQuery {
     Local,
     Remote,
     LocalAndRemote,
 }
 
-fn parse_branches(
+impl BranchQuery {
+    /// `local`, `remote` or `all`.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "local" => Some(BranchQuery::Local),
+            "remote" => Some(BranchQuery::Remote),
+            "all" => Some(BranchQuery::LocalAndRemote),
+            _ => None,
+        }
+    }
+}
+
+/// Names of branches with both `branch.<name>.remote` and `branch.<name>.merge` configured,
+/// read from a single config snapshot.
+fn tracking_branches(repo: &Repository) -> HashSet<String> {
+    let mut remotes = HashSet::new();
+    let mut merges = HashSet::new();
+    let Ok(cfg) = repo.config().and_then(|mut cfg| cfg.snapshot()) else {
+        return remotes;
+    };
+    if let Ok(mut entries) = cfg.entries(Some(r"^branch\..*\.(remote|merge)$")) {
+        while let Some(Ok(entry)) = entries.next() {
+            let Some(key) = entry.name().and_then(|n| n.strip_prefix("branch.")) else {
+                continue;
+            };
+            if let Some(name) = key.strip_suffix(".remote") {
+                remotes.insert(name.to_string());
+            } else if let Some(name) = key.strip_suffix(".merge") {
+                merges.insert(name.to_string());
+            }
+        }
+    }
+    remotes.retain(|name| merges.contains(name));
+    remotes
+}
+
+/// Upstream of a local branch, found by [`send_upstream_states`].
+pub struct UpstreamState {
+    pub name: String,
+    pub upstream: Option<String>,
+    pub is_gone: bool,
+}
+
+/// Looks up the upstream of each local branch in `names` and passes them to `send`, in batches
+/// as they are done. Upstream lookups dominate listing time on repositories with many branches,
+/// so large lists are split over threads, each with its own repository handle and sending its
+/// batch as soon as it finished.
+pub fn send_upstream_states(
     repo: &Repository,
+    names: &[String],
+    send: impl Fn(Vec<UpstreamState>) + Sync,
+) {
+    let tracking = tracking_branches(repo);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    if names.len() < PARALLEL_UPSTREAMS_MIN || threads == 1 {
+        send(upstream_states_in(repo, names, &tracking));
+        return;
+    }
+
+    let chunk_size = names.len().div_ceil(threads);
+    let repos: Result<Vec<Repository>, Error> = names
+        .chunks(chunk_size)
+        .map(|_| Repository::open(repo.path()))
+        .collect();
+    let Ok(repos) = repos else {
+        send(upstream_states_in(repo, names, &tracking));
+        return;
+    };
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = names
+            .chunks(chunk_size)
+            .zip(repos)
+            .map(|(chunk, repo)| {
+                let (tracking, send) = (&tracking, &send);
+                scope.spawn(move || send(upstream_states_in(&repo, chunk, tracking)))
+            })
+            .collect();
+        // A chunk whose thread failed leaves its branches without upstreams rather than taking
+        // the whole list down.
+        for handle in handles {
+            let _ = handle.join();
+        }
+    });
+}
+
+/// Sets the upstreams looked up by [`send_upstream_states`] on the branches they belong to.
+pub fn apply_upstream_states(items: &mut [BranchItem], states: Vec<UpstreamState>) {
+    let mut states: HashMap<String, UpstreamState> = states
+        .into_iter()
+        .map(|state| (state.name.clone(), state))
+        .collect();
+    for branch in items.iter_mut().filter(|b| !b.is_remote) {
+        if let Some(state) = states.remove(&branch.name) {
+            branch.upstream = state.upstream;
+            branch.is_gone = state.is_gone;
+        }
+    }
+}
+
+/// Names of the local branches in `items`, to look up their upstreams.
+pub fn local_names(items: &[BranchItem]) -> Vec<String> {
+    items
+        .iter()
+        .filter(|b| !b.is_remote)
+        .map(|b| b.name.clone())
+        .collect()
+}
+
+fn upstream_states_in(
+    repo: &Repository,
+    names: &[String],
+    tracking: &HashSet<String>,
+) -> Vec<UpstreamState> {
+    names
+        .iter()
+        .map(|name| {
+            let upstream = repo
+                .find_branch(name, BranchType::Local)
+                .and_then(|branch| branch.upstream());
+            let is_gone = tracking.contains(name.as_str())
+                && matches!(upstream, Err(ref e) if e.code() == ErrorCode::NotFound);
+            let upstream = upstream
+                .ok()
+                .map(|upstream| lossy(upstream.name_bytes().unwrap_or_default()).0);
+            UpstreamState {
+                name: name.clone(),
+                upstream,
+                is_gone,
+            }
+        })
+        .collect()
+}
+
+fn parse_branches(
     mut branches: Branches<'_>,
     branch_type: BranchType,
     items: &mut Vec<BranchItem>,
 ) {
     while let Some(Ok((branch, _))) = branches.next() {
-        if let Ok(name_opt) = branch.name() {
-            let mut name = name_opt.unwrap_or_default().to_string();
+        if let Ok(name_bytes) = branch.name_bytes() {
+            let oid_full = branch
+                .get()
+                .resolve()
+                .ok()
+                .and_then(|r| r.target())
+                .map(|oid| oid.to_string())
+                .unwrap_or_default();
 
-            if branch_type == BranchType::Remote {
-                if let Some((remote, branch_name)) = name.split_once('/') {
-                    name = format!("{remote}/{branch_name}");
-                }
-            }
+            items.push(BranchItem::from_name_bytes(
+                name_bytes,
+                oid_full,
+                branch.is_head(),
+                branch_type == BranchType::Remote,
+            ));
+        }
+    }
+}
 
-            let commit = branch.get().peel_to_commit().ok();
-            let oid_full = commit
-                .as_ref()
-                .map(|c| c.id().to_string())
-                .unwrap_or_default();
-            let summary = commit
-                .as_ref()
-                .and_then(|c| c.summary().map(|s| s.to_string()))
-                .unwrap_or_default();
+/// Parses branch names as printed by `git branch [-a]`, `git for-each-ref` or one per line.
+/// Also returns whether any of them was marked as a remote branch (`remotes/...`).
+pub fn parse_allowlist(input: &str) -> (HashSet<String>, bool) {
+    let mut names = HashSet::new();
+    let mut has_remote = false;
 
-            let cfg = repo.config().ok();
-            let remote_key = format!("branch.{}.remote", name);
-            let merge_key = format!("branch.{}.merge", name);
-            let has_cfg = cfg
-                .as_ref()
-                .map(|c| c.get_string(&remote_key).is_ok() && c.get_string(&merge_key).is_ok())
-                .unwrap_or(false);
-
-            let upstream_res = branch.upstream();
-            let has_upstream = upstream_res.is_ok();
-            let is_gone = has_cfg
-                && matches!(
-                    upstream_res.err().map(|e| e.code()),
-                    Some(ErrorCode::NotFound)
-                );
-
-            items.push(BranchItem {
-                name,
-                oid: oid_full,
-                summary,
-                is_head: branch.is_head(),
-                has_upstream,
-                is_gone,
-            });
+    for line in input.lines() {
+        let line = line.trim_start_matches(['*', '+', ' ', '\t']).trim_end();
+        let line = line.split(" -> ").next().unwrap_or_default();
+        if line.is_empty() || line.starts_with('(') {
+            continue;
         }
+        let name = if let Some(name) = line
+            .strip_prefix("refs/remotes/")
+            .or_else(|| line.strip_prefix("remotes/"))
+        {
+            has_remote = true;
+            name
+        } else {
+            line.strip_prefix("refs/heads/").unwrap_or(line)
+        };
+        names.insert(name.to_string());
     }
+
+    (names, has_remote)
 }
 
-pub fn query_branches(repo: &Repository, branch_query: &BranchQuery) -> Vec<BranchItem> {
+/// Branches read with [`read_refs`] rather than through libgit2, which is much faster on
+/// repositories with many refs. `None` when the refs can't be read that way.
+fn list_branches(repo: &Repository, branch_query: &BranchQuery) -> Option<Vec<BranchItem>> {
+    const HEADS: &str = "refs/heads/";
+    const REMOTES: &str = "refs/remotes/";
+    let prefixes: &[&str] = match branch_query {
+        BranchQuery::Local => &[HEADS],
+        BranchQuery::Remote => &[REMOTES],
+        BranchQuery::LocalAndRemote => &[HEADS, REMOTES],
+    };
+    let head = head_ref(repo);
+    let refs = read_refs(repo, prefixes)?;
+
+    let items = refs
+        .into_iter()
+        .map(|r| {
+            let is_remote = r.name.starts_with(REMOTES.as_bytes());
+            let prefix_len = if is_remote {
+                REMOTES.len()
+            } else {
+                HEADS.len()
+            };
+            BranchItem::from_name_bytes(
+                &r.name[prefix_len..],
+                r.target.map(|oid| oid.to_string()).unwrap_or_default(),
+                head.as_ref() == Some(&r.name),
+                is_remote,
+            )
+        })
+        .collect();
+    Some(items)
+}
+
+/// Branches read through libgit2, for refs [`list_branches`] can't read.
+fn list_branches_libgit2(repo: &Repository, branch_query: &BranchQuery) -> Vec<BranchItem> {
     let mut items = Vec::new();
 
     match branch_query {
         BranchQuery::Local => {
             if let Ok(branches) = repo.branches(Some(BranchType::Local)) {
-                parse_branches(repo, branches, BranchType::Local, &mut items);
+                parse_branches(branches, BranchType::Local, &mut items);
             }
         }
         BranchQuery::Remote => {
             if let Ok(branches) = repo.branches(Some(BranchType::Remote)) {
-                parse_branches(repo, branches, BranchType::Remote, &mut items);
+                parse_branches(branches, BranchType::Remote, &mut items);
             }
         }
         BranchQuery::LocalAndRemote => {
             if let Ok(branches) = repo.branches(Some(BranchType::Local)) {
-                parse_branches(repo, branches, BranchType::Local, &mut items);
+                parse_branches(branches, BranchType::Local, &mut items);
             }
             if let Ok(branches) = repo.branches(Some(BranchType::Remote)) {
-                parse_branches(repo, branches, BranchType::Remote, &mut items);
+                parse_branches(branches, BranchType::Remote, &mut items);
             }
         }
     }
+    items
+}
 
+pub fn query_branches(repo: &Repository, branch_query: &BranchQuery) -> Vec<BranchItem> {
+    let mut items = query_branches_without_upstreams(repo, branch_query);
+    let (sender, receiver) = mpsc::channel();
+    send_upstream_states(repo, &local_names(&items), |states| {
+        let _ = sender.send(states);
+    });
+    for states in receiver.try_iter() {
+        apply_upstream_states(&mut items, states);
+    }
     items
 }
 
-pub fn checkout_branch(repo: &Repository, name: &str) -> Result<(), Error> {
+/// Same as [`query_branches`], leaving the upstreams to [`send_upstream_states`].
+pub fn query_branches_without_upstreams(
+    repo: &Repository,
+    branch_query: &BranchQuery,
+) -> Vec<BranchItem> {
+    list_branches(repo, branch_query).unwrap_or_else(|| list_branches_libgit2(repo, branch_query))
+}
+
+/// Checks out the local branch `name`, which doesn't have to be valid UTF-8, in the worktree
+/// `repo` was opened in. A branch checked out in another worktree, as listed in `worktrees`, is
+/// refused before any file is touched, like `git switch` does. `force` overwrites local changes to the files that differ
+/// between HEAD and the branch instead of failing, changes to other files are kept.
+///
+/// `progress` is called with the number of files checked out so far and the total, and cancels
+/// the checkout once it returns false. libgit2 can't stop halfway, so the files it wrote and the
+/// index are put back once it is done and HEAD is left alone. Returns whether the branch was
+/// checked out.
+pub fn checkout_branch(
+    repo: &Repository,
+    name: &[u8],
+    worktrees: &WorktreeHeads,
+    force: bool,
+    mut progress: impl FnMut(usize, usize) -> bool,
+) -> Result<bool, Error> {
+    let (display_name, _) = lossy(name);
+    if let Some(path) = worktrees.checked_out_elsewhere(&display_name) {
+        return Err(Error::from_str(&format!(
+            "'{display_name}' is already checked out at {}",
+            path.display()
+        )));
+    }
+    let branch = find_local_branch(repo, name)?;
+    let reference = branch.get();
+    let tree = reference.peel_to_tree()?;
+    let head_tree = repo.head().and_then(|head| head.peel_to_tree()).ok();
+    let index_path = repo.path().join("index");
+    // Saved as it is on disk, so staged changes and index extensions survive a cancel.
+    let saved_index = fs::read(&index_path).ok();
+
+    let cancelled = Cell::new(false);
+    let mut cb = CheckoutBuilder::new();
+    // Whether any file has to change, a forced checkout without paths would touch all of them.
+    let mut changes = true;
+    if force {
+        cb.force();
+        let diff = repo.diff_tree_to_tree(head_tree.as_ref(), Some(&tree), None)?;
+        changes = diff.deltas().len() > 0;
+        for delta in diff.deltas() {
+            let paths = [delta.old_file().path(), delta.new_file().path()];
+            for path in paths.into_iter().flatten() {
+                cb.path(path);
+            }
+        }
+    } else {
+        cb.safe();
+    }
+    cb.progress(|_, done, total| {
+        if !progress(done, total) {
+            cancelled.set(true);
+        }
+    });
+    if changes {
+        repo.checkout_tree(tree.as_object(), Some(&mut cb))?;
+    }
+    drop(cb);
+
+    if cancelled.get() {
+        if let Some(head_tree) = head_tree {
+            undo_checkout(repo, &head_tree, &tree)?;
+        }
+        match saved_index {
+            Some(saved) => fs::write(&index_path, saved),
+            None => fs::remove_file(&index_path),
+        }
+        .map_err(|e| Error::from_str(&format!("couldn't restore the index: {e}")))?;
+        repo.index()?.read(true)?;
+        return Ok(false);
+    }
+    repo.set_head_bytes(reference.name_bytes())?;
+    Ok(true)
+}
+
+/// Puts back the files that checking out `to` changed while HEAD still points to `from`. Only
+/// those files are touched. After a safe checkout they had no local changes, so nothing is lost;
+/// after a forced one they get the content of `from`, the local changes it overwrote are gone.
+/// The index is left to the caller.
+fn undo_checkout(repo: &Repository, from: &Tree, to: &Tree) -> Result<(), Error> {
+    let diff = repo.diff_tree_to_tree(Some(from), Some(to), None)?;
+    let mut added = Vec::new();
+    let mut cb = CheckoutBuilder::new();
+    cb.force();
+    let mut changed = false;
+    for delta in diff.deltas() {
+        if delta.status() == Delta::Added {
+            added.extend(delta.new_file().path().map(Path::to_path_buf));
+        } else if let Some(path) = delta.old_file().path() {
+            cb.path(path);
+            changed = true;
+        }
+    }
+    // No paths would mean all of them.
+    if changed {
+        repo.checkout_tree(from.as_object(), Some(&mut cb))?;
+    }
+
+    let workdir = repo.workdir().unwrap_or(Path::new("."));
+    for path in &added {
+        let _ = fs::remove_file(workdir.join(path));
+    }
+    // Directories that only held added files, removing one that isn't empty fails.
+    for path in &added {
+        for dir in path
+            .ancestors()
+            .skip(1)
+            .take_while(|d| !d.as_os_str().is_empty())
+        {
+            if fs::remove_dir(workdir.join(dir)).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The branch checked out before the current one, `@{-1}` to git, going by the latest
+/// `checkout: moving from <old> to <new>` entry in the HEAD reflog.
+pub fn previous_branch(repo: &Repository) -> Result<Vec<u8>, Error> {
+    let reflog = repo.reflog("HEAD")?;
+    let from = reflog
+        .iter()
+        .filter_map(|entry| {
+            let message = entry.message_bytes()?;
+            let rest = message.strip_prefix(b"checkout: moving from ")?;
+            let end = rest.windows(4).position(|w| w == b" to ")?;
+            Some(rest[..end].to_vec())
+        })
+        .next()
+        .ok_or_else(|| Error::from_str("no previous checkout in the HEAD reflog"))?;
+    // Checkouts of a commit move from its id instead.
+    if find_local_branch(repo, &from).is_err() {
+        return Err(Error::from_str(&format!(
+            "the previous checkout, '{}', isn't a local branch",
+            lossy(&from).0
+        )));
+    }
+    Ok(from)
+}
+
+/// Deletes local branch `name` when its tip is on HEAD or on its upstream, like `git branch -d`.
+/// Branches that are checked out here or in another worktree are kept.
+pub fn delete_branch(repo: &Repository, name: &[u8]) -> Result<(), Error> {
+    let mut branch = find_local_branch(repo, name)?;
+    if branch.is_head() {
+        return Err(Error::from_str("it is checked out"));
+    }
+    let tip = branch
+        .get()
+        .target()
+        .ok_or_else(|| Error::from_str("it has no commit"))?;
+    let contains_tip = |oid: Option<Oid>| {
+        oid.is_some_and(|oid| oid == tip || repo.graph_descendant_of(oid, tip).unwrap_or(false))
+    };
+    let head = repo.head().ok().and_then(|head| head.target());
+    let upstream = branch.upstream().ok().and_then(|u| u.get().target());
+    if !contains_tip(head) && !contains_tip(upstream) {
+        return Err(Error::from_str("it is not fully merged"));
+    }
+    branch.delete()
+}
+
+/// Like [`Repository::find_branch`], but also finds branches whose name isn't valid UTF-8.
+pub fn find_local_branch<'r>(repo: &'r Repository, name: &[u8]) -> Result<Branch<'r>, Error> {
+    if let Ok(name) = std::str::from_utf8(name) {
+        return repo.find_branch(name, BranchType::Local);
+    }
+    repo.branches(Some(BranchType::Local))?
+        .flatten()
+        .map(|(branch, _)| branch)
+        .find(|branch| branch.name_bytes().is_ok_and(|n| n == name))
+        .ok_or_else(|| Error::from_str("branch not found"))
+}
+
+/// Commits `branch` has that its upstream doesn't and the other way around. `None` for remote
+/// branches and branches without an upstream.
+pub fn upstream_ahead_behind(repo: &Repository, branch: &BranchItem) -> Option<(usize, usize)> {
+    if branch.is_remote || branch.upstream.is_none() {
+        return None;
+    }
+    let upstream = repo.branch_upstream_name(&branch.refname()).ok()?;
+    let upstream = repo.refname_to_id(upstream.as_str()?).ok()?;
+    let local = Oid::from_str(&branch.oid).ok()?;
+    repo.graph_ahead_behind(local, upstream).ok()
+}
+
+/// Fast-forwards every local branch that is strictly behind its upstream. Branches that have
+/// diverged from their upstream are never touched.
+pub fn fast_forward_branches(repo: &Repository) -> FastForwardSummary {
+    let mut summary = FastForwardSummary::default();
+    let Ok(branches) = repo.branches(Some(BranchType::Local)) else {
+        return summary;
+    };
+
+    for (branch, _) in branches.flatten() {
+        let Ok(Some(name)) = branch.name().map(|n| n.map(|n| n.to_string())) else {
+            continue;
+        };
+        let Ok(upstream) = branch.upstream() else {
+            continue;
+        };
+        let (Some(local), Some(remote)) = (branch.get().target(), upstream.get().target()) else {
+            continue;
+        };
+        if local == remote {
+            continue;
+        }
+
+        match repo.graph_ahead_behind(local, remote) {
+            Ok((0, _)) => {}
+            Ok((_, 0)) => continue,
+            Ok(_) => {
+                summary.diverged.push(name);
+                continue;
+            }
+            Err(e) => {
+                summary.failed.push((name, e));
+                continue;
+            }
+        }
+
+        let result = if branch.is_head() {
+            fast_forward_head(repo, remote)
+        } else {
+            branch
+                .into_reference()
+                .set_target(remote, "gix: fast-forward")
+                .map(|_| ())
+        };
+        match result {
+            Ok(()) => summary.updated.push(name),
+            Err(e) => summary.failed.push((name, e)),
+        }
+    }
+
+    summary
+}
+
+fn fast_forward_head(repo: &Repository, target: Oid) -> Result<(), Error> {
     let mut cb = CheckoutBuilder::new();
     cb.safe();
 
-    let branch = repo.find_branch(name, BranchType::Local)?;
-    let reference = branch.get();
-    let commit = reference.peel_to_commit()?;
+    let commit = repo.find_commit(target)?;
     repo.checkout_tree(commit.as_object(), Some(&mut cb))?;
-    repo.set_head(
-        reference
-            .name()
-            .ok_or_else(|| Error::from_str("invalid ref name"))?,
-    )?;
+    repo.head()?.set_target(target, "gix: fast-forward")?;
     Ok(())
 }
+
+/// Rewinds each branch to the commit it pointed to at `time` (seconds since the epoch),
+/// dropping branches that didn't exist yet.
+pub fn rewind_branches(repo: &Repository, branches: Vec<BranchItem>, time: i64) -> Vec<BranchItem> {
+    branches
+        .into_iter()
+        .filter_map(|mut branch| {
+            let (oid, is_estimated) = branch_tip_at(repo, &branch, time)?;
+            let commit = repo.find_commit(oid).ok()?;
+            branch.oid = oid.to_string();
+            branch.details = Arc::new(OnceLock::from(CommitDetails::from_commit(&commit)));
+            branch.ahead_behind = Arc::default();
+            branch.last_change = Arc::default();
+            branch.activity = Arc::default();
+            branch.is_estimated = is_estimated;
+            Some(branch)
+        })
+        .collect()
+}
+
+/// Commit `branch` pointed to at `time`, read from its reflog when that reaches back far
+/// enough. Otherwise estimated as the newest first-parent ancestor committed before `time`.
+fn branch_tip_at(repo: &Repository, branch: &BranchItem, time: i64) -> Option<(Oid, bool)> {
+    let mut start = Oid::from_str(&branch.oid).ok()?;
+
+    if let Ok(reflog) = repo.reflog(&branch.refname())
+        && let Some(oldest) = reflog.get(reflog.len().saturating_sub(1))
+    {
+        if let Some(entry) = reflog
+            .iter()
+            .find(|entry| entry.committer().when().seconds() <= time)
+        {
+            let oid = entry.id_new();
+            return (!oid.is_zero()).then_some((oid, false));
+        }
+        if oldest.id_old().is_zero() {
+            return None;
+        }
+        start = oldest.id_old();
+    }
+
+    let mut commit = repo.find_commit(start).ok()?;
+    while commit.time().seconds() > time {
+        commit = commit.parent(0).ok()?;
+    }
+    Some((commit.id(), true))
+}
+
+/// Number of commits reachable from `tip` in each of the last `weeks` weeks, oldest first.
+/// With `path` only commits changing it are counted.
+fn commit_activity(
+    repo: &Repository,
+    tip: &str,
+    path: Option<&Path>,
+    weeks: usize,
+    now: i64,
+) -> Vec<usize> {
+    let mut counts = vec![0; weeks];
+    let cutoff = now - weeks as i64 * WEEK;
+
+    let Ok(mut revwalk) = repo.revwalk() else {
+        return counts;
+    };
+    let Ok(tip) = Oid::from_str(tip) else {
+        return counts;
+    };
+    if revwalk.push(tip).is_err() || revwalk.set_sorting(Sort::TIME).is_err() {
+        return counts;
+    }
+
+    for oid in revwalk.flatten() {
+        let Ok(commit) = repo.find_commit(oid) else {
+            continue;
+        };
+        let time = commit.time().seconds();
+        if time < cutoff {
+            break;
+        }
+        if path.is_some_and(|path| !touches_path(&commit, path)) {
+            continue;
+        }
+        let weeks_ago = ((now - time).max(0) / WEEK) as usize;
+        if weeks_ago < weeks {
+            counts[weeks - 1 - weeks_ago] += 1;
+        }
+    }
+    counts
+}
+
+/// Commit time of the newest commit from `tip` back that changes `path`.
+fn last_commit_touching(repo: &Repository, tip: &str, path: &Path) -> Option<i64> {
+    let mut revwalk = repo.revwalk().ok()?;
+    revwalk.push(Oid::from_str(tip).ok()?).ok()?;
+    revwalk.set_sorting(Sort::TIME).ok()?;
+    revwalk
+        .flatten()
+        .filter_map(|oid| repo.find_commit(oid).ok())
+        .find(|commit| touches_path(commit, path))
+        .map(|commit| commit.time().seconds())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{commit_file, fixture};
+    use git2::{Signature, Time};
+    use std::fs;
+
+    fn head(repo: &Repository) -> Oid {
+        repo.head().unwrap().target().unwrap()
+    }
+
+    fn summary(items: &[BranchItem]) -> Vec<(String, String, bool, bool)> {
+        items
+            .iter()
+            .map(|b| (b.name.clone(), b.oid.clone(), b.is_head, b.is_remote))
+            .collect()
+    }
+
+    #[test]
+    fn fast_listing_matches_libgit2() {
+        let repo = fixture("fast-refs");
+        let oid = head(&repo);
+        let zero = Oid::zero();
+        // Packed only, packed but overridden by a loose ref, and a packed remote branch.
+        fs::write(
+            repo.path().join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {oid} refs/heads/packed\n\
+                 {zero} refs/heads/stale\n\
+                 {oid} refs/remotes/origin/main\n\
+                 {oid} refs/tags/v1\n"
+            ),
+        )
+        .unwrap();
+        repo.reference("refs/heads/stale", oid, true, "").unwrap();
+        repo.reference("refs/heads/nested/loose", oid, false, "")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            false,
+            "",
+        )
+        .unwrap();
+
+        for query in [
+            BranchQuery::Local,
+            BranchQuery::Remote,
+            BranchQuery::LocalAndRemote,
+        ] {
+            let fast = list_branches(&repo, &query).unwrap();
+            let mut slow = list_branches_libgit2(&repo, &query);
+            slow.sort_by_key(BranchItem::refname);
+            assert_eq!(summary(&fast), summary(&slow));
+        }
+    }
+
+    #[test]
+    fn cancelled_checkout_puts_files_and_index_back() {
+        let repo = fixture("cancel-checkout");
+        let kept = commit_file(&repo, "kept.txt", "main", "Add kept");
+        repo.reference("refs/heads/feature", kept, true, "")
+            .unwrap();
+        repo.set_head("refs/heads/feature").unwrap();
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        fs::create_dir(repo.workdir().unwrap().join("dir")).unwrap();
+        commit_file(&repo, "dir/added.txt", "feature", "Add a file");
+        repo.set_head("refs/heads/main").unwrap();
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        // A staged change the checkout doesn't touch, and an index with extensions.
+        fs::write(repo.workdir().unwrap().join("kept.txt"), "staged").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("kept.txt")).unwrap();
+        index.write_tree().unwrap();
+        index.write().unwrap();
+        let index_before = fs::read(repo.path().join("index")).unwrap();
+
+        let mut calls = 0;
+        let checked_out = checkout_branch(
+            &repo,
+            b"feature",
+            &WorktreeHeads::read(&repo),
+            false,
+            |_, _| {
+                calls += 1;
+                false
+            },
+        );
+        assert!(!checked_out.unwrap());
+        assert!(calls > 0);
+        assert_eq!(repo.head().unwrap().shorthand(), Some("main"));
+        let workdir = repo.workdir().unwrap();
+        assert!(!workdir.join("dir").exists());
+        assert_eq!(
+            fs::read_to_string(workdir.join("kept.txt")).unwrap(),
+            "staged"
+        );
+        assert_eq!(fs::read(repo.path().join("index")).unwrap(), index_before);
+        let entry = repo.index().unwrap().get_path(Path::new("kept.txt"), 0);
+        let blob = repo.find_blob(entry.unwrap().id).unwrap();
+        assert_eq!(blob.content(), b"staged");
+
+        assert!(
+            checkout_branch(
+                &repo,
+                b"feature",
+                &WorktreeHeads::read(&repo),
+                false,
+                |_, _| true
+            )
+            .unwrap()
+        );
+        assert_eq!(repo.head().unwrap().shorthand(), Some("feature"));
+    }
+
+    #[test]
+    fn forced_checkout_keeps_changes_to_other_files() {
+        let repo = fixture("forced-checkout");
+        commit_file(&repo, "other.txt", "main", "Add other");
+        let base = commit_file(&repo, "shared.txt", "main", "Add shared");
+        repo.reference("refs/heads/feature", base, true, "")
+            .unwrap();
+        repo.set_head("refs/heads/feature").unwrap();
+        commit_file(&repo, "shared.txt", "feature", "Change shared");
+        repo.set_head("refs/heads/main").unwrap();
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        let workdir = repo.workdir().unwrap();
+        fs::write(workdir.join("shared.txt"), "local").unwrap();
+        fs::write(workdir.join("other.txt"), "mine").unwrap();
+
+        assert!(
+            checkout_branch(
+                &repo,
+                b"feature",
+                &WorktreeHeads::read(&repo),
+                false,
+                |_, _| true
+            )
+            .is_err()
+        );
+        assert!(
+            checkout_branch(
+                &repo,
+                b"feature",
+                &WorktreeHeads::read(&repo),
+                true,
+                |_, _| true
+            )
+            .unwrap()
+        );
+        assert_eq!(repo.head().unwrap().shorthand(), Some("feature"));
+        assert_eq!(
+            fs::read_to_string(workdir.join("shared.txt")).unwrap(),
+            "feature"
+        );
+        assert_eq!(
+            fs::read_to_string(workdir.join("other.txt")).unwrap(),
+            "mine"
+        );
+    }
+
+    #[test]
+    fn lists_many_packed_refs_like_libgit2() {
+        let repo = fixture("many-refs");
+        let oid = head(&repo);
+        let mut packed = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+        for i in 0..5_000 {
+            packed.push_str(&format!("{oid} refs/heads/branch-{i:05}\n"));
+        }
+        fs::write(repo.path().join("packed-refs"), packed).unwrap();
+
+        let fast = list_branches(&repo, &BranchQuery::Local).unwrap();
+        let mut slow = list_branches_libgit2(&repo, &BranchQuery::Local);
+        slow.sort_by_key(BranchItem::refname);
+        // The 5000 packed branches and the loose `main` and `feature`.
+        assert_eq!(fast.len(), 5_002);
+        assert_eq!(summary(&fast), summary(&slow));
+    }
+
+    #[test]
+    fn upstreams_arrive_in_batches() {
+        let repo = fixture("upstream-batches");
+        let oid = head(&repo);
+        repo.reference("refs/remotes/origin/main", oid, false, "")
+            .unwrap();
+        repo.remote("origin", "https://example.com/origin.git")
+            .unwrap();
+        let commit = repo.find_commit(oid).unwrap();
+        for i in 0..PARALLEL_UPSTREAMS_MIN {
+            let mut branch = repo.branch(&format!("b{i:03}"), &commit, false).unwrap();
+            if i % 2 == 0 {
+                branch.set_upstream(Some("origin/main")).unwrap();
+            }
+        }
+
+        let mut items = query_branches_without_upstreams(&repo, &BranchQuery::Local);
+        assert!(items.iter().all(|b| b.upstream.is_none()));
+        let (sender, receiver) = mpsc::channel();
+        send_upstream_states(&repo, &local_names(&items), |states| {
+            sender.send(states).unwrap();
+        });
+        let batches: Vec<_> = receiver.try_iter().collect();
+        if thread::available_parallelism().map_or(1, |n| n.get()) > 1 {
+            assert!(batches.len() > 1);
+        }
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), items.len());
+        for states in batches {
+            apply_upstream_states(&mut items, states);
+        }
+        let tracking = items.iter().filter(|b| b.upstream.is_some()).count();
+        assert_eq!(tracking, PARALLEL_UPSTREAMS_MIN / 2);
+        let b000 = items.iter().find(|b| b.name == "b000").unwrap();
+        assert_eq!(b000.upstream.as_deref(), Some("origin/main"));
+    }
+
+    #[test]
+    fn last_change_follows_the_path() {
+        let repo = fixture("last-change");
+        let commit_at = |path: &str, time: i64| {
+            fs::write(repo.workdir().unwrap().join(path), path).unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new(path)).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parent = repo.head().unwrap().peel_to_commit().unwrap();
+            let sig = Signature::new("Tester", "tester@example.com", &Time::new(time, 0)).unwrap();
+            repo.commit(Some("HEAD"), &sig, &sig, path, &tree, &[&parent])
+                .unwrap();
+        };
+        commit_at("a.txt", 100);
+        commit_at("b.txt", 200);
+
+        let items = query_branches(&repo, &BranchQuery::Local);
+        let main = items.iter().find(|b| b.name == "main").unwrap();
+        assert_eq!(main.last_change(&repo, None), Some(200));
+        assert_eq!(main.last_change(&repo, Some(Path::new("a.txt"))), Some(100));
+        let feature = items.iter().find(|b| b.name == "feature").unwrap();
+        assert_eq!(feature.last_change(&repo, Some(Path::new("a.txt"))), None);
+    }
+}
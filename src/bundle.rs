@@ -0,0 +1,77 @@
+use git2::Repository;
+use std::{
+    ffi::OsStr,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Namespace fetched bundle branches are stored under, so importing never moves local branches.
+pub const BUNDLE_REMOTE_PREFIX: &str = "refs/remotes/bundle/";
+
+/// Writes a bundle containing `refs` and everything reachable from them to `path`.
+pub fn create_bundle(repo: &Repository, path: &Path, refs: &[String]) -> Result<(), String> {
+    let mut args = vec![
+        "bundle".to_string(),
+        "create".to_string(),
+        path.display().to_string(),
+    ];
+    args.extend(refs.iter().cloned());
+    git(repo, &args).map(|_| ())
+}
+
+/// Checks that the repository has every commit the bundle at `path` builds on and returns
+/// the refs it contains.
+pub fn verify_bundle(repo: &Repository, path: &Path) -> Result<Vec<String>, String> {
+    let path = path.display().to_string();
+    git(repo, &["bundle", "verify", &path])?;
+    let heads = git(repo, &["bundle", "list-heads", &path])?;
+    Ok(heads
+        .lines()
+        .filter_map(|line| line.split_once(' ').map(|(_, name)| name.to_string()))
+        .filter(|name| name != "HEAD")
+        .collect())
+}
+
+/// Fetches `refs` from the bundle at `path`. Branches end up under [`BUNDLE_REMOTE_PREFIX`],
+/// other refs such as tags under their own name.
+pub fn fetch_bundle(repo: &Repository, path: &Path, refs: &[String]) -> Result<(), String> {
+    let mut args = vec!["fetch".to_string(), path.display().to_string()];
+    args.extend(
+        refs.iter()
+            .map(|name| match name.strip_prefix("refs/heads/") {
+                Some(branch) => format!("{name}:{BUNDLE_REMOTE_PREFIX}{branch}"),
+                None => format!("{name}:{name}"),
+            }),
+    );
+    git(repo, &args).map(|_| ())
+}
+
+/// Runs git in the repository, returning stdout or its error messages on failure.
+fn git(repo: &Repository, args: &[impl AsRef<OsStr>]) -> Result<String, String> {
+    let dir = repo.workdir().unwrap_or(repo.path());
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("could not run git: {e}"))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let messages: Vec<&str> = stderr
+            .lines()
+            .map(|line| {
+                line.trim_start_matches("error: ")
+                    .trim_start_matches("fatal: ")
+                    .trim()
+            })
+            .filter(|line| !line.is_empty())
+            .collect();
+        if messages.is_empty() {
+            Err(format!("git exited with {}", output.status))
+        } else {
+            Err(messages.join(" "))
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use std::{env, process::Command};
+
+/// Reads the system clipboard through the platform's command line tools. Terminals rarely allow
+/// reading it with OSC 52, so that is not tried.
+pub fn read_clipboard() -> Result<String, String> {
+    let candidates: &[(&str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbpaste", &[])]
+    } else if cfg!(windows) {
+        &[("powershell", &["-NoProfile", "-Command", "Get-Clipboard"])]
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        &[
+            ("wl-paste", &["--no-newline"]),
+            ("xclip", &["-selection", "clipboard", "-o"]),
+        ]
+    } else {
+        &[
+            ("xclip", &["-selection", "clipboard", "-o"]),
+            ("xsel", &["--clipboard", "--output"]),
+        ]
+    };
+
+    for (program, args) in candidates {
+        let Ok(output) = Command::new(program).args(*args).output() else {
+            continue;
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{program}: {}", stderr.trim()));
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    let names: Vec<&str> = candidates.iter().map(|(program, _)| *program).collect();
+    Err(format!(
+        "Couldn't read the clipboard, install {}",
+        names.join(" or ")
+    ))
+}
@@ -0,0 +1,150 @@
+//! Columns of the branch list, picked and ordered with `gix.columns`, and how a row is put
+//! together from them.
+
+use crate::term::Span;
+
+/// A column of the branch list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Oid,
+    /// Name with its label chips.
+    Name,
+    /// How long ago the tip was committed.
+    Age,
+    /// Commits ahead of and behind the upstream.
+    AheadBehind,
+    /// Commit activity sparkline, only while toggled on with `a`.
+    Activity,
+    Summary,
+}
+
+impl Column {
+    /// The columns shown unless `gix.columns` is set.
+    pub const DEFAULT: [Column; 6] = [
+        Column::Oid,
+        Column::Name,
+        Column::AheadBehind,
+        Column::Age,
+        Column::Activity,
+        Column::Summary,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().replace(['_', '-'], "").as_str() {
+            "oid" => Some(Column::Oid),
+            "name" => Some(Column::Name),
+            "age" => Some(Column::Age),
+            "aheadbehind" => Some(Column::AheadBehind),
+            "activity" => Some(Column::Activity),
+            "summary" => Some(Column::Summary),
+            _ => None,
+        }
+    }
+
+    /// Parses a list of columns such as `oid, name, age, ahead_behind, summary`, separated by
+    /// commas or spaces. Brackets and quotes are ignored, so `["oid", "name"]` works too.
+    pub fn parse_list(s: &str) -> Option<Vec<Self>> {
+        let columns: Option<Vec<Self>> = s
+            .split(|c: char| c == ',' || c.is_whitespace() || "[]\"'".contains(c))
+            .filter(|word| !word.is_empty())
+            .map(Self::parse)
+            .collect();
+        columns.filter(|columns| !columns.is_empty())
+    }
+
+    fn right_aligned(self) -> bool {
+        self == Column::Age
+    }
+
+    /// Space between this column and the next.
+    fn gap(self) -> usize {
+        if self == Column::Oid { 1 } else { 2 }
+    }
+}
+
+/// Columns with their widths.
+pub struct Layout {
+    columns: Vec<(Column, usize)>,
+}
+
+impl Layout {
+    /// Lays out `columns`, each `width(column)` cells wide. Columns 0 cells wide are left out.
+    pub fn new(columns: impl IntoIterator<Item = Column>, width: impl Fn(Column) -> usize) -> Self {
+        Self {
+            columns: columns
+                .into_iter()
+                .map(|column| (column, width(column)))
+                .filter(|(_, width)| *width > 0)
+                .collect(),
+        }
+    }
+
+    /// Cells before `column` starts, `None` when it isn't shown.
+    pub fn offset(&self, column: Column) -> Option<usize> {
+        let mut offset = 0;
+        for &(c, width) in &self.columns {
+            if c == column {
+                return Some(offset);
+            }
+            offset += width + c.gap();
+        }
+        None
+    }
+
+    /// Puts a row together from the spans `cell` gives for each column. Narrower cells are
+    /// padded to the width of their column, wider ones push the rest of the row to the right.
+    pub fn row(&self, mut cell: impl FnMut(Column) -> Vec<Span>) -> Vec<Span> {
+        let mut row = Vec::new();
+        let mut gap = 0;
+        for &(column, width) in &self.columns {
+            if gap > 0 {
+                row.push(Span::raw(" ".repeat(gap)));
+            }
+            let spans = cell(column);
+            let fill = width.saturating_sub(spans.iter().map(Span::width).sum());
+            let fill = (fill > 0).then(|| Span::raw(" ".repeat(fill)));
+            if column.right_aligned() {
+                row.extend(fill);
+                row.extend(spans);
+            } else {
+                row.extend(spans);
+                row.extend(fill);
+            }
+            gap = column.gap();
+        }
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_are_padded_to_their_column() {
+        assert_eq!(
+            Column::parse_list(r#"["oid", "name", "age", "ahead_behind"]"#),
+            Some(vec![
+                Column::Oid,
+                Column::Name,
+                Column::Age,
+                Column::AheadBehind
+            ])
+        );
+        assert_eq!(Column::parse_list("oid, nope"), None);
+
+        let layout = Layout::new(
+            [Column::Oid, Column::Name, Column::Age],
+            |column| match column {
+                Column::Name => 6,
+                _ => 4,
+            },
+        );
+        let row: String = layout
+            .row(|column| vec![Span::raw(format!("{column:?}").to_lowercase())])
+            .iter()
+            .map(|span| span.text.as_str())
+            .collect();
+        assert_eq!(row, "oid  name     age");
+    }
+}
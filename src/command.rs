@@ -0,0 +1,245 @@
+//! Commands typed after `:`, e.g.
+//!
+//! ```text
+//! :checkout feat
+//! :delete old-fix spike
+//! :sort date desc
+//! :filter merged && !head
+//! :pr feature/oauth-login
+//! ```
+//!
+//! Command names can be shortened as long as they stay unambiguous, `:del` is `:delete`.
+
+use crate::filter::SortKey;
+
+/// What a command's arguments are completed from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Branch,
+    LocalBranch,
+    Sort,
+    View,
+    /// Words of the filter language, see filter.rs.
+    Filter,
+    None,
+}
+
+/// A command as it can be typed, with what it takes.
+pub struct Spec {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub arg: ArgKind,
+}
+
+pub const COMMANDS: [Spec; 10] = [
+    Spec {
+        name: "checkout",
+        args: "<pattern>",
+        arg: ArgKind::LocalBranch,
+    },
+    Spec {
+        name: "delete",
+        args: "[<branch>...]",
+        arg: ArgKind::LocalBranch,
+    },
+    Spec {
+        name: "fetch",
+        args: "[<branch>...]",
+        arg: ArgKind::Branch,
+    },
+    Spec {
+        name: "filter",
+        args: "[<expression>]",
+        arg: ArgKind::Filter,
+    },
+    Spec {
+        name: "pr",
+        args: "[<branch>]",
+        arg: ArgKind::Branch,
+    },
+    Spec {
+        name: "push",
+        args: "[<branch>...]",
+        arg: ArgKind::LocalBranch,
+    },
+    Spec {
+        name: "search",
+        args: "[<text>]",
+        arg: ArgKind::None,
+    },
+    Spec {
+        name: "sort",
+        args: "[name|age|date|ahead|behind|author [desc]]",
+        arg: ArgKind::Sort,
+    },
+    Spec {
+        name: "sync",
+        args: "",
+        arg: ArgKind::None,
+    },
+    Spec {
+        name: "view",
+        args: "<view>",
+        arg: ArgKind::View,
+    },
+];
+
+/// Words the filter language is made of, for completion.
+const FILTER_WORDS: [&str; 13] = [
+    "age", "author", "name", "summary", "label", "head", "remote", "gone", "upstream", "merged",
+    "me", "sort", "desc",
+];
+
+pub enum TypedCommand {
+    /// The local branch matching a pattern.
+    Checkout(String),
+    /// Named branches, none for the marked or selected ones.
+    Delete(Vec<String>),
+    Fetch(Vec<String>),
+    Push(Vec<String>),
+    /// Pull request of the named branch, or of the selected one.
+    PullRequest(Option<String>),
+    /// Expression, empty to clear the filter.
+    Filter(String),
+    Search(String),
+    /// Column and whether it is reversed, `None` for unsorted.
+    Sort(Option<(SortKey, bool)>),
+    Sync,
+    View(String),
+}
+
+impl TypedCommand {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let (name, rest) = input.split_once(' ').unwrap_or((input, ""));
+        let rest = rest.trim();
+        let args: Vec<String> = rest.split_whitespace().map(str::to_string).collect();
+        let spec = find(name)?;
+        Ok(match spec.name {
+            "checkout" => match args.as_slice() {
+                [pattern] => TypedCommand::Checkout(pattern.clone()),
+                _ => return Err(usage(spec)),
+            },
+            "delete" => TypedCommand::Delete(args),
+            "fetch" => TypedCommand::Fetch(args),
+            "push" => TypedCommand::Push(args),
+            "pr" => match args.as_slice() {
+                [] => TypedCommand::PullRequest(None),
+                [branch] => TypedCommand::PullRequest(Some(branch.clone())),
+                _ => return Err(usage(spec)),
+            },
+            "filter" => TypedCommand::Filter(rest.to_string()),
+            "search" => TypedCommand::Search(rest.to_string()),
+            "sort" => match args.as_slice() {
+                [] => TypedCommand::Sort(None),
+                [key] | [key, _] => {
+                    let key = SortKey::from_name(key)
+                        .ok_or_else(|| format!("can't sort by '{key}', {}", usage(spec)))?;
+                    let desc = match args.get(1).map(String::as_str) {
+                        None | Some("asc") => false,
+                        Some("desc") => true,
+                        Some(_) => return Err(usage(spec)),
+                    };
+                    TypedCommand::Sort(Some((key, desc)))
+                }
+                _ => return Err(usage(spec)),
+            },
+            "sync" if args.is_empty() => TypedCommand::Sync,
+            "view" => match args.as_slice() {
+                [view] => TypedCommand::View(view.clone()),
+                _ => return Err(usage(spec)),
+            },
+            _ => return Err(usage(spec)),
+        })
+    }
+}
+
+/// The command `name` is, or is the start of.
+fn find(name: &str) -> Result<&'static Spec, String> {
+    if name.is_empty() {
+        return Err(format!("expected a command: {}", names(COMMANDS.iter())));
+    }
+    if let Some(spec) = COMMANDS.iter().find(|spec| spec.name == name) {
+        return Ok(spec);
+    }
+    let matches: Vec<&Spec> = COMMANDS
+        .iter()
+        .filter(|spec| spec.name.starts_with(name))
+        .collect();
+    match matches.as_slice() {
+        [spec] => Ok(spec),
+        [] => Err(format!(
+            "unknown command '{name}', expected {}",
+            names(COMMANDS.iter())
+        )),
+        _ => Err(format!(
+            "'{name}' could be {}",
+            names(matches.iter().copied())
+        )),
+    }
+}
+
+fn names<'a>(specs: impl Iterator<Item = &'a Spec>) -> String {
+    specs.map(|spec| spec.name).collect::<Vec<_>>().join(", ")
+}
+
+fn usage(spec: &Spec) -> String {
+    format!("usage: :{} {}", spec.name, spec.args)
+        .trim_end()
+        .to_string()
+}
+
+/// What the last word of `input` is completed from: `None` while the command name itself is
+/// typed, or the kind of argument of the command.
+pub fn completing(input: &str) -> Option<ArgKind> {
+    let (name, _) = input.trim_start().split_once(' ')?;
+    Some(find(name).map_or(ArgKind::None, |spec| spec.arg))
+}
+
+/// Candidates for the word being completed when it is of `kind`, given the branch names to
+/// pick from and the view names.
+pub fn candidates(kind: Option<ArgKind>, branches: &[&str], views: &[&str]) -> Vec<String> {
+    let words: Vec<&str> = match kind {
+        None => COMMANDS.iter().map(|spec| spec.name).collect(),
+        Some(ArgKind::Branch | ArgKind::LocalBranch) => branches.to_vec(),
+        Some(ArgKind::Sort) => SortKey::ALL
+            .iter()
+            .map(|key| key.name())
+            .chain(["date", "desc"])
+            .collect(),
+        Some(ArgKind::View) => views.to_vec(),
+        Some(ArgKind::Filter) => FILTER_WORDS.to_vec(),
+        Some(ArgKind::None) => Vec::new(),
+    };
+    words.into_iter().map(str::to_string).collect()
+}
+
+/// Completes the last word of `input` from `candidates`: to the candidate and a space when only
+/// one starts with it, otherwise as far as all of them agree. Returns the new input and the
+/// candidates that start with the word.
+pub fn complete(input: &str, candidates: &[String]) -> (String, Vec<String>) {
+    let start = input.rfind(' ').map_or(0, |i| i + 1);
+    let word = &input[start..];
+    let matches: Vec<String> = candidates
+        .iter()
+        .filter(|c| c.starts_with(word))
+        .cloned()
+        .collect();
+    let completed = match matches.as_slice() {
+        [] => word.to_string(),
+        [only] => format!("{only} "),
+        [first, rest @ ..] => {
+            let mut prefix = first.as_str();
+            for other in rest {
+                let common = prefix
+                    .char_indices()
+                    .zip(other.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(prefix.len().min(other.len()), |((i, _), _)| i);
+                prefix = &prefix[..common];
+            }
+            prefix.to_string()
+        }
+    };
+    (format!("{}{completed}", &input[..start]), matches)
+}
@@ -0,0 +1,249 @@
+use crate::{
+    View, branch::BranchQuery, columns::Column, doctor::Background, export::ExportTheme,
+    filter::SortKey, search::SearchMode,
+};
+use clap::ValueEnum;
+use git2::Repository;
+
+/// Defaults read from the `gix` section of the git config, e.g.
+///
+/// ```gitconfig
+/// [gix]
+///     view = status
+///     exportTheme = dark
+///     readOnly = true
+///     backupRemote = backup
+///     filter = author = me && !merged(main)
+///     background = light
+///     mouse = false
+///     bottomUp = true
+///     pinHead = true
+///     rowNumbers = true
+///     upstreamColumn = true
+///     wrapSummaries = true
+///     columns = oid, name, age, ahead_behind, summary
+///     summaryLength = 50
+///     branchNameLength = 30
+///     format = %(HEAD) %(refname:short) %(upstream:track) %(subject)
+///     search = substring
+///     myEmail = me@work.example
+///     myEmail = me@home.example
+/// ```
+///
+/// `myEmail` is read by [`crate::filter::Context`], for `me` and the `u` toggle.
+///
+/// Saved views of the branch list are sections of their own, cycled through with `V`:
+///
+/// ```gitconfig
+/// [gixView "mine-unmerged"]
+///     mine = true
+///     merged = false
+/// [gixView "releases"]
+///     branches = all
+///     search = release !rc
+///     sort = age desc
+/// [gixView "gone"]
+///     filter = gone
+/// ```
+///
+/// `branch.sort` (e.g. `-committerdate`) orders the branch list when no other sort is chosen.
+///
+/// Command line arguments take precedence over these. In a linked worktree with
+/// `extensions.worktreeConfig` set, values from `git config --worktree` override the shared ones,
+/// so e.g. one worktree can be made read-only.
+#[derive(Default)]
+pub struct Config {
+    pub view: Option<View>,
+    pub export_theme: ExportTheme,
+    pub read_only: bool,
+    /// Remote that `M` mirrors all local branches and tags to.
+    pub backup_remote: Option<String>,
+    /// Branch filter expression applied at startup, see [`crate::filter`].
+    pub filter: Option<String>,
+    /// Background of the terminal, for terminals that don't answer when asked. `auto` or
+    /// anything else asks.
+    pub background: Option<Background>,
+    /// Whether clicks select rows, on by default where the terminal reports them. Off leaves
+    /// selecting text to the terminal.
+    pub mouse: Option<bool>,
+    /// Draw lists from the bottom up, the first row at the bottom and the rest above it.
+    pub bottom_up: bool,
+    /// Keep the checked out branch in the first row of the branch list, whatever the sort and
+    /// filters.
+    pub pin_head: bool,
+    /// Number the rows of lists, for typing a row's number and enter to select it.
+    pub row_numbers: bool,
+    /// Show the upstream each branch tracks instead of only marking those without one.
+    pub upstream_column: bool,
+    /// Wrap long summaries onto more rows instead of cutting them off.
+    pub wrap_summaries: bool,
+    /// Columns of the branch list in order, see [`Column::parse_list`]. `None` for
+    /// [`Column::DEFAULT`].
+    pub columns: Option<Vec<Column>>,
+    /// Longest summary and branch name shown, also written by `{` `}` and `<` `>`.
+    pub summary_length: Option<usize>,
+    pub branch_name_length: Option<usize>,
+    /// Template for the rows of the branch list, replacing `columns`, see [`crate::format`].
+    pub format: Option<String>,
+    /// How the branch search matches names, fuzzy unless set to `substring`.
+    pub search: SearchMode,
+    /// `branch.sort`, as far as it maps to a column, for when no other sort is chosen.
+    pub branch_sort: Option<(SortKey, bool)>,
+    /// `gixView` sections in the order they first appear.
+    pub saved_views: Vec<SavedView>,
+}
+
+/// Branches listed, search, sort and filters saved under a name. What isn't set is cleared when
+/// switching to the view.
+#[derive(Clone, Default)]
+pub struct SavedView {
+    pub name: String,
+    /// `local`, `remote` or `all`, local when not set.
+    pub branches: Option<BranchQuery>,
+    pub search: Option<String>,
+    /// Column and whether it is reversed, e.g. `age desc`.
+    pub sort: Option<(SortKey, bool)>,
+    /// Filter expression, see [`crate::filter`].
+    pub filter: Option<String>,
+    /// Only branches merged into HEAD, or only those that aren't.
+    pub merged: Option<bool>,
+    pub mine: bool,
+}
+
+impl SavedView {
+    /// Sets `key` of the `gixView` section, ignoring unknown keys and values.
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "branches" => self.branches = BranchQuery::parse(value),
+            "search" => self.search = Some(value.to_string()),
+            "sort" => self.sort = parse_sort(value),
+            "filter" => self.filter = Some(value.to_string()),
+            "merged" => self.merged = parse_bool(value),
+            "mine" => self.mine = parse_bool(value).unwrap_or(false),
+            _ => {}
+        }
+    }
+}
+
+fn parse_sort(value: &str) -> Option<(SortKey, bool)> {
+    let mut words = value.split_whitespace();
+    let key = SortKey::from_name(words.next()?)?;
+    let desc = match words.next() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return None,
+    };
+    Some((key, desc))
+}
+
+/// Column for a `git branch --sort` key such as `-committerdate`, `-` reversing it. Dates sort
+/// oldest first like in git, which is a descending [`SortKey::Age`].
+fn parse_branch_sort(value: &str) -> Option<(SortKey, bool)> {
+    let (reversed, key) = match value.trim().strip_prefix('-') {
+        Some(key) => (true, key),
+        None => (false, value.trim()),
+    };
+    let (key, desc) = match key {
+        "refname" | "refname:short" => (SortKey::Name, false),
+        "committerdate" | "authordate" | "creatordate" => (SortKey::Age, true),
+        "authoremail" => (SortKey::Author, false),
+        _ => return None,
+    };
+    Some((key, desc != reversed))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn load_saved_views(cfg: &git2::Config) -> Vec<SavedView> {
+    let mut views: Vec<SavedView> = Vec::new();
+    let Ok(entries) = cfg.entries(Some("gixview\\..*")) else {
+        return views;
+    };
+    entries
+        .for_each(|entry| {
+            let (Some(name), Some(value)) = (entry.name(), entry.value()) else {
+                return;
+            };
+            let Some((view, key)) = name
+                .strip_prefix("gixview.")
+                .and_then(|rest| rest.rsplit_once('.'))
+            else {
+                return;
+            };
+            let i = match views.iter().position(|v| v.name == view) {
+                Some(i) => i,
+                None => {
+                    views.push(SavedView {
+                        name: view.to_string(),
+                        ..SavedView::default()
+                    });
+                    views.len() - 1
+                }
+            };
+            views[i].set(key, value);
+        })
+        .ok();
+    views
+}
+
+impl Config {
+    pub fn load(repo: &Repository) -> Self {
+        let Ok(cfg) = repo.config() else {
+            return Self::default();
+        };
+
+        Self {
+            view: cfg
+                .get_string("gix.view")
+                .ok()
+                .and_then(|s| View::from_str(&s, true).ok()),
+            export_theme: cfg
+                .get_string("gix.exportTheme")
+                .ok()
+                .and_then(|s| ExportTheme::parse(&s))
+                .unwrap_or_default(),
+            read_only: cfg.get_bool("gix.readOnly").unwrap_or(false),
+            backup_remote: cfg.get_string("gix.backupRemote").ok(),
+            filter: cfg.get_string("gix.filter").ok(),
+            background: cfg
+                .get_string("gix.background")
+                .ok()
+                .and_then(|s| Background::parse(&s)),
+            mouse: cfg.get_bool("gix.mouse").ok(),
+            bottom_up: cfg.get_bool("gix.bottomUp").unwrap_or(false),
+            pin_head: cfg.get_bool("gix.pinHead").unwrap_or(false),
+            row_numbers: cfg.get_bool("gix.rowNumbers").unwrap_or(false),
+            upstream_column: cfg.get_bool("gix.upstreamColumn").unwrap_or(false),
+            wrap_summaries: cfg.get_bool("gix.wrapSummaries").unwrap_or(false),
+            summary_length: cfg
+                .get_i64("gix.summaryLength")
+                .ok()
+                .and_then(|n| usize::try_from(n).ok()),
+            branch_name_length: cfg
+                .get_i64("gix.branchNameLength")
+                .ok()
+                .and_then(|n| usize::try_from(n).ok()),
+            format: cfg.get_string("gix.format").ok(),
+            columns: cfg
+                .get_string("gix.columns")
+                .ok()
+                .and_then(|s| Column::parse_list(&s)),
+            search: cfg
+                .get_string("gix.search")
+                .ok()
+                .and_then(|s| SearchMode::parse(&s))
+                .unwrap_or_default(),
+            branch_sort: cfg
+                .get_string("branch.sort")
+                .ok()
+                .and_then(|s| parse_branch_sort(&s)),
+            saved_views: load_saved_views(&cfg),
+        }
+    }
+}
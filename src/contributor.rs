@@ -0,0 +1,55 @@
+use crate::lossy::lossy;
+use git2::{Repository, Sort};
+use std::collections::HashMap;
+
+pub struct ContributorItem {
+    pub name: String,
+    pub email: String,
+    pub commits: usize,
+    /// Author time of the most recent commit, in seconds since the epoch.
+    pub last_activity: i64,
+}
+
+/// Aggregates commits per author email over all local and remote branches, or only over
+/// the history of `branch`. Sorted by commit count, most active first.
+pub fn query_contributors(repo: &Repository, branch: Option<&str>) -> Vec<ContributorItem> {
+    let Ok(mut revwalk) = repo.revwalk() else {
+        return Vec::new();
+    };
+    let pushed = match branch {
+        Some(branch) => repo
+            .revparse_single(branch)
+            .and_then(|o| revwalk.push(o.id())),
+        None => revwalk
+            .push_glob("refs/heads")
+            .and_then(|_| revwalk.push_glob("refs/remotes")),
+    };
+    if pushed.is_err() || revwalk.set_sorting(Sort::TIME).is_err() {
+        return Vec::new();
+    }
+
+    let mut by_email: HashMap<String, ContributorItem> = HashMap::new();
+    for oid in revwalk.flatten() {
+        let Ok(commit) = repo.find_commit(oid) else {
+            continue;
+        };
+        let author = commit.author();
+        let email = lossy(author.email_bytes()).0;
+        let time = author.when().seconds();
+
+        let item = by_email
+            .entry(email.clone())
+            .or_insert_with(|| ContributorItem {
+                name: lossy(author.name_bytes()).0,
+                email,
+                commits: 0,
+                last_activity: time,
+            });
+        item.commits += 1;
+        item.last_activity = item.last_activity.max(time);
+    }
+
+    let mut items: Vec<ContributorItem> = by_email.into_values().collect();
+    items.sort_by(|a, b| b.commits.cmp(&a.commits).then(a.name.cmp(&b.name)));
+    items
+}
@@ -0,0 +1,157 @@
+use git2::{Repository, Time};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const DAY: i64 = 86_400;
+pub const WEEK: i64 = 7 * DAY;
+
+/// Current time in seconds since the epoch.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Formats as `YYYY-MM-DD HH:MM:SS +HHMM` in the time's own offset.
+pub fn format_time(time: Time) -> String {
+    let offset = time.offset_minutes() as i64;
+    let secs = time.seconds() + offset * 60;
+    let (year, month, day) = civil_from_days(secs.div_euclid(DAY));
+    let rem = secs.rem_euclid(DAY);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} {}{:02}{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60,
+    )
+}
+
+/// Formats seconds since the epoch as a `YYYY-MM-DD` UTC date.
+pub fn format_day(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(DAY));
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Time from `seconds` to `now` in its largest unit, e.g. `5m`, `3h`, `4d`, `2w`, `8mo` or `3y`.
+pub fn format_age(seconds: i64, now: i64) -> String {
+    match (now - seconds).max(0) {
+        age if age < 3600 => format!("{}m", age / 60),
+        age if age < DAY => format!("{}h", age / 3600),
+        age if age < 2 * WEEK => format!("{}d", age / DAY),
+        age if age < 60 * DAY => format!("{}w", age / WEEK),
+        age if age < 365 * DAY => format!("{}mo", age / (30 * DAY)),
+        age => format!("{}y", age / (365 * DAY)),
+    }
+}
+
+/// Parses a point in time into seconds since the epoch (UTC). Understands `now`, `yesterday`,
+/// `N <unit>s ago`, `[last] <weekday>`, `YYYY-MM-DD [HH:MM[:SS]]` and, failing those, any
+/// revspec resolving to a commit, which stands for its commit time. Bare dates and weekdays
+/// mean the end of that day.
+pub fn parse_time(repo: &Repository, input: &str, now: i64) -> Option<i64> {
+    let input = input.trim().to_lowercase();
+    let end_of_day = |days: i64| days * DAY + DAY - 1;
+
+    match input.as_str() {
+        "" => return None,
+        "now" => return Some(now),
+        "yesterday" => return Some(end_of_day(now.div_euclid(DAY) - 1)),
+        _ => {}
+    }
+
+    if let Some(ago) = input.strip_suffix(" ago") {
+        let (n, unit) = ago.split_once(' ')?;
+        let n: i64 = n.parse().ok()?;
+        let unit = match unit.trim_end_matches('s') {
+            "second" | "sec" => 1,
+            "minute" | "min" => 60,
+            "hour" => 3600,
+            "day" => DAY,
+            "week" => WEEK,
+            "month" => 30 * DAY,
+            "year" => 365 * DAY,
+            _ => return None,
+        };
+        return Some(now - n * unit);
+    }
+
+    let weekday = input.strip_prefix("last ").unwrap_or(&input);
+    if let Some(target) = [
+        "sunday",
+        "monday",
+        "tuesday",
+        "wednesday",
+        "thursday",
+        "friday",
+        "saturday",
+    ]
+    .iter()
+    .position(|d| *d == weekday || d[..3] == *weekday)
+    {
+        // 1970-01-01 was a Thursday.
+        let today = now.div_euclid(DAY);
+        let current = (today + 4).rem_euclid(7);
+        let back = (current - target as i64 - 1).rem_euclid(7) + 1;
+        return Some(end_of_day(today - back));
+    }
+
+    if let Some(time) = parse_datetime(&input) {
+        return Some(time);
+    }
+
+    repo.revparse_single(&input)
+        .ok()?
+        .peel_to_commit()
+        .ok()
+        .map(|c| c.time().seconds())
+}
+
+fn parse_datetime(input: &str) -> Option<i64> {
+    let (date, time) = match input.split_once([' ', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (input, None),
+    };
+
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+
+    let Some(time) = time else {
+        return Some(days * DAY + DAY - 1);
+    };
+    let mut parts = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute) = (parts.next()??, parts.next()??);
+    let second = parts.next().flatten().unwrap_or(0);
+    Some(days * DAY + hour * 3600 + minute * 60 + second)
+}
+
+// Civil date conversions, see http://howardhinnant.github.io/date_algorithms.html
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
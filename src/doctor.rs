@@ -0,0 +1,285 @@
+use crossterm::terminal::supports_keyboard_enhancement;
+use std::{
+    env,
+    io::{IsTerminal, stdout},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    None,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorSupport {
+    fn name(self) -> &'static str {
+        match self {
+            ColorSupport::None => "none",
+            ColorSupport::Ansi16 => "16 colors",
+            ColorSupport::Ansi256 => "256 colors",
+            ColorSupport::TrueColor => "24-bit true color",
+        }
+    }
+}
+
+/// Whether the terminal draws light text on a dark background or the other way around.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Background {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl Background {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "dark" => Some(Background::Dark),
+            "light" => Some(Background::Light),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Background::Dark => "dark",
+            Background::Light => "light",
+        }
+    }
+}
+
+/// What the terminal gix runs in is believed to support, guessed from the environment.
+pub struct Capabilities {
+    pub term: String,
+    pub colors: ColorSupport,
+    /// Colors were turned off with `--no-color` or `NO_COLOR`.
+    pub no_color: bool,
+    pub unicode: bool,
+    pub mouse: bool,
+    /// Set by [`Self::probe_kitty_keyboard`], which has to ask the terminal.
+    pub kitty_keyboard: Option<bool>,
+    /// Set by [`Self::probe_background`], `None` when the terminal didn't say.
+    pub background: Option<Background>,
+    pub osc52: bool,
+    /// Clickable OSC 8 links.
+    pub hyperlinks: bool,
+}
+
+impl Capabilities {
+    /// Detects capabilities from environment variables only, cheap enough to run at startup.
+    pub fn detect(no_color: bool) -> Self {
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+
+        let colors = if term.is_empty() || term == "dumb" {
+            ColorSupport::None
+        } else if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        };
+
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|var| env::var(var).ok().filter(|v| !v.is_empty()))
+            .unwrap_or_default()
+            .to_lowercase();
+        let unicode = locale.contains("utf-8") || locale.contains("utf8");
+
+        let osc52 = ["kitty", "alacritty", "wezterm", "foot", "tmux", "xterm"]
+            .iter()
+            .any(|t| term.contains(t))
+            || ["iTerm.app", "WezTerm", "ghostty"].contains(&program.as_str());
+
+        // Terminals ignoring OSC 8 may print it, so only those known to understand it get links.
+        let hyperlinks = ["kitty", "wezterm", "foot", "alacritty", "ghostty"]
+            .iter()
+            .any(|t| term.contains(t))
+            || ["iTerm.app", "WezTerm", "ghostty", "vscode"].contains(&program.as_str())
+            || env::var("VTE_VERSION").is_ok_and(|v| v.parse().is_ok_and(|v: u32| v >= 5000))
+            || env::var_os("WT_SESSION").is_some();
+
+        Self {
+            mouse: colors != ColorSupport::None && term != "linux",
+            term,
+            colors,
+            no_color,
+            unicode,
+            kitty_keyboard: None,
+            background: None,
+            osc52,
+            hyperlinks,
+        }
+    }
+
+    /// Colors the UI is drawn with, `None` when they are turned off.
+    pub fn palette(&self) -> ColorSupport {
+        if self.no_color {
+            ColorSupport::None
+        } else {
+            self.colors
+        }
+    }
+
+    /// Asks the terminal whether it supports the kitty keyboard protocol.
+    pub fn probe_kitty_keyboard(&mut self) {
+        if stdout().is_terminal() {
+            self.kitty_keyboard = supports_keyboard_enhancement().ok();
+        }
+    }
+
+    /// Asks the terminal for its background color (OSC 11).
+    pub fn probe_background(&mut self) {
+        if stdout().is_terminal() {
+            self.background = query_background();
+        }
+    }
+
+    /// Features gix turned off or that will look wrong in this terminal. Turning colors off on
+    /// purpose is not a downgrade.
+    pub fn downgrades(&self) -> Vec<String> {
+        let mut downgrades = Vec::new();
+        if !self.no_color && self.colors == ColorSupport::None {
+            downgrades.push(format!("colors disabled, TERM={:?} has none", self.term));
+        }
+        if !self.unicode {
+            downgrades.push("locale is not UTF-8, drawing with ASCII only".to_string());
+        }
+        downgrades
+    }
+
+    /// Human readable report printed by `gix doctor-terminal`.
+    pub fn report(&self) -> Vec<String> {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        let mut lines = vec![
+            format!("TERM:            {}", self.term),
+            format!(
+                "colors:          {}{}",
+                self.colors.name(),
+                if self.no_color {
+                    " (turned off by --no-color or NO_COLOR)"
+                } else {
+                    ""
+                }
+            ),
+            format!("unicode:         {}", yes_no(self.unicode)),
+            format!("mouse:           {}", yes_no(self.mouse)),
+            format!(
+                "kitty keyboard:  {}",
+                self.kitty_keyboard
+                    .map_or("unknown (not a terminal)", yes_no)
+            ),
+            format!(
+                "background:      {}",
+                self.background
+                    .map_or("unknown (no answer to OSC 11)", Background::name)
+            ),
+            format!("OSC 52:          {}", yes_no(self.osc52)),
+            format!("hyperlinks:      {}", yes_no(self.hyperlinks)),
+        ];
+
+        let downgrades = self.downgrades();
+        lines.push(String::new());
+        if downgrades.is_empty() {
+            lines.push("No features downgraded.".to_string());
+        } else {
+            lines.push("Downgraded:".to_string());
+            lines.extend(downgrades.iter().map(|d| format!("  {d}")));
+        }
+        lines
+    }
+}
+
+/// Background of the controlling terminal going by the color it answers OSC 11 with.
+#[cfg(unix)]
+fn query_background() -> Option<Background> {
+    use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+    use std::{fs::OpenOptions, io::Write};
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    enable_raw_mode().ok()?;
+    // Every terminal answers the device attributes query (`ESC [ c`). Once that answer is in, a
+    // terminal that ignores OSC 11 won't answer it any more, no need to wait for a timeout.
+    let reply = tty
+        .write_all(b"\x1b]11;?\x1b\\\x1b[c")
+        .and_then(|_| tty.flush())
+        .map(|_| read_reply(&mut tty));
+    let _ = disable_raw_mode();
+    parse_background(&reply.ok()?)
+}
+
+#[cfg(not(unix))]
+fn query_background() -> Option<Background> {
+    None
+}
+
+/// Reads what the terminal sends until the device attributes answer, `ESC [ ? ... c`, or until
+/// nothing arrives for a while.
+#[cfg(unix)]
+fn read_reply(tty: &mut std::fs::File) -> Vec<u8> {
+    use std::{io::Read, os::fd::AsRawFd};
+
+    let mut reply = Vec::new();
+    let mut pollfd = libc::pollfd {
+        fd: tty.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    while !(reply.ends_with(b"c") && reply.windows(3).any(|w| w == b"\x1b[?")) {
+        // SAFETY: pollfd points at one valid pollfd for the duration of the call.
+        if unsafe { libc::poll(&mut pollfd, 1, 200) } <= 0 {
+            break;
+        }
+        let mut buf = [0; 64];
+        match tty.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => reply.extend_from_slice(&buf[..n]),
+        }
+    }
+    reply
+}
+
+/// Background from an OSC 11 answer such as `ESC ] 11 ; rgb:ffff/ffff/dddd ESC \`, light when
+/// the color is more than half as bright as white.
+fn parse_background(reply: &[u8]) -> Option<Background> {
+    let reply = String::from_utf8_lossy(reply);
+    let (_, color) = reply.split_once("]11;rgb:")?;
+    let color = color.split(['\x07', '\x1b']).next()?;
+    let mut channels = color.split('/').map(|hex| {
+        let max = 16u32.checked_pow(hex.len() as u32)?.checked_sub(1)?;
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        Some(value as f64 / max as f64)
+    });
+    let (r, g, b) = (channels.next()??, channels.next()??, channels.next()??);
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    Some(if luminance > 0.5 {
+        Background::Light
+    } else {
+        Background::Dark
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn background_from_osc_11_answer() {
+        let light = b"\x1b]11;rgb:ffff/ffff/dddd\x1b\\\x1b[?62;22c";
+        assert_eq!(parse_background(light), Some(Background::Light));
+        let dark = b"\x1b]11;rgb:1e1e/1e1e/2e2e\x07\x1b[?1;2c";
+        assert_eq!(parse_background(dark), Some(Background::Dark));
+        assert_eq!(
+            parse_background(b"\x1b]11;rgb:f/f/f\x07"),
+            Some(Background::Light)
+        );
+        assert_eq!(parse_background(b"\x1b[?1;2c"), None);
+    }
+}
@@ -0,0 +1,119 @@
+use crossterm::event::{self, Event, KeyEventKind};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+/// Time between ticks, also how long the input thread waits for input before checking whether
+/// it was paused.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub enum LoopEvent {
+    /// Key, mouse, paste or resize event from the terminal.
+    Input(Event),
+    /// Sent every `TICK_INTERVAL` for whatever changes without input, such as spinners and
+    /// results of work running in the background.
+    Tick,
+}
+
+/// Terminal input and ticks merged into one channel, each produced by a thread of its own, so
+/// waiting for the next event never holds up a frame that is due.
+pub struct EventLoop {
+    receiver: Receiver<LoopEvent>,
+    input: Arc<InputGate>,
+}
+
+/// Lets the input thread be paused while another program has the terminal.
+struct InputGate {
+    paused: AtomicBool,
+    /// Held by the input thread while it polls, so pausing can wait for a poll to finish.
+    polling: Mutex<()>,
+}
+
+impl EventLoop {
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let input = Arc::new(InputGate {
+            paused: AtomicBool::new(false),
+            polling: Mutex::new(()),
+        });
+
+        let gate = Arc::clone(&input);
+        let input_sender = sender.clone();
+        thread::spawn(move || read_input(&gate, &input_sender));
+        thread::spawn(move || {
+            while sender.send(LoopEvent::Tick).is_ok() {
+                thread::sleep(TICK_INTERVAL);
+            }
+        });
+
+        Self { receiver, input }
+    }
+
+    /// Next event, waiting as long as it takes. Ticks that piled up while the caller was busy
+    /// are merged into one.
+    pub fn next(&self) -> LoopEvent {
+        let mut event = self.receiver.recv().expect("event threads stopped");
+        while matches!(event, LoopEvent::Tick) {
+            match self.receiver.try_recv() {
+                Ok(next) => event = next,
+                Err(_) => break,
+            }
+        }
+        event
+    }
+
+    /// An event that is already waiting, without blocking.
+    pub fn try_next(&self) -> Option<LoopEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Stops reading input until `resume` so a program gix hands the terminal to gets all of
+    /// it. Returns once the input thread is no longer waiting for input.
+    pub fn pause(&self) {
+        self.input.paused.store(true, Ordering::SeqCst);
+        drop(self.input.polling.lock());
+    }
+
+    pub fn resume(&self) {
+        self.input.paused.store(false, Ordering::SeqCst);
+    }
+}
+
+fn read_input(gate: &InputGate, sender: &Sender<LoopEvent>) {
+    loop {
+        if gate.paused.load(Ordering::SeqCst) {
+            thread::sleep(TICK_INTERVAL);
+            continue;
+        }
+        let Ok(_polling) = gate.polling.lock() else {
+            return;
+        };
+        if gate.paused.load(Ordering::SeqCst) {
+            continue;
+        }
+        match event::poll(TICK_INTERVAL) {
+            Ok(true) => {
+                let Ok(event) = event::read() else {
+                    return;
+                };
+                // Releases come on Windows, nothing is bound to them.
+                if let Event::Key(key) = &event
+                    && key.kind == KeyEventKind::Release
+                {
+                    continue;
+                }
+                if sender.send(LoopEvent::Input(event)).is_err() {
+                    return;
+                }
+            }
+            Ok(false) => {}
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use crate::log::commit_header;
+use git2::{Diff, DiffFormat, DiffOptions, Error, Oid, Repository};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ExportTheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl ExportTheme {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "light" => Some(ExportTheme::Light),
+            "dark" => Some(ExportTheme::Dark),
+            _ => None,
+        }
+    }
+
+    fn css(self) -> &'static str {
+        match self {
+            ExportTheme::Light => {
+                "body{background:#fff;color:#24292f}.meta{color:#57606a}\
+                 .file{background:#f6f8fa;color:#24292f}.hunk{background:#ddf4ff;color:#0969da}\
+                 .add{background:#e6ffec;color:#116329}.del{background:#ffebe9;color:#82071e}\
+                 .kw{color:#cf222e}.str{color:#0a3069}.num{color:#0550ae}.com{color:#6e7781}"
+            }
+            ExportTheme::Dark => {
+                "body{background:#0d1117;color:#c9d1d9}.meta{color:#8b949e}\
+                 .file{background:#161b22;color:#c9d1d9}.hunk{background:#121d2f;color:#79c0ff}\
+                 .add{background:#12261e;color:#7ee787}.del{background:#25171c;color:#ffa198}\
+                 .kw{color:#ff7b72}.str{color:#a5d6ff}.num{color:#79c0ff}.com{color:#8b949e}"
+            }
+        }
+    }
+}
+
+/// Exports the changes `oid` introduces on top of its first parent to
+/// `gix-<short oid>.html` in the current directory. With `path` only changes under it are
+/// included.
+pub fn export_commit(
+    repo: &Repository,
+    oid: Oid,
+    path: Option<&Path>,
+    theme: ExportTheme,
+) -> Result<PathBuf, Error> {
+    let commit = repo.find_commit(oid)?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(
+        parent_tree.as_ref(),
+        Some(&commit.tree()?),
+        Some(&mut diff_options(path)),
+    )?;
+
+    let mut header = commit_header(&commit);
+    header.push(String::new());
+    header.push(commit.message().unwrap_or_default().trim_end().to_string());
+
+    let short: String = oid.to_string().chars().take(7).collect();
+    let title = format!("{short} {}", commit.summary().unwrap_or_default());
+    write_export(&format!("gix-{short}.html"), &title, &header, &diff, theme)
+}
+
+/// Exports what branch `name` changes compared to its merge-base with HEAD to
+/// `gix-<name>.html` in the current directory. With `path` only changes under it are included.
+pub fn export_branch(
+    repo: &Repository,
+    name: &str,
+    path: Option<&Path>,
+    theme: ExportTheme,
+) -> Result<PathBuf, Error> {
+    let tip = repo.revparse_single(name)?.peel_to_commit()?;
+    let head = repo.head()?.peel_to_commit()?;
+    let base = repo.find_commit(repo.merge_base(head.id(), tip.id())?)?;
+    let diff = repo.diff_tree_to_tree(
+        Some(&base.tree()?),
+        Some(&tip.tree()?),
+        Some(&mut diff_options(path)),
+    )?;
+
+    let header = vec![
+        format!("branch {name}"),
+        format!("base   {}", base.id()),
+        format!("tip    {}", tip.id()),
+    ];
+
+    let file_name = format!("gix-{}.html", name.replace('/', "-"));
+    write_export(&file_name, &format!("{name} diff"), &header, &diff, theme)
+}
+
+fn diff_options(path: Option<&Path>) -> DiffOptions {
+    let mut opts = DiffOptions::new();
+    if let Some(path) = path {
+        opts.pathspec(path);
+    }
+    opts
+}
+
+fn write_export(
+    file_name: &str,
+    title: &str,
+    header: &[String],
+    diff: &Diff,
+    theme: ExportTheme,
+) -> Result<PathBuf, Error> {
+    let mut body = String::new();
+    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+        let class = match line.origin() {
+            '+' | '>' => "add",
+            '-' | '<' => "del",
+            'F' => "file",
+            'H' => "hunk",
+            _ => "ctx",
+        };
+        let prefix = match line.origin() {
+            c @ ('+' | '-' | ' ') => c.to_string(),
+            _ => String::new(),
+        };
+        let content = String::from_utf8_lossy(line.content());
+        let content = content.trim_end_matches('\n');
+        let syntax = delta.new_file().path().and_then(Syntax::of);
+        let content = match syntax {
+            Some(syntax) if matches!(class, "add" | "del" | "ctx") => highlight(content, &syntax),
+            _ => escape(content),
+        };
+        body.push_str(&format!(
+            "<span class=\"{class}\">{}{content}</span>",
+            escape(&prefix)
+        ));
+        true
+    })?;
+
+    let html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>body{{font-family:monospace;margin:2em}}pre{{margin:0}}\
+         pre>span{{display:block;white-space:pre-wrap}}{}</style>\n</head>\n<body>\n\
+         <pre class=\"meta\">{}</pre>\n<pre>{}</pre>\n</body>\n</html>\n",
+        escape(title),
+        theme.css(),
+        escape(&header.join("\n")),
+        body,
+    );
+
+    let path = env::current_dir()
+        .map_err(|e| Error::from_str(&e.to_string()))?
+        .join(file_name);
+    fs::write(&path, html).map_err(|e| Error::from_str(&e.to_string()))?;
+    Ok(path)
+}
+
+/// How the lines of a file are highlighted: line comments, strings, numbers and keywords, which
+/// reads well enough in a diff without parsing the whole file.
+struct Syntax {
+    comment: &'static str,
+    /// Characters strings are quoted with.
+    quotes: &'static str,
+    /// Separated by spaces.
+    keywords: &'static str,
+}
+
+const C_LIKE_KEYWORDS: &str = "break case catch class const continue default do else enum \
+    export extends false final for func function if import interface let new nil null package \
+    private protected public return static struct switch this throw true try type var void while";
+
+impl Syntax {
+    /// Syntax of the file at `path`, going by its extension.
+    fn of(path: &Path) -> Option<Self> {
+        let (comment, quotes, keywords) = match path.extension()?.to_str()? {
+            "rs" => (
+                "//",
+                "\"",
+                "as async await break const continue crate dyn else enum false fn for if impl \
+                 in let loop match mod move mut pub ref return self Self static struct super \
+                 trait true type unsafe use where while",
+            ),
+            "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "go" | "java" | "js" | "jsx" | "kt"
+            | "swift" | "ts" | "tsx" => ("//", "\"'", C_LIKE_KEYWORDS),
+            "py" => (
+                "#",
+                "\"'",
+                "and as assert async await break class continue def del elif else except False \
+                 finally for from global if import in is lambda None nonlocal not or pass raise \
+                 return True try while with yield",
+            ),
+            "sh" | "bash" | "zsh" => (
+                "#",
+                "\"'",
+                "case do done elif else esac export fi for function if in local return then \
+                 until while",
+            ),
+            "rb" => (
+                "#",
+                "\"'",
+                "begin class def do else elsif end ensure false if module nil rescue return \
+                 self then true unless until while yield",
+            ),
+            "toml" | "yaml" | "yml" => ("#", "\"'", "false true"),
+            _ => return None,
+        };
+        Some(Self {
+            comment,
+            quotes,
+            keywords,
+        })
+    }
+
+    fn is_keyword(&self, word: &str) -> bool {
+        self.keywords
+            .split_whitespace()
+            .any(|keyword| keyword == word)
+    }
+}
+
+/// `line` as escaped HTML with its comment, strings, numbers and keywords in spans.
+fn highlight(line: &str, syntax: &Syntax) -> String {
+    let span = |class: &str, text: &str| format!("<span class=\"{class}\">{}</span>", escape(text));
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut html = String::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with(syntax.comment) {
+            html.push_str(&span("com", rest));
+            rest.len()
+        } else if syntax.quotes.contains(c) {
+            // Up to the closing quote, or the end of the line for strings spanning lines.
+            let mut escaped = false;
+            let end = rest[1..].find(|next: char| {
+                let closes = next == c && !escaped;
+                escaped = next == '\\' && !escaped;
+                closes
+            });
+            let len = end.map_or(rest.len(), |end| end + 2);
+            html.push_str(&span("str", &rest[..len]));
+            len
+        } else if is_word(c) {
+            let len = rest
+                .find(|c: char| !is_word(c) && c != '.')
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            if c.is_ascii_digit() {
+                html.push_str(&span("num", word));
+                len
+            } else {
+                // Only up to a `.`, which numbers keep.
+                let len = word.find('.').unwrap_or(len);
+                let word = &rest[..len];
+                if syntax.is_keyword(word) {
+                    html.push_str(&span("kw", word));
+                } else {
+                    html.push_str(&escape(word));
+                }
+                len
+            }
+        } else {
+            html.push_str(&escape(&rest[..c.len_utf8()]));
+            c.len_utf8()
+        };
+        rest = &rest[len..];
+    }
+    html
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_by_file_type() {
+        let rust = Syntax::of(Path::new("src/main.rs")).unwrap();
+        assert_eq!(
+            highlight(r#"    let s = "a \"b\" <c>"; // x < 1.5"#, &rust),
+            "    <span class=\"kw\">let</span> s = \
+             <span class=\"str\">&quot;a \\&quot;b\\&quot; &lt;c&gt;&quot;</span>; \
+             <span class=\"com\">// x &lt; 1.5</span>"
+        );
+        assert_eq!(
+            highlight("fn f(x: &'a u8) -> u32 { x.len() + 0x1F }", &rust),
+            "<span class=\"kw\">fn</span> f(x: &amp;'a u8) -&gt; u32 { x.len() + \
+             <span class=\"num\">0x1F</span> }"
+        );
+        let python = Syntax::of(Path::new("setup.py")).unwrap();
+        assert_eq!(
+            highlight("if 'lettered' in names: # 2", &python),
+            "<span class=\"kw\">if</span> <span class=\"str\">'lettered'</span> \
+             <span class=\"kw\">in</span> names: <span class=\"com\"># 2</span>"
+        );
+        assert!(Syntax::of(Path::new("README")).is_none());
+        assert!(Syntax::of(Path::new("notes.txt")).is_none());
+    }
+}
@@ -0,0 +1,23 @@
+//! Files tracked at HEAD, listed by the files view to open their history or blame from.
+
+use crate::lossy::lossy;
+use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
+use std::path::Path;
+
+/// Paths of the files in HEAD's tree in tree order, only those under `path` if given.
+pub fn query_files(repo: &Repository, path: Option<&Path>) -> Vec<String> {
+    let mut files = Vec::new();
+    let Ok(tree) = repo.head().and_then(|head| head.peel_to_tree()) else {
+        return files;
+    };
+    let _ = tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            let file = format!("{dir}{}", lossy(entry.name_bytes()).0);
+            if path.is_none_or(|path| Path::new(&file).starts_with(path)) {
+                files.push(file);
+            }
+        }
+        TreeWalkResult::Ok
+    });
+    files
+}
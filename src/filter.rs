@@ -0,0 +1,827 @@
+//! A small expression language for filtering and sorting the branch list, e.g.
+//!
+//! ```text
+//! age < 30d && author = me && !merged(main) sort age
+//! ```
+//!
+//! - `age`, compared to a duration: `h`ours, `d`ays, `w`eeks, `m`onths or `y`ears
+//! - `author` (tip author email), `name`, `summary` and `label`, compared with `=`, `!=` or `~`
+//!   (contains), case-insensitively. `me` is any `gix.myEmail`, or `user.email` without one. A
+//!   branch matches `label` when one of its labels does, and `label != x` when none is `x`
+//! - `head`, `remote`, `gone` and `upstream` flags, and `merged(<revision>)` with any revision
+//!   git reads, such as `HEAD~3` or `main@{1}`, `merged` alone meaning `merged(HEAD)`
+//! - `&&`, `||`, `!` and parentheses
+//! - an optional trailing `sort name|age|ahead|behind|author [desc]`, `ahead` and `behind`
+//!   counting commits relative to the upstream and `date` being another name for `age`
+
+use crate::{
+    branch::BranchItem,
+    date::{DAY, WEEK},
+    labels::Labels,
+};
+use git2::{Oid, Repository};
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap};
+
+/// A parsed filter expression, see the module documentation.
+pub struct Filter {
+    /// The expression as typed.
+    pub source: String,
+    expr: Option<Expr>,
+    sort: Option<(SortKey, bool)>,
+}
+
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Flag(Flag),
+    Age(Comparison, i64),
+    Text(Field, TextOp, Text),
+    Merged(String),
+}
+
+#[derive(Clone, Copy)]
+enum Flag {
+    Head,
+    Remote,
+    Gone,
+    Upstream,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Author,
+    Name,
+    Summary,
+    Label,
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Copy)]
+enum TextOp {
+    Eq,
+    Ne,
+    Contains,
+}
+
+enum Text {
+    Me,
+    Literal(String),
+}
+
+/// Column the branch list is ordered by.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Age,
+    Ahead,
+    Behind,
+    Author,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 5] = [
+        SortKey::Name,
+        SortKey::Age,
+        SortKey::Ahead,
+        SortKey::Behind,
+        SortKey::Author,
+    ];
+
+    /// Key called `name`, also taking `date` for [`SortKey::Age`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "date" => Some(SortKey::Age),
+            _ => Self::ALL.into_iter().find(|key| key.name() == name),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Age => "age",
+            SortKey::Ahead => "ahead",
+            SortKey::Behind => "behind",
+            SortKey::Author => "author",
+        }
+    }
+}
+
+/// What expressions are evaluated against besides the branch itself.
+pub struct Context<'a> {
+    repo: &'a Repository,
+    now: i64,
+    /// What `me` stands for, lowercased: the `gix.myEmail` addresses, or `user.email` when
+    /// there are none.
+    me: Vec<String>,
+    /// Revisions used in `merged(...)`, resolved once per evaluation.
+    revisions: RefCell<HashMap<String, Option<Oid>>>,
+    labels: Labels,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(repo: &'a Repository, now: i64) -> Self {
+        Self {
+            repo,
+            now,
+            me: my_emails(repo),
+            revisions: RefCell::default(),
+            labels: Labels::load(repo.commondir()),
+        }
+    }
+
+    /// Whether the tip of `branch` was authored by one of the addresses `me` stands for.
+    pub fn is_mine(&self, branch: &BranchItem) -> bool {
+        let email = branch.details(self.repo).author_email.to_lowercase();
+        self.me.contains(&email)
+    }
+
+    /// Whether the tip of `branch` is `revision` or one of its ancestors. Unknown revisions
+    /// have nothing merged.
+    pub fn is_merged(&self, branch: &BranchItem, revision: &str) -> bool {
+        let (Some(target), Ok(tip)) = (self.revision(revision), Oid::from_str(&branch.oid)) else {
+            return false;
+        };
+        tip == target || self.repo.graph_descendant_of(target, tip).unwrap_or(false)
+    }
+
+    /// Whether `commit` is the tip of `branch` or one of its ancestors.
+    pub fn contains(&self, branch: &BranchItem, commit: Oid) -> bool {
+        let Ok(tip) = Oid::from_str(&branch.oid) else {
+            return false;
+        };
+        tip == commit || self.repo.graph_descendant_of(tip, commit).unwrap_or(false)
+    }
+
+    fn revision(&self, name: &str) -> Option<Oid> {
+        *self
+            .revisions
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                self.repo
+                    .revparse_single(name)
+                    .and_then(|object| object.peel_to_commit())
+                    .map(|commit| commit.id())
+                    .ok()
+            })
+    }
+}
+
+impl Filter {
+    /// Parses `source`. Errors say what was expected and at which column.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            end: source.chars().count() + 1,
+        };
+
+        let expr = match parser.peek() {
+            None => None,
+            Some(Token::Word(word)) if word == "sort" => None,
+            Some(_) => Some(parser.or()?),
+        };
+        let sort = if parser.eat_word("sort") {
+            let word = parser.word()?;
+            let Some(key) = SortKey::from_name(&word) else {
+                return Err(parser.error_before(format!(
+                    "can't sort by '{word}', expected name, age, ahead, behind or author"
+                )));
+            };
+            Some((key, parser.eat_word("desc")))
+        } else {
+            None
+        };
+        if parser.peek().is_some() {
+            return Err(parser.error("expected && or || or sort"));
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            expr,
+            sort,
+        })
+    }
+
+    /// Checks that every revision in `merged(...)` exists.
+    pub fn validate(&self, ctx: &Context) -> Result<(), String> {
+        match &self.expr {
+            Some(expr) => expr.validate(ctx),
+            None => Ok(()),
+        }
+    }
+
+    pub fn matches(&self, branch: &BranchItem, ctx: &Context) -> bool {
+        self.expr.as_ref().is_none_or(|expr| expr.eval(branch, ctx))
+    }
+
+    /// Column of the sort clause the filter ends with, and whether it is reversed.
+    pub fn sort(&self) -> Option<(SortKey, bool)> {
+        self.sort
+    }
+}
+
+/// Sorts `branches` by `key`, ascending unless `desc`. Ahead and behind count commits relative to
+/// the upstream, branches without one sort as if they had none.
+pub fn sort_branches(branches: &mut [BranchItem], key: SortKey, desc: bool, ctx: &Context) {
+    // Counted once up front, the comparisons would otherwise walk the graph over and over.
+    let counts: HashMap<String, usize> = match key {
+        SortKey::Ahead | SortKey::Behind => branches
+            .iter()
+            .map(|b| {
+                let (ahead, behind) = b.ahead_behind(ctx.repo).unwrap_or_default();
+                let count = if key == SortKey::Ahead { ahead } else { behind };
+                (b.refname(), count)
+            })
+            .collect(),
+        _ => HashMap::new(),
+    };
+    branches.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => a.name.cmp(&b.name),
+            // Youngest first, like an ascending age.
+            SortKey::Age => b.details(ctx.repo).time.cmp(&a.details(ctx.repo).time),
+            SortKey::Ahead | SortKey::Behind => counts[&a.refname()].cmp(&counts[&b.refname()]),
+            SortKey::Author => a
+                .details(ctx.repo)
+                .author_email
+                .to_lowercase()
+                .cmp(&b.details(ctx.repo).author_email.to_lowercase()),
+        };
+        if desc { ordering.reverse() } else { ordering }
+    });
+}
+
+impl Expr {
+    fn validate(&self, ctx: &Context) -> Result<(), String> {
+        match self {
+            Expr::And(a, b) | Expr::Or(a, b) => a.validate(ctx).and(b.validate(ctx)),
+            Expr::Not(a) => a.validate(ctx),
+            Expr::Merged(revision) if ctx.revision(revision).is_none() => {
+                Err(format!("unknown revision '{revision}' in merged(...)"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn eval(&self, branch: &BranchItem, ctx: &Context) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(branch, ctx) && b.eval(branch, ctx),
+            Expr::Or(a, b) => a.eval(branch, ctx) || b.eval(branch, ctx),
+            Expr::Not(a) => !a.eval(branch, ctx),
+            Expr::Flag(Flag::Head) => branch.is_head,
+            Expr::Flag(Flag::Remote) => branch.is_remote,
+            Expr::Flag(Flag::Gone) => branch.is_gone,
+            Expr::Flag(Flag::Upstream) => branch.upstream.is_some(),
+            Expr::Age(comparison, seconds) => {
+                let age = ctx.now - branch.details(ctx.repo).time;
+                let ordering = age.cmp(seconds);
+                match comparison {
+                    Comparison::Eq => ordering == Ordering::Equal,
+                    Comparison::Ne => ordering != Ordering::Equal,
+                    Comparison::Lt => ordering == Ordering::Less,
+                    Comparison::Le => ordering != Ordering::Greater,
+                    Comparison::Gt => ordering == Ordering::Greater,
+                    Comparison::Ge => ordering != Ordering::Less,
+                }
+            }
+            Expr::Text(field, op, text) => {
+                let values = match field {
+                    Field::Author => vec![branch.details(ctx.repo).author_email.clone()],
+                    Field::Name => vec![branch.name.clone()],
+                    Field::Summary => vec![branch.details(ctx.repo).summary.clone()],
+                    Field::Label => ctx.labels.get(&branch.refname()).to_vec(),
+                };
+                let mut values = values.iter().map(|value| value.to_lowercase());
+                let texts = match text {
+                    Text::Me if ctx.me.is_empty() => return false,
+                    Text::Me => ctx.me.clone(),
+                    Text::Literal(text) => vec![text.to_lowercase()],
+                };
+                match op {
+                    TextOp::Eq => values.any(|value| texts.contains(&value)),
+                    TextOp::Ne => values.all(|value| !texts.contains(&value)),
+                    TextOp::Contains => {
+                        values.any(|value| texts.iter().any(|text| value.contains(text)))
+                    }
+                }
+            }
+            Expr::Merged(revision) => ctx.is_merged(branch, revision),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("'{word}'"),
+            Token::Quoted(text) => format!("\"{text}\""),
+            Token::Symbol(symbol) => format!("'{symbol}'"),
+        }
+    }
+}
+
+/// Longer symbols first so `!=` isn't read as `!` followed by `=`.
+const SYMBOLS: [&str; 12] = [
+    "&&", "||", "!=", "<=", ">=", "!", "(", ")", "=", "<", ">", "~",
+];
+
+/// Splits `source` into tokens with the column each starts at.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|c| *c == '"')
+                .ok_or_else(|| format!("unclosed \" at column {column}"))?;
+            tokens.push((
+                Token::Quoted(chars[i + 1..i + 1 + end].iter().collect()),
+                column,
+            ));
+            i += end + 2;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| {
+            let symbol: Vec<char> = symbol.chars().collect();
+            chars[i..].starts_with(&symbol)
+        }) {
+            tokens.push((Token::Symbol(symbol), column));
+            i += symbol.len();
+            // Revisions have characters that are symbols elsewhere, as in `merged(HEAD~3)`.
+            let after_merged = tokens.len() >= 2
+                && tokens[tokens.len() - 2].0 == Token::Word("merged".to_string());
+            if *symbol == "(" && after_merged {
+                while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+                    i += 1;
+                }
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| !c.is_whitespace() && **c != ')')
+                    .count();
+                if len > 0 {
+                    tokens.push((Token::Word(chars[i..i + len].iter().collect()), i + 1));
+                    i += len;
+                }
+            }
+        } else if is_word_char(c) {
+            let len = chars[i..].iter().take_while(|c| is_word_char(**c)).count();
+            tokens.push((Token::Word(chars[i..i + len].iter().collect()), column));
+            i += len;
+        } else {
+            return Err(format!("unexpected '{c}' at column {column}"));
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-./@+".contains(c)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    /// Column just past the input, where "unexpected end" errors point.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, expected: &str) -> String {
+        match self.tokens.get(self.pos) {
+            Some((token, column)) => {
+                format!("{expected}, found {} at column {column}", token.describe())
+            }
+            None => format!("{expected} at column {}", self.end),
+        }
+    }
+
+    /// Error about the token just consumed.
+    fn error_before(&self, message: String) -> String {
+        let column = self.tokens[self.pos - 1].1;
+        format!("{message} at column {column}")
+    }
+
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Word(w)) if w == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Word(word)) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.error("expected a word")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err(self.error("expected )"));
+            }
+            return Ok(expr);
+        }
+
+        let name = match self.peek() {
+            Some(Token::Word(word)) => word.clone(),
+            _ => return Err(self.error("expected a field, flag or merged(...)")),
+        };
+        self.pos += 1;
+        match name.as_str() {
+            "head" => Ok(Expr::Flag(Flag::Head)),
+            "remote" => Ok(Expr::Flag(Flag::Remote)),
+            "gone" => Ok(Expr::Flag(Flag::Gone)),
+            "upstream" => Ok(Expr::Flag(Flag::Upstream)),
+            "merged" => {
+                if !self.eat("(") {
+                    return Ok(Expr::Merged("HEAD".to_string()));
+                }
+                let revision = self.word()?;
+                if !self.eat(")") {
+                    return Err(self.error("expected )"));
+                }
+                Ok(Expr::Merged(revision))
+            }
+            "age" => {
+                let comparison = match self.next() {
+                    Some(Token::Symbol("=")) => Comparison::Eq,
+                    Some(Token::Symbol("!=")) => Comparison::Ne,
+                    Some(Token::Symbol("<")) => Comparison::Lt,
+                    Some(Token::Symbol("<=")) => Comparison::Le,
+                    Some(Token::Symbol(">")) => Comparison::Gt,
+                    Some(Token::Symbol(">=")) => Comparison::Ge,
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error("expected a comparison after age"));
+                    }
+                };
+                let duration = self.word()?;
+                let seconds = parse_duration(&duration).map_err(|e| self.error_before(e))?;
+                Ok(Expr::Age(comparison, seconds))
+            }
+            "author" | "name" | "summary" | "label" => {
+                let field = match name.as_str() {
+                    "author" => Field::Author,
+                    "name" => Field::Name,
+                    "summary" => Field::Summary,
+                    _ => Field::Label,
+                };
+                let op = match self.next() {
+                    Some(Token::Symbol("=")) => TextOp::Eq,
+                    Some(Token::Symbol("!=")) => TextOp::Ne,
+                    Some(Token::Symbol("~")) => TextOp::Contains,
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error(&format!("expected =, != or ~ after {name}")));
+                    }
+                };
+                let text = match self.next() {
+                    Some(Token::Word(word)) if word == "me" => Text::Me,
+                    Some(Token::Word(text)) | Some(Token::Quoted(text)) => Text::Literal(text),
+                    _ => {
+                        self.pos -= 1;
+                        return Err(self.error("expected a value"));
+                    }
+                };
+                Ok(Expr::Text(field, op, text))
+            }
+            _ => Err(self.error_before(format!(
+                "unknown field '{name}', expected age, author, name, summary, label, head, \
+                 remote, gone, upstream or merged(...)"
+            ))),
+        }
+    }
+}
+
+/// Lowercased addresses of `gix.myEmail`, which can be given more than once, or `user.email`.
+fn my_emails(repo: &Repository) -> Vec<String> {
+    let Ok(config) = repo.config() else {
+        return Vec::new();
+    };
+    let mut emails: Vec<String> = Vec::new();
+    if let Ok(entries) = config.multivar("gix.myEmail", None) {
+        entries
+            .for_each(|entry| {
+                if let Some(email) = entry.value() {
+                    emails.push(email.to_lowercase());
+                }
+            })
+            .ok();
+    }
+    if emails.is_empty()
+        && let Ok(email) = config.get_string("user.email")
+    {
+        emails.push(email.to_lowercase());
+    }
+    emails
+}
+
+/// Seconds in a duration such as `30d`.
+fn parse_duration(input: &str) -> Result<i64, String> {
+    let invalid = || format!("'{input}' is not a duration like 12h, 30d, 2w, 6m or 1y");
+    let unit = input.chars().last().ok_or_else(invalid)?;
+    let n: i64 = input[..input.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    let seconds = match unit {
+        'h' => 3600,
+        'd' => DAY,
+        'w' => WEEK,
+        'm' => 30 * DAY,
+        'y' => 365 * DAY,
+        _ => return Err(invalid()),
+    };
+    n.checked_mul(seconds)
+        .ok_or_else(|| format!("'{input}' is too long a duration"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        branch::{BranchQuery, query_branches},
+        fixture::fixture,
+    };
+    use git2::{Signature, Time};
+
+    /// Branches `main` (HEAD) and `feature` at the initial commit by the user, and `topic` a day
+    /// old by someone else, labelled `wip`. `feature` has an upstream that is gone.
+    fn branches(name: &str) -> (Repository, Vec<BranchItem>) {
+        let repo = fixture(name);
+        let mut config = repo.config().unwrap();
+        config.set_str("user.email", "Tester@example.com").unwrap();
+        {
+            let initial = repo.head().unwrap().peel_to_commit().unwrap();
+            let other = Signature::new("Other", "other@example.com", &Time::new(9 * DAY, 0));
+            let other = other.unwrap();
+            let tree = initial.tree().unwrap();
+            let summary = "Fix the login page";
+            repo.commit(
+                Some("refs/heads/topic"),
+                &other,
+                &other,
+                summary,
+                &tree,
+                &[&initial],
+            )
+            .unwrap();
+        }
+        let mut labels = Labels::load(repo.commondir());
+        labels
+            .set(repo.commondir(), "refs/heads/topic", "wip, urgent")
+            .unwrap();
+        let mut branches = query_branches(&repo, &BranchQuery::Local);
+        let feature = branches.iter_mut().find(|b| b.name == "feature").unwrap();
+        feature.upstream = Some("origin/feature".to_string());
+        feature.is_gone = true;
+        (repo, branches)
+    }
+
+    /// Names of the branches matching `source`, ten days after the initial commit.
+    fn matching(repo: &Repository, branches: &[BranchItem], source: &str) -> Vec<String> {
+        let filter = Filter::parse(source).unwrap();
+        let ctx = Context::new(repo, 10 * DAY);
+        filter.validate(&ctx).unwrap();
+        let matches = branches.iter().filter(|b| filter.matches(b, &ctx));
+        matches.map(|b| b.name.clone()).collect()
+    }
+
+    #[test]
+    fn predicates_match_their_branches() {
+        let (repo, branches) = branches("filter-predicates");
+        for (source, names) in [
+            ("", &["feature", "main", "topic"][..]),
+            ("head", &["main"]),
+            ("remote", &[]),
+            ("upstream", &["feature"]),
+            ("gone", &["feature"]),
+            ("age < 2d", &["topic"]),
+            ("age >= 10d", &["feature", "main"]),
+            ("age = 1d", &["topic"]),
+            ("age != 1d", &["feature", "main"]),
+            ("age <= 1d", &["topic"]),
+            ("age > 1w", &["feature", "main"]),
+            ("author = me", &["feature", "main"]),
+            ("author ~ OTHER", &["topic"]),
+            ("name != main", &["feature", "topic"]),
+            ("summary ~ login", &["topic"]),
+            ("label = WIP", &["topic"]),
+            ("label != wip", &["feature", "main"]),
+            ("label ~ urg", &["topic"]),
+            ("merged", &["feature", "main"]),
+            ("merged(topic)", &["feature", "main", "topic"]),
+            ("merged(topic~1)", &["feature", "main"]),
+        ] {
+            assert_eq!(matching(&repo, &branches, source), names, "{source}");
+        }
+    }
+
+    #[test]
+    fn operators_bind_and_quotes_group() {
+        let (repo, branches) = branches("filter-precedence");
+        for (source, names) in [
+            // `&&` binds tighter than `||`, and `!` only to what follows it.
+            ("head && name = feature || name = topic", &["topic"][..]),
+            ("head && (name = feature || name = topic)", &[]),
+            ("!head && author = me", &["feature"]),
+            ("!(head && author = me)", &["feature", "topic"]),
+            ("!!head", &["main"]),
+            // Quotes keep spaces and words that would be keywords.
+            (r#"summary = "fix the LOGIN page""#, &["topic"]),
+            (r#"summary ~ "the login""#, &["topic"]),
+            (r#"name = "me""#, &[]),
+            ("author=me&&!gone", &["main"]),
+        ] {
+            assert_eq!(matching(&repo, &branches, source), names, "{source}");
+        }
+
+        let filter = Filter::parse("  head || gone sort date desc ").unwrap();
+        assert_eq!(filter.source, "head || gone sort date desc");
+        assert!(filter.sort() == Some((SortKey::Age, true)));
+        assert!(Filter::parse("sort name").unwrap().sort() == Some((SortKey::Name, false)));
+    }
+
+    #[test]
+    fn errors_say_what_was_expected_where() {
+        for (source, error) in [
+            (
+                "nme = x",
+                "unknown field 'nme', expected age, author, name, summary, label, \
+                         head, remote, gone, upstream or merged(...) at column 1",
+            ),
+            ("(head", "expected ) at column 6"),
+            (
+                "head &&",
+                "expected a field, flag or merged(...) at column 8",
+            ),
+            (
+                r#"name "x""#,
+                r#"expected =, != or ~ after name, found "x" at column 6"#,
+            ),
+            (
+                "age ~ 3d",
+                "expected a comparison after age, found '~' at column 5",
+            ),
+            (
+                "age < 3x",
+                "'3x' is not a duration like 12h, 30d, 2w, 6m or 1y at column 7",
+            ),
+            ("author = (", "expected a value, found '(' at column 10"),
+            (
+                "head name",
+                "expected && or || or sort, found 'name' at column 6",
+            ),
+            (
+                "head sort size",
+                "can't sort by 'size', expected name, age, ahead, behind or \
+                                author at column 11",
+            ),
+            (r#"summary = "open"#, "unclosed \" at column 11"),
+            ("head $", "unexpected '$' at column 6"),
+        ] {
+            assert_eq!(
+                Filter::parse(source).err().as_deref(),
+                Some(error),
+                "{source}"
+            );
+        }
+
+        let (repo, _) = branches("filter-errors");
+        let filter = Filter::parse("head || merged(nope)").unwrap();
+        assert_eq!(
+            filter.validate(&Context::new(&repo, 0)),
+            Err("unknown revision 'nope' in merged(...)".to_string())
+        );
+    }
+
+    #[test]
+    fn revisions_keep_their_symbols() {
+        let word = |word: &str| Token::Word(word.to_string());
+        let tokens: Vec<Token> = tokenize("merged(HEAD~3) && !merged( main^2 )||merged(@{-1})")
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                word("merged"),
+                Token::Symbol("("),
+                word("HEAD~3"),
+                Token::Symbol(")"),
+                Token::Symbol("&&"),
+                Token::Symbol("!"),
+                word("merged"),
+                Token::Symbol("("),
+                word("main^2"),
+                Token::Symbol(")"),
+                Token::Symbol("||"),
+                word("merged"),
+                Token::Symbol("("),
+                word("@{-1}"),
+                Token::Symbol(")"),
+            ]
+        );
+        // Elsewhere `~` is still the operator.
+        assert_eq!(
+            tokenize("name~x").unwrap(),
+            [(word("name"), 1), (Token::Symbol("~"), 5), (word("x"), 6)]
+        );
+        assert_eq!(
+            Filter::parse("merged()").err().as_deref(),
+            Some("expected a word, found ')' at column 8")
+        );
+    }
+
+    #[test]
+    fn durations_are_checked() {
+        assert_eq!(parse_duration("12h"), Ok(12 * 3600));
+        assert_eq!(parse_duration("2w"), Ok(2 * WEEK));
+        assert_eq!(parse_duration("1y"), Ok(365 * DAY));
+        assert_eq!(
+            parse_duration("99999999999999y"),
+            Err("'99999999999999y' is too long a duration".to_string())
+        );
+        assert_eq!(
+            Filter::parse("age > 99999999999999y").err().as_deref(),
+            Some("'99999999999999y' is too long a duration at column 7")
+        );
+        for input in ["3", "3x", "d", "1.5d"] {
+            assert_eq!(
+                parse_duration(input),
+                Err(format!(
+                    "'{input}' is not a duration like 12h, 30d, 2w, 6m or 1y"
+                ))
+            );
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Repositories for the tests to run against.
+
+use git2::{Oid, Repository, RepositoryInitOptions, Signature, Time};
+use std::{env, fs};
+
+/// Author and committer of the fixture commits, at a fixed time so object ids are the same on
+/// every run.
+pub fn signature() -> Signature<'static> {
+    Signature::new("Tester", "tester@example.com", &Time::new(0, 0)).unwrap()
+}
+
+/// Repository in a fresh temporary directory with a single commit on `main`, also pointed to by
+/// `feature`.
+pub fn fixture(name: &str) -> Repository {
+    let dir = env::temp_dir().join(format!("gix-test-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let repo =
+        Repository::init_opts(&dir, RepositoryInitOptions::new().initial_head("main")).unwrap();
+    {
+        let sig = signature();
+        let tree_id = repo.index().unwrap().write_tree().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let oid = repo
+            .commit(Some("HEAD"), &sig, &sig, "Initial commit", &tree, &[])
+            .unwrap();
+        let commit = repo.find_commit(oid).unwrap();
+        repo.branch("feature", &commit, false).unwrap();
+    }
+    repo
+}
+
+/// Writes `content` to `path` in the working tree, stages it and commits it on top of HEAD.
+pub fn commit_file(repo: &Repository, path: &str, content: &str, message: &str) -> Oid {
+    fs::write(repo.workdir().unwrap().join(path), content).unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(path.as_ref()).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let sig = signature();
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[&head])
+        .unwrap()
+}
@@ -0,0 +1,259 @@
+use crate::{api::Client, branch::BranchItem, json::Json};
+use git2::Repository;
+use std::path::Path;
+
+/// Web UI layouts of the forges links are made for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// GitHub and most others: `/tree/<branch>`, `/commit/<sha>`.
+    GitHub,
+    /// `/-/tree/<branch>`, `/-/commit/<sha>`.
+    GitLab,
+    /// `/branch/<branch>`, `/commits/<sha>`.
+    Bitbucket,
+}
+
+/// A pull request, or merge request on GitLab.
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    /// `open`, `draft`, `merged` or `closed`.
+    pub state: &'static str,
+    pub url: String,
+}
+
+/// Web UI of the repository on the forge hosting its remote, for linking branches and commits.
+pub struct Forge {
+    /// Remote whose URL the links are made from.
+    remote: String,
+    /// Repository page, e.g. `https://github.com/ludvighe/gix`.
+    base: String,
+    layout: Layout,
+}
+
+impl Forge {
+    /// Forge of `origin`, or of the first remote if there is no `origin`. `None` when the
+    /// remote URL isn't on a web server, e.g. a local path.
+    pub fn detect(repo: &Repository) -> Option<Self> {
+        let remotes = repo.remotes().ok()?;
+        let remote = if remotes.iter().flatten().any(|name| name == "origin") {
+            "origin".to_string()
+        } else {
+            remotes.iter().flatten().next()?.to_string()
+        };
+        let url = repo.find_remote(&remote).ok()?.url()?.to_string();
+        let base = web_url(&url)?;
+        let layout = if base.contains("gitlab") {
+            Layout::GitLab
+        } else if base.contains("bitbucket.org") {
+            Layout::Bitbucket
+        } else {
+            Layout::GitHub
+        };
+        Some(Self {
+            remote,
+            base,
+            layout,
+        })
+    }
+
+    pub fn commit_url(&self, oid: &str) -> String {
+        match self.layout {
+            Layout::GitHub => format!("{}/commit/{oid}", self.base),
+            Layout::GitLab => format!("{}/-/commit/{oid}", self.base),
+            Layout::Bitbucket => format!("{}/commits/{oid}", self.base),
+        }
+    }
+
+    /// Page of `branch` on the forge.
+    pub fn branch_url(&self, branch: &BranchItem) -> Option<String> {
+        let name = encode(self.branch_name(branch)?);
+        Some(match self.layout {
+            Layout::GitHub => format!("{}/tree/{name}", self.base),
+            Layout::GitLab => format!("{}/-/tree/{name}", self.base),
+            Layout::Bitbucket => format!("{}/branch/{name}", self.base),
+        })
+    }
+
+    /// Name of `branch` on the forge. Remote branches have to be on the forge's remote, local
+    /// ones need an upstream and are assumed to be pushed under the same name.
+    fn branch_name<'a>(&self, branch: &'a BranchItem) -> Option<&'a str> {
+        if branch.is_remote {
+            branch.name.strip_prefix(&format!("{}/", self.remote))
+        } else if branch.upstream.is_some() {
+            Some(&branch.name)
+        } else {
+            None
+        }
+    }
+
+    /// Host and path of the repository page, e.g. `github.com` and `ludvighe/gix`.
+    fn host_and_path(&self) -> (&str, &str) {
+        let address = self.base.strip_prefix("https://").unwrap_or(&self.base);
+        address.split_once('/').unwrap_or((address, ""))
+    }
+
+    /// Client for the forge's REST API, `None` for Bitbucket, which gix doesn't read from.
+    pub fn api(&self, git_dir: &Path) -> Option<Client> {
+        let (host, _) = self.host_and_path();
+        let root = match self.layout {
+            Layout::GitHub if host == "github.com" => "https://api.github.com".to_string(),
+            Layout::GitHub => format!("https://{host}/api/v3"),
+            Layout::GitLab => format!("https://{host}/api/v4"),
+            Layout::Bitbucket => return None,
+        };
+        Some(Client::new(root, host.to_string(), git_dir))
+    }
+
+    /// API path of the pull requests from `branch`, newest first, see [`Forge::api`].
+    pub fn pull_requests_path(&self, branch: &BranchItem) -> Option<String> {
+        let name = self.branch_name(branch)?;
+        let (_, path) = self.host_and_path();
+        let owner = path.split('/').next()?;
+        match self.layout {
+            Layout::GitHub => Some(format!(
+                "/repos/{path}/pulls?head={}&state=all&sort=created&direction=desc",
+                encode_component(&format!("{owner}:{name}"))
+            )),
+            Layout::GitLab => Some(format!(
+                "/projects/{}/merge_requests?source_branch={}&state=all&order_by=created_at",
+                encode_component(path),
+                encode_component(name)
+            )),
+            Layout::Bitbucket => None,
+        }
+    }
+
+    /// The first pull request in the response to a [`Forge::pull_requests_path`] request.
+    pub fn newest_pull_request(&self, body: &str) -> Result<Option<PullRequest>, String> {
+        let json = Json::parse(body)?;
+        let Some(pull) = json.as_array().ok_or("expected a list")?.first() else {
+            return Ok(None);
+        };
+        let text = |key: &str| pull.get(key).and_then(Json::as_str).unwrap_or_default();
+        let draft = pull.get("draft") == Some(&Json::Bool(true));
+        let (number, state, url) = if self.layout == Layout::GitLab {
+            let state = match text("state") {
+                "merged" => "merged",
+                "opened" if draft => "draft",
+                "opened" => "open",
+                _ => "closed",
+            };
+            (pull.get("iid"), state, text("web_url"))
+        } else {
+            let merged = pull.get("merged_at").is_some_and(|at| *at != Json::Null);
+            let state = match text("state") {
+                _ if merged => "merged",
+                "open" if draft => "draft",
+                "open" => "open",
+                _ => "closed",
+            };
+            (pull.get("number"), state, text("html_url"))
+        };
+        Ok(Some(PullRequest {
+            number: number.and_then(Json::as_u64).ok_or("expected a number")?,
+            title: text("title").to_string(),
+            state,
+            url: url.to_string(),
+        }))
+    }
+}
+
+/// `https://` address of the repository behind a remote URL such as
+/// `git@github.com:owner/repo.git`, `ssh://git@host:22/owner/repo` or
+/// `https://host/owner/repo.git`.
+fn web_url(url: &str) -> Option<String> {
+    let (host, path) = if let Some((scheme, rest)) = url.split_once("://") {
+        if !["https", "http", "ssh", "git", "git+ssh", "ssh+git"].contains(&scheme) {
+            return None;
+        }
+        let (authority, path) = rest.split_once('/')?;
+        let host = authority.rsplit('@').next()?;
+        // Ports of ssh and git servers say nothing about the web server.
+        let host = match scheme {
+            "https" | "http" => host,
+            _ => host.split(':').next()?,
+        };
+        (host, path)
+    } else {
+        // scp-like `[user@]host:path`, which can't contain a slash before the colon.
+        let (authority, path) = url.split_once(':')?;
+        if authority.contains('/') {
+            return None;
+        }
+        (authority.rsplit('@').next()?, path)
+    };
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if host.is_empty() || path.is_empty() {
+        return None;
+    }
+    Some(format!("https://{host}/{path}"))
+}
+
+/// Percent-encodes what can't appear as is in a URL path, keeping `/`.
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// Percent-encodes `text` for a query value or a single path segment.
+fn encode_component(text: &str) -> String {
+    encode(text).replace('/', "%2F")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn web_url_from_remote_urls() {
+        let expected = Some("https://github.com/ludvighe/gix".to_string());
+        assert_eq!(web_url("git@github.com:ludvighe/gix.git"), expected);
+        assert_eq!(web_url("https://github.com/ludvighe/gix.git"), expected);
+        assert_eq!(web_url("ssh://git@github.com:22/ludvighe/gix"), expected);
+        assert_eq!(
+            web_url("https://git.example.com:8443/team/app/"),
+            Some("https://git.example.com:8443/team/app".to_string())
+        );
+        assert_eq!(web_url("/srv/git/app.git"), None);
+        assert_eq!(web_url("file:///srv/git/app.git"), None);
+        assert_eq!(encode("feat/a#1 b"), "feat/a%231%20b");
+    }
+
+    #[test]
+    fn newest_pull_request_of_either_forge() {
+        let mut forge = Forge {
+            remote: "origin".to_string(),
+            base: "https://github.com/ludvighe/gix".to_string(),
+            layout: Layout::GitHub,
+        };
+        let pull = forge
+            .newest_pull_request(
+                r#"[{"number": 7, "title": "Login", "state": "closed", "draft": false,
+                     "merged_at": "2024-05-01T10:00:00Z",
+                     "html_url": "https://github.com/ludvighe/gix/pull/7"}]"#,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!((pull.number, pull.state), (7, "merged"));
+        assert_eq!(pull.url, "https://github.com/ludvighe/gix/pull/7");
+        assert!(forge.newest_pull_request("[]").unwrap().is_none());
+
+        forge.layout = Layout::GitLab;
+        let merge = forge
+            .newest_pull_request(
+                r#"[{"iid": 3, "title": "Login", "state": "opened", "draft": true}]"#,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!((merge.number, merge.state), (3, "draft"));
+    }
+}
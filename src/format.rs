@@ -0,0 +1,154 @@
+//! Rows of the branch list written by a template in `gix.format`, in the style of
+//! `git for-each-ref --format`, e.g.
+//!
+//! ```text
+//! %(HEAD) %(refname:short) %(upstream:track) %(subject)
+//! ```
+//!
+//! Fields:
+//!
+//! - `refname`, `refname:short`: the full and the short name of the branch
+//! - `objectname`, `objectname:short`: the tip commit
+//! - `upstream`, `upstream:short`: the full and the short name of the upstream
+//! - `upstream:track`: `[ahead 1, behind 2]`, `[gone]` or nothing when in sync
+//! - `upstream:trackshort`: `>`, `<`, `<>` or `=`
+//! - `subject`, `authoremail`: of the tip commit
+//! - `committerdate:short`, `committerdate:relative`: `2024-05-01` or `3d ago`
+//! - `HEAD`: `*` for the checked out branch, a space otherwise
+//!
+//! `%%` is a literal `%`.
+
+use crate::{
+    branch::BranchItem,
+    date::{format_age, format_day},
+};
+use git2::Repository;
+
+#[derive(Clone, Copy)]
+enum Field {
+    Refname,
+    RefnameShort,
+    Objectname,
+    ObjectnameShort,
+    Upstream,
+    UpstreamShort,
+    UpstreamTrack,
+    UpstreamTrackShort,
+    Subject,
+    AuthorEmail,
+    CommitterDateShort,
+    CommitterDateRelative,
+    Head,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "refname" => Field::Refname,
+            "refname:short" => Field::RefnameShort,
+            "objectname" => Field::Objectname,
+            "objectname:short" => Field::ObjectnameShort,
+            "upstream" => Field::Upstream,
+            "upstream:short" => Field::UpstreamShort,
+            "upstream:track" => Field::UpstreamTrack,
+            "upstream:trackshort" => Field::UpstreamTrackShort,
+            "subject" | "contents:subject" => Field::Subject,
+            "authoremail" => Field::AuthorEmail,
+            "committerdate:short" => Field::CommitterDateShort,
+            "committerdate:relative" => Field::CommitterDateRelative,
+            "HEAD" => Field::Head,
+            _ => return None,
+        })
+    }
+}
+
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+pub struct RowFormat {
+    parts: Vec<Part>,
+}
+
+impl RowFormat {
+    /// Parses `template`. Errors name the unknown field or where an unclosed `%(` starts.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(i) = rest.find('%') {
+            text.push_str(&rest[..i]);
+            let after = &rest[i + 1..];
+            if let Some(after) = after.strip_prefix('%') {
+                text.push('%');
+                rest = after;
+            } else if let Some(after) = after.strip_prefix('(') {
+                let column = template.len() - rest.len() + i + 1;
+                let end = after
+                    .find(')')
+                    .ok_or_else(|| format!("%( at column {column} isn't closed"))?;
+                let name = &after[..end];
+                let field = Field::parse(name).ok_or_else(|| format!("unknown field %({name})"))?;
+                parts.push(Part::Text(std::mem::take(&mut text)));
+                parts.push(Part::Field(field));
+                rest = &after[end + 1..];
+            } else {
+                text.push('%');
+                rest = after;
+            }
+        }
+        text.push_str(rest);
+        parts.push(Part::Text(text));
+        Ok(Self { parts })
+    }
+
+    /// The row of `branch`, with relative dates counted back from `now`.
+    pub fn render(&self, branch: &BranchItem, repo: &Repository, now: i64) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field(field) => value(*field, branch, repo, now),
+            })
+            .collect()
+    }
+}
+
+fn value(field: Field, branch: &BranchItem, repo: &Repository, now: i64) -> String {
+    match field {
+        Field::Refname => branch.refname(),
+        Field::RefnameShort => branch.name.clone(),
+        Field::Objectname => branch.oid.clone(),
+        Field::ObjectnameShort => branch.short_oid(),
+        Field::Upstream => repo
+            .branch_upstream_name(&branch.refname())
+            .ok()
+            .and_then(|name| name.as_str().map(str::to_string))
+            .filter(|_| branch.upstream.is_some())
+            .unwrap_or_default(),
+        Field::UpstreamShort => branch.upstream.clone().unwrap_or_default(),
+        Field::UpstreamTrack => match branch.ahead_behind(repo) {
+            _ if branch.is_gone => "[gone]".to_string(),
+            Some((0, 0)) | None => String::new(),
+            Some((ahead, 0)) => format!("[ahead {ahead}]"),
+            Some((0, behind)) => format!("[behind {behind}]"),
+            Some((ahead, behind)) => format!("[ahead {ahead}, behind {behind}]"),
+        },
+        Field::UpstreamTrackShort => match branch.ahead_behind(repo) {
+            None => "",
+            Some((0, 0)) => "=",
+            Some((_, 0)) => ">",
+            Some((0, _)) => "<",
+            Some(_) => "<>",
+        }
+        .to_string(),
+        Field::Subject => branch.details(repo).summary.clone(),
+        Field::AuthorEmail => format!("<{}>", branch.details(repo).author_email),
+        Field::CommitterDateShort => format_day(branch.details(repo).time),
+        Field::CommitterDateRelative => {
+            format!("{} ago", format_age(branch.details(repo).time, now))
+        }
+        Field::Head => if branch.is_head { "*" } else { " " }.to_string(),
+    }
+}
@@ -0,0 +1,96 @@
+use git2::Repository;
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Captured result of running a git hook.
+pub struct HookRun {
+    pub name: String,
+    pub args: Vec<String>,
+    /// Exit code, `None` when the hook was killed or could not be started.
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookRun {
+    pub fn succeeded(&self) -> bool {
+        self.status == Some(0)
+    }
+
+    /// Status line followed by the hook's stdout and stderr.
+    pub fn lines(&self) -> Vec<String> {
+        let status = match self.status {
+            Some(0) => "succeeded".to_string(),
+            Some(code) => format!("failed with exit code {code}"),
+            None => "did not run to completion".to_string(),
+        };
+        let mut lines = vec![format!("{} hook {status}", self.name), String::new()];
+        lines.extend(self.stdout.lines().map(|l| l.to_string()));
+        if !self.stderr.is_empty() {
+            lines.push(String::new());
+            lines.push("stderr:".to_string());
+            lines.extend(self.stderr.lines().map(|l| l.to_string()));
+        }
+        lines
+    }
+}
+
+/// Runs hook `name` from the repository's hooks directory the way git would: from the top of
+/// the working tree, without stdin. Returns `None` when no such executable hook exists.
+pub fn run_hook(repo: &Repository, name: &str, args: &[String]) -> Option<HookRun> {
+    let path = hooks_dir(repo).join(name);
+    if !is_executable(&path) {
+        return None;
+    }
+
+    let output = Command::new(&path)
+        .args(args)
+        .current_dir(repo.workdir().unwrap_or(repo.path()))
+        .stdin(Stdio::null())
+        .output();
+
+    Some(match output {
+        Ok(output) => HookRun {
+            name: name.to_string(),
+            args: args.to_vec(),
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        },
+        Err(e) => HookRun {
+            name: name.to_string(),
+            args: args.to_vec(),
+            status: None,
+            stdout: String::new(),
+            stderr: format!("could not run {}: {e}", path.display()),
+        },
+    })
+}
+
+/// `core.hooksPath` (relative to the working tree) or `hooks` in the common git directory.
+fn hooks_dir(repo: &Repository) -> PathBuf {
+    let configured = repo
+        .config()
+        .ok()
+        .and_then(|cfg| cfg.get_path("core.hooksPath").ok());
+
+    match configured {
+        Some(path) if path.is_relative() => repo.workdir().unwrap_or(repo.path()).join(path),
+        Some(path) => path,
+        None => repo.commondir().join("hooks"),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
@@ -0,0 +1,238 @@
+use git2::{Repository, StatusOptions};
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+#[derive(Clone, Copy)]
+pub enum IgnoreFile {
+    /// `.gitignore` at the top of the working tree.
+    Gitignore,
+    /// `.git/info/exclude`, not shared with others.
+    Exclude,
+}
+
+impl IgnoreFile {
+    pub fn name(self) -> &'static str {
+        match self {
+            IgnoreFile::Gitignore => ".gitignore",
+            IgnoreFile::Exclude => ".git/info/exclude",
+        }
+    }
+
+    fn path(self, repo: &Repository) -> Option<PathBuf> {
+        match self {
+            IgnoreFile::Gitignore => repo.workdir().map(|w| w.join(".gitignore")),
+            IgnoreFile::Exclude => Some(repo.commondir().join("info").join("exclude")),
+        }
+    }
+}
+
+/// A pattern line of an ignore file and the untracked files it currently ignores.
+pub struct IgnoreRule {
+    pub file: IgnoreFile,
+    pub line: usize,
+    pub pattern: String,
+    pub matches: Vec<String>,
+    /// Why the pattern doesn't do what it looks like, see [`validate_pattern`].
+    pub problem: Option<String>,
+}
+
+/// Rules from `.gitignore` and `.git/info/exclude` with the untracked files each one is the
+/// deciding pattern for, as `git check-ignore` reports it.
+pub fn query_ignore_rules(repo: &Repository) -> Vec<IgnoreRule> {
+    let matches = deciding_patterns(repo).unwrap_or_default();
+
+    let mut rules = Vec::new();
+    for file in [IgnoreFile::Gitignore, IgnoreFile::Exclude] {
+        let Some(contents) = file.path(repo).and_then(|p| fs::read_to_string(p).ok()) else {
+            continue;
+        };
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rules.push(IgnoreRule {
+                file,
+                line: i + 1,
+                pattern: line.to_string(),
+                matches: matches
+                    .get(&(file.name().to_string(), i + 1))
+                    .cloned()
+                    .unwrap_or_default(),
+                problem: validate_pattern(line).err(),
+            });
+        }
+    }
+    rules
+}
+
+/// Untracked and ignored paths grouped by the `(ignore file, line)` that decides about them.
+fn deciding_patterns(repo: &Repository) -> Option<HashMap<(String, usize), Vec<String>>> {
+    let workdir = repo.workdir()?;
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .include_ignored(true)
+        .recurse_untracked_dirs(true);
+    let paths: Vec<String> = repo
+        .statuses(Some(&mut opts))
+        .ok()?
+        .iter()
+        .filter(|e| e.status().is_wt_new() || e.status().is_ignored())
+        .filter_map(|e| e.path().map(|p| p.to_string()))
+        .collect();
+
+    let mut child = Command::new("git")
+        .args(["check-ignore", "--verbose", "--stdin", "-z"])
+        .current_dir(workdir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdin = child.stdin.take()?;
+    let input = paths.join("\0");
+    thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().ok()?;
+
+    // With -z each match is four fields: source, line number, pattern, path.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let fields: Vec<&str> = stdout.split('\0').collect();
+    let mut matches: HashMap<(String, usize), Vec<String>> = HashMap::new();
+    for chunk in fields.chunks_exact(4) {
+        let Ok(line) = chunk[1].parse() else {
+            continue;
+        };
+        let file = if Path::new(chunk[0]).ends_with("info/exclude") {
+            IgnoreFile::Exclude.name()
+        } else {
+            chunk[0]
+        };
+        matches
+            .entry((file.to_string(), line))
+            .or_default()
+            .push(chunk[3].to_string());
+    }
+    Some(matches)
+}
+
+/// Rejects patterns git would silently treat differently than they read.
+pub fn validate_pattern(pattern: &str) -> Result<(), String> {
+    let body = pattern.strip_prefix('!').unwrap_or(pattern);
+    if body.trim().is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    if pattern.ends_with(' ') && !pattern.ends_with("\\ ") {
+        return Err("trailing spaces are ignored unless escaped with \\".to_string());
+    }
+
+    let chars: Vec<char> = body.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 == chars.len() => return Err("ends with a lone \\".to_string()),
+            '\\' => i += 1,
+            '[' if !chars[i + 1..].contains(&']') => {
+                return Err("unclosed [ never matches".to_string());
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let before = i == 0 || chars[i - 1] == '/';
+                let after = chars.get(i + 2).is_none_or(|c| *c == '/');
+                if !(before && after) {
+                    return Err("** only spans directories between slashes".to_string());
+                }
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Pattern matching exactly the file at `path` from the top of the working tree, with the
+/// characters git would read as wildcards, negation, comments or trailing space escaped.
+pub fn path_pattern(path: &str) -> String {
+    let mut pattern = String::from("/");
+    for c in path.chars() {
+        if matches!(c, '\\' | '[' | ']' | '*' | '?' | '!' | '#' | ' ') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+/// Appends `pattern` as a new line to `file`, creating it if needed.
+pub fn add_pattern(repo: &Repository, file: IgnoreFile, pattern: &str) -> Result<(), String> {
+    validate_pattern(pattern)?;
+    let path = file
+        .path(repo)
+        .ok_or_else(|| "repository has no working tree".to_string())?;
+
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let mut line = String::new();
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        line.push('\n');
+    }
+    line.push_str(pattern);
+    line.push('\n');
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(line.as_bytes()))
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::fixture;
+
+    #[test]
+    fn validate_pattern_rejects_misleading_patterns() {
+        for pattern in [
+            "target/",
+            "*.log",
+            "!keep.log",
+            "/a/**/b",
+            "**/c",
+            "trailing\\ ",
+        ] {
+            assert_eq!(validate_pattern(pattern), Ok(()), "{pattern}");
+        }
+        for (pattern, problem) in [
+            ("!", "empty pattern"),
+            ("a.log ", "trailing spaces"),
+            ("a\\", "lone \\"),
+            ("[abc", "unclosed ["),
+            ("a**/b", "**"),
+        ] {
+            let err = validate_pattern(pattern).unwrap_err();
+            assert!(err.contains(problem), "{pattern}: {err}");
+        }
+    }
+
+    #[test]
+    fn path_pattern_matches_only_that_path() {
+        let repo = fixture("ignore-escape");
+        let path = "#[a]* ?! ";
+        assert_eq!(path_pattern(path), "/\\#\\[a\\]\\*\\ \\?\\!\\ ");
+        assert_eq!(validate_pattern(&path_pattern(path)), Ok(()));
+        add_pattern(&repo, IgnoreFile::Exclude, &path_pattern(path)).unwrap();
+
+        assert!(repo.is_path_ignored(path).unwrap());
+        for other in ["#a", "#[a]x ?! ", "#a  b! "] {
+            assert!(!repo.is_path_ignored(other).unwrap(), "{other}");
+        }
+    }
+}
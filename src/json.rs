@@ -0,0 +1,276 @@
+//! Reading JSON, as much of it as the responses of forge APIs need, see api.rs.
+
+/// Deepest nesting of arrays and objects [`Json::parse`] reads, so a hostile response can't
+/// overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON value. Object members keep the order they came in.
+#[derive(Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            text,
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("end of input"));
+        }
+        Ok(value)
+    }
+
+    /// Member `key` of an object, `None` for other values or when it is missing.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of the next character.
+    pos: usize,
+    /// Arrays and objects the parser is inside of.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, expected: &str) -> String {
+        format!("expected {expected} at byte {}", self.pos)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skips whitespace and `expected`, returning whether it was there.
+    fn eat(&mut self, expected: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(expected);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        if matches!(self.peek(), Some(b'[' | b'{')) {
+            if self.depth == MAX_DEPTH {
+                return Err(format!(
+                    "nested deeper than {MAX_DEPTH} at byte {}",
+                    self.pos
+                ));
+            }
+            self.depth += 1;
+            let value = self.container();
+            self.depth -= 1;
+            return value;
+        }
+        let rest = &self.text[self.pos..];
+        for (word, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if rest.starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        match self.peek() {
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                    .unwrap_or(rest.len());
+                let number = rest[..len].parse().map_err(|_| self.error("a number"))?;
+                self.pos += len;
+                Ok(Json::Number(number))
+            }
+            _ => Err(self.error("a value")),
+        }
+    }
+
+    /// An array or an object.
+    fn container(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat(b']') {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.eat(b']') {
+                        return Ok(Json::Array(items));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.error("',' or ']'"));
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.eat(b'}') {
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return Err(self.error("':'"));
+                    }
+                    members.push((key, self.value()?));
+                    if self.eat(b'}') {
+                        return Ok(Json::Object(members));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.error("',' or '}'"));
+                    }
+                }
+            }
+            _ => Err(self.error("'[' or '{'")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("a string"));
+        }
+        self.pos += 1;
+        let mut string = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let end = rest
+                .find(['"', '\\'])
+                .ok_or_else(|| self.error("the end of the string"))?;
+            string.push_str(&rest[..end]);
+            self.pos += end + 1;
+            if rest.as_bytes()[end] == b'"' {
+                return Ok(string);
+            }
+            let escape = self.peek().ok_or_else(|| self.error("an escape"))?;
+            self.pos += 1;
+            string.push(match escape {
+                b'"' => '"',
+                b'\\' => '\\',
+                b'/' => '/',
+                b'b' => '\u{8}',
+                b'f' => '\u{c}',
+                b'n' => '\n',
+                b'r' => '\r',
+                b't' => '\t',
+                b'u' => self.unicode_escape()?,
+                _ => return Err(self.error("an escape")),
+            });
+        }
+    }
+
+    /// The character of a `\u` escape, reading the second half of a surrogate pair too.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) && self.text[self.pos..].starts_with("\\u") {
+            self.pos += 2;
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+        } else {
+            high
+        };
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("four hex digits"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("four hex digits"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_forge_responses() {
+        let json = Json::parse(
+            r#"[{"number": 42, "title": "Fix \"login\" \u00e9\ud83d\ude00", "draft": false,
+                "head": {"ref": "feature/oauth"}, "labels": [], "merged_at": null}]"#,
+        )
+        .unwrap();
+        let pull = &json.as_array().unwrap()[0];
+        assert_eq!(pull.get("number").and_then(Json::as_u64), Some(42));
+        assert_eq!(
+            pull.get("title").and_then(Json::as_str),
+            Some("Fix \"login\" é😀")
+        );
+        assert_eq!(
+            pull.get("head")
+                .and_then(|head| head.get("ref"))
+                .and_then(Json::as_str),
+            Some("feature/oauth")
+        );
+        assert_eq!(pull.get("merged_at"), Some(&Json::Null));
+        assert_eq!(pull.get("missing"), None);
+
+        assert!(Json::parse(r#"{"a": 1"#).is_err());
+        assert!(Json::parse("[1] 2").is_err());
+    }
+
+    #[test]
+    fn refuses_deep_nesting() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Json::parse(&nested(100_000)),
+            Err("nested deeper than 128 at byte 128".to_string())
+        );
+        assert!(Json::parse(&format!("{}1", r#"{"a":"#.repeat(MAX_DEPTH + 1))).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const FILE_NAME: &str = "gix-labels";
+
+/// Free-form labels on branches such as `needs review` or `blocked`, for personal triage. They
+/// are kept in the common git directory of this clone and never pushed anywhere.
+#[derive(Default)]
+pub struct Labels {
+    /// Full ref names to their labels, in the order they were given.
+    by_ref: BTreeMap<String, Vec<String>>,
+}
+
+impl Labels {
+    /// Labels saved in `common_dir`, none if there is no file yet. Each line is a ref name and
+    /// one of its labels separated by a tab.
+    pub fn load(common_dir: &Path) -> Self {
+        let mut labels = Self::default();
+        let Ok(content) = fs::read_to_string(path(common_dir)) else {
+            return labels;
+        };
+        for line in content.lines() {
+            if let Some((refname, label)) = line.split_once('\t') {
+                labels
+                    .by_ref
+                    .entry(refname.to_string())
+                    .or_default()
+                    .push(label.to_string());
+            }
+        }
+        labels
+    }
+
+    pub fn get(&self, refname: &str) -> &[String] {
+        self.by_ref.get(refname).map_or(&[], Vec::as_slice)
+    }
+
+    /// Replaces the labels of `refname` with the comma separated ones in `input` and saves
+    /// them. An empty `input` removes them all.
+    pub fn set(&mut self, common_dir: &Path, refname: &str, input: &str) -> io::Result<()> {
+        let mut labels: Vec<String> = Vec::new();
+        for label in input.split(',') {
+            let label = label.replace(['\t', '\n', '\r'], " ").trim().to_string();
+            if !label.is_empty() && !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        if labels.is_empty() {
+            self.by_ref.remove(refname);
+        } else {
+            self.by_ref.insert(refname.to_string(), labels);
+        }
+
+        let mut content = String::new();
+        for (refname, labels) in &self.by_ref {
+            for label in labels {
+                content.push_str(&format!("{refname}\t{label}\n"));
+            }
+        }
+        fs::write(path(common_dir), content)
+    }
+}
+
+fn path(common_dir: &Path) -> PathBuf {
+    common_dir.join(FILE_NAME)
+}
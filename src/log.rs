@@ -0,0 +1,259 @@
+use crate::{date::format_time, lossy::lossy};
+use git2::{Commit, Delta, DiffFindOptions, ErrorCode, Oid, Repository, Sort};
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+};
+
+const LOG_LIMIT: usize = 1_000;
+
+pub struct CommitItem {
+    pub oid: Oid,
+    pub summary: String,
+    pub author: String,
+    /// Commit time in seconds since the epoch.
+    pub time: i64,
+}
+
+impl CommitItem {
+    fn from_commit(commit: &Commit) -> Self {
+        Self {
+            oid: commit.id(),
+            summary: lossy(commit.summary_bytes().unwrap_or_default()).0,
+            author: lossy(commit.author().name_bytes()).0,
+            time: commit.time().seconds(),
+        }
+    }
+
+    pub fn short_oid(&self) -> String {
+        self.oid.to_string().chars().take(7).collect()
+    }
+}
+
+/// Commits reachable from HEAD, newest first. With `path` only commits changing it are listed.
+pub fn query_log(repo: &Repository, path: Option<&Path>) -> Vec<CommitItem> {
+    let mut items = Vec::new();
+
+    let Ok(mut revwalk) = repo.revwalk() else {
+        return items;
+    };
+    if revwalk.push_head().is_err() || revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME).is_err()
+    {
+        return items;
+    }
+
+    let commits = revwalk
+        .flatten()
+        .filter_map(|oid| repo.find_commit(oid).ok())
+        .filter(|commit| path.is_none_or(|path| touches_path(commit, path)))
+        .take(LOG_LIMIT);
+    for commit in commits {
+        items.push(CommitItem::from_commit(&commit));
+    }
+
+    items
+}
+
+/// `commit`, `Author:` and `Date:` lines like `git show` starts with.
+pub fn commit_header(commit: &Commit) -> Vec<String> {
+    let author = commit.author();
+    vec![
+        format!("commit {}", commit.id()),
+        format!(
+            "Author: {} <{}>",
+            author.name().unwrap_or_default(),
+            author.email().unwrap_or_default()
+        ),
+        format!("Date:   {}", format_time(author.when())),
+    ]
+}
+
+/// What the commit view shows of `oid`: the header, the indented message and the files it
+/// changes compared to its first parent, like `git show --name-status`.
+pub fn show_commit(repo: &Repository, oid: Oid) -> Result<Vec<String>, git2::Error> {
+    let commit = repo.find_commit(oid)?;
+    let mut lines = commit_header(&commit);
+    lines.push(String::new());
+    let message = lossy(commit.message_bytes()).0;
+    lines.extend(message.trim_end().lines().map(|line| format!("    {line}")));
+    lines.push(String::new());
+
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+    for delta in diff.deltas() {
+        let path = |file: git2::DiffFile| lossy(file.path_bytes().unwrap_or_default()).0;
+        lines.push(match delta.status() {
+            Delta::Renamed => format!(
+                "R  {} -> {}",
+                path(delta.old_file()),
+                path(delta.new_file())
+            ),
+            Delta::Deleted => format!("D  {}", path(delta.old_file())),
+            status => {
+                let code = match status {
+                    Delta::Added => 'A',
+                    Delta::Typechange => 'T',
+                    _ => 'M',
+                };
+                format!("{code}  {}", path(delta.new_file()))
+            }
+        });
+    }
+    Ok(lines)
+}
+
+/// Commits `rev` could mean when it is a short id the object database finds ambiguous, newest
+/// first. Only then are the objects gone through for ids with that prefix. Tags are peeled to
+/// their commit, trees and blobs are left out. Empty when `rev` isn't an ambiguous short id.
+pub fn ambiguous_commits(repo: &Repository, rev: &str) -> Vec<CommitItem> {
+    let prefix = rev.trim().to_ascii_lowercase();
+    if !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Vec::new();
+    }
+    let Ok(odb) = repo.odb() else {
+        return Vec::new();
+    };
+    let found = Oid::from_str(&prefix).and_then(|oid| odb.exists_prefix(oid, prefix.len()));
+    if !matches!(found, Err(e) if e.code() == ErrorCode::Ambiguous) {
+        return Vec::new();
+    }
+    let mut oids = Vec::new();
+    let _ = odb.foreach(|oid| {
+        if oid.to_string().starts_with(&prefix) {
+            oids.push(*oid);
+        }
+        true
+    });
+
+    let mut commits: Vec<CommitItem> = oids
+        .into_iter()
+        .filter_map(|oid| repo.find_object(oid, None).ok()?.peel_to_commit().ok())
+        .map(|commit| CommitItem::from_commit(&commit))
+        .collect();
+    // A tag and its commit can both match, and objects can be in several packs.
+    commits.sort_by_key(|c| (Reverse(c.time), c.oid));
+    commits.dedup_by_key(|c| c.oid);
+    commits
+}
+
+/// Number of commits in equally long slices of time between the oldest and newest commit,
+/// oldest slice first. Drawn as the log minimap.
+pub struct Density {
+    pub counts: Vec<usize>,
+    oldest: i64,
+    newest: i64,
+}
+
+impl Density {
+    pub fn new(commits: &[CommitItem], slices: usize) -> Self {
+        let oldest = commits.iter().map(|c| c.time).min().unwrap_or(0);
+        let newest = commits.iter().map(|c| c.time).max().unwrap_or(0);
+        let mut density = Self {
+            counts: vec![0; slices.max(1)],
+            oldest,
+            newest,
+        };
+        for commit in commits {
+            let slice = density.slice_of(commit.time);
+            density.counts[slice] += 1;
+        }
+        density
+    }
+
+    /// Slice a commit made at `time` falls into.
+    pub fn slice_of(&self, time: i64) -> usize {
+        let span = (self.newest - self.oldest).max(1) as i128;
+        let offset = (time.clamp(self.oldest, self.newest) - self.oldest) as i128;
+        let last = self.counts.len() - 1;
+        (offset * last as i128 / span) as usize
+    }
+}
+
+/// A commit that changed a file, with the path the file had at that commit.
+pub struct FileRevision {
+    pub commit: CommitItem,
+    pub path: String,
+}
+
+/// Commits reachable from HEAD that changed the file at `path`, newest first. Like
+/// `git log --follow`, the file is followed to its old name when a commit renamed it.
+pub fn file_history(repo: &Repository, path: &str) -> Vec<FileRevision> {
+    let mut items = Vec::new();
+
+    let Ok(mut revwalk) = repo.revwalk() else {
+        return items;
+    };
+    if revwalk.push_head().is_err() || revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME).is_err()
+    {
+        return items;
+    }
+
+    let mut path = PathBuf::from(path);
+    for commit in revwalk
+        .flatten()
+        .filter_map(|oid| repo.find_commit(oid).ok())
+    {
+        let parent = commit.parent(0).ok();
+        let id = entry_id(&commit, &path);
+        let parent_id = parent.as_ref().and_then(|parent| entry_id(parent, &path));
+        if id == parent_id {
+            continue;
+        }
+        items.push(FileRevision {
+            commit: CommitItem::from_commit(&commit),
+            path: path.to_string_lossy().into_owned(),
+        });
+        if items.len() == LOG_LIMIT {
+            break;
+        }
+        if id.is_some()
+            && parent_id.is_none()
+            && let Some(parent) = parent
+        {
+            match renamed_from(repo, &parent, &commit, &path) {
+                Some(old_path) => path = old_path,
+                // The file was created here, there is no older history to follow.
+                None => break,
+            }
+        }
+    }
+
+    items
+}
+
+/// The path `path` had in `parent` if `commit` renamed it.
+fn renamed_from(
+    repo: &Repository,
+    parent: &Commit,
+    commit: &Commit,
+    path: &Path,
+) -> Option<PathBuf> {
+    let mut diff = repo
+        .diff_tree_to_tree(Some(&parent.tree().ok()?), Some(&commit.tree().ok()?), None)
+        .ok()?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))
+        .ok()?;
+    diff.deltas()
+        .filter(|delta| delta.status() == Delta::Renamed)
+        .find(|delta| delta.new_file().path() == Some(path))
+        .and_then(|delta| delta.old_file().path().map(Path::to_path_buf))
+}
+
+fn entry_id(commit: &Commit, path: &Path) -> Option<Oid> {
+    commit
+        .tree()
+        .ok()
+        .and_then(|tree| tree.get_path(path).ok().map(|entry| entry.id()))
+}
+
+/// Whether `commit` changes the file or directory at `path` compared to its first parent.
+pub fn touches_path(commit: &Commit, path: &Path) -> bool {
+    match commit.parent(0) {
+        Ok(parent) => entry_id(commit, path) != entry_id(&parent, path),
+        Err(_) => entry_id(commit, path).is_some(),
+    }
+}
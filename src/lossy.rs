@@ -0,0 +1,38 @@
+use std::{borrow::Cow, path::PathBuf};
+
+/// Shown next to names and paths that aren't valid UTF-8, whose displayed text has
+/// replacement characters and can't be typed back.
+pub const INVALID_UTF8: &str = "[not UTF-8]";
+
+/// `bytes` as text for display, with invalid UTF-8 replaced. Also returns whether anything
+/// had to be replaced.
+pub fn lossy(bytes: &[u8]) -> (String, bool) {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(text) => (text.to_string(), false),
+        Cow::Owned(text) => (text, true),
+    }
+}
+
+/// Path of a file as git stores it, byte for byte where the platform allows.
+#[cfg(unix)]
+pub fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(lossy(bytes).0)
+}
+
+/// Bytes of `path`, the inverse of [`bytes_to_path`].
+#[cfg(unix)]
+pub fn path_to_bytes(path: &std::path::Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &std::path::Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
@@ -1,24 +1,266 @@
 use crate::{
-    branch::{BranchItem, BranchQuery, checkout_branch, query_branches},
-    term::{Term, Vec2},
+    api::{Client, Response},
+    audit::{AuditEntry, RefTips},
+    blame::{Blame, blame_file, blame_parent},
+    branch::{
+        BranchItem, BranchQuery, UpstreamState, apply_upstream_states, checkout_branch,
+        delete_branch, fast_forward_branches, find_local_branch, local_names, parse_allowlist,
+        previous_branch, query_branches, query_branches_without_upstreams, rewind_branches,
+        send_upstream_states,
+    },
+    bundle::{create_bundle, fetch_bundle, verify_bundle},
+    clipboard::read_clipboard,
+    columns::{Column, Layout},
+    command::{ArgKind, TypedCommand},
+    config::Config,
+    contributor::{ContributorItem, query_contributors},
+    date::{DAY, WEEK, format_age, format_day, format_time, now, parse_time},
+    doctor::Capabilities,
+    event_loop::{LoopEvent, TICK_INTERVAL},
+    export::{export_branch, export_commit},
+    files::query_files,
+    filter::{Context, Filter, SortKey, sort_branches},
+    forge::Forge,
+    format::RowFormat,
+    hook::{HookRun, run_hook},
+    ignore::{IgnoreFile, IgnoreRule, add_pattern, path_pattern, query_ignore_rules},
+    labels::Labels,
+    log::{
+        CommitItem, Density, FileRevision, ambiguous_commits, file_history, query_log, show_commit,
+    },
+    lossy::{INVALID_UTF8, lossy, path_to_bytes},
+    profile::{Phase, Profiler},
+    rebase::{
+        Todo, Verb, fixup_commit, fixup_message, pushed_to, reword_commit, reworded_message,
+        run_todo,
+    },
+    refs::head_ref,
+    remote::{RemoteItem, backup_push, fetch_all, fetch_branch, push_branch, query_remotes},
+    search::Query,
+    snapshot::{SavedTodo, Snapshot},
+    stack::{RestackOutcome, Stacks, detect_stacks, order_by_stack, restack},
+    stash::{StashItem, pop_stash, query_stashes, stash_changes},
+    status::{IndexFlag, StatusItem, is_dirty, query_status, toggle_index_flag},
+    term::{
+        BoxWidget, Input, Line, ListState, ListWidget, Span, Term, Vec2, Viewport, display_width,
+        pad, progress_bar, sparkline, truncate, wrap,
+    },
+    tool::{difftool, difftool_revisions, mergetool},
+    worktree::{WorktreeHeads, WorktreeItem, query_worktrees},
 };
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use crossterm::{
-    event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    event::{
+        Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     style::{Attribute, Color},
 };
-use git2::Repository;
-use std::{path::Path, process::exit};
+use git2::{ErrorCode, Oid, Repository};
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::{IsTerminal, Read, Write, stdin, stdout},
+    path::{Path, PathBuf},
+    process::exit,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
 
+mod api;
+mod audit;
+mod blame;
 mod branch;
+mod bundle;
+mod clipboard;
+mod columns;
+mod command;
+mod config;
+mod contributor;
+mod date;
+mod doctor;
+mod event_loop;
+mod export;
+mod files;
+mod filter;
+#[cfg(test)]
+mod fixture;
+mod forge;
+mod format;
+mod hook;
+mod ignore;
+mod json;
+mod labels;
+mod log;
+mod lossy;
+#[cfg(feature = "gitoxide")]
+mod oxide;
+mod profile;
+mod rebase;
+mod refs;
+mod remote;
+mod search;
+mod snapshot;
+mod stack;
+mod stash;
+mod status;
 mod term;
+mod tool;
+mod worktree;
+
+/// Advanced every tick while branches load.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
 
-const EVENT_POLL_TIMEOUT_MS: u64 = 10_000;
+// Exit codes
+const EXIT_CANCEL: i32 = 1;
+const EXIT_REPO_ERROR: i32 = 2;
+const EXIT_OPERATION_FAILED: i32 = 3;
 const PADDING: usize = 2;
+/// Longest time between the clicks of a double-click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// Smallest terminal the layout fits in: the view tabs, one row of a list and the prompt line.
+const MIN_SIZE: Vec2 = Vec2::new(PADDING as u16 * 2 + 16, PADDING as u16 * 2 + 1);
 
 // Shortcuts:
+//   "tab"       = next view
+//   "shift+tab" = previous view
+//   "S"         = sync: fetch and prune all remotes, fast-forward branches behind upstream
+//   "ctrl+r"    = reload the current view from the repository
+//   "M"         = mirror all local branches and tags to the gix.backupRemote remote
+//   "ctrl+z"    = suspend to the shell, "fg" resumes
+//   ":"         = command line, e.g. ":checkout feat", ":delete old-fix", ":sort date desc",
+//                 ":filter merged", ":view log" (or a saved view), ":pr" (the pull request of the
+//                 selected branch), see command.rs. "tab" completes
+//
+// Lists (and the blame view):
+//   "j" / "k"               = move down / up, a count before them moves that many rows (12j)
+//   "pgdn" / "pgup"         = move a page down / up
+//   "ctrl+d" / "ctrl+u"     = move half a page down / up
+//   "home" / "end", "g"/"G" = jump to the top / bottom
+//   "#"                     = toggle row numbers (gix.rowNumbers), a number and "enter" selects
+//                             that row (12 enter)
+//
+// Search and prompts:
+//   "left" / "right", "home" / "end" = move the cursor
+//   "ctrl+w" / "ctrl+u"              = delete the word / everything before the cursor
+//   "ctrl+v"                         = paste from the system clipboard
+//
+// Hook output view:
+//   "r" = run the hook again
+//   "s" / "esc" = skip, back to branches
+//
+// Bundle view:
+//   "space" = toggle the selected ref
+//   "enter" = write the bundle / fetch the checked refs from it
+//   "esc"   = cancel
+//
+// Worktrees view:
+//   "enter" = continue in the selected worktree, or print its path and exit (--pick)
+//
+// Contributors view:
+//   "enter" = list branches whose tip is authored by the selected contributor
+//
+// Status view:
+//   "d" = open the selected file in the configured diff.tool
+//   "m" = resolve the selected conflict with the configured merge.tool
+//   "i" = add an ignore pattern for the selected untracked file to .gitignore
+//   "I" = add an ignore pattern for the selected untracked file to .git/info/exclude
+//   "u" = toggle assume-unchanged on the selected file
+//   "w" = toggle skip-worktree on the selected file
+//   "h" = show the history of the selected file
+//
+// Files view (the files at HEAD):
+//   "h" / "enter" = show the history of the selected file
+//   "b"           = blame the selected file
+//
+// File history view:
+//   "space" = mark the selected revision
+//   "d"     = compare the marked and selected revisions in the configured diff.tool, without a
+//             mark the selected revision and the one before it
+//   "b"     = blame the file at the selected revision
+//   "esc"   = back to the view the history was opened from
+//
+// Blame view:
+//   "j" / "k" = move down / up
+//   "v"       = mark the start of a line range
+//   "l"       = blame only the marked range, or the whole file again
+//   "p"       = blame again at the parent of the commit that changed the selected line
+//   "h"       = show the history of the file
+//   "enter"   = show the commit that changed the selected line
+//   "esc"     = back to the file history, or the files view
+//
+// Commit view (what the blamed commit changed):
+//   "j" / "k" = scroll
+//   "esc"     = back to the blame
+//
+// Candidates view (a short id typed into the time-travel or contains prompt matched several
+// commits):
+//   "enter" = time-travel to the selected commit, or list the branches containing it
+//   "esc"   = cancel
+//
+// Log view:
+//   "x" = export the selected commit as html
+//   "K" = jump to the next slice of the minimap above
+//   "J" = jump to the next slice of the minimap below
+//   "i" = interactively rebase the commits after the selected one
+//   "r" = reword the selected commit, recreating the commits after it
+//   "f" = commit the staged changes as a fixup! of the selected commit
+//
+// Rebase view (todo list, newest first like the log, so the first row is applied last):
+//   "p" / "r" / "s" / "f" / "d" = pick / reword / squash / fixup / drop the selected commit
+//   "alt+j" / "alt+k"           = move the selected commit down / up
+//   "A"                         = autosquash: fold fixup!/squash! commits into their targets
+//   "enter"                     = run the rebase
+//   "esc"                       = cancel
+//
+// Branches view:
+//   "enter" = print branch and exit (--pick)
 //   "r" = toggle between local/local-and-remote/remote branches
-//   "/" = search branches
+//   "-" = check out the previously checked out branch, like `git checkout -`
+//   "t" = toggle stacked branch tree
+//   "R" = restack the selected branch and the branches stacked on it
+//   "x" = export the branch diff against HEAD as html
+//   "f" = filter (and sort) branches with an expression, see filter.rs
+//   "a" = toggle commit activity sparklines
+//   "<" / ">" = narrow / widen the branch name column, kept in gix.branchNameLength
+//   "{" / "}" = narrow / widen the summary column, kept in gix.summaryLength
+//   "w" = toggle wrapping long summaries onto more rows instead of cutting them off
+//         (gix.wrapSummaries)
+//   "U" = toggle showing the upstream each branch tracks (gix.upstreamColumn)
+//   "p" = pin the checked out branch to the first row, whatever the sort and filters
+//   "o" = sort by the next column: name, age, ahead, behind, author, then unsorted again
+//   "O" = reverse the sort order
+//   "s" = sort newest commit first, then by name, most ahead of upstream first, by author, then
+//         unsorted again
+//   "C" = contributors to the selected branch
+//   "m" = only list branches merged into HEAD, then only unmerged ones, then all again
+//   "u" = only list my branches, whose tip is authored by user.email or any gix.myEmail
+//   "c" = only list branches containing a commit, like `git branch --contains`, empty to clear
+//   "e" = with remote branches listed, only list those of the first remote, then the next one,
+//         then all again
+//   "esc" = clear the merged, my branches, contains, remote and contributor filters
+//   "V" = switch to the next saved view (gixView sections of the config, see config.rs), after
+//         the last one back to the plain list
+//   "T" = time-travel: show where branches pointed at a date or revision (again to leave)
+//   "H" = show the output of the last hook run
+//   "b" = create a bundle of branches and tags
+//   "B" = import refs from a bundle file
+//   "L" = edit the local labels of the selected branch, e.g. "needs review, blocked"
+//   "space" = mark / unmark the selected branch, "esc" unmarks all
+//   "D" = delete the marked branches, or the selected one, if they are merged
+//   "P" = push the marked branches, or the selected one, to their upstream (or origin)
+//   "F" = fetch the upstream of the marked branches, or the selected one
+//   click / double-click = select / check out the branch (print it and exit with --pick)
+//   with uncommitted changes, checking out asks first:
+//       -> "s"   = stash the changes and switch
+//       -> "f"   = switch anyway, discarding the changes in the way
+//       -> "esc" = cancel
+//   "/" = fuzzy search branches, best matches first (gix.search = substring to match as typed),
+//         pasting a branch name also starts a search for it. Terms separated by spaces all have
+//         to match, "!term" excludes matches ("release !rc")
+//       -> "tab"   = also match the summary and author of the tip commit
 //       -> "enter" = accept search
 //       -> "esc"   = cancel search
 
@@ -30,336 +272,5211 @@ struct Args {
     #[arg(short, long, default_value = ".")]
     directory: String,
 
-    /// Latest commit summary max length
-    #[arg(short, long, default_value_t = 72)]
-    summary_length: usize,
+    /// View to start in (overrides gix.view)
+    #[arg(long, value_enum)]
+    view: Option<View>,
 
-    /// Branch name max length
-    #[arg(short, long, default_value_t = 42)]
-    branch_name_length: usize,
+    /// Start with remote branches listed
+    #[arg(long, action = clap::ArgAction::SetTrue, conflicts_with = "all")]
+    remote: bool,
+
+    /// Start with both local and remote branches listed
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    all: bool,
+
+    /// Only consider changes under this path in the log, status, exports and activity
+    #[arg(long, value_name = "SUBDIR")]
+    path: Option<String>,
+
+    /// Start with the branch search filter set to this pattern
+    #[arg(long)]
+    search: Option<String>,
+    /// Latest commit summary max length [default: gix.summaryLength or 72]
+    #[arg(short, long)]
+    summary_length: Option<usize>,
+
+    /// Branch name max length [default: gix.branchNameLength or 42]
+    #[arg(short, long)]
+    branch_name_length: Option<usize>,
+
+    /// Print the branch (or worktree path) selected with enter to stdout and exit
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    pick: bool,
+
+    /// Disable all actions that modify the repository (overrides gix.readOnly)
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    read_only: bool,
+
+    /// Number of weeks shown in commit activity sparklines
+    #[arg(long, default_value_t = 12)]
+    activity_weeks: usize,
+
+    /// Library used to list branches, gitoxide needs gix built with the gitoxide feature
+    #[arg(long, value_enum, default_value_t = Backend::Libgit2)]
+    backend: Backend,
+
+    /// Disable colors and text attributes, also set by the NO_COLOR environment variable
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    no_color: bool,
+
+    /// Draw with ASCII characters only, the default when the locale isn't UTF-8
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    ascii: bool,
 
     /// Render debug info
     #[arg(short = 'D', long, action = clap::ArgAction::SetTrue)]
     debug: bool,
+
+    /// Show per-frame query and render timings and append them to gix-profile.log in the git directory
+    #[arg(long, action = clap::ArgAction::SetTrue)]
+    profile: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print shell completions for bash, zsh, fish, elvish or powershell
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Report detected terminal capabilities and which features are downgraded
+    DoctorTerminal,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum View {
+    #[default]
+    Branches,
+    Log,
+    Status,
+    /// Files tracked at HEAD, to open their history or blame.
+    Files,
+    Stashes,
+    Remotes,
+    Contributors,
+    Worktrees,
+    Ignore,
+    /// Operations gix performed that can move refs, see audit.rs.
+    Audit,
+    /// Output of the last hook run, only reachable from other views.
+    #[value(skip)]
+    Hook,
+    /// Ref checklist for creating or importing a bundle, only reachable from other views.
+    #[value(skip)]
+    Bundle,
+    /// Commits that changed one file, only reachable from the status and files views and the
+    /// blame.
+    #[value(skip)]
+    History,
+    /// Blame of one file at a revision, only reachable from the file history and the files view.
+    #[value(skip)]
+    Blame,
+    /// Commits an ambiguous short id could mean, reachable from the prompts taking a revision.
+    #[value(skip)]
+    Candidates,
+    /// Todo list of an interactive rebase, only reachable from the log.
+    #[value(skip)]
+    Rebase,
+    /// Header, message and changed files of one commit, only reachable from the blame.
+    #[value(skip)]
+    Commit,
+}
+
+impl View {
+    fn next(self) -> Self {
+        let views = Self::value_variants();
+        let i = views.iter().position(|v| *v == self).unwrap_or(0);
+        views[(i + 1) % views.len()]
+    }
+
+    fn prev(self) -> Self {
+        let views = Self::value_variants();
+        let i = views.iter().position(|v| *v == self).unwrap_or(0);
+        views[(i + views.len() - 1) % views.len()]
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            View::Branches => "branches",
+            View::Log => "log",
+            View::Status => "status",
+            View::Files => "files",
+            View::Stashes => "stashes",
+            View::Remotes => "remotes",
+            View::Contributors => "contributors",
+            View::Worktrees => "worktrees",
+            View::Ignore => "ignore",
+            View::Audit => "audit",
+            View::Hook => "hook",
+            View::Bundle => "bundle",
+            View::History => "history",
+            View::Blame => "blame",
+            View::Candidates => "candidates",
+            View::Rebase => "rebase",
+            View::Commit => "commit",
+        }
+    }
+}
+
+/// Library read-only queries go through. Everything else always uses libgit2.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    #[default]
+    Libgit2,
+    Gitoxide,
+}
+
+enum PromptKind {
+    AsOf,
+    Filter,
+    BundleCreate,
+    BundleImport,
+    Ignore(IgnoreFile),
+    /// New summary of a commit in the rebase view or the log, with the remote it was already
+    /// pushed to.
+    Reword(Oid, Option<String>),
+    /// Labels of the branch with this full ref name.
+    Labels(String),
+    /// A command such as `delete <branch>`, see command.rs.
+    Command,
+    /// Commit the branches listed have to contain.
+    Contains,
+}
+
+impl PromptKind {
+    fn label(&self) -> String {
+        if let PromptKind::Reword(_, Some(remote)) = self {
+            return format!("reword (already on {remote}, rewrites published history):");
+        }
+        match self {
+            PromptKind::AsOf => "as of (date or revision):",
+            PromptKind::Filter => "filter:",
+            PromptKind::BundleCreate => "write bundle to:",
+            PromptKind::BundleImport => "import bundle from:",
+            PromptKind::Ignore(IgnoreFile::Gitignore) => "add to .gitignore:",
+            PromptKind::Ignore(IgnoreFile::Exclude) => "add to .git/info/exclude:",
+            PromptKind::Reword(..) => "reword:",
+            PromptKind::Labels(_) => "labels (comma separated):",
+            PromptKind::Command => ":",
+            PromptKind::Contains => "branches containing (commit or revision):",
+        }
+        .to_string()
+    }
+
+    /// Name the prompt is saved under in a [`Snapshot`], `None` for prompts that can't be
+    /// restored on their own.
+    fn key(&self) -> Option<&'static str> {
+        match self {
+            PromptKind::AsOf => Some("as-of"),
+            PromptKind::Filter => Some("filter"),
+            PromptKind::BundleCreate => None,
+            PromptKind::BundleImport => Some("bundle-import"),
+            PromptKind::Ignore(IgnoreFile::Gitignore) => Some("gitignore"),
+            PromptKind::Ignore(IgnoreFile::Exclude) => Some("exclude"),
+            PromptKind::Reword(..) => None,
+            PromptKind::Labels(_) => None,
+            PromptKind::Command => Some("command"),
+            PromptKind::Contains => Some("contains"),
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "as-of" => Some(PromptKind::AsOf),
+            "filter" => Some(PromptKind::Filter),
+            "bundle-import" => Some(PromptKind::BundleImport),
+            "gitignore" => Some(PromptKind::Ignore(IgnoreFile::Gitignore)),
+            "exclude" => Some(PromptKind::Ignore(IgnoreFile::Exclude)),
+            "command" => Some(PromptKind::Command),
+            "contains" => Some(PromptKind::Contains),
+            _ => None,
+        }
+    }
+}
+
+/// Refs to pick from while creating a bundle, or importing the one at `import`.
+struct BundleSelection {
+    import: Option<PathBuf>,
+    refs: Vec<(String, bool)>,
+}
+
+impl BundleSelection {
+    fn checked(&self) -> Vec<String> {
+        self.refs
+            .iter()
+            .filter(|(_, checked)| *checked)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// Single line input shown at the bottom while active.
+struct Prompt {
+    kind: PromptKind,
+    input: Input,
+}
+
+/// What a branch query on a worker thread sends, see `State::refresh_branches`.
+enum BranchLoad {
+    /// The branches and how long it took to list them, local ones possibly without upstreams.
+    Branches(Vec<BranchItem>, Duration),
+    /// Upstreams of some of the branches sent before.
+    Upstreams(Vec<UpstreamState>),
 }
 
 struct State {
     renders: usize,
     repo: Repository,
+    config: Config,
+    view: View,
+    /// Queried branches before the in-memory filters, refreshed by `refresh_branches`.
+    all_branches: Vec<BranchItem>,
     branches: Vec<BranchItem>,
-    selected_row: usize,
-    search_string: String,
+    /// Receives the results of a branch query running on a worker thread.
+    loading_branches: Option<Receiver<BranchLoad>>,
+    spinner: usize,
+    profiler: Profiler,
+    backend: Backend,
+    /// Web UI branches and commits link to, when the remote is on one.
+    forge: Option<Forge>,
+    /// REST API of the forge, when gix can read from it.
+    api: Option<Client>,
+    /// Branch whose pull request is being looked up, and where the response arrives.
+    loading_pull_request: Option<(String, Receiver<Result<Response, String>>)>,
+    labels: Labels,
+    commits: Vec<CommitItem>,
+    /// Scroll position of lists, the log and top-down text such as hook output and blame.
+    list_viewport: Viewport,
+    log_viewport: Viewport,
+    text_viewport: Viewport,
+    statuses: Vec<StatusItem>,
+    stashes: Vec<StashItem>,
+    remotes: Vec<RemoteItem>,
+    contributors: Vec<ContributorItem>,
+    worktrees: Vec<WorktreeItem>,
+    /// Branches checked out in other worktrees, read again with the branch list.
+    worktree_heads: WorktreeHeads,
+    ignore_rules: Vec<IgnoreRule>,
+    audit_log: Vec<AuditEntry>,
+    /// Paths the files view lists.
+    files: Vec<String>,
+    history: Vec<FileRevision>,
+    /// File the history view is about.
+    history_path: Option<String>,
+    /// View esc in the history goes back to.
+    history_from: View,
+    /// Revision in the history view the selected one is compared to.
+    history_mark: Option<usize>,
+    blame: Option<Blame>,
+    /// View esc in the blame goes back to.
+    blame_from: View,
+    /// Lines of the commit view, see [`show_commit`].
+    commit_lines: Vec<String>,
+    /// Selected line in the blame view, counted from the top.
+    blame_cursor: usize,
+    /// Line the blame view's range selection started at.
+    blame_mark: Option<usize>,
+    /// Commits the short id typed into a prompt could mean, and that prompt.
+    candidates: Vec<CommitItem>,
+    candidates_for: Option<PromptKind>,
+    /// Interactive rebase being edited in the rebase view.
+    rebase: Option<Todo>,
+    /// Branch the contributors view is limited to, or the whole repository.
+    contributors_branch: Option<String>,
+    /// Only list branches whose tip was authored by this email.
+    author_filter: Option<String>,
+    /// Only list branches that are merged into HEAD, or only those that aren't.
+    merged_filter: Option<bool>,
+    /// Only list branches whose tip is authored by me, see [`Context::is_mine`].
+    mine_only: bool,
+    /// Only list branches whose history contains this commit, as typed and resolved.
+    contains_filter: Option<(String, Oid)>,
+    /// Only list remote branches of this remote, local branches are still listed.
+    remote_filter: Option<String>,
+    /// Index of the saved view switched to with `V` in `config.saved_views`.
+    saved_view: Option<usize>,
+    filter: Option<Filter>,
+    /// Column picked with `o` and whether it is reversed, takes over from the filter's sort.
+    sort: Option<(SortKey, bool)>,
+    /// Only list these branches, read from stdin when it is not a terminal.
+    allowlist: Option<HashSet<String>>,
+    /// Path relative to the top of the working tree that views are scoped to.
+    path: Option<PathBuf>,
+    list: ListState,
+    search: Input,
+    /// Whether the search also matches the summary and author of branch tips.
+    search_all: bool,
     branch_query: BranchQuery,
+    /// Detected branch stacks while the stack tree is shown.
+    stacks: Option<Stacks>,
+    /// Time-travel point as typed by the user and in seconds since the epoch.
+    as_of: Option<(String, i64)>,
+    show_activity: bool,
+    /// Whether the checked out branch is pinned to the first row, see [`Config::pin_head`].
+    pin_head: bool,
+    /// Whether lists are numbered, see [`Config::row_numbers`].
+    row_numbers: bool,
+    /// See [`Config::upstream_column`].
+    show_upstream: bool,
+    /// See [`Config::wrap_summaries`].
+    wrap_summaries: bool,
+    /// Longest summary and branch name shown, adjusted with `{` `}` and `<` `>`.
+    summary_length: usize,
+    branch_name_length: usize,
+    /// Template branch rows are written with instead of the columns, see format.rs.
+    row_format: Option<RowFormat>,
+    /// Full ref names of the branches marked with space, which bulk actions act on.
+    marked: HashSet<String>,
+    /// When and on which row the last click was, to tell double-clicks.
+    last_click: Option<(Instant, usize)>,
+    /// Count typed before a movement, e.g. the 12 of `12j`.
+    count: Option<usize>,
+    /// Last hook run by gix and how far its output is scrolled.
+    hook: Option<HookRun>,
+    bundle: Option<BundleSelection>,
+    prompt: Option<Prompt>,
+    /// Candidates the last tab in the command line left to pick from, shown above it.
+    completions: Vec<String>,
+    /// Input as last written to the snapshot file.
+    snapshot: Snapshot,
+    /// Snapshot of a previous session that is offered to be restored.
+    restore: Option<Snapshot>,
+    /// Branch waiting to be checked out until it is decided what happens to the uncommitted
+    /// changes in the way.
+    dirty_checkout: Option<Vec<u8>>,
     error: Option<String>,
+    message: Option<String>,
+    /// Whether the most recent operation failed, reported through the exit code.
+    operation_failed: bool,
+    /// Outcome of the first operation finished since this was last taken, for the audit log.
+    outcome: Option<Result<String, String>>,
+    picked: Option<Vec<u8>>,
 }
 
 impl State {
-    fn new(repo: Repository) -> Self {
+    fn new(repo: Repository, config: Config, view: View) -> Self {
+        let forge = Forge::detect(&repo);
+        let api = forge.as_ref().and_then(|forge| forge.api(repo.commondir()));
+        let labels = Labels::load(repo.commondir());
+        let bottom_up = config.bottom_up;
+        let pin_head = config.pin_head;
+        let row_numbers = config.row_numbers;
+        let show_upstream = config.upstream_column;
+        let wrap_summaries = config.wrap_summaries;
+        let summary_length = config.summary_length.unwrap_or(72);
+        let branch_name_length = config.branch_name_length.unwrap_or(42);
         Self {
             renders: 0,
             repo,
+            config,
+            view,
+            all_branches: Vec::new(),
             branches: Vec::new(),
-            selected_row: 0,
-            search_string: String::new(),
+            loading_branches: None,
+            spinner: 0,
+            profiler: Profiler::default(),
+            forge,
+            api,
+            loading_pull_request: None,
+            labels,
+            backend: Backend::default(),
+            commits: Vec::new(),
+            list_viewport: Viewport::new(bottom_up, true),
+            log_viewport: Viewport::new(bottom_up, false),
+            text_viewport: Viewport::new(false, true),
+            statuses: Vec::new(),
+            stashes: Vec::new(),
+            remotes: Vec::new(),
+            contributors: Vec::new(),
+            worktrees: Vec::new(),
+            worktree_heads: WorktreeHeads::default(),
+            ignore_rules: Vec::new(),
+            audit_log: Vec::new(),
+            history: Vec::new(),
+            files: Vec::new(),
+            history_path: None,
+            history_from: View::Status,
+            history_mark: None,
+            blame: None,
+            blame_from: View::History,
+            commit_lines: Vec::new(),
+            blame_cursor: 0,
+            blame_mark: None,
+            candidates: Vec::new(),
+            candidates_for: None,
+            rebase: None,
+            contributors_branch: None,
+            author_filter: None,
+            merged_filter: None,
+            mine_only: false,
+            contains_filter: None,
+            remote_filter: None,
+            saved_view: None,
+            filter: None,
+            sort: None,
+            allowlist: None,
+            path: None,
+            list: ListState::default(),
+            search: Input::default(),
+            search_all: false,
             branch_query: BranchQuery::Local,
+            stacks: None,
+            as_of: None,
+            show_activity: false,
+            pin_head,
+            row_numbers,
+            show_upstream,
+            wrap_summaries,
+            summary_length,
+            branch_name_length,
+            row_format: None,
+            marked: HashSet::new(),
+            last_click: None,
+            count: None,
+            hook: None,
+            bundle: None,
+            prompt: None,
+            completions: Vec::new(),
+            snapshot: Snapshot::default(),
+            restore: None,
+            dirty_checkout: None,
             error: None,
+            message: None,
+            operation_failed: false,
+            outcome: None,
+            picked: None,
         }
     }
-}
-
-fn main() {
-    let args = Args::parse();
-    let mut do_run = true;
-    let mut do_render = true;
-    let mut do_search = false;
 
-    let directory = Path::new(&args.directory).canonicalize().unwrap();
-    let repo = match Repository::open(directory) {
-        Ok(repo) => repo,
-        Err(err) => {
-            eprintln!("fatal: {}", err.message());
-            exit(1);
+    /// Switches to `view`, loading its rows.
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+        self.list = ListState::default();
+        self.list_viewport.reset();
+        self.log_viewport.reset();
+        self.text_viewport.reset();
+        let start = Instant::now();
+        match view {
+            View::Branches => self.refresh_branches(),
+            View::Hook
+            | View::Bundle
+            | View::Blame
+            | View::Candidates
+            | View::Rebase
+            | View::Commit => {}
+            View::Log => self.commits = query_log(&self.repo, self.path.as_deref()),
+            View::Status => self.statuses = query_status(&self.repo, self.path.as_deref()),
+            View::Files => self.files = query_files(&self.repo, self.path.as_deref()),
+            View::Stashes => self.stashes = query_stashes(&mut self.repo),
+            View::Remotes => self.remotes = query_remotes(&self.repo),
+            View::Contributors => {
+                self.contributors =
+                    query_contributors(&self.repo, self.contributors_branch.as_deref())
+            }
+            View::Worktrees => self.worktrees = query_worktrees(&self.repo),
+            View::Ignore => self.ignore_rules = query_ignore_rules(&self.repo),
+            View::Audit => self.audit_log = audit::load(self.repo.commondir()),
+            View::History => {
+                self.history = match &self.history_path {
+                    Some(path) => file_history(&self.repo, path),
+                    None => Vec::new(),
+                }
+            }
         }
-    };
-    let mut state = State::new(repo);
+        // Branches are queried in the background and timed on the worker thread.
+        if view != View::Branches {
+            self.profiler.record(Phase::Query, start.elapsed());
+        }
+        self.list.set_len(self.n_rows());
+    }
 
-    let mut term = Term::new();
-    term.clear_all();
-    while do_run {
-        if do_render {
-            render_branches(&mut term, &mut state, &args);
-            let max_y = (Term::size().y) as usize - PADDING;
-            if do_search || !state.search_string.is_empty() {
-                term.write_text(
-                    Vec2::from((PADDING, max_y)),
-                    format!("/ {}", state.search_string),
-                );
-            }
+    /// Loads the rows of the current view again, keeping the selection where possible.
+    fn reload(&mut self) {
+        let selected = self.list.index();
+        self.set_view(self.view);
+        self.list.select(selected);
+    }
 
-            if args.debug {
-                render_debug_info(&mut term, &mut state, &args);
+    /// Queries branches from the repository again. Needed after anything that moves refs or
+    /// changes which branches are queried, other filters only need `filter_branches`.
+    ///
+    /// The query runs on a worker thread with its own repository handle, the current list stays
+    /// in place until `receive_branches` picks up the result. Upstreams looked up through libgit2
+    /// follow in batches, merged into the list as they arrive. A query still running is
+    /// abandoned.
+    fn refresh_branches(&mut self) {
+        self.worktree_heads = WorktreeHeads::read(&self.repo);
+        let query = self.branch_query.clone();
+        let allowlist = self.allowlist.clone();
+        let time = self.as_of.as_ref().map(|(_, time)| *time);
+        let backend = self.backend;
+
+        let Ok(repo) = Repository::open(self.repo.path()) else {
+            let start = Instant::now();
+            let (mut branches, pending) =
+                load_branches(&self.repo, backend, &query, allowlist.as_ref(), time);
+            let (sender, receiver) = mpsc::channel();
+            send_upstream_states(&self.repo, &pending, |states| {
+                let _ = sender.send(states);
+            });
+            for states in receiver.try_iter() {
+                apply_upstream_states(&mut branches, states);
             }
-            if let Some(error) = state.error.take() {
-                term.write_text(Vec2::from((PADDING, max_y)), error);
+            self.all_branches = branches;
+            self.profiler.record(Phase::Query, start.elapsed());
+            self.filter_branches();
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let start = Instant::now();
+            let (branches, pending) =
+                load_branches(&repo, backend, &query, allowlist.as_ref(), time);
+            // The receiver is gone when another refresh replaced this one.
+            if sender
+                .send(BranchLoad::Branches(branches, start.elapsed()))
+                .is_ok()
+            {
+                send_upstream_states(&repo, &pending, |states| {
+                    let _ = sender.send(BranchLoad::Upstreams(states));
+                });
             }
-            do_render = false;
-        }
-        if let Some(event) = term.read_event(EVENT_POLL_TIMEOUT_MS) {
-            if do_search {
-                if let Event::Key(key_event) = event {
-                    if key_event.kind == KeyEventKind::Press {
-                        match key_event.code {
-                            KeyCode::Char(c) => state.search_string.push(c),
-                            KeyCode::Backspace => {
-                                state.search_string.pop();
-                            }
-                            KeyCode::Esc => {
-                                state.search_string = String::new();
-                                do_search = false;
-                            }
-                            KeyCode::Enter => {
-                                do_search = false;
-                            }
-                            _ => {}
-                        }
-                        do_render = true;
-                    }
+        });
+        self.loading_branches = Some(receiver);
+    }
+
+    fn spinner_frame(&self) -> char {
+        SPINNER[self.spinner % SPINNER.len()]
+    }
+
+    /// Takes the branches and upstreams a background query sent so far. Returns whether anything
+    /// changed.
+    fn receive_branches(&mut self) -> bool {
+        let Some(receiver) = &self.loading_branches else {
+            return false;
+        };
+        let mut changed = false;
+        let finished = loop {
+            match receiver.try_recv() {
+                Ok(BranchLoad::Branches(branches, elapsed)) => {
+                    self.profiler.record(Phase::Query, elapsed);
+                    self.all_branches = branches;
                 }
-            } else {
-                handle_branch_event(
-                    event,
-                    &mut state,
-                    &mut do_run,
-                    &mut do_render,
-                    &mut do_search,
-                );
+                Ok(BranchLoad::Upstreams(states)) => {
+                    apply_upstream_states(&mut self.all_branches, states);
+                }
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
             }
+            changed = true;
+        };
+        if finished {
+            self.loading_branches = None;
+        }
+        if changed {
+            self.filter_branches();
         }
+        changed || finished
     }
-    term.close();
-}
 
-fn render_debug_info(term: &mut Term, state: &mut State, args: &Args) {
-    state.renders += 1;
-    let term_size = Term::size();
-    let x = term_size.x - 24 - PADDING as u16;
-    let y = term_size.y - 1 - PADDING as u16;
-    term.draw_text_bubble(
-        Vec2::new(x, y - 3),
-        format!(
-            "Renders:    {}\nSize:       {}\nSum len:    {}\nBranch len: {}",
-            state.renders,
-            Term::size(),
-            args.summary_length,
-            args.branch_name_length,
-        ),
-    );
-}
+    /// Shows the pull request being looked up once the response arrived. Returns whether it did.
+    fn receive_pull_request(&mut self) -> bool {
+        let Some((name, receiver)) = &self.loading_pull_request else {
+            return false;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => Err("the request went missing".to_string()),
+        };
+        let name = name.clone();
+        self.loading_pull_request = None;
+        let Some(forge) = &self.forge else {
+            return true;
+        };
+        let shown = result.and_then(|response| {
+            let stale = response
+                .stale
+                .map(|reason| format!(" ({reason}, from the cache)"))
+                .unwrap_or_default();
+            Ok(match forge.newest_pull_request(&response.body)? {
+                Some(pull) => format!(
+                    "#{} {} ({}) {}{stale}",
+                    pull.number, pull.title, pull.state, pull.url
+                ),
+                None => format!("No pull request from '{name}'{stale}"),
+            })
+        });
+        match shown {
+            Ok(message) => self.message = Some(message),
+            Err(e) => self.error = Some(format!("Couldn't look up the pull request: {e}")),
+        }
+        true
+    }
 
-fn render_branches(term: &mut Term, state: &mut State, args: &Args) {
-    state.branches = query_branches(&state.repo, &state.branch_query)
-        .into_iter()
-        .filter(|b| {
-            if state.search_string.is_empty() {
-                true;
-            }
-            b.name
-                .to_lowercase()
-                .contains(&state.search_string.to_lowercase())
-        })
-        .collect();
+    /// Applies the author filter, search and stack ordering to the queried branches and pins
+    /// the checked out branch. The selection stays on the same branch when it is still listed.
+    fn filter_branches(&mut self) {
+        let start = Instant::now();
+        let selected = self
+            .branches
+            .get(self.list.index())
+            .map(BranchItem::refname);
+        let search = self.search.text();
+        let query = Query::parse(search);
+        let ctx = Context::new(&self.repo, now());
+        let mut scored: Vec<(i32, BranchItem)> = self
+            .all_branches
+            .iter()
+            .filter(|b| {
+                self.author_filter.as_ref().is_none_or(|email| {
+                    b.details(&self.repo)
+                        .author_email
+                        .eq_ignore_ascii_case(email)
+                })
+            })
+            .filter_map(|b| {
+                let score = if self.search_all && !search.is_empty() {
+                    let details = b.details(&self.repo);
+                    query.score(
+                        self.config.search,
+                        &[&b.name, &details.summary, &details.author_email],
+                    )
+                } else {
+                    query.score(self.config.search, &[&b.name])
+                };
+                Some((score?, b))
+            })
+            .filter(|(_, b)| {
+                !b.is_remote
+                    || self.remote_filter.as_ref().is_none_or(|remote| {
+                        b.name
+                            .strip_prefix(remote.as_str())
+                            .is_some_and(|rest| rest.starts_with('/'))
+                    })
+            })
+            .filter(|(_, b)| !self.mine_only || ctx.is_mine(b))
+            .filter(|(_, b)| {
+                self.contains_filter
+                    .as_ref()
+                    .is_none_or(|(_, commit)| ctx.contains(b, *commit))
+            })
+            .filter(|(_, b)| {
+                self.merged_filter
+                    .is_none_or(|merged| ctx.is_merged(b, "HEAD") == merged)
+            })
+            .filter(|(_, b)| self.filter.as_ref().is_none_or(|f| f.matches(b, &ctx)))
+            .map(|(score, b)| (score, b.clone()))
+            .collect();
+        // Best matches first, unless sorted by a column.
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        let mut branches: Vec<BranchItem> = scored.into_iter().map(|(_, b)| b).collect();
+        // branch.sort orders the list like `git branch` does, but not the matches of a search.
+        let sort = self
+            .sort
+            .or_else(|| self.filter.as_ref().and_then(Filter::sort))
+            .or(self.config.branch_sort.filter(|_| search.is_empty()));
+        if let Some((key, desc)) = sort {
+            sort_branches(&mut branches, key, desc, &ctx);
+        }
+        self.branches = match &self.stacks {
+            Some(stacks) => order_by_stack(branches, stacks),
+            None => branches,
+        };
+        if self.pin_head
+            && let Some(head) = self.all_branches.iter().find(|b| b.is_head && !b.is_remote)
+        {
+            self.branches.retain(|b| !b.is_head || b.is_remote);
+            self.branches.insert(0, head.clone());
+        }
+        self.list.set_len(self.n_rows());
+        if let Some(i) = selected.and_then(|refname| {
+            self.branches
+                .iter()
+                .position(|branch| branch.refname() == refname)
+        }) {
+            self.list.select(i);
+        }
+        self.profiler.record(Phase::Filter, start.elapsed());
+    }
 
-    let n_branches = state.branches.len();
-    if n_branches == 0 {
-        state.selected_row = 0;
-    } else if state.selected_row >= n_branches {
-        state.selected_row = n_branches - 1;
+    /// Moves the selection of the list or the blame cursor, and the view with it so the
+    /// selection stays where it is on screen unless the content ends. `height` is the number of
+    /// rows the list is drawn in.
+    fn jump(&mut self, jump: Jump, height: u16) {
+        if self.view == View::Blame {
+            let len = self.blame.as_ref().map_or(0, |b| b.lines.len());
+            let last = len.saturating_sub(1);
+            self.blame_cursor = match jump.rows(height) {
+                Some(rows) => {
+                    self.text_viewport.scroll_by(rows);
+                    self.blame_cursor.saturating_add_signed(rows).min(last)
+                }
+                None if jump == Jump::Top => 0,
+                None => last,
+            };
+            return;
+        }
+        let bottom_up = self.config.bottom_up;
+        match jump.rows(height) {
+            Some(rows) => {
+                let rows = if bottom_up { -rows } else { rows };
+                self.list.move_by(rows);
+                let viewport = match self.view {
+                    View::Log => &mut self.log_viewport,
+                    _ => &mut self.list_viewport,
+                };
+                viewport.scroll_by(rows);
+            }
+            None if (jump == Jump::Top) == bottom_up => self.list.select_last(),
+            None => self.list.select_first(),
+        }
     }
 
-    if state.selected_row > n_branches {
-        state.selected_row = n_branches - 1
+    /// Moves the selection `rows` down the screen, up when negative, stopping at the ends.
+    fn move_down(&mut self, rows: isize) {
+        self.list
+            .move_by(if self.config.bottom_up { -rows } else { rows });
     }
-    let longest_name = {
-        let mut n = 0;
-        for branch in state.branches.iter() {
-            let challenge = branch.name.len();
-            if challenge >= args.branch_name_length {
-                n = args.branch_name_length + 3;
-                break;
-            }
-            if challenge > n {
-                n = challenge;
-            }
+
+    /// Moves the selection one row down the screen, or up, wrapping around at the ends.
+    fn step(&mut self, down: bool) {
+        if down != self.config.bottom_up {
+            self.list.select_next();
+        } else {
+            self.list.select_previous();
         }
-        n
-    };
-    let longest_summary = args.summary_length + 6;
+    }
 
-    let term_size = Term::size();
-    let max_y = (term_size.y - 1) as usize - PADDING;
-    term.clear_all();
-    if n_branches == 0 {
-        term.set_fg_color(Color::Grey);
-        term.set_attribute(Attribute::Dim);
-        term.write_text(Vec2::from((PADDING, max_y)), "> No branches found");
-        term.reset_colors();
-        term.reset_attributes();
-        return;
+    /// Branches bulk actions act on: the marked ones that are listed, or the selected one when
+    /// none are.
+    fn targets(&self) -> Vec<BranchItem> {
+        let marked: Vec<BranchItem> = self
+            .branches
+            .iter()
+            .filter(|branch| self.marked.contains(&branch.refname()))
+            .cloned()
+            .collect();
+        if marked.is_empty() {
+            self.branches
+                .get(self.list.index())
+                .cloned()
+                .into_iter()
+                .collect()
+        } else {
+            marked
+        }
     }
 
-    for (i, branch) in state.branches.iter().enumerate() {
-        if i > term_size.y as usize - PADDING * 2 - 1 {
-            term.set_fg_color(Color::Grey);
-            term.set_attribute(Attribute::Dim);
-            term.write_text(
-                Vec2::from((PADDING + 2, max_y - i)),
-                format!("... {} truncated", n_branches - i - 1),
-            );
-            term.reset_attributes();
-            term.reset_colors();
-            break;
+    /// Whether `action` may modify the repository. Shows an error in read-only mode.
+    fn allow_mutation(&mut self, action: &str) -> bool {
+        if self.config.read_only {
+            self.error = Some(format!("Read-only mode: {action} is disabled"));
         }
-        let prefix = if i == state.selected_row { ">" } else { " " };
-        if i == state.selected_row {
-            term.set_attribute(Attribute::Bold);
+        !self.config.read_only
+    }
+
+    /// Shows the outcome of an operation and remembers whether it failed.
+    fn finish_operation(&mut self, result: Result<String, String>) {
+        self.operation_failed = result.is_err();
+        if self.outcome.is_none() {
+            self.outcome = Some(result.clone());
         }
-        if branch.is_head {
-            term.set_fg_color(Color::DarkGreen);
+        match result {
+            Ok(message) => self.message = Some(message),
+            Err(error) => self.error = Some(error),
         }
-        if branch.is_gone {
-            term.set_attribute(Attribute::CrossedOut);
+    }
+
+    /// Number of rows in the current view.
+    fn n_rows(&self) -> usize {
+        match self.view {
+            View::Branches => self.branches.len(),
+            View::Log => self.commits.len(),
+            View::Status => self.statuses.len(),
+            View::Files => self.files.len(),
+            View::Stashes => self.stashes.len(),
+            View::Remotes => self.remotes.len(),
+            View::Contributors => self.contributors.len(),
+            View::Worktrees => self.worktrees.len(),
+            View::Ignore => self.ignore_rules.len(),
+            View::Audit => self.audit_log.len(),
+            View::Hook | View::Commit => 0,
+            View::Bundle => self.bundle.as_ref().map_or(0, |b| b.refs.len()),
+            View::History => self.history.len(),
+            View::Blame => 0,
+            View::Candidates => self.candidates.len(),
+            View::Rebase => self.rebase.as_ref().map_or(0, |todo| todo.items.len()),
         }
+    }
 
-        let branch_name = {
-            let s = branch.name.chars().take(args.branch_name_length).collect();
-            if branch.name.chars().count() > args.branch_name_length {
-                format!("{s}...")
+    /// Writes the input that is being typed to the snapshot file if it changed since the last
+    /// write. The search only counts while it is being typed.
+    fn save_snapshot(&mut self, searching: bool) {
+        if self.restore.is_some() {
+            return;
+        }
+        let snapshot = Snapshot {
+            view: self
+                .view
+                .to_possible_value()
+                .map(|v| v.get_name().to_string()),
+            prompt: self.prompt.as_ref().and_then(|prompt| {
+                let key = prompt.kind.key()?;
+                Some((key.to_string(), prompt.input.text().to_string()))
+            }),
+            search: if searching {
+                self.search.text().to_string()
             } else {
-                s
-            }
+                String::new()
+            },
+            rebase: self.rebase.as_ref().map(|todo| SavedTodo {
+                onto: todo.onto.to_string(),
+                items: todo
+                    .items
+                    .iter()
+                    .map(|item| {
+                        let verb = item.verb.name().to_string();
+                        (verb, item.oid.to_string(), item.reword.clone())
+                    })
+                    .collect(),
+            }),
         };
+        if snapshot != self.snapshot {
+            // Losing the snapshot is not worth interrupting what is being typed for.
+            let _ = snapshot.save(self.repo.path());
+            self.snapshot = snapshot;
+        }
+    }
 
-        let branch_summary = {
-            let summary: String = branch.summary.chars().take(args.summary_length).collect();
-            if branch.summary.chars().count() > args.summary_length {
-                format!("'{summary}...'")
-            } else {
-                format!("'{summary}'")
-            }
+    /// Reopens the prompt or search of the snapshot being offered. Returns whether a search was
+    /// restored, which continues being typed.
+    fn restore_snapshot(&mut self) -> bool {
+        let Some(snapshot) = self.restore.take() else {
+            return false;
         };
-        let main_str = format!(
-            "{prefix} {} {branch_name:<name_width$}  {branch_summary:<summary_width$}",
-            branch.short_oid(),
-            name_width = longest_name,
-            summary_width = longest_summary
-        );
-        let mut cursor_x = PADDING + main_str.len();
-
-        term.write_text(Vec2::from((PADDING, max_y - i)), main_str);
-
-        term.set_fg_color(Color::Grey);
-        term.set_attribute(Attribute::Dim);
-
-        if !branch.has_upstream {
-            let msg = " [no upstream]";
-            term.write_text(Vec2::from((cursor_x, max_y - i)), msg);
-            cursor_x += msg.len();
+        if let Some(view) = snapshot.view.and_then(|v| View::from_str(&v, true).ok()) {
+            self.set_view(view);
         }
-        if branch.is_gone {
-            let msg = " [gone]";
-            term.write_text(Vec2::from((cursor_x, max_y - i)), msg);
+        if let Some(saved) = snapshot.rebase {
+            self.restore_rebase(saved);
         }
+        self.prompt = snapshot.prompt.and_then(|(kind, input)| {
+            Some(Prompt {
+                kind: PromptKind::from_key(&kind)?,
+                input: Input::new(input),
+            })
+        });
+        if snapshot.search.is_empty() {
+            return false;
+        }
+        self.search = Input::new(snapshot.search);
+        self.filter_branches();
+        self.prompt.is_none()
+    }
 
-        term.reset_attributes();
-        term.reset_colors();
+    /// Reopens the rebase view with a saved todo list, unless its commits changed since.
+    fn restore_rebase(&mut self, saved: SavedTodo) {
+        let items = saved.items.into_iter().map(|(verb, oid, reword)| {
+            let verb = Verb::from_name(&verb);
+            Some((verb?, Oid::from_str(&oid).ok()?, reword))
+        });
+        let restored = match (Oid::from_str(&saved.onto), items.collect::<Option<_>>()) {
+            (Ok(onto), Some(items)) => Todo::restore(&self.repo, onto, items),
+            _ => Err(git2::Error::from_str("the snapshot is damaged")),
+        };
+        match restored {
+            Ok(todo) => {
+                self.rebase = Some(todo);
+                self.set_view(View::Rebase);
+            }
+            Err(e) => self.error = Some(format!("Can't restore the rebase: {}", e.message())),
+        }
     }
 }
 
-fn handle_branch_event(
-    event: Event,
-    state: &mut State,
-    do_run: &mut bool,
-    do_render: &mut bool,
-    do_search: &mut bool,
-) {
-    match event {
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('q'),
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Char('c'),
-            modifiers: KeyModifiers::CONTROL,
-            ..
-        })
-        | Event::Key(KeyEvent {
-            code: KeyCode::Esc, ..
-        }) => *do_run = false,
-        Event::Resize(_w, _h) => *do_render = true,
+/// Branches matching `query` and the allowlist, rewound to `time` when time-travelling, and the
+/// names of those whose upstreams are still to be looked up with `send_upstream_states`.
+fn load_branches(
+    repo: &Repository,
+    backend: Backend,
+    query: &BranchQuery,
+    allowlist: Option<&HashSet<String>>,
+    time: Option<i64>,
+) -> (Vec<BranchItem>, Vec<String>) {
+    let (branches, with_upstreams) = match backend {
+        // libgit2 is the fallback should gitoxide fail to open the repository.
+        #[cfg(feature = "gitoxide")]
+        Backend::Gitoxide => match oxide::query_branches(repo.path(), query) {
+            Ok(branches) => (branches, true),
+            Err(_) => (query_branches_without_upstreams(repo, query), false),
+        },
+        _ => (query_branches_without_upstreams(repo, query), false),
+    };
+    let branches = branches
+        .into_iter()
+        .filter(|b| allowlist.is_none_or(|names| names.contains(&b.name)))
+        .collect();
+    let branches = match time {
+        Some(time) => rewind_branches(repo, branches, time),
+        None => branches,
+    };
+    let pending = if with_upstreams {
+        Vec::new()
+    } else {
+        local_names(&branches)
+    };
+    (branches, pending)
+}
 
-        // Movement
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('k'),
-            ..
-        }) => {
-            let n_branches = state.branches.len();
-            if n_branches != 0 {
-                if state.selected_row == n_branches - 1 {
-                    state.selected_row = 0;
-                } else {
-                    state.selected_row += 1;
-                }
-                *do_render = true;
+/// Completions for `shell` generated by clap_complete. In bash, zsh and fish `--search` also
+/// completes the local branch names, which `GIX_COMPLETE=branches gix` lists.
+fn completions(shell: Shell) -> String {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Args::command(), "gix", &mut script);
+    let script = String::from_utf8_lossy(&script).into_owned();
+    let branches = "env GIX_COMPLETE=branches gix 2>/dev/null";
+    match shell {
+        Shell::Bash => script.replacen(
+            "--search)\n                    COMPREPLY=($(compgen -f \"${cur}\"))",
+            &format!(
+                "--search)\n                    \
+                 COMPREPLY=($(compgen -W \"$({branches})\" -- \"${{cur}}\"))"
+            ),
+            1,
+        ),
+        Shell::Zsh => script.replacen(
+            ":SEARCH:_default'",
+            &format!(":SEARCH:{{compadd -- ${{(f)\"$({branches})\"}}}}'"),
+            1,
+        ),
+        Shell::Fish => format!(
+            "{script}complete -c gix -n \"__fish_gix_needs_command\" -l search -f \
+             -a \"({branches})\"\n"
+        ),
+        _ => script,
+    }
+}
+
+fn main() {
+    if env::var_os("GIX_COMPLETE").is_some_and(|what| what == "branches") {
+        // Completing has nothing to offer outside of a repository, that isn't an error.
+        if let Ok(repo) = Repository::discover(".") {
+            for branch in query_branches(&repo, &BranchQuery::Local) {
+                println!("{}", branch.name);
             }
         }
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('j'),
-            ..
-        }) => {
-            let n_branches = state.branches.len();
-            if n_branches != 0 {
-                if state.selected_row == 0 {
-                    state.selected_row = state.branches.len() - 1;
-                } else {
-                    state.selected_row -= 1;
-                }
-                *do_render = true;
+        return;
+    }
+    let args = Args::parse();
+    let no_color = args.no_color || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let mut capabilities = Capabilities::detect(no_color);
+    match args.command {
+        Some(Command::Completions { shell }) => {
+            print!("{}", completions(shell));
+            return;
+        }
+        Some(Command::DoctorTerminal) => {
+            capabilities.probe_background();
+            capabilities.probe_kitty_keyboard();
+            for line in capabilities.report() {
+                println!("{line}");
             }
+            return;
         }
+        None => {}
+    }
 
-        // Actions
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('l'),
-            ..
-        }) => {
-            if state.branches.len() != 0 {
-                let selected_branch_name = &state.branches[state.selected_row].name;
-
-                if let Err(e) = checkout_branch(&state.repo, selected_branch_name) {
-                    state.error = Some(e.to_string());
-                }
-                *do_render = true;
+    let directory = match Path::new(&args.directory).canonicalize() {
+        Ok(directory) => directory,
+        Err(err) => {
+            eprintln!("fatal: {}: {err}", args.directory);
+            exit(EXIT_REPO_ERROR);
+        }
+    };
+    let repo = match Repository::open(directory) {
+        Ok(repo) => repo,
+        Err(err) => {
+            eprintln!("fatal: {}", err.message());
+            exit(EXIT_REPO_ERROR);
+        }
+    };
+    let path = match &args.path {
+        Some(input) => match scope_path(&repo, input) {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("fatal: {input}: {err}");
+                exit(EXIT_REPO_ERROR);
+            }
+        },
+        None => None,
+    };
+    let mut config = Config::load(&repo);
+    config.read_only |= args.read_only;
+    let view = args.view.or(config.view).unwrap_or_default();
+    if args.backend == Backend::Gitoxide && !cfg!(feature = "gitoxide") {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::InvalidValue,
+                "the gitoxide backend needs gix built with `--features gitoxide`",
+            )
+            .exit();
+    }
+    let mut state = State::new(repo, config, View::Branches);
+    state.path = path;
+    state.backend = args.backend;
+    if args.profile {
+        state.profiler = match Profiler::with_log(&state.repo.path().join("gix-profile.log")) {
+            Ok(profiler) => profiler,
+            Err(err) => {
+                eprintln!("fatal: could not open the profile log: {err}");
+                exit(EXIT_OPERATION_FAILED);
             }
+        };
+    }
+    if args.remote {
+        state.branch_query = BranchQuery::Remote;
+    } else if args.all {
+        state.branch_query = BranchQuery::LocalAndRemote;
+    }
+    if let Some(search) = &args.search {
+        state.search = Input::new(search.clone());
+    }
+    if let Some(length) = args.summary_length {
+        state.summary_length = length;
+    }
+    if let Some(length) = args.branch_name_length {
+        state.branch_name_length = length;
+    }
+    if !stdin().is_terminal() {
+        let mut input = String::new();
+        if let Err(err) = stdin().read_to_string(&mut input) {
+            eprintln!("fatal: could not read branch names from stdin: {err}");
+            exit(EXIT_OPERATION_FAILED);
         }
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('/'),
-            ..
-        }) => {
-            *do_search = true;
-            *do_render = true;
+        let (names, has_remote) = parse_allowlist(&input);
+        if has_remote && !args.remote {
+            state.branch_query = BranchQuery::LocalAndRemote;
         }
-        Event::Key(KeyEvent {
-            code: KeyCode::Char('r'),
-            ..
+        state.allowlist = Some(names);
+    }
+    if let Some(input) = state.config.filter.clone()
+        && let Err(err) = apply_filter(&mut state, &input)
+    {
+        state.error = Some(format!("gix.filter: {err}"));
+    }
+    if let Some(template) = &state.config.format {
+        match RowFormat::parse(template) {
+            Ok(format) => state.row_format = Some(format),
+            Err(err) => state.error = Some(format!("gix.format: {err}")),
+        }
+    }
+    state.set_view(view);
+    if let Some(snapshot) = Snapshot::load(state.repo.path()) {
+        state.snapshot = snapshot.clone();
+        if !snapshot.is_empty() {
+            state.restore = Some(snapshot);
+        }
+    }
+
+    let downgrades = capabilities.downgrades();
+    if !downgrades.is_empty() {
+        state.message = Some(format!(
+            "Terminal: {} (see gix doctor-terminal)",
+            downgrades.join(", ")
+        ));
+    }
+    // Asked before the terminal's input thread starts, which would otherwise hold up the answer.
+    if state.config.background.is_none() {
+        capabilities.probe_background();
+    }
+    capabilities.probe_kitty_keyboard();
+    let background = state.config.background.or(capabilities.background);
+    let mut term = match Term::new(
+        capabilities.palette(),
+        background.unwrap_or_default(),
+        args.ascii || !capabilities.unicode,
+        capabilities.hyperlinks,
+        state.config.mouse.unwrap_or(capabilities.mouse),
+        capabilities.kitty_keyboard == Some(true),
+    ) {
+        Ok(term) => term,
+        Err(err) => {
+            eprintln!("fatal: could not set up the terminal: {err}");
+            exit(EXIT_OPERATION_FAILED);
+        }
+    };
+    run(&mut term, &mut state, &args);
+    term.close();
+
+    if args.pick {
+        match state.picked {
+            Some(mut name) => {
+                // Raw bytes, so a name that isn't valid UTF-8 can still be passed on to git.
+                name.push(b'\n');
+                let _ = stdout().write_all(&name);
+            }
+            None => exit(EXIT_CANCEL),
+        }
+    }
+    if state.operation_failed {
+        exit(EXIT_OPERATION_FAILED);
+    }
+}
+
+/// Draws frames and handles events until gix is quit.
+fn run(term: &mut Term, state: &mut State, args: &Args) {
+    let mut do_run = true;
+    let mut do_render = true;
+    let mut do_search = false;
+    while do_run {
+        let size = term.size();
+        if do_render && (size.x < MIN_SIZE.x || size.y < MIN_SIZE.y) {
+            term.begin_frame();
+            let text = format!("terminal too small (need {}x{})", MIN_SIZE.x, MIN_SIZE.y);
+            term.write_text(Vec2::new(0, 0), truncate(&text, size.x as usize));
+            term.end_frame();
+            do_render = false;
+        }
+        if do_render {
+            term.set_title(&window_title(&state.repo));
+            term.begin_frame();
+            state.profiler.begin_frame();
+            render_view(term, state, args);
+            let max_y = (term.size().y as usize).saturating_sub(PADDING);
+            if let Some(prompt) = &state.prompt {
+                let label = prompt.kind.label();
+                term.write_text(Vec2::from((PADDING, max_y)), &label);
+                let at = Vec2::from((PADDING + label.len() + 1, max_y));
+                prompt.input.render(term, at, true);
+                if !state.completions.is_empty() {
+                    let text = state.completions.join("  ");
+                    let width = (term.size().x as usize).saturating_sub(PADDING * 2);
+                    let span = Span::raw(truncate(&text, width)).dim();
+                    term.write_spans(Vec2::from((PADDING, max_y - 1)), &[span]);
+                }
+            } else if do_search || !state.search.is_empty() {
+                let label = if state.search_all {
+                    "/ [+summary +author]"
+                } else {
+                    "/"
+                };
+                term.write_text(Vec2::from((PADDING, max_y)), label);
+                let at = Vec2::from((PADDING + label.len() + 1, max_y));
+                state.search.render(term, at, do_search);
+            }
+
+            if args.debug {
+                render_debug_info(term, state);
+            }
+            if args.profile {
+                render_profile(term, state);
+            }
+            // Messages wait until the restore or checkout question is answered.
+            if let Some(snapshot) = &state.restore {
+                term.write_text(
+                    Vec2::from((PADDING, max_y)),
+                    format!(
+                        "Restore {} from the last session? (y/n)",
+                        snapshot.describe()
+                    ),
+                );
+            } else if let Some(name) = &state.dirty_checkout {
+                term.write_text(
+                    Vec2::from((PADDING, max_y)),
+                    format!(
+                        "Uncommitted changes, checking out '{}': s = stash them and switch, \
+                         f = force (discards those to files the branches differ in), esc = cancel",
+                        lossy(name).0
+                    ),
+                );
+            } else if let Some(error) = state.error.take() {
+                term.write_text(Vec2::from((PADDING, max_y)), error);
+            } else if let Some(message) = state.message.take() {
+                term.set_fg_color(Color::Grey);
+                term.write_text(Vec2::from((PADDING, max_y)), message);
+                term.reset_colors();
+            }
+            if let Some(count) = state.count {
+                let count = count.to_string();
+                let x = (term.size().x as usize).saturating_sub(PADDING + count.len());
+                term.write_bold_text(Vec2::from((x, max_y)), count);
+            }
+            state.profiler.lap(Phase::Draw);
+            term.end_frame();
+            state.profiler.end_frame();
+            do_render = false;
+        }
+        let event = term.next_event();
+        if matches!(event, LoopEvent::Tick) && state.loading_branches.is_some() {
+            state.spinner += 1;
+            do_render |= state.view == View::Branches;
+        }
+        do_render |= state.receive_branches();
+        do_render |= state.receive_pull_request();
+        if let LoopEvent::Input(event) = event {
+            if is_suspend(&event) {
+                if let Err(e) = term.suspend() {
+                    state.error = Some(format!("Couldn't suspend: {e}"));
+                }
+                do_render = true;
+            } else if state.restore.is_some() {
+                if let Event::Key(key_event) = event
+                    && key_event.kind == KeyEventKind::Press
+                {
+                    if key_event.code == KeyCode::Char('y') {
+                        do_search = state.restore_snapshot();
+                    } else {
+                        state.restore = None;
+                    }
+                    do_render = true;
+                }
+            } else if state.dirty_checkout.is_some() {
+                if let Event::Key(key_event) = event
+                    && key_event.kind == KeyEventKind::Press
+                    && let Some(name) = state.dirty_checkout.take()
+                {
+                    answer_dirty_checkout(term, state, &name, key_event.code);
+                    do_render = true;
+                }
+            } else if do_search {
+                if paste_into(&mut state.search, &event, &mut state.error) {
+                    state.filter_branches();
+                    do_render = true;
+                } else if let Event::Key(key_event) = event
+                    && key_event.kind == KeyEventKind::Press
+                {
+                    match key_event.code {
+                        KeyCode::Esc => {
+                            state.search.clear();
+                            do_search = false;
+                        }
+                        KeyCode::Enter => {
+                            do_search = false;
+                        }
+                        KeyCode::Tab => state.search_all = !state.search_all,
+                        _ => {
+                            state.search.handle_key(key_event);
+                        }
+                    }
+                    state.filter_branches();
+                    do_render = true;
+                }
+            } else if state.prompt.is_some() {
+                handle_prompt_event(event, term, state, &mut do_render);
+            } else {
+                handle_event(
+                    event,
+                    term,
+                    state,
+                    args,
+                    &mut do_run,
+                    &mut do_render,
+                    &mut do_search,
+                );
+            }
+            state.save_snapshot(do_search);
+        }
+    }
+}
+
+/// `gix: <repository> (<branch>)`, telling terminal tabs with gix open apart.
+fn window_title(repo: &Repository) -> String {
+    let dir = repo.workdir().unwrap_or(repo.path());
+    let name = dir
+        .file_name()
+        .map_or_else(|| dir.display().to_string(), |n| n.to_string_lossy().into());
+    let head = match repo.head() {
+        Ok(head) if head.is_branch() => lossy(head.shorthand_bytes()).0,
+        Ok(head) => head
+            .target()
+            .map_or_else(String::new, |oid| oid.to_string()[..7].to_string()),
+        // Unborn branch of a new repository.
+        Err(_) => head_ref(repo)
+            .map(|name| lossy(name.strip_prefix(b"refs/heads/").unwrap_or(&name)).0)
+            .unwrap_or_default(),
+    };
+    format!("gix: {name} ({head})")
+}
+
+/// Ctrl+z, which raw mode delivers as a key instead of stopping gix.
+fn is_suspend(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('z'),
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) if modifiers.contains(KeyModifiers::CONTROL)
+    )
+}
+
+/// Resolves `--path` to a path relative to the top of the working tree. Existing paths are taken
+/// relative to the current directory, others as relative to the repository root. `None` when
+/// it names the whole working tree.
+fn scope_path(repo: &Repository, input: &str) -> Result<Option<PathBuf>, String> {
+    let Some(workdir) = repo.workdir().and_then(|w| w.canonicalize().ok()) else {
+        return Ok(Some(PathBuf::from(input)));
+    };
+    let path = match Path::new(input).canonicalize() {
+        Ok(path) => path
+            .strip_prefix(&workdir)
+            .map(Path::to_path_buf)
+            .map_err(|_| "outside the repository".to_string())?,
+        Err(_) => PathBuf::from(input.trim_start_matches("./").trim_end_matches('/')),
+    };
+    Ok((!path.as_os_str().is_empty()).then_some(path))
+}
+
+fn render_debug_info(term: &mut Term, state: &mut State) {
+    state.renders += 1;
+    let lines = [
+        format!("Renders:    {}", state.renders),
+        format!("Size:       {}", term.size()),
+        format!("Sum len:    {}", state.summary_length),
+        format!("Branch len: {}", state.branch_name_length),
+    ];
+    let width = lines.iter().map(|l| display_width(l)).max().unwrap_or(0);
+    let panel = BoxWidget::new().title("Debug");
+    let size = panel.size_for(Vec2::new(width as u16, lines.len() as u16));
+    let term_size = term.size();
+    let at = Vec2::new(
+        term_size.x.saturating_sub(size.x + PADDING as u16),
+        term_size.y.saturating_sub(size.y + PADDING as u16),
+    );
+    panel.render_lines(term, at, &lines);
+}
+
+/// Timings of the previous frame, since the current one is still being drawn.
+fn render_profile(term: &mut Term, state: &State) {
+    let lines = state.profiler.summary();
+    let width = lines.iter().map(|l| display_width(l)).max().unwrap_or(0);
+    let panel = BoxWidget::new().title("Profile");
+    let size = panel.size_for(Vec2::new(width as u16, lines.len() as u16));
+    let x = term.size().x.saturating_sub(size.x + PADDING as u16);
+    panel.render_lines(term, Vec2::new(x, 1), &lines);
+}
+
+fn render_view(term: &mut Term, state: &mut State, args: &Args) {
+    match state.view {
+        View::Branches => render_branches(term, state, args),
+        View::Log => render_log(term, state),
+        View::Status => {
+            let rows = state
+                .statuses
+                .iter()
+                .map(|s| {
+                    let mut row = format!("{} {}", s.code(), s.path);
+                    if s.raw_path.is_some() {
+                        row.push_str(&format!(" {INVALID_UTF8}"));
+                    }
+                    if s.assume_unchanged {
+                        row.push_str(" [assume-unchanged]");
+                    }
+                    if s.skip_worktree {
+                        row.push_str(" [skip-worktree]");
+                    }
+                    row
+                })
+                .collect();
+            render_rows(term, state, rows, "> Working tree clean");
+        }
+        View::Files => {
+            let rows = state.files.clone();
+            render_rows(term, state, rows, "> No files at HEAD");
+        }
+        View::Stashes => {
+            let rows = state
+                .stashes
+                .iter()
+                .map(|s| {
+                    let oid: String = s.oid.to_string().chars().take(7).collect();
+                    format!("stash@{{{}}} {oid} {}", s.index, s.message)
+                })
+                .collect();
+            render_rows(term, state, rows, "> No stashes found");
+        }
+        View::Remotes => {
+            let rows = state
+                .remotes
+                .iter()
+                .map(|r| format!("{} {}", r.name, r.url))
+                .collect();
+            render_rows(term, state, rows, "> No remotes found");
+        }
+        View::Contributors => {
+            let rows = state
+                .contributors
+                .iter()
+                .map(|c| {
+                    format!(
+                        "{:>6}  {}  {} <{}>",
+                        c.commits,
+                        format_day(c.last_activity),
+                        c.name,
+                        c.email
+                    )
+                })
+                .collect();
+            render_rows(term, state, rows, "> No contributors found");
+        }
+        View::Worktrees => {
+            let rows = state
+                .worktrees
+                .iter()
+                .map(|w| {
+                    let mut row = format!(
+                        "{} {}  {}  {}",
+                        if w.is_current { "*" } else { " " },
+                        w.name,
+                        w.branch.as_deref().unwrap_or("(detached)"),
+                        w.path.display()
+                    );
+                    if w.is_locked {
+                        row.push_str(" [locked]");
+                    }
+                    if w.is_missing {
+                        row.push_str(" [missing]");
+                    }
+                    row
+                })
+                .collect();
+            render_rows(term, state, rows, "> No worktrees found");
+        }
+        View::Ignore => {
+            let rows = state
+                .ignore_rules
+                .iter()
+                .map(|rule| {
+                    let mut row = format!(
+                        "{}:{}  {}  {} ignored",
+                        rule.file.name(),
+                        rule.line,
+                        rule.pattern,
+                        rule.matches.len()
+                    );
+                    if !rule.matches.is_empty() {
+                        let shown: Vec<&str> =
+                            rule.matches.iter().take(3).map(|m| m.as_str()).collect();
+                        row.push_str(&format!(": {}", shown.join(", ")));
+                        if rule.matches.len() > shown.len() {
+                            row.push_str(", ...");
+                        }
+                    }
+                    if let Some(problem) = &rule.problem {
+                        row.push_str(&format!(" [{problem}]"));
+                    }
+                    row
+                })
+                .collect();
+            render_rows(term, state, rows, "> No ignore patterns found");
+        }
+        View::Audit => {
+            let short = |oid: Oid| {
+                if oid.is_zero() {
+                    "-".to_string()
+                } else {
+                    oid.to_string()[..7].to_string()
+                }
+            };
+            let rows = state
+                .audit_log
+                .iter()
+                .map(|entry| {
+                    let mut row = format!(
+                        "{}  {}  {}",
+                        format_time(git2::Time::new(entry.time, 0)),
+                        entry.operation,
+                        entry.message
+                    );
+                    if entry.failed {
+                        row.push_str(" [failed]");
+                    }
+                    match entry.changes.as_slice() {
+                        [] => {}
+                        [change] => row.push_str(&format!(
+                            " [{} {}..{}]",
+                            change.refname,
+                            short(change.old),
+                            short(change.new)
+                        )),
+                        changes => row.push_str(&format!(" [{} refs moved]", changes.len())),
+                    }
+                    row
+                })
+                .collect();
+            render_rows(term, state, rows, "> Nothing done through gix yet");
+        }
+        View::Hook => render_hook(term, state),
+        View::Commit => render_commit(term, state),
+        View::Bundle => {
+            let rows = state
+                .bundle
+                .iter()
+                .flat_map(|b| &b.refs)
+                .map(|(name, checked)| format!("[{}] {name}", if *checked { "x" } else { " " }))
+                .collect();
+            render_rows(term, state, rows, "> No refs to bundle");
+        }
+        View::History => {
+            let rows = state
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, r)| {
+                    let mark = if state.history_mark == Some(i) {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    let c = &r.commit;
+                    let mut row = format!("{mark} {} {}  {}", c.short_oid(), c.summary, c.author);
+                    if state.history_path.as_ref() != Some(&r.path) {
+                        row.push_str(&format!(" ({})", r.path));
+                    }
+                    row
+                })
+                .collect();
+            render_rows(term, state, rows, "> No commits changed this file");
+        }
+        View::Blame => render_blame(term, state),
+        View::Candidates => {
+            let rows = state
+                .candidates
+                .iter()
+                .map(|c| {
+                    format!(
+                        "{} {}  {}  {}",
+                        c.short_oid(),
+                        c.summary,
+                        c.author,
+                        format_day(c.time)
+                    )
+                })
+                .collect();
+            render_rows(term, state, rows, "> No commits with this id");
+        }
+        View::Rebase => {
+            let rows = state
+                .rebase
+                .iter()
+                .flat_map(|todo| &todo.items)
+                .map(|item| {
+                    let summary = match (item.verb, &item.reword) {
+                        (Verb::Reword, Some(summary)) => summary,
+                        _ => &item.summary,
+                    };
+                    format!(
+                        "{:<6} {} {summary}",
+                        item.verb.name(),
+                        &item.oid.to_string()[..7]
+                    )
+                })
+                .collect();
+            render_rows(term, state, rows, "> Nothing to rebase");
+        }
+    }
+    render_view_tabs(term, state);
+}
+
+fn render_view_tabs(term: &mut Term, state: &State) {
+    let mut x = PADDING;
+    let mut views = View::value_variants().to_vec();
+    if matches!(
+        state.view,
+        View::Hook
+            | View::Bundle
+            | View::History
+            | View::Blame
+            | View::Candidates
+            | View::Rebase
+            | View::Commit
+    ) {
+        views.push(state.view);
+    }
+    for view in &views {
+        let name = view.name();
+        if *view == state.view {
+            term.set_selected();
+            term.write_text(Vec2::from((x, 0)), name);
+            term.reset_attributes();
+        } else {
+            term.set_fg_color(Color::Grey);
+            term.set_attribute(Attribute::Dim);
+            term.write_text(Vec2::from((x, 0)), name);
+            term.reset_attributes();
+            term.reset_colors();
+        }
+        x += name.len() + 2;
+    }
+    let mut filters = Vec::new();
+    if state.view == View::Branches
+        && state.loading_branches.is_some()
+        && !state.branches.is_empty()
+    {
+        filters.push(format!("{} loading", state.spinner_frame()));
+    }
+    if let Some(path) = &state.path {
+        filters.push(format!("path {}", path.display()));
+    }
+    if let Some((input, _)) = &state.as_of {
+        filters.push(format!("as of {input}"));
+    }
+    if let Some(view) = state
+        .saved_view
+        .and_then(|i| state.config.saved_views.get(i))
+    {
+        filters.push(format!("view {}", view.name));
+    }
+    if let Some(email) = &state.author_filter {
+        filters.push(format!("author {email}"));
+    }
+    if state.mine_only {
+        filters.push("mine".to_string());
+    }
+    match state.merged_filter {
+        Some(true) => filters.push("merged into HEAD".to_string()),
+        Some(false) => filters.push("not merged into HEAD".to_string()),
+        None => {}
+    }
+    if let Some(remote) = &state.remote_filter {
+        filters.push(format!("remote {remote}"));
+    }
+    if let Some((input, _)) = &state.contains_filter {
+        filters.push(format!("contains {input}"));
+    }
+    if state.view == View::Branches
+        && let Some(filter) = &state.filter
+    {
+        filters.push(format!("filter {}", filter.source));
+    }
+    if state.view == View::Contributors
+        && let Some(branch) = &state.contributors_branch
+    {
+        filters.push(format!("on {branch}"));
+    }
+    if !filters.is_empty() {
+        term.set_fg_color(Color::Yellow);
+        term.write_text(Vec2::from((x, 0)), filters.join("  "));
+        term.reset_colors();
+    }
+}
+
+/// Renders plain text rows for views without any per-row styling.
+fn render_rows(term: &mut Term, state: &mut State, rows: Vec<String>, empty_msg: &str) {
+    state.profiler.lap(Phase::Layout);
+    let (at, size) = list_area(term.size(), 0);
+    ListWidget::new(empty_msg)
+        .numbered(state.row_numbers)
+        .render(term, at, size, &state.list, &mut state.list_viewport, |i| {
+            Line::from(rows[i].clone())
+        });
+}
+
+/// Where lists are drawn, between the view tabs and the prompt line, leaving `footer` lines
+/// for hints.
+fn list_area(term_size: Vec2, footer: u16) -> (Vec2, Vec2) {
+    let padding = PADDING as u16;
+    let size = Vec2::new(
+        term_size.x.saturating_sub(padding * 2),
+        term_size.y.saturating_sub(padding * 2 + footer).max(1),
+    );
+    (Vec2::new(padding, padding), size)
+}
+
+/// Renders the log scrolled so the selected commit stays visible, with a minimap of commit
+/// density over time in the rightmost column. The newest commits are at the top of both, or the
+/// oldest ones with `gix.bottomUp`.
+fn render_log(term: &mut Term, state: &mut State) {
+    state.profiler.lap(Phase::Layout);
+    let n_commits = state.commits.len();
+    let term_size = term.size();
+    let (at, size) = list_area(term_size, 0);
+    let commits = &state.commits;
+    let forge = state.forge.as_ref();
+    ListWidget::new("> No commits found")
+        .numbered(state.row_numbers)
+        .render(term, at, size, &state.list, &mut state.log_viewport, |i| {
+            let c = &commits[i];
+            let mut oid = Span::raw(c.short_oid());
+            if let Some(forge) = forge {
+                oid = oid.link(forge.commit_url(&c.oid.to_string()));
+            }
+            Line::from(vec![
+                oid,
+                Span::raw(format!(" {}  {}", c.summary, c.author)),
+            ])
+        });
+    if n_commits == 0 {
+        return;
+    }
+    let height = size.y as usize;
+    let visible = state.log_viewport.visible(height, n_commits);
+    let bottom_up = state.config.bottom_up;
+    if visible.end < n_commits {
+        let msg = format!("... {} older", n_commits - visible.end);
+        // In the padding on the side the older commits are on, below the prompt line when
+        // that's at the bottom.
+        let y = if bottom_up {
+            PADDING - 1
+        } else {
+            (term_size.y as usize).saturating_sub(PADDING - 1)
+        };
+        term.write_spans(Vec2::from((PADDING + 2, y)), &[Span::raw(msg).dim()]);
+    }
+
+    let shades = term.glyphs().shades;
+    let density = Density::new(commits, height);
+    let max = density.counts.iter().copied().max().unwrap_or(0).max(1);
+    let shown: Vec<usize> = visible.map(|i| density.slice_of(commits[i].time)).collect();
+    let shown_slices =
+        shown.iter().min().copied().unwrap_or(0)..=shown.iter().max().copied().unwrap_or(0);
+    let x = term_size.x as usize - 1 - PADDING;
+    let last = density.counts.len() - 1;
+    for (slice, count) in density.counts.iter().enumerate() {
+        let shade = match count {
+            0 => shades[0],
+            n => shades[1 + (n - 1) * (shades.len() - 2) / max],
+        };
+        let row = if bottom_up { slice } else { last - slice };
+        let at = Vec2::from((x, PADDING + row));
+        if shown_slices.contains(&slice) {
+            term.set_selected();
+            term.write_spans(at, &[Span::raw(shade).fg(Color::DarkGreen)]);
+            term.reset_attributes();
+        } else {
+            term.write_spans(at, &[Span::raw(shade).dim()]);
+        }
+    }
+}
+
+/// Renders the blame top-down, scrolled so the selected line stays visible.
+fn render_blame(term: &mut Term, state: &mut State) {
+    let Some(blame) = &state.blame else {
+        return;
+    };
+    state.profiler.lap(Phase::Layout);
+
+    let term_size = term.size();
+    let cursor = state.blame_cursor;
+    let range = state
+        .blame_mark
+        .map(|mark| mark.min(cursor)..=mark.max(cursor));
+    let last_line = blame.first_line + blame.lines.len();
+    let number_width = last_line.to_string().len();
+
+    let (at, size) = list_area(term_size, 1);
+    state
+        .text_viewport
+        .render(term, at, size, blame.lines.len(), Some(cursor), |i| {
+            let line = &blame.lines[i];
+            let marker = if range.as_ref().is_some_and(|r| r.contains(&i)) {
+                "|"
+            } else {
+                " "
+            };
+            Line::from(format!(
+                "{marker} {} {} {:>number_width$} {}",
+                line.short_oid(),
+                pad(&truncate(&line.author, 12), 12),
+                blame.first_line + i,
+                line.content,
+            ))
+        });
+
+    let max_y = (term_size.y as usize).saturating_sub(PADDING);
+    term.write_spans(
+        Vec2::from((PADDING, max_y)),
+        &[Span::raw(format!(
+            "{} @ {}  v: mark  l: limit  p: parent  h: history  enter: commit",
+            blame.path.display(),
+            blame.at.to_string().chars().take(7).collect::<String>()
+        ))
+        .dim()],
+    );
+}
+
+/// Renders the commit view top-down, the header dimmed.
+fn render_commit(term: &mut Term, state: &mut State) {
+    let lines = &state.commit_lines;
+    let term_size = term.size();
+    let (at, size) = list_area(term_size, 1);
+    state
+        .text_viewport
+        .render(term, at, size, lines.len(), None, |i| {
+            let span = Span::raw(lines[i].clone());
+            match i {
+                0 => Line::from(vec![span.fg(Color::DarkYellow)]),
+                1 | 2 => Line::from(vec![span.dim()]),
+                _ => Line::from(vec![span]),
+            }
+        });
+
+    let max_y = (term_size.y as usize).saturating_sub(PADDING);
+    term.write_spans(
+        Vec2::from((PADDING, max_y)),
+        &[Span::raw("j/k: scroll  esc: back to the blame").dim()],
+    );
+}
+
+/// Renders the captured hook output top-down, the first line colored by the outcome.
+fn render_hook(term: &mut Term, state: &mut State) {
+    let Some(hook) = &state.hook else {
+        return;
+    };
+
+    let lines = hook.lines();
+    let term_size = term.size();
+    let outcome = if hook.succeeded() {
+        Color::DarkGreen
+    } else {
+        Color::Red
+    };
+    let (at, size) = list_area(term_size, 1);
+    state
+        .text_viewport
+        .render(term, at, size, lines.len(), None, |i| {
+            let span = Span::raw(lines[i].clone());
+            if i == 0 {
+                Line::from(vec![span.fg(outcome).attribute(Attribute::Bold)])
+            } else {
+                Line::from(vec![span])
+            }
+        });
+
+    let max_y = (term_size.y as usize).saturating_sub(PADDING);
+    term.write_spans(
+        Vec2::from((PADDING, max_y)),
+        &[Span::raw("r: retry  s: skip  j/k: scroll").dim()],
+    );
+}
+
+fn render_branches(term: &mut Term, state: &mut State, args: &Args) {
+    // Label chips go right after the name, in the name column.
+    let chips = |branch: &BranchItem| -> Vec<Span> {
+        state
+            .labels
+            .get(&branch.refname())
+            .iter()
+            .flat_map(|label| {
+                [
+                    Span::raw(" "),
+                    Span::raw(format!(" {label} "))
+                        .fg(Color::Black)
+                        .bg(label_color(label)),
+                ]
+            })
+            .collect()
+    };
+    let chips_width = |chips: &[Span]| chips.iter().map(Span::width).sum::<usize>();
+    let longest_name = {
+        let mut n = 0;
+        for branch in state.branches.iter() {
+            let challenge =
+                display_width(&branch.name) + branch.stack_depth * 2 + chips_width(&chips(branch));
+            if challenge >= state.branch_name_length {
+                n = state.branch_name_length + 3;
+                break;
+            }
+            if challenge > n {
+                n = challenge;
+            }
+        }
+        n
+    };
+    let longest_summary = state.summary_length + 6;
+    state.profiler.lap(Phase::Layout);
+
+    let now = now();
+    // Ages while time-travelling are as of then.
+    let age_now = state.as_of.as_ref().map_or(now, |(_, time)| *time);
+    let empty = if state.loading_branches.is_some() {
+        format!("> Loading branches {}", state.spinner_frame())
+    } else {
+        "> No branches found".to_string()
+    };
+    let (at, size) = list_area(term.size(), 0);
+    render_sort_header(term, state.sort, at - Vec2::new(0, 1));
+    let branches = &state.branches;
+    let glyphs = term.glyphs();
+    let (bars, up, down) = (glyphs.bars, glyphs.up, glyphs.down);
+    let wrapping = state.wrap_summaries && state.row_format.is_none();
+    let heights: Vec<usize> = if wrapping {
+        // Only lines around the view and the selection, which it may scroll to, load their
+        // details to be measured. The others count as one row until something loaded them.
+        let height = size.y as usize;
+        let near = |line: usize, i: usize| i + height >= line && i < line + height;
+        let (first, selected) = (state.list_viewport.first_line(), state.list.index());
+        state
+            .branches
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                let details = if near(first, i) || near(selected, i) {
+                    Some(b.details(&state.repo))
+                } else {
+                    b.loaded_details()
+                };
+                details.map_or(1, |d| wrap_summary(&d.summary, state.summary_length).len())
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    state.list_viewport.set_heights(&heights);
+    let layout = Layout::new(
+        state
+            .config
+            .columns
+            .as_deref()
+            .unwrap_or(&Column::DEFAULT)
+            .iter()
+            .copied()
+            .filter(|column| *column != Column::Activity || state.show_activity),
+        |column| match column {
+            Column::Oid => 7,
+            Column::Name => longest_name,
+            Column::Age => 4,
+            // Room for two-digit counts, larger ones push the rest of the row over.
+            Column::AheadBehind if state.branches.iter().any(|b| b.upstream.is_some()) => 7,
+            Column::AheadBehind => 0,
+            Column::Activity => args.activity_weeks,
+            Column::Summary => longest_summary,
+        },
+    );
+    let branch_line = |i: usize| {
+        let branch = &branches[i];
+
+        let indent = branch.stack_depth * 2;
+        // Characters of the name that are shown, before any "...".
+        let (branch_name, shown) = {
+            let name = format!("{}{}", " ".repeat(indent), branch.name);
+            let s = truncate(&name, state.branch_name_length);
+            let shown = s.chars().count();
+            if s.len() < name.len() {
+                (format!("{s}..."), shown)
+            } else {
+                (s, shown)
+            }
+        };
+        let matched: Vec<usize> = Query::parse(state.search.text())
+            .positions(state.config.search, &branch.name)
+            .into_iter()
+            .map(|i| i + indent)
+            .filter(|i| *i < shown)
+            .collect();
+
+        let mut summary_lines = if wrapping {
+            wrap_summary(&branch.details(&state.repo).summary, state.summary_length)
+        } else {
+            Vec::new()
+        }
+        .into_iter();
+        let branch_summary = if let Some(first) = summary_lines.next() {
+            first
+        } else {
+            let full_summary = &branch.details(&state.repo).summary;
+            let summary = truncate(full_summary, state.summary_length);
+            if summary.len() < full_summary.len() {
+                format!("'{summary}...'")
+            } else {
+                format!("'{summary}'")
+            }
+        };
+        let mut oid = Span::raw(branch.short_oid());
+        let mut name: Vec<Span> = match_runs(&branch_name, &matched)
+            .into_iter()
+            .map(|(text, matched)| {
+                let span = Span::raw(text);
+                if matched {
+                    span.fg(Color::Yellow).attribute(Attribute::Bold)
+                } else {
+                    span
+                }
+            })
+            .collect();
+        if let Some(forge) = &state.forge {
+            oid = oid.link(forge.commit_url(&branch.oid));
+            if let Some(url) = forge.branch_url(branch) {
+                for span in &mut name {
+                    span.link = Some(url.clone());
+                }
+            }
+        }
+        let mut name = Some(name);
+        let mut oid = Some(oid);
+        let mut main = match &state.row_format {
+            Some(format) => vec![Span::raw(format.render(branch, &state.repo, age_now))],
+            None => layout.row(|column| match column {
+                Column::Oid => oid.take().into_iter().collect(),
+                Column::Name => {
+                    let mut spans = name.take().unwrap_or_default();
+                    spans.extend(chips(branch));
+                    spans
+                }
+                // With a path, how long ago the branch last changed it.
+                Column::Age => vec![
+                    match branch.last_change(&state.repo, state.path.as_deref()) {
+                        Some(time) => age_span(time, age_now),
+                        None => Span::raw("   -").dim(),
+                    },
+                ],
+                Column::AheadBehind => branch
+                    .ahead_behind(&state.repo)
+                    .map(|(ahead, behind)| divergence_span(ahead, behind, up, down))
+                    .into_iter()
+                    .collect(),
+                Column::Activity => {
+                    let counts = branch.activity(
+                        &state.repo,
+                        state.path.as_deref(),
+                        args.activity_weeks,
+                        now,
+                    );
+                    vec![Span::raw(sparkline(counts, &bars))]
+                }
+                Column::Summary => vec![Span::raw(branch_summary.clone())],
+            }),
+        };
+        for span in &mut main {
+            // Matched characters keep their highlight and label chips their colors.
+            if span.bg.is_some() {
+                continue;
+            }
+            if branch.is_head && span.fg.is_none() {
+                span.fg = Some(Color::DarkGreen);
+            }
+            if branch.is_gone {
+                span.attributes.set(Attribute::CrossedOut);
+            }
+        }
+        if !state.marked.is_empty() {
+            let marker = if state.marked.contains(&branch.refname()) {
+                Span::raw("* ").attribute(Attribute::Bold)
+            } else {
+                Span::raw("  ")
+            };
+            main.insert(0, marker);
+        }
+
+        let mut line = Line::from(main);
+        if let Some(offset) = layout.offset(Column::Summary) {
+            let indent = offset + if state.marked.is_empty() { 0 } else { 2 };
+            line.continued = summary_lines
+                .map(|text| vec![Span::raw(" ".repeat(indent)), Span::raw(text)])
+                .collect();
+        }
+        // The template says all there is to say.
+        if state.row_format.is_some() {
+            return line;
+        }
+        if branch.raw_name.is_some() {
+            line.push(Span::raw(format!(" {INVALID_UTF8}")).dim());
+        }
+        match &branch.upstream {
+            Some(upstream) if state.show_upstream => {
+                line.push(Span::raw(format!(" {upstream}")).fg(Color::DarkCyan));
+            }
+            Some(_) => {}
+            None => line.push(Span::raw(" [no upstream]").dim()),
+        }
+        if branch.is_gone {
+            line.push(Span::raw(" [gone]").dim());
+        }
+        if branch.is_estimated {
+            line.push(Span::raw(" [estimated, no reflog]").dim());
+        }
+        if i == 0 && state.pin_head && branch.is_head {
+            line.push(Span::raw(" [pinned]").dim());
+        }
+        if let Some(parent) = state.stacks.as_ref().and_then(|s| s.get(&branch.name))
+            && parent.needs_restack()
+        {
+            line.push(Span::raw(format!(" [{} moved, needs restack]", parent.name)).dim());
+        }
+        line
+    };
+    ListWidget::new(empty).numbered(state.row_numbers).render(
+        term,
+        at,
+        size,
+        &state.list,
+        &mut state.list_viewport,
+        branch_line,
+    );
+}
+
+/// Splits `text` into runs of the characters at `positions` and of the ones in between, each
+/// with whether it is matched, to highlight what the search matched.
+fn match_runs(text: &str, positions: &[usize]) -> Vec<(String, bool)> {
+    let mut runs: Vec<(String, bool)> = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let matched = positions.contains(&i);
+        match runs.last_mut() {
+            Some((run, last)) if *last == matched => run.push(c),
+            _ => runs.push((c.to_string(), matched)),
+        }
+    }
+    runs
+}
+
+/// Age of a branch tip for its column, fading the longer the branch has been left alone.
+fn age_span(time: i64, now: i64) -> Span {
+    let span = Span::raw(format!("{:>4}", format_age(time, now)));
+    match now - time {
+        age if age < WEEK => span,
+        age if age < 90 * DAY => span.dim(),
+        _ => span.fg(Color::DarkGrey).dim(),
+    }
+}
+
+/// `summary` in quotes, broken into lines of at most `length` characters and the quotes.
+fn wrap_summary(summary: &str, length: usize) -> Vec<String> {
+    let mut lines = wrap(&format!("'{summary}'"), length + 2);
+    if lines.is_empty() {
+        lines.push("''".to_string());
+    }
+    lines
+}
+
+/// Shortest a branch name or summary can be made with `<` or `{`.
+const MIN_COLUMN_LENGTH: usize = 8;
+
+/// Keeps a length adjusted with `<` `>` `{` `}` in the repository's own config for next time.
+fn save_length(repo: &Repository, key: &str, length: usize) -> Result<(), git2::Error> {
+    repo.config()?
+        .open_level(git2::ConfigLevel::Local)?
+        .set_i64(key, length as i64)
+}
+
+/// How a branch relates to its upstream: `=` when they are the same, commits to push `↑n`,
+/// commits to pull `↓m` or both when they diverged, the more work the louder the color.
+fn divergence_span(ahead: usize, behind: usize, up: char, down: char) -> Span {
+    match (ahead, behind) {
+        (0, 0) => Span::raw("=").dim(),
+        (ahead, 0) => Span::raw(format!("{up}{ahead}")).fg(Color::Cyan),
+        (0, behind) => Span::raw(format!("{down}{behind}")).fg(Color::Yellow),
+        (ahead, behind) => Span::raw(format!("{up}{ahead} {down}{behind}")).fg(Color::Red),
+    }
+}
+
+/// The columns the branch list can be sorted by, the active one marked with an arrow pointing
+/// the way it is sorted.
+fn render_sort_header(term: &mut Term, sort: Option<(SortKey, bool)>, at: Vec2) {
+    let glyphs = term.glyphs();
+    let mut spans = vec![Span::raw("sort (o/O) ").dim()];
+    for key in SortKey::ALL {
+        spans.push(match sort {
+            Some((active, desc)) if active == key => {
+                let arrow = if desc { glyphs.down } else { glyphs.up };
+                Span::raw(format!("{} {arrow}  ", key.name())).attribute(Attribute::Bold)
+            }
+            _ => Span::raw(format!("{}  ", key.name())).dim(),
+        });
+    }
+    term.write_spans(at, &spans);
+}
+
+/// Background of a label's chip. The same label always gets the same color.
+fn label_color(label: &str) -> Color {
+    const COLORS: [Color; 6] = [
+        Color::Cyan,
+        Color::Magenta,
+        Color::Yellow,
+        Color::Blue,
+        Color::Green,
+        Color::Red,
+    ];
+    let hash = label.bytes().fold(0usize, |hash, b| {
+        hash.wrapping_mul(31).wrapping_add(b as usize)
+    });
+    COLORS[hash % COLORS.len()]
+}
+
+/// Page-wise moves and jumps to either end, bound the same in every list.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    PageDown,
+    PageUp,
+    HalfPageDown,
+    HalfPageUp,
+    Top,
+    Bottom,
+}
+
+impl Jump {
+    fn from_key(key: &KeyEvent) -> Option<Self> {
+        Some(match (key.code, key.modifiers) {
+            (KeyCode::PageDown, _) => Jump::PageDown,
+            (KeyCode::PageUp, _) => Jump::PageUp,
+            (KeyCode::Char('d'), KeyModifiers::CONTROL) => Jump::HalfPageDown,
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => Jump::HalfPageUp,
+            (KeyCode::Home, _) | (KeyCode::Char('g'), KeyModifiers::NONE) => Jump::Top,
+            (KeyCode::End, _) | (KeyCode::Char('G'), _) => Jump::Bottom,
+            _ => return None,
+        })
+    }
+
+    /// Rows moved down the screen in a view `height` rows high, `None` for the jumps to an end.
+    fn rows(self, height: u16) -> Option<isize> {
+        let page = height.max(1) as isize;
+        let half = (page / 2).max(1);
+        match self {
+            Jump::PageDown => Some(page),
+            Jump::PageUp => Some(-page),
+            Jump::HalfPageDown => Some(half),
+            Jump::HalfPageUp => Some(-half),
+            Jump::Top | Jump::Bottom => None,
+        }
+    }
+}
+
+fn handle_event(
+    event: Event,
+    term: &mut Term,
+    state: &mut State,
+    args: &Args,
+    do_run: &mut bool,
+    do_render: &mut bool,
+    do_search: &mut bool,
+) {
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char(digit @ '0'..='9'),
+        modifiers: KeyModifiers::NONE,
+        ..
+    }) = event
+        && (digit != '0' || state.count.is_some())
+    {
+        let count = state.count.unwrap_or(0);
+        let digit = digit.to_digit(10).unwrap_or(0) as usize;
+        state.count = Some(count.saturating_mul(10).saturating_add(digit));
+        *do_render = true;
+        return;
+    }
+    // Any other key uses up the count, esc only cancels it.
+    let count = state.count.take();
+    if count.is_some()
+        && matches!(
+            event,
+            Event::Key(KeyEvent {
+                code: KeyCode::Esc,
+                ..
+            })
+        )
+    {
+        *do_render = true;
+        return;
+    }
+    // A count before enter selects that row, as numbered with `#`.
+    if let Some(row) = count
+        && matches!(
+            event,
+            Event::Key(KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            })
+        )
+        && !matches!(
+            state.view,
+            View::Hook | View::Rebase | View::Blame | View::Commit
+        )
+    {
+        state.list.select(row - 1);
+        *do_render = true;
+        return;
+    }
+    // The hook output, the rebase todo list and the commit aren't lists to move through.
+    if !matches!(state.view, View::Hook | View::Rebase | View::Commit)
+        && let Event::Key(key) = &event
+        && let Some(jump) = Jump::from_key(key)
+    {
+        let footer = u16::from(state.view == View::Blame);
+        state.jump(jump, list_area(term.size(), footer).1.y);
+        *do_render = true;
+        return;
+    }
+    match event {
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Branches && state.filter.is_some() => {
+            state.filter = None;
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Branches && !state.marked.is_empty() => {
+            state.marked.clear();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Branches
+            && (state.merged_filter.is_some()
+                || state.mine_only
+                || state.contains_filter.is_some()
+                || state.remote_filter.is_some()) =>
+        {
+            state.merged_filter = None;
+            state.mine_only = false;
+            state.contains_filter = None;
+            state.remote_filter = None;
+            state.saved_view = None;
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Branches && state.author_filter.is_some() => {
+            state.author_filter = None;
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Blame && state.blame_from != View::History => {
+            state.blame = None;
+            state.set_view(state.blame_from);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Blame => {
+            // The history is still loaded, go back to the revision that was blamed last.
+            let at = state.blame.take().map(|b| b.at);
+            state.view = View::History;
+            state.list.set_len(state.history.len());
+            state.list.select(
+                state
+                    .history
+                    .iter()
+                    .position(|r| Some(r.commit.oid) == at)
+                    .unwrap_or(0),
+            );
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::History => {
+            state.set_view(state.history_from);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Commit => {
+            // The blame is still loaded and keeps its cursor.
+            state.commit_lines.clear();
+            state.set_view(View::Blame);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if state.view == View::Rebase => {
+            state.rebase = None;
+            state.set_view(View::Log);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) if matches!(state.view, View::Hook | View::Bundle | View::Candidates) => {
+            state.bundle = None;
+            state.candidates.clear();
+            state.candidates_for = None;
+            state.set_view(View::Branches);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('q'),
+            ..
+        })
+        | Event::Key(KeyEvent {
+            code: KeyCode::Char('c'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        })
+        | Event::Key(KeyEvent {
+            code: KeyCode::Esc, ..
+        }) => *do_run = false,
+        Event::Resize(_w, _h) => *do_render = true,
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('r'),
+            modifiers: KeyModifiers::CONTROL,
+            ..
+        }) => {
+            state.reload();
+            *do_render = true;
+        }
+
+        // Views
+        Event::Key(KeyEvent {
+            code: KeyCode::Tab, ..
+        }) => {
+            state.contributors_branch = None;
+            state.set_view(state.view.next());
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::BackTab,
+            ..
+        }) => {
+            state.contributors_branch = None;
+            state.set_view(state.view.prev());
+            *do_render = true;
+        }
+
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('S'),
+            ..
+        }) => {
+            if state.allow_mutation("sync") {
+                audited(state, "sync", sync_all);
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('#'),
+            ..
+        }) => {
+            state.row_numbers = !state.row_numbers;
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(':'),
+            ..
+        }) => {
+            state.prompt = Some(Prompt {
+                kind: PromptKind::Command,
+                input: Input::default(),
+            });
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('M'),
+            ..
+        }) => {
+            if state.allow_mutation("backup") {
+                audited(state, "backup", |state| backup(term, state));
+            }
+            *do_render = true;
+        }
+
+        _ if state.view == View::Hook => handle_hook_event(event, state, do_render),
+        _ if state.view == View::Commit => handle_commit_event(event, state, do_render),
+        _ if state.view == View::Blame => handle_blame_event(event, state, count, do_render),
+        _ if state.view == View::Rebase => handle_rebase_event(event, state, do_render),
+
+        // Movement
+        // Single steps wrap around, counted ones stop at the ends.
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(key @ ('j' | 'k')),
+            ..
+        }) => {
+            let down = key == 'j';
+            match count {
+                Some(count) if down => state.move_down(count as isize),
+                Some(count) => state.move_down(-(count as isize)),
+                None => state.step(down),
+            }
+            *do_render = true;
+        }
+
+        _ if state.view == View::Branches => {
+            handle_branch_event(event, term, state, args, do_run, do_render, do_search)
+        }
+        _ if state.view == View::Log => handle_log_event(event, term, state, do_render),
+        _ if state.view == View::Bundle => handle_bundle_event(event, state, do_render),
+        Event::Key(KeyEvent {
+            code: KeyCode::Enter,
+            ..
+        }) if state.view == View::Candidates && !state.candidates.is_empty() => {
+            let commit = state.candidates.swap_remove(state.list.index());
+            state.candidates.clear();
+            match state.candidates_for.take() {
+                Some(PromptKind::Contains) => {
+                    state.contains_filter = Some((commit.short_oid(), commit.oid));
+                    state.filter_branches();
+                }
+                _ => state.as_of = Some((commit.short_oid(), commit.time)),
+            }
+            state.set_view(View::Branches);
+            *do_render = true;
+        }
+        _ if state.view == View::Status => handle_status_event(event, term, state, do_render),
+        _ if state.view == View::History => handle_history_event(event, term, state, do_render),
+        _ if state.view == View::Files => handle_files_event(event, state, do_render),
+        Event::Key(KeyEvent {
+            code: KeyCode::Enter,
+            ..
+        }) if state.view == View::Contributors && !state.contributors.is_empty() => {
+            state.author_filter = Some(state.contributors[state.list.index()].email.clone());
+            state.set_view(View::Branches);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Enter,
+            ..
+        }) if state.view == View::Worktrees && !state.worktrees.is_empty() => {
+            let worktree = &state.worktrees[state.list.index()];
+            if args.pick {
+                state.picked = Some(path_to_bytes(&worktree.path));
+                *do_run = false;
+            } else {
+                switch_worktree(state);
+            }
+            *do_render = true;
+        }
+        _ => {}
+    }
+}
+
+/// Continues in the selected worktree as if gix had been started there.
+fn switch_worktree(state: &mut State) {
+    let worktree = &state.worktrees[state.list.index()];
+    if worktree.is_current {
+        state.message = Some(format!("Already in worktree '{}'", worktree.name));
+        return;
+    }
+    let name = worktree.name.clone();
+    match Repository::open(&worktree.path) {
+        Ok(repo) => {
+            state.repo = repo;
+            state.stacks = None;
+            state.hook = None;
+            state.set_view(View::Branches);
+            state.message = Some(format!("Switched to worktree '{name}'"));
+        }
+        Err(e) => state.error = Some(format!("Couldn't open worktree '{name}': {}", e.message())),
+    }
+}
+
+/// Runs `operation`, which reports how it went with `finish_operation`, and appends it to the
+/// audit log with the local branches it moved. Nothing is logged if it never got to finish.
+fn audited(state: &mut State, operation: &str, run: impl FnOnce(&mut State)) {
+    let before = RefTips::capture(&state.repo);
+    state.outcome = None;
+    run(state);
+    let Some(outcome) = state.outcome.take() else {
+        return;
+    };
+    let changes = before.changes(&RefTips::capture(&state.repo));
+    let entry = AuditEntry::new(operation, &outcome, changes);
+    if let Err(e) = audit::append(state.repo.commondir(), &entry) {
+        state.error = Some(format!("Couldn't write the audit log: {e}"));
+    }
+}
+
+/// Mirrors local branches and tags to the configured backup remote.
+fn backup(term: &mut Term, state: &mut State) {
+    let Some(name) = state.config.backup_remote.clone() else {
+        state.error = Some("No backup remote, set gix.backupRemote to a remote name".to_string());
+        return;
+    };
+    let max_y = (term.size().y as usize).saturating_sub(PADDING);
+    term.write_text(
+        Vec2::from((PADDING, max_y)),
+        format!("Backing up to {name}..."),
+    );
+    term.end_frame();
+
+    let refs = match backup_push(&state.repo, &name) {
+        Ok(refs) => refs,
+        Err(e) => {
+            state.finish_operation(Err(format!("Backup to {name} failed: {}", e.message())));
+            return;
+        }
+    };
+    let pushed = refs
+        .iter()
+        .filter(|r| r.error.is_none() && !r.deleted)
+        .count();
+    let deleted = refs
+        .iter()
+        .filter(|r| r.error.is_none() && r.deleted)
+        .count();
+    let failed: Vec<String> = refs
+        .iter()
+        .filter_map(|r| Some(format!("{} ({})", r.refname, r.error.as_ref()?)))
+        .collect();
+
+    let mut message = format!("Backup to {name}: {pushed} refs pushed, {deleted} deleted");
+    if failed.is_empty() {
+        state.finish_operation(Ok(message));
+    } else {
+        message.push_str(&format!(", failed {}", failed.join(", ")));
+        state.finish_operation(Err(message));
+    }
+}
+
+/// Actions on the marked branches, or the selected one when none are marked.
+#[derive(Clone, Copy)]
+enum Bulk {
+    /// Deletes local branches that are merged, like `git branch -d`.
+    Delete,
+    /// Pushes local branches to their upstream.
+    Push,
+    /// Fetches the remote branches tracked.
+    Fetch,
+}
+
+impl Bulk {
+    fn name(self) -> &'static str {
+        match self {
+            Bulk::Delete => "delete",
+            Bulk::Push => "push",
+            Bulk::Fetch => "fetch",
+        }
+    }
+
+    /// Verb for the progress and outcome messages.
+    fn doing(self) -> (&'static str, &'static str) {
+        match self {
+            Bulk::Delete => ("Deleting", "Deleted"),
+            Bulk::Push => ("Pushing", "Pushed"),
+            Bulk::Fetch => ("Fetching", "Fetched"),
+        }
+    }
+
+    fn run(self, repo: &Repository, branch: &BranchItem) -> Result<(), git2::Error> {
+        match self {
+            Bulk::Delete | Bulk::Push if branch.is_remote => {
+                Err(git2::Error::from_str("it is a remote branch"))
+            }
+            Bulk::Delete => delete_branch(repo, branch.name_bytes()),
+            Bulk::Push => push_branch(repo, &branch.refname()).map(|_| ()),
+            Bulk::Fetch => fetch_branch(repo, &branch.refname()).map(|_| ()),
+        }
+    }
+}
+
+/// Runs `bulk` on each of `targets`, showing which one it is at in the prompt line. Branches
+/// it succeeded on are unmarked.
+fn run_bulk(term: &mut Term, state: &mut State, bulk: Bulk, targets: Vec<BranchItem>) {
+    let (doing, did) = bulk.doing();
+    let max_y = (term.size().y as usize).saturating_sub(PADDING);
+    let width = (term.size().x as usize).saturating_sub(PADDING * 2);
+    let mut done = Vec::new();
+    let mut failed = Vec::new();
+    for (i, branch) in targets.iter().enumerate() {
+        let text = format!("{doing} {} ({}/{})...", branch.name, i + 1, targets.len());
+        term.write_text(Vec2::from((PADDING, max_y)), pad(&text, width));
+        term.end_frame();
+        match bulk.run(&state.repo, branch) {
+            Ok(()) => {
+                state.marked.remove(&branch.refname());
+                done.push(branch.name.clone());
+            }
+            Err(e) => failed.push(format!("{} ({})", branch.name, e.message())),
+        }
+    }
+
+    let mut parts = Vec::new();
+    if !done.is_empty() {
+        parts.push(format!("{did} {}", done.join(", ")));
+    }
+    if !failed.is_empty() {
+        let couldnt = if done.is_empty() {
+            "Couldn't"
+        } else {
+            "couldn't"
+        };
+        parts.push(format!("{couldnt} {} {}", bulk.name(), failed.join(", ")));
+    }
+    let message = parts.join("; ");
+    if failed.is_empty() {
+        state.finish_operation(Ok(message));
+    } else {
+        state.finish_operation(Err(message));
+    }
+    state.refresh_branches();
+}
+
+fn sync_all(state: &mut State) {
+    let failed_remotes = fetch_all(&state.repo);
+    let summary = fast_forward_branches(&state.repo);
+
+    let mut parts = Vec::new();
+    if summary.updated.is_empty() {
+        parts.push("no branches behind upstream".to_string());
+    } else {
+        parts.push(format!("fast-forwarded {}", summary.updated.join(", ")));
+    }
+    if !summary.diverged.is_empty() {
+        parts.push(format!("diverged {}", summary.diverged.join(", ")));
+    }
+    for (name, e) in &summary.failed {
+        parts.push(format!("{name} failed: {}", e.message()));
+    }
+    for (name, e) in &failed_remotes {
+        parts.push(format!("fetch {name} failed: {}", e.message()));
+    }
+
+    let message = format!("Sync: {}", parts.join("; "));
+    if summary.failed.is_empty() && failed_remotes.is_empty() {
+        state.finish_operation(Ok(message));
+    } else {
+        state.finish_operation(Err(message));
+    }
+    if state.stacks.is_some() {
+        state.stacks = Some(detect_stacks(&state.repo));
+    }
+    state.reload();
+}
+
+fn handle_prompt_event(event: Event, term: &mut Term, state: &mut State, do_render: &mut bool) {
+    if let Some(prompt) = &mut state.prompt
+        && paste_into(&mut prompt.input, &event, &mut state.error)
+    {
+        *do_render = true;
+        return;
+    }
+    let Event::Key(key_event) = event else {
+        return;
+    };
+    if key_event.kind != KeyEventKind::Press {
+        return;
+    }
+    let Some(prompt) = &mut state.prompt else {
+        return;
+    };
+    state.completions.clear();
+
+    match key_event.code {
+        KeyCode::Esc => state.prompt = None,
+        KeyCode::Enter => {
+            if let Some(prompt) = state.prompt.take() {
+                submit_prompt(term, state, prompt);
+            }
+        }
+        KeyCode::Tab if matches!(prompt.kind, PromptKind::Command) => {
+            let kind = command::completing(prompt.input.text());
+            let branches: Vec<&str> = state
+                .all_branches
+                .iter()
+                .filter(|b| kind != Some(ArgKind::LocalBranch) || !b.is_remote)
+                .map(|b| b.name.as_str())
+                .collect();
+            let views: Vec<String> = View::value_variants()
+                .iter()
+                .filter_map(|view| Some(view.to_possible_value()?.get_name().to_string()))
+                .chain(state.config.saved_views.iter().map(|v| v.name.clone()))
+                .collect();
+            let views: Vec<&str> = views.iter().map(String::as_str).collect();
+            let candidates = command::candidates(kind, &branches, &views);
+            let (text, matches) = command::complete(prompt.input.text(), &candidates);
+            prompt.input = Input::new(text);
+            if matches.len() > 1 {
+                state.completions = matches;
+            }
+        }
+        _ => {
+            prompt.input.handle_key(key_event);
+        }
+    }
+    *do_render = true;
+}
+
+/// Inserts pasted text into `input`: terminal paste events, and the system clipboard on
+/// ctrl+v for terminals that don't paste by themselves. Returns whether `event` was a paste.
+fn paste_into(input: &mut Input, event: &Event, error: &mut Option<String>) -> bool {
+    let text = match event {
+        Event::Paste(text) => text.clone(),
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('v'),
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) if modifiers.contains(KeyModifiers::CONTROL) => match read_clipboard() {
+            Ok(text) => text,
+            Err(e) => {
+                *error = Some(e);
+                return true;
+            }
+        },
+        _ => return false,
+    };
+    input.insert_str(&text);
+    true
+}
+
+fn submit_prompt(term: &mut Term, state: &mut State, prompt: Prompt) {
+    match prompt.kind {
+        PromptKind::Command => {
+            if let Err(e) = run_command(term, state, prompt.input.text()) {
+                // Keep the prompt open so the command can be fixed.
+                state.error = Some(format!(":{}: {e}", prompt.input.text().trim()));
+                state.prompt = Some(prompt);
+            }
+        }
+        PromptKind::Ignore(file) => {
+            let result = add_pattern(&state.repo, file, prompt.input.text());
+            state.finish_operation(
+                result
+                    .map(|_| format!("Added '{}' to {}", prompt.input.text(), file.name()))
+                    .map_err(|e| format!("Couldn't add '{}': {e}", prompt.input.text())),
+            );
+            state.reload();
+        }
+        PromptKind::BundleCreate => {
+            let Some(bundle) = state.bundle.take() else {
+                return;
+            };
+            let path = absolute_path(prompt.input.text());
+            let refs = bundle.checked();
+            let result = create_bundle(&state.repo, &path, &refs);
+            state.finish_operation(
+                result
+                    .map(|_| format!("Wrote {} refs to {}", refs.len(), path.display()))
+                    .map_err(|e| format!("Bundle failed: {e}")),
+            );
+            state.set_view(View::Branches);
+        }
+        PromptKind::BundleImport => {
+            let path = absolute_path(prompt.input.text());
+            match verify_bundle(&state.repo, &path) {
+                Ok(refs) => {
+                    state.bundle = Some(BundleSelection {
+                        import: Some(path),
+                        refs: refs.into_iter().map(|name| (name, true)).collect(),
+                    });
+                    state.set_view(View::Bundle);
+                }
+                Err(e) => state.finish_operation(Err(format!("Bundle verification failed: {e}"))),
+            }
+        }
+        PromptKind::Reword(..) if prompt.input.text().trim().is_empty() => {
+            state.error = Some("Empty summary, the commit keeps its message".to_string());
+        }
+        PromptKind::Reword(oid, _) if state.view == View::Rebase => {
+            let item = state
+                .rebase
+                .iter_mut()
+                .flat_map(|todo| &mut todo.items)
+                .find(|item| item.oid == oid);
+            if let Some(item) = item {
+                item.verb = Verb::Reword;
+                item.reword = Some(prompt.input.text().trim().to_string());
+            }
+        }
+        PromptKind::Reword(oid, _) => {
+            let summary = prompt.input.text().trim().to_string();
+            let operation = format!("reword {}", &oid.to_string()[..7]);
+            audited(state, &operation, |state| {
+                let message = match reworded_message(&state.repo, oid, &summary) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        return state
+                            .finish_operation(Err(format!("Reword failed: {}", e.message())));
+                    }
+                };
+                let Some(message) = run_commit_hooks(state, &message) else {
+                    return;
+                };
+                let result = reword_commit(&state.repo, oid, &message);
+                state.finish_operation(
+                    result
+                        .map(|tip| format!("Reworded, HEAD is now at {}", &tip.to_string()[..7]))
+                        .map_err(|e| format!("Reword failed: {}", e.message())),
+                );
+            });
+            state.reload();
+        }
+        PromptKind::Labels(refname) => {
+            let common_dir = state.repo.commondir().to_path_buf();
+            if let Err(e) = state.labels.set(&common_dir, &refname, prompt.input.text()) {
+                state.error = Some(format!("Couldn't save the labels: {e}"));
+            }
+            state.filter_branches();
+        }
+        PromptKind::Filter if prompt.input.text().trim().is_empty() => {
+            state.filter = None;
+            state.filter_branches();
+        }
+        PromptKind::Filter => match apply_filter(state, prompt.input.text()) {
+            Ok(()) => {}
+            Err(e) => {
+                // Keep the prompt open so the expression can be fixed.
+                state.error = Some(format!("Filter: {e}"));
+                state.prompt = Some(prompt);
+            }
+        },
+        PromptKind::Contains if prompt.input.text().trim().is_empty() => {
+            state.contains_filter = None;
+            state.filter_branches();
+        }
+        PromptKind::Contains => {
+            let input = prompt.input.text().trim().to_string();
+            match state
+                .repo
+                .revparse_single(&input)
+                .and_then(|object| object.peel_to_commit())
+                .map(|commit| commit.id())
+            {
+                Ok(commit) => {
+                    state.contains_filter = Some((input, commit));
+                    state.filter_branches();
+                }
+                Err(e) if e.code() == ErrorCode::Ambiguous => {
+                    if !offer_candidates(state, PromptKind::Contains, &input) {
+                        state.error = Some(format!("'{input}' is ambiguous"));
+                        state.prompt = Some(prompt);
+                    }
+                }
+                Err(e) => {
+                    state.error = Some(format!("Couldn't find commit '{input}': {}", e.message()));
+                    state.prompt = Some(prompt);
+                }
+            }
+        }
+        PromptKind::AsOf => match parse_time(&state.repo, prompt.input.text(), now()) {
+            Some(time) => {
+                state.as_of = Some((prompt.input.text().to_string(), time));
+                state.refresh_branches();
+            }
+            None => {
+                if !offer_candidates(state, PromptKind::AsOf, prompt.input.text()) {
+                    state.error = Some(format!(
+                        "Couldn't parse '{}' as a date or revision",
+                        prompt.input.text()
+                    ));
+                }
+            }
+        },
+    }
+}
+
+/// Lists the commits `input`, typed into the prompt `kind`, could mean to pick from when it is
+/// an ambiguous short id. Returns whether it was.
+fn offer_candidates(state: &mut State, kind: PromptKind, input: &str) -> bool {
+    let candidates = ambiguous_commits(&state.repo, input);
+    if candidates.is_empty() {
+        return false;
+    }
+    let purpose = match kind {
+        PromptKind::Contains => "list the branches containing",
+        _ => "time-travel to",
+    };
+    state.candidates = candidates;
+    state.candidates_for = Some(kind);
+    state.set_view(View::Candidates);
+    state.message = Some(format!(
+        "'{input}' is ambiguous, pick the commit to {purpose}"
+    ));
+    true
+}
+
+/// Parses `input` and filters the branches with it.
+fn apply_filter(state: &mut State, input: &str) -> Result<(), String> {
+    let filter = Filter::parse(input)?;
+    filter.validate(&Context::new(&state.repo, now()))?;
+    state.filter = Some(filter);
+    state.filter_branches();
+    Ok(())
+}
+
+/// Switches the branch list to saved view `index`, or clears what saved views set when `None`.
+fn apply_saved_view(state: &mut State, index: Option<usize>) {
+    let view = index
+        .and_then(|i| state.config.saved_views.get(i))
+        .cloned()
+        .unwrap_or_default();
+    state.saved_view = index;
+    state.search = Input::new(view.search.unwrap_or_default());
+    state.sort = view.sort;
+    state.merged_filter = view.merged;
+    state.mine_only = view.mine;
+    state.filter = None;
+    if let Some(source) = &view.filter
+        && let Err(e) = apply_filter(state, source)
+    {
+        state.error = Some(format!("gixView.{}.filter: {e}", view.name));
+    }
+    let query = view.branches.unwrap_or(BranchQuery::Local);
+    if query == state.branch_query {
+        state.filter_branches();
+    } else {
+        if query == BranchQuery::Local {
+            state.remote_filter = None;
+        }
+        state.branch_query = query;
+        state.refresh_branches();
+    }
+}
+
+/// Runs a command typed after `:`. Commands about the branch list show it.
+fn run_command(term: &mut Term, state: &mut State, input: &str) -> Result<(), String> {
+    let command = TypedCommand::parse(input)?;
+    if matches!(
+        command,
+        TypedCommand::Filter(_) | TypedCommand::Search(_) | TypedCommand::Sort(_)
+    ) && state.view != View::Branches
+    {
+        state.set_view(View::Branches);
+    }
+    match command {
+        TypedCommand::Checkout(pattern) => {
+            let local = || state.all_branches.iter().filter(|b| !b.is_remote);
+            let lowercase = pattern.to_lowercase();
+            let matching: Vec<&BranchItem> = match local().find(|b| b.name == pattern) {
+                Some(branch) => vec![branch],
+                None => local()
+                    .filter(|b| b.name.to_lowercase().contains(&lowercase))
+                    .collect(),
+            };
+            let name = match matching.as_slice() {
+                [branch] => branch.name_bytes().to_vec(),
+                [] => return Err(format!("no local branch matches '{pattern}'")),
+                _ => {
+                    let names: Vec<&str> = matching.iter().map(|b| b.name.as_str()).collect();
+                    return Err(format!("'{pattern}' matches {}", names.join(", ")));
+                }
+            };
+            if state.allow_mutation("checkout") {
+                let operation = format!("checkout {}", lossy(&name).0);
+                audited(state, &operation, |state| checkout(term, state, &name));
+            }
+        }
+        TypedCommand::Delete(names) => bulk_command(term, state, Bulk::Delete, &names)?,
+        TypedCommand::Fetch(names) => bulk_command(term, state, Bulk::Fetch, &names)?,
+        TypedCommand::Push(names) => bulk_command(term, state, Bulk::Push, &names)?,
+        TypedCommand::PullRequest(name) => {
+            let branch = match name {
+                Some(name) => state.all_branches.iter().find(|b| b.name == name),
+                None => state.branches.get(state.list.index()),
+            }
+            .ok_or("no such branch")?;
+            let (Some(forge), Some(api)) = (&state.forge, &state.api) else {
+                return Err("pull requests can only be looked up on GitHub and GitLab".to_string());
+            };
+            let path = forge
+                .pull_requests_path(branch)
+                .ok_or_else(|| format!("'{}' has no upstream on the forge", branch.name))?;
+            state.message = Some(format!(
+                "Looking up the pull request of '{}'...",
+                branch.name
+            ));
+            state.loading_pull_request = Some((branch.name.clone(), api.fetch(&path)));
+        }
+        TypedCommand::Filter(input) if input.is_empty() => {
+            state.filter = None;
+            state.filter_branches();
+        }
+        TypedCommand::Filter(input) => apply_filter(state, &input)?,
+        TypedCommand::Search(text) => {
+            state.search = Input::new(text);
+            state.filter_branches();
+        }
+        TypedCommand::Sort(sort) => {
+            state.sort = sort;
+            state.filter_branches();
+        }
+        TypedCommand::Sync => {
+            if state.allow_mutation("sync") {
+                audited(state, "sync", sync_all);
+            }
+        }
+        TypedCommand::View(name) => {
+            if let Some(i) = state.config.saved_views.iter().position(|v| v.name == name) {
+                state.set_view(View::Branches);
+                apply_saved_view(state, Some(i));
+                return Ok(());
+            }
+            let view = View::from_str(&name, true).map_err(|_| format!("no view '{name}'"))?;
+            state.contributors_branch = None;
+            state.set_view(view);
+        }
+    }
+    Ok(())
+}
+
+/// Runs `bulk` on the branches called `names`, or on the marked or selected ones when there
+/// are no names.
+fn bulk_command(
+    term: &mut Term,
+    state: &mut State,
+    bulk: Bulk,
+    names: &[String],
+) -> Result<(), String> {
+    let targets = if names.is_empty() {
+        state.targets()
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                state
+                    .all_branches
+                    .iter()
+                    .find(|b| b.name == *name)
+                    .cloned()
+                    .ok_or_else(|| format!("no branch '{name}'"))
+            })
+            .collect::<Result<_, _>>()?
+    };
+    if targets.is_empty() {
+        return Err("no branch selected".to_string());
+    }
+    if state.allow_mutation(bulk.name()) {
+        let names: Vec<&str> = targets.iter().map(|b| b.name.as_str()).collect();
+        let operation = format!("{} {}", bulk.name(), names.join(", "));
+        audited(state, &operation, |state| {
+            run_bulk(term, state, bulk, targets)
+        });
+    }
+    Ok(())
+}
+
+fn handle_commit_event(event: Event, state: &mut State, do_render: &mut bool) {
+    let Event::Key(KeyEvent { code, .. }) = event else {
+        return;
+    };
+    match code {
+        KeyCode::Char('j') => state.text_viewport.scroll_by(1),
+        KeyCode::Char('k') => state.text_viewport.scroll_by(-1),
+        _ => return,
+    }
+    *do_render = true;
+}
+
+fn handle_hook_event(event: Event, state: &mut State, do_render: &mut bool) {
+    let Event::Key(KeyEvent { code, .. }) = event else {
+        return;
+    };
+    match code {
+        KeyCode::Char('j') => state.text_viewport.scroll_by(1),
+        KeyCode::Char('k') => state.text_viewport.scroll_by(-1),
+        KeyCode::Char('s') => state.set_view(View::Branches),
+        KeyCode::Char('r') => {
+            if let Some(hook) = state.hook.take() {
+                run_hook_and_report(state, &hook.name, &hook.args);
+            }
+        }
+        _ => return,
+    }
+    *do_render = true;
+}
+
+/// Runs hook `name` if the repository has one. On failure the hook output view is opened and
+/// the operation is marked failed; otherwise the output is kept for `H`. Returns whether the
+/// hook succeeded or there is none.
+fn run_hook_and_report(state: &mut State, name: &str, args: &[String]) -> bool {
+    let Some(hook) = run_hook(&state.repo, name, args) else {
+        return true;
+    };
+    let succeeded = hook.succeeded();
+    if succeeded {
+        if state.view == View::Hook {
+            state.finish_operation(Ok(format!("{name} hook succeeded")));
+        }
+    } else {
+        state.finish_operation(Err(format!("{name} hook failed")));
+        state.set_view(View::Hook);
+    }
+    state.hook = Some(hook);
+    succeeded
+}
+
+/// Runs the pre-commit and commit-msg hooks like `git commit` does before committing with
+/// `message`, reporting them like [`run_hook_and_report`]. Returns the message as commit-msg
+/// left it, `None` when a hook failed and nothing may be committed.
+fn run_commit_hooks(state: &mut State, message: &str) -> Option<String> {
+    if !run_hook_and_report(state, "pre-commit", &[]) {
+        return None;
+    }
+    let path = state.repo.path().join("COMMIT_EDITMSG");
+    let result = fs::write(&path, message).and_then(|()| {
+        let args = [path.display().to_string()];
+        if !run_hook_and_report(state, "commit-msg", &args) {
+            return Ok(None);
+        }
+        fs::read_to_string(&path).map(Some)
+    });
+    result.unwrap_or_else(|e| {
+        state.finish_operation(Err(format!("Couldn't pass the message to commit-msg: {e}")));
+        None
+    })
+}
+
+/// Checks out the selected branch and runs the post-checkout hook like `git checkout` does.
+/// Shows how many files are checked out so far, esc cancels.
+fn checkout_selected(term: &mut Term, state: &mut State) {
+    let name = state.branches[state.list.index()].name_bytes().to_vec();
+    checkout(term, state, &name);
+}
+
+/// Checks out the local branch `name_bytes`, showing progress in the prompt line, and runs the
+/// post-checkout hook. With uncommitted changes it first asks whether to stash them or
+/// overwrite them, unless the branch points at HEAD and no file has to change, or the checkout
+/// is refused anyway.
+fn checkout(term: &mut Term, state: &mut State, name_bytes: &[u8]) {
+    let tip = find_local_branch(&state.repo, name_bytes)
+        .ok()
+        .and_then(|branch| branch.get().target());
+    let head = state.repo.head().ok().and_then(|head| head.target());
+    if tip.is_some()
+        && tip != head
+        && state
+            .worktree_heads
+            .checked_out_elsewhere(&lossy(name_bytes).0)
+            .is_none()
+        && is_dirty(&state.repo)
+    {
+        state.dirty_checkout = Some(name_bytes.to_vec());
+        return;
+    }
+    switch_to(term, state, name_bytes, false);
+}
+
+/// Carries out the checkout of `name` that waited on `key`: "s" stashes the uncommitted
+/// changes first, "f" overwrites them, anything else cancels.
+fn answer_dirty_checkout(term: &mut Term, state: &mut State, name: &[u8], key: KeyCode) {
+    let display_name = lossy(name).0;
+    match key {
+        KeyCode::Char('s') => {
+            let operation = format!("checkout {display_name} (stash)");
+            audited(state, &operation, |state| {
+                match stash_changes(&mut state.repo) {
+                    // Still on the old branch, so the changes go back where they were.
+                    Ok(_) => {
+                        if !switch_to(term, state, name, false)
+                            && let Err(e) = pop_stash(&mut state.repo)
+                        {
+                            state.finish_operation(Err(format!(
+                                "Couldn't put the changes back, they are in stash@{{0}}: {}",
+                                e.message()
+                            )));
+                        }
+                    }
+                    Err(e) => state.finish_operation(Err(format!(
+                        "Couldn't stash the changes: {}",
+                        e.message()
+                    ))),
+                }
+            });
+        }
+        KeyCode::Char('f') => {
+            let operation = format!("checkout {display_name} (force)");
+            audited(state, &operation, |state| {
+                switch_to(term, state, name, true);
+            });
+        }
+        _ => state.message = Some(format!("Checkout of '{display_name}' cancelled")),
+    }
+}
+
+/// Checks out `name_bytes` without asking, see [`checkout`]. `force` overwrites local changes.
+/// Returns whether the branch was checked out, failures and cancels are reported.
+fn switch_to(term: &mut Term, state: &mut State, name_bytes: &[u8], force: bool) -> bool {
+    let name = lossy(name_bytes).0;
+    let head_oid = |repo: &Repository| {
+        repo.head()
+            .ok()
+            .and_then(|h| h.target())
+            .map(|oid| oid.to_string())
+            .unwrap_or_else(|| git2::Oid::zero().to_string())
+    };
+
+    let previous = head_oid(&state.repo);
+    let max_y = (term.size().y as usize).saturating_sub(PADDING);
+    let width = (term.size().x as usize).saturating_sub(PADDING * 2);
+    let mut last_draw: Option<Instant> = None;
+    let mut cancelled = false;
+    let worktrees = &state.worktree_heads;
+    let result = checkout_branch(&state.repo, name_bytes, worktrees, force, |done, total| {
+        let is_esc = |event: &Event| matches!(event, Event::Key(key) if key.code == KeyCode::Esc);
+        cancelled |= std::iter::from_fn(|| term.poll_input()).any(|e| is_esc(&e));
+        if done == total || last_draw.is_none_or(|t| t.elapsed() >= TICK_INTERVAL) {
+            let text = if cancelled {
+                format!("Cancelling checkout of '{name}', putting files back...")
+            } else {
+                format!(
+                    "Checking out '{name}' {} {done}/{total} files, esc to cancel",
+                    progress_bar(done, total, 20)
+                )
+            };
+            term.write_text(Vec2::from((PADDING, max_y)), pad(&text, width));
+            term.end_frame();
+            last_draw = Some(Instant::now());
+        }
+        !cancelled
+    });
+    let checked_out = result.as_ref().is_ok_and(|&done| done);
+    state.finish_operation(
+        result
+            .map(|done| {
+                if done {
+                    format!("Switched to branch '{name}'")
+                } else {
+                    format!("Checkout of '{name}' cancelled")
+                }
+            })
+            .map_err(|e| e.to_string()),
+    );
+    if checked_out {
+        state.refresh_branches();
+        let args = [previous, head_oid(&state.repo), "1".to_string()];
+        run_hook_and_report(state, "post-checkout", &args);
+    }
+    checked_out
+}
+
+fn handle_status_event(event: Event, term: &mut Term, state: &mut State, do_render: &mut bool) {
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char(key @ ('i' | 'I')),
+        ..
+    }) = event
+        && let Some(item) = state.statuses.get(state.list.index())
+    {
+        let path = item.path.clone();
+        if !item.status.is_wt_new() {
+            state.error = Some(format!("{path} is not untracked"));
+        } else if state.allow_mutation("ignore") {
+            let file = if key == 'i' {
+                IgnoreFile::Gitignore
+            } else {
+                IgnoreFile::Exclude
+            };
+            state.prompt = Some(Prompt {
+                kind: PromptKind::Ignore(file),
+                input: Input::new(path_pattern(&path)),
+            });
+        }
+        *do_render = true;
+        return;
+    }
+
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char('h'),
+        ..
+    }) = event
+        && let Some(item) = state.statuses.get(state.list.index())
+    {
+        if item.status.is_wt_new() {
+            state.error = Some(format!("{} is untracked, it has no history", item.path));
+        } else {
+            open_history(state, item.path.clone(), View::Status);
+        }
+        *do_render = true;
+        return;
+    }
+
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char(key @ ('u' | 'w')),
+        ..
+    }) = event
+        && let Some(item) = state.statuses.get(state.list.index())
+    {
+        let path = item.path.clone();
+        let fs_path = item.fs_path();
+        let (flag, name) = if key == 'u' {
+            (IndexFlag::AssumeUnchanged, "assume-unchanged")
+        } else {
+            (IndexFlag::SkipWorktree, "skip-worktree")
+        };
+        if state.allow_mutation(name) {
+            let result = toggle_index_flag(&state.repo, &fs_path, flag);
+            state.finish_operation(
+                result
+                    .map(|set| format!("{} {name} on {path}", if set { "Set" } else { "Cleared" }))
+                    .map_err(|e| format!("Couldn't toggle {name} on {path}: {}", e.message())),
+            );
+            state.reload();
+        }
+        *do_render = true;
+        return;
+    }
+
+    let Event::Key(KeyEvent {
+        code: KeyCode::Char(key @ ('d' | 'm')),
+        ..
+    }) = event
+    else {
+        return;
+    };
+    let Some(item) = state.statuses.get(state.list.index()) else {
+        return;
+    };
+    let path = item.path.clone();
+    let fs_path = item.fs_path();
+    let status = item.status;
+
+    let result = if key == 'm' {
+        if !status.is_conflicted() {
+            state.error = Some(format!("{path} has no conflicts"));
+            *do_render = true;
+            return;
+        }
+        if !state.allow_mutation("mergetool") {
+            *do_render = true;
+            return;
+        }
+        run_external(term, || mergetool(&state.repo, &fs_path))
+    } else if status.is_wt_new() {
+        state.error = Some(format!("{path} is untracked, nothing to diff"));
+        *do_render = true;
+        return;
+    } else {
+        let cached = !(status.is_wt_modified()
+            || status.is_wt_deleted()
+            || status.is_wt_renamed()
+            || status.is_wt_typechange()
+            || status.is_conflicted());
+        run_external(term, || difftool(&state.repo, &fs_path, cached))
+    };
+
+    if let Err(error) = result {
+        state.finish_operation(Err(error));
+    }
+    state.reload();
+    *do_render = true;
+}
+
+fn handle_history_event(event: Event, term: &mut Term, state: &mut State, do_render: &mut bool) {
+    let Event::Key(KeyEvent { code, .. }) = event else {
+        return;
+    };
+    let selected = state.list.index();
+    match code {
+        KeyCode::Char(' ') if selected < state.history.len() => {
+            state.history_mark = if state.history_mark == Some(selected) {
+                None
+            } else {
+                Some(selected)
+            };
+        }
+        KeyCode::Char('d') if selected < state.history.len() => {
+            // Rows are newest first, the older revision is the one further down the list.
+            let other = state.history_mark.unwrap_or(selected + 1);
+            if other == selected {
+                state.error = Some("Select another revision to compare to".to_string());
+            } else if other >= state.history.len() {
+                state.error = Some("First revision of the file, mark one to compare".to_string());
+            } else {
+                let (from, to) = (
+                    &state.history[other.max(selected)],
+                    &state.history[other.min(selected)],
+                );
+                let from = (from.commit.oid, from.path.as_str());
+                let to = (to.commit.oid, to.path.as_str());
+                let result = run_external(term, || difftool_revisions(&state.repo, from, to));
+                if let Err(error) = result {
+                    state.finish_operation(Err(error));
+                }
+            }
+        }
+        KeyCode::Char('b') if selected < state.history.len() => {
+            let revision = &state.history[selected];
+            let (path, at) = (PathBuf::from(&revision.path), revision.commit.oid);
+            state.blame_from = View::History;
+            open_blame(state, &path, at, None, 0);
+        }
+        _ => return,
+    }
+    *do_render = true;
+}
+
+/// Switches to the history of the file at `path`, esc going back to `from`.
+fn open_history(state: &mut State, path: String, from: View) {
+    state.history_path = Some(path);
+    state.history_mark = None;
+    state.history_from = from;
+    state.set_view(View::History);
+}
+
+fn handle_files_event(event: Event, state: &mut State, do_render: &mut bool) {
+    let Event::Key(KeyEvent { code, .. }) = event else {
+        return;
+    };
+    let Some(path) = state.files.get(state.list.index()).cloned() else {
+        return;
+    };
+    match code {
+        KeyCode::Char('h') | KeyCode::Enter => open_history(state, path, View::Files),
+        KeyCode::Char('b') => {
+            let head = state.repo.head().and_then(|head| head.peel_to_commit());
+            match head.map(|head| head.id()) {
+                Ok(head) => {
+                    state.blame_from = View::Files;
+                    open_blame(state, Path::new(&path), head, None, 0);
+                }
+                Err(e) => state.error = Some(format!("Couldn't blame: {}", e.message())),
+            }
+        }
+        _ => return,
+    }
+    *do_render = true;
+}
+
+/// Switches to the blame of `path` at `at` with line `cursor` selected, or shows why it can't.
+fn open_blame(
+    state: &mut State,
+    path: &Path,
+    at: Oid,
+    range: Option<(usize, usize)>,
+    cursor: usize,
+) {
+    match blame_file(&state.repo, path, at, range) {
+        Ok(blame) => {
+            state.blame_cursor = cursor.min(blame.lines.len().saturating_sub(1));
+            state.blame_mark = None;
+            state.blame = Some(blame);
+            state.set_view(View::Blame);
+        }
+        Err(error) => state.error = Some(format!("Couldn't blame: {error}")),
+    }
+}
+
+fn handle_blame_event(event: Event, state: &mut State, count: Option<usize>, do_render: &mut bool) {
+    let Event::Key(KeyEvent { code, .. }) = event else {
+        return;
+    };
+    let Some(blame) = &state.blame else {
+        return;
+    };
+    let cursor = state.blame_cursor;
+    match code {
+        KeyCode::Char('j') => {
+            let last = blame.lines.len().saturating_sub(1);
+            state.blame_cursor = cursor.saturating_add(count.unwrap_or(1)).min(last);
+        }
+        KeyCode::Char('k') => state.blame_cursor = cursor.saturating_sub(count.unwrap_or(1)),
+        KeyCode::Char('v') => {
+            state.blame_mark = if state.blame_mark.is_some() {
+                None
+            } else {
+                Some(cursor)
+            };
+        }
+        KeyCode::Char('l') => {
+            let (path, at) = (blame.path.clone(), blame.at);
+            match state.blame_mark {
+                Some(mark) => {
+                    let first = blame.first_line + mark.min(cursor);
+                    let last = blame.first_line + mark.max(cursor);
+                    open_blame(state, &path, at, Some((first, last)), 0);
+                }
+                None if blame.limited => {
+                    let line = blame.first_line + cursor - 1;
+                    open_blame(state, &path, at, None, line);
+                }
+                None => state.error = Some("Mark a line range with v first".to_string()),
+            }
+        }
+        KeyCode::Char('p') => {
+            let Some(line) = blame.lines.get(cursor) else {
+                return;
+            };
+            let (path, cursor) = (line.orig_path.clone(), line.orig_line - 1);
+            match blame_parent(&state.repo, line.oid) {
+                Ok(parent) => open_blame(state, &path, parent, None, cursor),
+                Err(error) => state.error = Some(error),
+            }
+        }
+        KeyCode::Char('h') => {
+            let (path, at) = (blame.path.to_string_lossy().into_owned(), blame.at);
+            // The history replaces the blame, esc goes back to where that came from.
+            let from = match state.blame_from {
+                View::History => state.history_from,
+                from => from,
+            };
+            state.blame = None;
+            open_history(state, path, from);
+            if let Some(row) = state.history.iter().position(|r| r.commit.oid == at) {
+                state.list.select(row);
+            }
+        }
+        KeyCode::Enter => {
+            let Some(oid) = blame.lines.get(cursor).map(|line| line.oid) else {
+                return;
+            };
+            match show_commit(&state.repo, oid) {
+                Ok(lines) => {
+                    state.commit_lines = lines;
+                    state.set_view(View::Commit);
+                }
+                Err(e) => state.error = Some(format!("Couldn't show {oid}: {}", e.message())),
+            }
+        }
+        _ => return,
+    }
+    *do_render = true;
+}
+
+/// Hands the terminal to an external program for the duration of `f`.
+fn run_external<T>(term: &mut Term, f: impl FnOnce() -> T) -> T {
+    term.close();
+    let result = f();
+    if let Err(err) = term.resume() {
+        eprintln!("fatal: could not set up the terminal: {err}");
+        exit(EXIT_OPERATION_FAILED);
+    }
+    result
+}
+
+fn handle_bundle_event(event: Event, state: &mut State, do_render: &mut bool) {
+    let Event::Key(KeyEvent { code, .. }) = event else {
+        return;
+    };
+    let Some(bundle) = &mut state.bundle else {
+        return;
+    };
+    match code {
+        KeyCode::Char(' ') => {
+            if let Some((_, checked)) = bundle.refs.get_mut(state.list.index()) {
+                *checked = !*checked;
+            }
+        }
+        KeyCode::Enter if bundle.checked().is_empty() => {
+            state.error = Some("No refs checked".to_string());
+        }
+        KeyCode::Enter => match &bundle.import {
+            Some(path) => {
+                let path = path.clone();
+                let refs = bundle.checked();
+                let operation = format!("bundle import {}", path.display());
+                audited(state, &operation, |state| {
+                    let result = fetch_bundle(&state.repo, &path, &refs);
+                    state.finish_operation(
+                        result
+                            .map(|_| {
+                                format!(
+                                    "Fetched {} refs from {}, branches as bundle/<name>",
+                                    refs.len(),
+                                    path.display()
+                                )
+                            })
+                            .map_err(|e| format!("Bundle import failed: {e}")),
+                    );
+                });
+                state.bundle = None;
+                state.set_view(View::Branches);
+            }
+            None => {
+                let checked = bundle.checked();
+                let name = checked[0].rsplit('/').next().unwrap_or_default();
+                state.prompt = Some(Prompt {
+                    kind: PromptKind::BundleCreate,
+                    input: Input::new(format!("{name}.bundle")),
+                });
+            }
+        },
+        _ => return,
+    }
+    *do_render = true;
+}
+
+/// `input` relative to the directory gix was started in.
+fn absolute_path(input: &str) -> PathBuf {
+    env::current_dir()
+        .map(|dir| dir.join(input))
+        .unwrap_or_else(|_| PathBuf::from(input))
+}
+
+fn handle_log_event(event: Event, term: &Term, state: &mut State, do_render: &mut bool) {
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char('f'),
+        ..
+    }) = event
+        && !state.commits.is_empty()
+    {
+        if state.allow_mutation("fixup") {
+            let target = state.commits[state.list.index()].short_oid();
+            let oid = state.commits[state.list.index()].oid;
+            audited(state, &format!("fixup {target}"), |state| {
+                let message = match fixup_message(&state.repo, oid) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        return state
+                            .finish_operation(Err(format!("Fixup failed: {}", e.message())));
+                    }
+                };
+                let Some(message) = run_commit_hooks(state, &message) else {
+                    return;
+                };
+                let result = fixup_commit(&state.repo, &message);
+                state.finish_operation(
+                    result
+                        .map(|_| format!("Committed a fixup! of {target}, autosquash folds it in"))
+                        .map_err(|e| format!("Fixup failed: {}", e.message())),
+                );
+            });
+            state.reload();
+        }
+        *do_render = true;
+    }
+
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char('r'),
+        ..
+    }) = event
+        && !state.commits.is_empty()
+    {
+        if state.allow_mutation("reword") {
+            let commit = &state.commits[state.list.index()];
+            // Warned about in the label, an error would cover the prompt being typed into.
+            let remote = pushed_to(&state.repo, commit.oid);
+            state.prompt = Some(Prompt {
+                kind: PromptKind::Reword(commit.oid, remote),
+                input: Input::new(commit.summary.clone()),
+            });
+        }
+        *do_render = true;
+    }
+
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char('i'),
+        ..
+    }) = event
+        && !state.commits.is_empty()
+    {
+        if state.allow_mutation("rebase") {
+            let onto = state.commits[state.list.index()].oid;
+            match Todo::after(&state.repo, onto) {
+                Ok(mut todo) => {
+                    let auto = state
+                        .repo
+                        .config()
+                        .and_then(|cfg| cfg.get_bool("rebase.autoSquash"))
+                        .unwrap_or(false);
+                    let folds = todo.items.iter().filter(|item| item.is_fold()).count();
+                    if auto {
+                        state.message = Some(autosquash_message(&todo.autosquash()));
+                    } else if folds > 0 {
+                        state.message = Some(format!(
+                            "{folds} fixup!/squash! commits, A autosquashes them"
+                        ));
+                    }
+                    state.rebase = Some(todo);
+                    state.set_view(View::Rebase);
+                }
+                Err(e) => state.error = Some(format!("Can't rebase: {}", e.message())),
+            }
+        }
+        *do_render = true;
+    }
+
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char('x'),
+        ..
+    }) = event
+        && !state.commits.is_empty()
+    {
+        let oid = state.commits[state.list.index()].oid;
+        let result = export_commit(
+            &state.repo,
+            oid,
+            state.path.as_deref(),
+            state.config.export_theme,
+        );
+        state.finish_operation(
+            result
+                .map(|path| format!("Exported to {}", path.display()))
+                .map_err(|e| format!("Export failed: {}", e.message())),
+        );
+        *do_render = true;
+    }
+
+    if let Event::Key(KeyEvent {
+        code: KeyCode::Char(key @ ('J' | 'K')),
+        ..
+    }) = event
+        && let Some(selected) = state.commits.get(state.list.index())
+    {
+        let height = (term.size().y as usize).saturating_sub(PADDING * 2).max(1);
+        let density = Density::new(&state.commits, height);
+        let slice = density.slice_of(selected.time);
+        let in_other_slice = |c: &CommitItem| density.slice_of(c.time) != slice;
+        // Rows are newest first: older commits have higher indices, drawn below unless the list
+        // is bottom-up.
+        let older = (key == 'J') != state.config.bottom_up;
+        let target = if older {
+            state.commits[state.list.index()..]
+                .iter()
+                .position(in_other_slice)
+                .map(|i| state.list.index() + i)
+        } else {
+            state.commits[..state.list.index()]
+                .iter()
+                .rposition(in_other_slice)
+        };
+        if let Some(row) = target {
+            state.list.select(row);
+            *do_render = true;
+        }
+    }
+}
+
+fn handle_rebase_event(event: Event, state: &mut State, do_render: &mut bool) {
+    let Event::Key(KeyEvent {
+        code, modifiers, ..
+    }) = event
+    else {
+        return;
+    };
+    let Some(todo) = &mut state.rebase else {
+        return;
+    };
+    let i = state.list.index();
+    match code {
+        KeyCode::Char(key @ ('p' | 's' | 'f' | 'd')) if modifiers.is_empty() => {
+            if let Some(item) = todo.items.get_mut(i) {
+                item.verb = match key {
+                    'p' => Verb::Pick,
+                    's' => Verb::Squash,
+                    'f' => Verb::Fixup,
+                    _ => Verb::Drop,
+                };
+            }
+        }
+        KeyCode::Char('r') => {
+            if let Some(item) = todo.items.get(i) {
+                let summary = item.reword.as_ref().unwrap_or(&item.summary);
+                state.prompt = Some(Prompt {
+                    kind: PromptKind::Reword(item.oid, None),
+                    input: Input::new(summary.clone()),
+                });
+            }
+        }
+        // Rows are newest first, down the screen is earlier in the rebase unless drawn bottom-up.
+        KeyCode::Char(key @ ('j' | 'k')) if modifiers.contains(KeyModifiers::ALT) => {
+            if let Some(to) = todo.move_item(i, (key == 'j') == state.config.bottom_up) {
+                state.list.select(to);
+            }
+        }
+        KeyCode::Char(key @ ('j' | 'k')) => state.step(key == 'j'),
+        KeyCode::Char('A') => state.message = Some(autosquash_message(&todo.autosquash())),
+        KeyCode::Enter => {
+            let operation = format!("rebase -i {}", &todo.onto.to_string()[..7]);
+            audited(state, &operation, |state| {
+                let Some(todo) = &state.rebase else {
+                    return;
+                };
+                let result = run_todo(&state.repo, todo);
+                state.finish_operation(
+                    result
+                        .map(|tip| format!("Rebased, HEAD is now at {}", &tip.to_string()[..7]))
+                        .map_err(|e| format!("Rebase failed: {}", e.message())),
+                );
+            });
+            if !state.operation_failed {
+                state.rebase = None;
+                state.set_view(View::Log);
+            }
+        }
+        _ => return,
+    }
+    *do_render = true;
+}
+
+/// Says which commits autosquash folded into which.
+fn autosquash_message(folded: &[(Oid, Oid)]) -> String {
+    if folded.is_empty() {
+        return "Autosquash: no fixup!/squash! commit names an earlier one".to_string();
+    }
+    let short = |oid: &Oid| oid.to_string()[..7].to_string();
+    let folds: Vec<String> = folded
+        .iter()
+        .map(|(fold, into)| format!("{} into {}", short(fold), short(into)))
+        .collect();
+    format!("Autosquash folded {}", folds.join(", "))
+}
+
+fn restack_selected(term: &mut Term, state: &mut State) {
+    let stacks = detect_stacks(&state.repo);
+    let root = state.branches[state.list.index()].name.clone();
+    let max_y = (term.size().y as usize).saturating_sub(PADDING);
+
+    let result = restack(&state.repo, &stacks, &root, |i, n, name| {
+        term.write_text(
+            Vec2::from((PADDING, max_y)),
+            format!("Restacking {i}/{n}: {name}"),
+        );
+        term.end_frame();
+    });
+    let result = match result {
+        Ok(RestackOutcome::Done(done)) if done.is_empty() => {
+            Ok(format!("Restack: nothing stacked on {root} needs it"))
+        }
+        Ok(RestackOutcome::Done(done)) => Ok(format!("Restacked {}", done.join(", "))),
+        Ok(RestackOutcome::Paused { branch, done }) => {
+            let mut msg = format!(
+                "Restack paused on conflicts in {branch}: resolve, `git rebase --continue`, then restack again"
+            );
+            if !done.is_empty() {
+                msg = format!("Restacked {}. {msg}", done.join(", "));
+            }
+            Err(msg)
+        }
+        Err(e) => Err(format!("Restack failed: {}", e.message())),
+    };
+    state.finish_operation(result);
+
+    if state.stacks.is_some() {
+        state.stacks = Some(detect_stacks(&state.repo));
+    }
+    state.refresh_branches();
+}
+
+fn handle_branch_event(
+    event: Event,
+    term: &mut Term,
+    state: &mut State,
+    args: &Args,
+    do_run: &mut bool,
+    do_render: &mut bool,
+    do_search: &mut bool,
+) {
+    match event {
+        // Actions
+        Event::Key(KeyEvent {
+            code: KeyCode::Enter,
+            ..
+        }) if args.pick && !state.branches.is_empty() => {
+            state.picked = Some(state.branches[state.list.index()].name_bytes().to_vec());
+            *do_run = false;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('l'),
+            ..
+        }) if !state.branches.is_empty() => {
+            if state.allow_mutation("checkout") {
+                let operation = format!("checkout {}", state.branches[state.list.index()].name);
+                audited(state, &operation, |state| checkout_selected(term, state));
+            }
+            *do_render = true;
+        }
+        Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            row,
+            ..
+        }) => {
+            let (at, size) = list_area(term.size(), 0);
+            let Some(i) = state.list_viewport.line_at(at, size, state.list.len(), row) else {
+                return;
+            };
+            let double = state
+                .last_click
+                .is_some_and(|(time, last)| last == i && time.elapsed() < DOUBLE_CLICK);
+            state.last_click = (!double).then(|| (Instant::now(), i));
+            state.list.select(i);
+            if double && args.pick {
+                state.picked = Some(state.branches[i].name_bytes().to_vec());
+                *do_run = false;
+            } else if double && state.allow_mutation("checkout") {
+                let operation = format!("checkout {}", state.branches[i].name);
+                audited(state, &operation, |state| checkout_selected(term, state));
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('-'),
+            ..
+        }) => {
+            if state.allow_mutation("checkout") {
+                match previous_branch(&state.repo) {
+                    Ok(name) => {
+                        let operation = format!("checkout {}", lossy(&name).0);
+                        audited(state, &operation, |state| checkout(term, state, &name));
+                    }
+                    Err(e) => state.error = Some(e.message().to_string()),
+                }
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(' '),
+            ..
+        }) if !state.branches.is_empty() => {
+            let refname = state.branches[state.list.index()].refname();
+            if !state.marked.remove(&refname) {
+                state.marked.insert(refname);
+            }
+            state.move_down(1);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(key @ ('D' | 'P' | 'F')),
+            ..
+        }) if !state.branches.is_empty() => {
+            let bulk = match key {
+                'D' => Bulk::Delete,
+                'P' => Bulk::Push,
+                _ => Bulk::Fetch,
+            };
+            if let Err(e) = bulk_command(term, state, bulk, &[]) {
+                state.error = Some(e);
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('t'),
+            ..
+        }) => {
+            state.stacks = match state.stacks {
+                Some(_) => None,
+                None => Some(detect_stacks(&state.repo)),
+            };
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('R'),
+            ..
+        }) if !state.branches.is_empty() => {
+            if state.allow_mutation("restack") {
+                let operation = format!("restack {}", state.branches[state.list.index()].name);
+                audited(state, &operation, |state| restack_selected(term, state));
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('a'),
+            ..
+        }) => {
+            state.show_activity = !state.show_activity;
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('m'),
+            ..
+        }) => {
+            state.merged_filter = match state.merged_filter {
+                None => Some(true),
+                Some(true) => Some(false),
+                Some(false) => None,
+            };
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('u'),
+            ..
+        }) => {
+            state.mine_only = !state.mine_only;
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('V'),
+            ..
+        }) => {
+            let count = state.config.saved_views.len();
+            if count == 0 {
+                state.error = Some(
+                    "No saved views, add [gixView \"<name>\"] sections to the git config".into(),
+                );
+            } else {
+                let next = match state.saved_view {
+                    None => Some(0),
+                    Some(i) => Some(i + 1).filter(|i| *i < count),
+                };
+                apply_saved_view(state, next);
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('e'),
+            ..
+        }) if !matches!(state.branch_query, BranchQuery::Local) => {
+            let names: Vec<String> = query_remotes(&state.repo)
+                .into_iter()
+                .map(|remote| remote.name)
+                .collect();
+            state.remote_filter = match &state.remote_filter {
+                None => names.first().cloned(),
+                Some(current) => names.iter().skip_while(|n| *n != current).nth(1).cloned(),
+            };
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('c'),
+            ..
+        }) => {
+            state.prompt = Some(Prompt {
+                kind: PromptKind::Contains,
+                input: Input::new(
+                    state
+                        .contains_filter
+                        .as_ref()
+                        .map(|(input, _)| input.clone())
+                        .unwrap_or_default(),
+                ),
+            });
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('p'),
+            ..
+        }) => {
+            state.pin_head = !state.pin_head;
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('o'),
+            ..
+        }) => {
+            state.sort = match state.sort {
+                None => Some((SortKey::ALL[0], false)),
+                Some((key, desc)) => {
+                    let i = SortKey::ALL.iter().position(|k| *k == key).unwrap_or(0);
+                    SortKey::ALL.get(i + 1).map(|key| (*key, desc))
+                }
+            };
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(c @ ('<' | '>' | '{' | '}')),
+            ..
+        }) => {
+            let (length, key, step) = if matches!(c, '<' | '>') {
+                (&mut state.branch_name_length, "gix.branchNameLength", 4)
+            } else {
+                (&mut state.summary_length, "gix.summaryLength", 8)
+            };
+            *length = if matches!(c, '<' | '{') {
+                length.saturating_sub(step).max(MIN_COLUMN_LENGTH)
+            } else {
+                *length + step
+            };
+            let length = *length;
+            // Read-only mode doesn't write the config, the width only lasts this session.
+            if !state.config.read_only
+                && let Err(e) = save_length(&state.repo, key, length)
+            {
+                state.error = Some(format!("Couldn't save {key}: {}", e.message()));
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('w'),
+            ..
+        }) => {
+            state.wrap_summaries = !state.wrap_summaries;
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('U'),
+            ..
+        }) => {
+            state.show_upstream = !state.show_upstream;
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('s'),
+            ..
+        }) => {
+            // Orders that help find recent work, rather than every column both ways.
+            const SORTS: [(SortKey, bool); 4] = [
+                (SortKey::Age, false),
+                (SortKey::Name, false),
+                (SortKey::Ahead, true),
+                (SortKey::Author, false),
+            ];
+            state.sort = match state
+                .sort
+                .and_then(|sort| SORTS.iter().position(|s| *s == sort))
+            {
+                Some(i) => SORTS.get(i + 1).copied(),
+                None => Some(SORTS[0]),
+            };
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('O'),
+            ..
+        }) => {
+            let (key, desc) = state.sort.unwrap_or((SortKey::ALL[0], false));
+            state.sort = Some((key, !desc));
+            state.filter_branches();
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('f'),
+            ..
+        }) => {
+            state.prompt = Some(Prompt {
+                kind: PromptKind::Filter,
+                input: Input::new(
+                    state
+                        .filter
+                        .as_ref()
+                        .map(|f| f.source.clone())
+                        .unwrap_or_default(),
+                ),
+            });
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('C'),
+            ..
+        }) if !state.branches.is_empty() => {
+            state.contributors_branch = Some(state.branches[state.list.index()].name.clone());
+            state.set_view(View::Contributors);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('T'),
+            ..
+        }) => {
+            if state.as_of.take().is_none() {
+                state.prompt = Some(Prompt {
+                    kind: PromptKind::AsOf,
+                    input: Input::default(),
+                });
+            } else {
+                state.refresh_branches();
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('b'),
+            ..
+        }) if !state.branches.is_empty() => {
+            let selected = state.branches[state.list.index()].refname();
+            let mut refs: Vec<(String, bool)> = query_branches(&state.repo, &BranchQuery::Local)
+                .iter()
+                .map(|b| b.refname())
+                .chain(
+                    state
+                        .repo
+                        .tag_names(None)
+                        .iter()
+                        .flat_map(|tags| tags.iter().flatten().map(|t| format!("refs/tags/{t}")))
+                        .collect::<Vec<_>>(),
+                )
+                .map(|name| {
+                    let checked = name == selected;
+                    (name, checked)
+                })
+                .collect();
+            refs.sort_by_key(|(name, checked)| (!checked, name.clone()));
+            state.bundle = Some(BundleSelection { import: None, refs });
+            state.set_view(View::Bundle);
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('B'),
+            ..
+        }) => {
+            if state.allow_mutation("bundle import") {
+                state.prompt = Some(Prompt {
+                    kind: PromptKind::BundleImport,
+                    input: Input::default(),
+                });
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('H'),
+            ..
+        }) => {
+            if state.hook.is_some() {
+                state.set_view(View::Hook);
+            } else {
+                state.error = Some("No hook has run yet".to_string());
+            }
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('x'),
+            ..
+        }) if !state.branches.is_empty() => {
+            let name = &state.branches[state.list.index()].name;
+            let result = export_branch(
+                &state.repo,
+                name,
+                state.path.as_deref(),
+                state.config.export_theme,
+            );
+            state.finish_operation(
+                result
+                    .map(|path| format!("Exported to {}", path.display()))
+                    .map_err(|e| format!("Export failed: {}", e.message())),
+            );
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('L'),
+            ..
+        }) if !state.branches.is_empty() => {
+            let refname = state.branches[state.list.index()].refname();
+            let labels = state.labels.get(&refname).join(", ");
+            state.prompt = Some(Prompt {
+                kind: PromptKind::Labels(refname),
+                input: Input::new(labels),
+            });
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('/'),
+            ..
+        }) => {
+            *do_search = true;
+            *do_render = true;
+        }
+        // A branch name pasted outside the search is searched for instead of being taken as keys.
+        Event::Paste(text) => {
+            state.search.clear();
+            state.search.insert_str(&text);
+            state.filter_branches();
+            *do_search = true;
+            *do_render = true;
+        }
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('r'),
+            ..
         }) => {
             match state.branch_query {
                 BranchQuery::Local => state.branch_query = BranchQuery::LocalAndRemote,
                 BranchQuery::LocalAndRemote => state.branch_query = BranchQuery::Remote,
-                BranchQuery::Remote => state.branch_query = BranchQuery::Local,
+                BranchQuery::Remote => {
+                    state.branch_query = BranchQuery::Local;
+                    state.remote_filter = None;
+                }
             };
+            state.refresh_branches();
             *do_render = true;
         }
 
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{commit_file, fixture, signature};
+    use git2::{BranchType, Signature, Time, build::CheckoutBuilder};
+    use std::fs;
+
+    /// State showing the branches of `repo` once they finished loading.
+    fn branches_state(repo: Repository) -> State {
+        let config = Config::load(&repo);
+        let mut state = State::new(repo, config, View::Branches);
+        state.set_view(View::Branches);
+        while state.loading_branches.is_some() {
+            state.receive_branches();
+            thread::sleep(Duration::from_millis(5));
+        }
+        state
+    }
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::from(code))
+    }
+
+    fn type_keys(term: &mut Term, text: &str) {
+        for c in text.chars() {
+            term.push_event(key(KeyCode::Char(c)));
+        }
+    }
+
+    #[test]
+    fn render_branches_snapshot() {
+        let mut state = branches_state(fixture("render"));
+        state.branch_name_length = 10;
+        state.summary_length = 20;
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(70, 6));
+        term.begin_frame();
+        render_branches(&mut term, &mut state, &args);
+        term.end_frame();
+        // The fixture commits are from 1970.
+        let age = format_age(0, now());
+        assert_eq!(
+            term.lines(),
+            [
+                "".to_string(),
+                "  sort (o/O) name  age  ahead  behind  author".to_string(),
+                format!("  > db8ba70 feature  {age:>4}  'Initial commit'           [no upstream]"),
+                format!("    db8ba70 main     {age:>4}  'Initial commit'           [no upstream]"),
+                "".to_string(),
+                "".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn upstream_column_names_the_upstream() {
+        let repo = fixture("upstream-column");
+        repo.find_branch("feature", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("main"))
+            .unwrap();
+        let mut state = branches_state(repo);
+        assert_eq!(state.branches[0].upstream.as_deref(), Some("main"));
+
+        state.summary_length = 20;
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('U')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        let lines = term.lines();
+        assert!(lines[2].contains("feature") && lines[2].trim_end().ends_with(" main"));
+        // In sync with its upstream.
+        assert!(lines[2].contains("feature  = "));
+        assert!(lines[3].trim_end().ends_with("[no upstream]"));
+    }
+
+    #[test]
+    fn row_format_writes_rows_from_the_template() {
+        let mut state = branches_state(fixture("row-format"));
+        state.row_format = Some(
+            RowFormat::parse("%(HEAD) %(refname:short) 100%% %(subject) %(upstream:track)")
+                .unwrap(),
+        );
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(40, 6));
+        term.begin_frame();
+        render_branches(&mut term, &mut state, &args);
+        term.end_frame();
+        assert_eq!(term.lines()[2], "  >   feature 100% Initial commit");
+        assert_eq!(term.lines()[3], "    * main 100% Initial commit");
+
+        assert_eq!(
+            RowFormat::parse("%(refname) %(nope)").err().as_deref(),
+            Some("unknown field %(nope)")
+        );
+        assert_eq!(
+            RowFormat::parse("x %(refname").err().as_deref(),
+            Some("%( at column 3 isn't closed")
+        );
+    }
+
+    #[test]
+    fn widths_are_adjusted_and_kept() {
+        let mut state = branches_state(fixture("widths"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        for c in ['>', '>', '{'] {
+            term.push_event(key(KeyCode::Char(c)));
+        }
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!((state.branch_name_length, state.summary_length), (50, 64));
+
+        let config = Config::load(&state.repo);
+        assert_eq!(config.branch_name_length, Some(50));
+        assert_eq!(config.summary_length, Some(64));
+
+        state.config.read_only = true;
+        term.push_event(key(KeyCode::Char('>')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.branch_name_length, 54);
+        assert_eq!(Config::load(&state.repo).branch_name_length, Some(50));
+    }
+
+    #[test]
+    fn w_wraps_summaries_onto_more_rows() {
+        let repo = fixture("wrap");
+        {
+            let head = repo.head().unwrap().peel_to_commit().unwrap();
+            for i in 0..50 {
+                repo.branch(&format!("wip-{i:02}"), &head, false).unwrap();
+            }
+        }
+        let mut state = branches_state(repo);
+        state.summary_length = 8;
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('w')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert!(state.wrap_summaries);
+        let lines = term.lines();
+        let first = lines.iter().position(|l| l.contains("'Initial")).unwrap();
+        let indent = lines[first].find("'Initial").unwrap();
+        assert_eq!(lines[first + 1].find("commit'"), Some(indent));
+        assert!(!lines[first + 1].contains("main"));
+        // Branches far from the view are measured once they come near it.
+        let last = state.branches.last().unwrap();
+        assert!(last.loaded_details().is_none());
+    }
+
+    #[test]
+    fn checkout_with_uncommitted_changes_asks_to_stash_them() {
+        let repo = fixture("dirty-checkout");
+        {
+            let sig = signature();
+            let parent = repo.head().unwrap().peel_to_commit().unwrap();
+            let mut tree = repo.treebuilder(None).unwrap();
+            let blob = repo.blob(b"feature\n").unwrap();
+            tree.insert("feature.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+            repo.commit(
+                Some("refs/heads/feature"),
+                &sig,
+                &sig,
+                "Add feature",
+                &tree,
+                &[&parent],
+            )
+            .unwrap();
+
+            fs::write(repo.workdir().unwrap().join("notes.txt"), "wip\n").unwrap();
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("notes.txt")).unwrap();
+            index.write().unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Tester").unwrap();
+            config.set_str("user.email", "tester@example.com").unwrap();
+        }
+        let mut state = branches_state(repo);
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char(':')));
+        type_keys(&mut term, "checkout feature");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('s')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert_eq!(state.repo.head().unwrap().shorthand(), Some("feature"));
+        let workdir = state.repo.workdir().unwrap().to_path_buf();
+        assert!(workdir.join("feature.txt").exists());
+        assert!(!workdir.join("notes.txt").exists());
+        assert_eq!(query_stashes(&mut state.repo).len(), 1);
+
+        // An untracked file in the way makes the checkout fail after stashing, which puts the
+        // staged change back.
+        {
+            let main = state.repo.revparse_single("main").unwrap();
+            let mut checkout = CheckoutBuilder::new();
+            state
+                .repo
+                .checkout_tree(&main, Some(checkout.force()))
+                .unwrap();
+            state.repo.set_head("refs/heads/main").unwrap();
+        }
+        pop_stash(&mut state.repo).unwrap();
+        fs::write(workdir.join("feature.txt"), "untracked\n").unwrap();
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char(':')));
+        type_keys(&mut term, "checkout feature");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('s')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert_eq!(state.repo.head().unwrap().shorthand(), Some("main"));
+        assert!(state.operation_failed);
+        assert!(query_stashes(&mut state.repo).is_empty());
+        let index = state.repo.index().unwrap();
+        assert!(index.get_path(Path::new("notes.txt"), 0).is_some());
+        assert_eq!(
+            fs::read_to_string(workdir.join("notes.txt")).unwrap(),
+            "wip\n"
+        );
+        assert_eq!(
+            fs::read_to_string(workdir.join("feature.txt")).unwrap(),
+            "untracked\n"
+        );
+    }
+
+    #[test]
+    fn reword_and_fixup_run_the_commit_hooks() {
+        let repo = fixture("commit-hooks");
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Tester").unwrap();
+            config.set_str("user.email", "tester@example.com").unwrap();
+            commit_file(&repo, "a.txt", "a", "Add a");
+            let hooks = repo.path().join("hooks");
+            fs::create_dir_all(&hooks).unwrap();
+            for (name, script) in [
+                (
+                    "pre-commit",
+                    "test ! -e block || { echo blocked >&2; exit 1; }",
+                ),
+                (
+                    "commit-msg",
+                    r#"printf '\nSigned-off-by: Tester\n' >> "$1""#,
+                ),
+            ] {
+                let path = hooks.join(name);
+                fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            }
+        }
+        let mut state = branches_state(repo);
+        state.set_view(View::Log);
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('r')));
+        type_keys(&mut term, "!");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        fn head(state: &State) -> git2::Commit<'_> {
+            state.repo.head().unwrap().peel_to_commit().unwrap()
+        }
+        assert_eq!(
+            head(&state).message(),
+            Some("Add a!\n\nSigned-off-by: Tester\n")
+        );
+
+        // A failing pre-commit hook keeps the fixup from being committed.
+        let workdir = state.repo.workdir().unwrap().to_path_buf();
+        fs::write(workdir.join("block"), "").unwrap();
+        fs::write(workdir.join("a.txt"), "a, fixed").unwrap();
+        let mut index = state.repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        let reworded = head(&state).id();
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('f')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(head(&state).id(), reworded);
+        assert_eq!(state.view, View::Hook);
+        assert_eq!(state.hook.as_ref().unwrap().stderr, "blocked\n");
+
+        fs::remove_file(workdir.join("block")).unwrap();
+        state.set_view(View::Log);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('f')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(head(&state).parent_id(0).unwrap(), reworded);
+        assert_eq!(
+            head(&state).message(),
+            Some("fixup! Add a!\nSigned-off-by: Tester\n")
+        );
+    }
+
+    #[test]
+    fn completions_offer_branch_names_for_search() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let script = completions(shell);
+            assert!(
+                script.contains("(env GIX_COMPLETE=branches gix 2>/dev/null)"),
+                "{shell}"
+            );
+        }
+        assert!(
+            !completions(Shell::Bash)
+                .contains("--search)\n                    COMPREPLY=($(compgen -f")
+        );
+        assert!(!completions(Shell::Zsh).contains(":SEARCH:_default"));
+    }
+
+    #[test]
+    fn event_loop_search_and_checkout() {
+        let mut state = branches_state(fixture("checkout"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('/')));
+        type_keys(&mut term, "feat");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('l')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        let head = state.repo.head().unwrap();
+        assert_eq!(head.shorthand(), Some("feature"));
+        let lines = term.lines();
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("> ") && l.contains("feature"))
+        );
+        assert!(!lines.iter().any(|l| l.contains(" main ")));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("Switched to branch 'feature'"))
+        );
+    }
+
+    #[test]
+    fn tiny_terminal_asks_for_more_room() {
+        let mut state = branches_state(fixture("tiny"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(40, 1));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert_eq!(term.lines(), ["terminal too small (need 20x5)"]);
+    }
+
+    #[test]
+    fn paste_searches_branches() {
+        let mut state = branches_state(fixture("paste"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(Event::Paste("feature\n".to_string()));
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('l')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert_eq!(state.search.text(), "feature");
+        assert_eq!(state.repo.head().unwrap().shorthand(), Some("feature"));
+    }
+
+    #[test]
+    fn sort_column_cycles_and_reverses() {
+        let mut state = branches_state(fixture("sort"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        for c in ['o', 'o', 'O'] {
+            term.push_event(key(KeyCode::Char(c)));
+        }
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert!(matches!(state.sort, Some((SortKey::Age, true))));
+        assert!(term.lines()[1].contains("age ↓"));
+
+        // s starts over with its own orders.
+        for c in ['s', 's', 's'] {
+            term.push_event(key(KeyCode::Char(c)));
+        }
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert!(matches!(state.sort, Some((SortKey::Ahead, true))));
+    }
+
+    #[test]
+    fn branch_sort_config_orders_the_list() {
+        let repo = fixture("branch-sort");
+        repo.config()
+            .unwrap()
+            .set_str("branch.sort", "-refname")
+            .unwrap();
+        let mut state = branches_state(repo);
+        let names: Vec<&str> = state.branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["main", "feature"]);
+
+        state.sort = Some((SortKey::Name, false));
+        state.filter_branches();
+        assert_eq!(state.branches[0].name, "feature");
+    }
+
+    #[test]
+    fn double_click_checks_out_branch() {
+        let mut state = branches_state(fixture("click"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        // The list starts in row 2 with "feature".
+        let click = Event::Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 10,
+            row: 2,
+            modifiers: KeyModifiers::NONE,
+        });
+        term.push_event(click.clone());
+        term.push_event(click);
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert_eq!(state.repo.head().unwrap().shorthand(), Some("feature"));
+    }
+
+    #[test]
+    fn bottom_up_lists_start_at_the_bottom() {
+        let repo = fixture("bottom-up");
+        repo.config()
+            .unwrap()
+            .set_bool("gix.bottomUp", true)
+            .unwrap();
+        let mut state = branches_state(repo);
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(70, 6));
+        term.push_event(key(KeyCode::Char('k')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        let lines = term.lines();
+        assert!(lines[2].starts_with("  > db8ba70 main"));
+        assert!(lines[3].starts_with("    db8ba70 feature"));
+    }
+
+    #[test]
+    fn search_keeps_the_selected_branch() {
+        let mut state = branches_state(fixture("keep-selection"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('j')));
+        term.push_event(key(KeyCode::Char('/')));
+        type_keys(&mut term, "ma");
+        term.push_event(key(KeyCode::Backspace));
+        term.push_event(key(KeyCode::Backspace));
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert_eq!(state.branches.len(), 2);
+        assert_eq!(state.branches[state.list.index()].name, "main");
+
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('/')));
+        type_keys(&mut term, "nothing");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert!(state.branches.is_empty());
+        assert_eq!(state.list.selected(), None);
+    }
+
+    #[test]
+    fn dash_checks_out_the_previous_branch() {
+        let mut state = branches_state(fixture("previous"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('l')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.repo.head().unwrap().shorthand(), Some("feature"));
+
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('-')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.repo.head().unwrap().shorthand(), Some("main"));
+    }
+
+    #[test]
+    fn delete_acts_on_marked_branches() {
+        let mut state = branches_state(fixture("delete"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char(' ')));
+        term.push_event(key(KeyCode::Char(' ')));
+        term.push_event(key(KeyCode::Char('D')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        // The checked out branch is kept and stays marked.
+        assert!(
+            state
+                .repo
+                .find_branch("feature", git2::BranchType::Local)
+                .is_err()
+        );
+        assert!(
+            state
+                .repo
+                .find_branch("main", git2::BranchType::Local)
+                .is_ok()
+        );
+        assert_eq!(state.marked.iter().collect::<Vec<_>>(), ["refs/heads/main"]);
+        assert!(state.operation_failed);
+    }
+
+    #[test]
+    fn command_line_completes_and_runs() {
+        let mut state = branches_state(fixture("command"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        type_keys(&mut term, ":ch");
+        term.push_event(key(KeyCode::Tab));
+        type_keys(&mut term, "fea");
+        term.push_event(key(KeyCode::Tab));
+        term.push_event(key(KeyCode::Enter));
+        type_keys(&mut term, ":sort date desc");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        assert_eq!(state.repo.head().unwrap().shorthand(), Some("feature"));
+        assert!(state.sort == Some((SortKey::Age, true)));
+        assert!(state.prompt.is_none());
+    }
+
+    #[test]
+    fn pinned_head_ignores_the_search() {
+        let mut state = branches_state(fixture("pin"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('p')));
+        term.push_event(key(KeyCode::Char('/')));
+        type_keys(&mut term, "feat");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+
+        let names: Vec<&str> = state.branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["main", "feature"]);
+    }
+
+    #[test]
+    fn number_and_enter_select_the_row() {
+        let mut state = branches_state(fixture("row"));
+        state.branch_name_length = 10;
+        state.summary_length = 20;
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(70, 6));
+        term.push_event(key(KeyCode::Char('#')));
+        type_keys(&mut term, "2");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.branches[state.list.index()].name, "main");
+
+        term.begin_frame();
+        render_branches(&mut term, &mut state, &args);
+        term.end_frame();
+        assert!(term.lines()[3].starts_with("  > 2 db8ba70 main"));
+    }
+
+    #[test]
+    fn tab_searches_summaries_too() {
+        let mut state = branches_state(fixture("search-all"));
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('/')));
+        type_keys(&mut term, "initial");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert!(state.branches.is_empty());
+
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('/')));
+        term.push_event(key(KeyCode::Tab));
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.search.text(), "initial");
+        assert_eq!(state.branches.len(), 2);
+    }
+
+    #[test]
+    fn u_lists_only_my_branches() {
+        let repo = fixture("mine");
+        let mut config = repo.config().unwrap();
+        config
+            .set_multivar("gix.myEmail", "^$", "me@work.example")
+            .unwrap();
+        let mut state = branches_state(repo);
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('u')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert!(state.branches.is_empty());
+
+        config
+            .set_multivar("gix.myEmail", "^$", "Tester@example.com")
+            .unwrap();
+        state.filter_branches();
+        assert_eq!(state.branches.len(), 2);
+    }
+
+    #[test]
+    fn c_lists_branches_containing_a_commit() {
+        let repo = fixture("contains");
+        {
+            let parent = repo.head().unwrap().peel_to_commit().unwrap();
+            let sig = Signature::new("Tester", "tester@example.com", &Time::new(60, 0)).unwrap();
+            repo.commit(
+                Some("refs/heads/feature"),
+                &sig,
+                &sig,
+                "Feature",
+                &parent.tree().unwrap(),
+                &[&parent],
+            )
+            .unwrap();
+        }
+        let mut state = branches_state(repo);
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('c')));
+        type_keys(&mut term, "feature");
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        let names: Vec<&str> = state.branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["feature"]);
+
+        let main = state.repo.revparse_single("main").unwrap().id();
+        state.contains_filter = Some(("main".to_string(), main));
+        state.filter_branches();
+        assert_eq!(state.branches.len(), 2);
+    }
+
+    #[test]
+    fn ambiguous_short_ids_are_picked_from_a_list() {
+        let repo = fixture("ambiguous");
+        let sig = signature();
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        // Commits until two of them share the first four hex digits.
+        let mut seen = HashSet::new();
+        let prefix: String = (0..)
+            .find_map(|i| {
+                let oid = repo
+                    .commit(None, &sig, &sig, &format!("Commit {i}"), &tree, &[])
+                    .unwrap();
+                let prefix = oid.to_string()[..4].to_string();
+                (!seen.insert(prefix.clone())).then_some(prefix)
+            })
+            .unwrap();
+        drop(tree);
+        let mut state = branches_state(repo);
+        let args = Args::parse_from(["gix"]);
+
+        for prompt in ['c', 'T'] {
+            let mut term = Term::test(Vec2::new(80, 10));
+            term.push_event(key(KeyCode::Char(prompt)));
+            type_keys(&mut term, &prefix);
+            term.push_event(key(KeyCode::Enter));
+            term.push_event(key(KeyCode::Char('q')));
+            run(&mut term, &mut state, &args);
+            assert_eq!(state.view, View::Candidates);
+            assert_eq!(state.candidates.len(), 2);
+            assert!(
+                state
+                    .candidates
+                    .iter()
+                    .all(|c| c.oid.to_string().starts_with(&prefix))
+            );
+
+            let mut term = Term::test(Vec2::new(80, 10));
+            term.push_event(key(KeyCode::Enter));
+            term.push_event(key(KeyCode::Char('q')));
+            run(&mut term, &mut state, &args);
+            assert_eq!(state.view, View::Branches);
+        }
+        let (_, contained) = state.contains_filter.as_ref().unwrap();
+        assert!(contained.to_string().starts_with(&prefix));
+        let (as_of, _) = state.as_of.as_ref().unwrap();
+        assert!(as_of.starts_with(&prefix));
+    }
+
+    #[test]
+    fn files_view_opens_history_and_blame() {
+        let repo = fixture("files-view");
+        commit_file(&repo, "a.txt", "one\n", "Add a");
+        commit_file(&repo, "a.txt", "two\n", "Change a");
+        let mut state = branches_state(repo);
+        state.set_view(View::Files);
+        assert_eq!(state.files, ["a.txt"]);
+        let args = Args::parse_from(["gix"]);
+        let send = |state: &mut State, keys: &[KeyCode]| {
+            let mut term = Term::test(Vec2::new(80, 10));
+            for &code in keys {
+                term.push_event(key(code));
+            }
+            term.push_event(key(KeyCode::Char('q')));
+            run(&mut term, state, &args);
+        };
+
+        send(&mut state, &[KeyCode::Char('b')]);
+        assert_eq!(state.view, View::Blame);
+        send(&mut state, &[KeyCode::Char('h')]);
+        assert_eq!(state.view, View::History);
+        assert_eq!(state.history_path.as_deref(), Some("a.txt"));
+        assert_eq!(state.history.len(), 2);
+        send(&mut state, &[KeyCode::Esc]);
+        assert_eq!(state.view, View::Files);
+
+        send(
+            &mut state,
+            &[KeyCode::Enter, KeyCode::Char('b'), KeyCode::Esc],
+        );
+        assert_eq!(state.view, View::History);
+        send(&mut state, &[KeyCode::Esc]);
+        assert_eq!(state.view, View::Files);
+    }
+
+    #[test]
+    fn blame_enter_shows_the_commit() {
+        let repo = fixture("blame-commit");
+        let oid = commit_file(&repo, "a.txt", "one\n", "Add a\n\nWith a body");
+        let mut state = branches_state(repo);
+        state.set_view(View::Files);
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('b')));
+        term.push_event(key(KeyCode::Enter));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.view, View::Commit);
+        assert_eq!(state.commit_lines[0], format!("commit {oid}"));
+        assert!(state.commit_lines.contains(&"    With a body".to_string()));
+        assert_eq!(state.commit_lines.last().unwrap(), "A  a.txt");
+
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Esc));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.view, View::Blame);
+    }
+
+    #[test]
+    fn e_cycles_through_remotes() {
+        let repo = fixture("remote-filter");
+        let head = repo.head().unwrap().target().unwrap();
+        for remote in ["origin", "fork"] {
+            repo.remote(remote, &format!("https://example.com/{remote}.git"))
+                .unwrap();
+            repo.reference(&format!("refs/remotes/{remote}/main"), head, false, "")
+                .unwrap();
+        }
+        let mut state = branches_state(repo);
+        state.branch_query = BranchQuery::Remote;
+        state.refresh_branches();
+        while state.loading_branches.is_some() {
+            state.receive_branches();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(state.branches.len(), 2);
+
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('e')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        let names: Vec<&str> = state.branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["fork/main"]);
+
+        term.push_event(key(KeyCode::Char('e')));
+        term.push_event(key(KeyCode::Char('e')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.remote_filter, None);
+        assert_eq!(state.branches.len(), 2);
+    }
+
+    #[test]
+    fn shift_v_cycles_through_saved_views() {
+        let repo = fixture("saved-views");
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("gixView.feat.search", "feat").unwrap();
+            config.set_str("gixView.sorted.sort", "name desc").unwrap();
+        }
+        let mut state = branches_state(repo);
+        let args = Args::parse_from(["gix"]);
+        let mut term = Term::test(Vec2::new(80, 10));
+        term.push_event(key(KeyCode::Char('V')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        let names: Vec<&str> = state.branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["feature"]);
+
+        term.push_event(key(KeyCode::Char('V')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        let names: Vec<&str> = state.branches.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["main", "feature"]);
+
+        term.push_event(key(KeyCode::Char('V')));
+        term.push_event(key(KeyCode::Char('q')));
+        run(&mut term, &mut state, &args);
+        assert_eq!(state.saved_view, None);
+        assert!(state.sort.is_none());
+    }
+}
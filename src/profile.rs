@@ -0,0 +1,138 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Copy)]
+pub enum Phase {
+    /// Git queries, including branch queries finishing on the worker thread.
+    Query,
+    /// In-memory filters such as search and the author filter.
+    Filter,
+    /// Working out rows and column widths.
+    Layout,
+    /// Writing rows and overlays to the frame buffer.
+    Draw,
+    /// Diffing the frame against the previous one and writing it to the terminal.
+    Flush,
+}
+
+const PHASES: [Phase; 5] = [
+    Phase::Query,
+    Phase::Filter,
+    Phase::Layout,
+    Phase::Draw,
+    Phase::Flush,
+];
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Query => "query",
+            Phase::Filter => "filter",
+            Phase::Layout => "layout",
+            Phase::Draw => "draw",
+            Phase::Flush => "flush",
+        }
+    }
+}
+
+/// Per-frame timings collected with `--profile`. Work between two frames, like the queries a key
+/// press triggers, is counted towards the next frame.
+///
+/// A disabled profiler still measures, which is cheap, but never logs.
+#[derive(Default)]
+pub struct Profiler {
+    log: Option<(PathBuf, File)>,
+    frames: usize,
+    current: [Duration; 5],
+    /// Timings of the last finished frame.
+    last: [Duration; 5],
+    lap: Option<Instant>,
+}
+
+impl Profiler {
+    /// Profiler appending a line per frame to `path`.
+    pub fn with_log(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(Self {
+            log: Some((path.to_path_buf(), file)),
+            ..Self::default()
+        })
+    }
+
+    pub fn record(&mut self, phase: Phase, elapsed: Duration) {
+        self.current[phase as usize] += elapsed;
+    }
+
+    /// Starts timing the rendering phases, see [`Self::lap`].
+    pub fn begin_frame(&mut self) {
+        self.lap = Some(Instant::now());
+    }
+
+    /// Counts the time since the previous lap (or [`Self::begin_frame`]) towards `phase`.
+    pub fn lap(&mut self, phase: Phase) {
+        let now = Instant::now();
+        if let Some(lap) = self.lap.replace(now) {
+            self.record(phase, now - lap);
+        }
+    }
+
+    /// Finishes the frame after it was flushed, logging its timings.
+    pub fn end_frame(&mut self) {
+        self.lap(Phase::Flush);
+        self.lap = None;
+        self.frames += 1;
+        self.last = std::mem::take(&mut self.current);
+
+        let line = format!("frame={} {}\n", self.frames, self.timings());
+        if let Some((_, file)) = &mut self.log {
+            // Losing a line of profiling output is not worth interrupting the session for.
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    /// Lines shown in the profiling overlay.
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![format!("Frame:  {}", self.frames)];
+        lines.extend(PHASES.iter().map(|phase| {
+            format!(
+                "{:<7} {}",
+                format!("{}:", phase.name()),
+                format_duration(self.last[*phase as usize])
+            )
+        }));
+        lines.push(format!(
+            "Total:  {}",
+            format_duration(self.last.iter().sum())
+        ));
+        if let Some((path, _)) = &self.log {
+            lines.push(format!("Log:    {}", path.display()));
+        }
+        lines
+    }
+
+    fn timings(&self) -> String {
+        PHASES
+            .iter()
+            .map(|phase| {
+                format!(
+                    "{}={}",
+                    phase.name(),
+                    format_duration(self.last[*phase as usize])
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,663 @@
+use crate::{lossy::lossy, status::is_dirty};
+use git2::{BranchType, Commit, Error, Oid, Repository, Sort, build::CheckoutBuilder};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Pick,
+    /// Pick with a new summary line.
+    Reword,
+    /// Meld into the commit applied before, keeping both messages.
+    Squash,
+    /// Meld into the commit applied before, keeping only its message.
+    Fixup,
+    Drop,
+}
+
+impl Verb {
+    pub fn name(self) -> &'static str {
+        match self {
+            Verb::Pick => "pick",
+            Verb::Reword => "reword",
+            Verb::Squash => "squash",
+            Verb::Fixup => "fixup",
+            Verb::Drop => "drop",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Verb::Pick,
+            Verb::Reword,
+            Verb::Squash,
+            Verb::Fixup,
+            Verb::Drop,
+        ]
+        .into_iter()
+        .find(|verb| verb.name() == name)
+    }
+}
+
+/// A line of the todo list.
+pub struct TodoItem {
+    pub verb: Verb,
+    pub oid: Oid,
+    pub summary: String,
+    /// Summary line replacing the original one when reworded.
+    pub reword: Option<String>,
+}
+
+impl TodoItem {
+    /// Whether autosquash would fold the commit, going by its `fixup! `/`squash! ` summary.
+    pub fn is_fold(&self) -> bool {
+        fold_target(&self.summary).is_some()
+    }
+}
+
+/// Todo list of an interactive rebase of the commits after `onto`.
+pub struct Todo {
+    pub onto: Oid,
+    /// Newest first like the log, so the last item is applied first.
+    pub items: Vec<TodoItem>,
+}
+
+impl Todo {
+    /// The commits between `onto` and HEAD, all picked, like `git rebase -i <onto>` starts out.
+    pub fn after(repo: &Repository, onto: Oid) -> Result<Self, Error> {
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push_head()?;
+        revwalk.hide(onto)?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL)?;
+
+        let mut items = Vec::new();
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            if commit.parent_count() != 1 {
+                return Err(Error::from_str(
+                    "merge commits can't be rebased, pick a commit after the last merge",
+                ));
+            }
+            items.push(TodoItem {
+                verb: Verb::Pick,
+                oid: commit.id(),
+                summary: lossy(commit.summary_bytes().unwrap_or_default()).0,
+                reword: None,
+            });
+        }
+        if items.is_empty() {
+            return Err(Error::from_str("no commits after the selected one"));
+        }
+        Ok(Self { onto, items })
+    }
+
+    /// The todo list `items` that was being edited, as long as they are still the commits
+    /// between `onto` and HEAD.
+    pub fn restore(
+        repo: &Repository,
+        onto: Oid,
+        items: Vec<(Verb, Oid, Option<String>)>,
+    ) -> Result<Self, Error> {
+        let moved = || Error::from_str("HEAD moved since the todo list was edited");
+        let mut todo = Self::after(repo, onto)?;
+        let mut restored = Vec::with_capacity(items.len());
+        for (verb, oid, reword) in items {
+            let i = todo
+                .items
+                .iter()
+                .position(|item| item.oid == oid)
+                .ok_or_else(moved)?;
+            let mut item = todo.items.swap_remove(i);
+            item.verb = verb;
+            item.reword = reword;
+            restored.push(item);
+        }
+        if !todo.items.is_empty() {
+            return Err(moved());
+        }
+        todo.items = restored;
+        Ok(todo)
+    }
+
+    /// Moves `fixup!` and `squash!` commits right after the commit they name, applied before
+    /// them, and makes them fix it up or squash into it, like `git rebase --autosquash`. The
+    /// commit is named by its summary, the start of its summary or its id. Returns the folded
+    /// commits, each with the commit it was folded into.
+    pub fn autosquash(&mut self) -> Vec<(Oid, Oid)> {
+        // In the order the items are applied, oldest first.
+        self.items.reverse();
+        let mut folded = Vec::new();
+        let mut i = 0;
+        while i < self.items.len() {
+            let Some((verb, name)) = fold_target(&self.items[i].summary) else {
+                i += 1;
+                continue;
+            };
+            let target = self.items[..i].iter().position(|item| {
+                item.summary == name
+                    || item.summary.starts_with(name)
+                    || (name.len() >= 4 && item.oid.to_string().starts_with(name))
+            });
+            let Some(target) = target else {
+                i += 1;
+                continue;
+            };
+            // After the target and whatever was folded into it already.
+            let mut to = target + 1;
+            while to < i && matches!(self.items[to].verb, Verb::Fixup | Verb::Squash) {
+                to += 1;
+            }
+            let mut item = self.items.remove(i);
+            item.verb = verb;
+            folded.push((item.oid, self.items[target].oid));
+            self.items.insert(to, item);
+            i += 1;
+        }
+        self.items.reverse();
+        folded
+    }
+
+    /// Swaps item `i` with the one applied after it (`later`) or before it. Returns where the
+    /// item ended up, `None` when it already is at that end.
+    pub fn move_item(&mut self, i: usize, later: bool) -> Option<usize> {
+        let to = if later { i.checked_sub(1)? } else { i + 1 };
+        if to >= self.items.len() {
+            return None;
+        }
+        self.items.swap(i, to);
+        Some(to)
+    }
+}
+
+/// Applies `todo` on top of its `onto` commit and moves HEAD there, with its branch if it is on
+/// one. Commits are cherry-picked in memory, so nothing is changed unless every one of them
+/// applies cleanly. Returns the new HEAD commit.
+pub fn run_todo(repo: &Repository, todo: &Todo) -> Result<Oid, Error> {
+    if is_dirty(repo) {
+        return Err(Error::from_str(
+            "working tree has uncommitted changes, commit or stash them first",
+        ));
+    }
+    let first = todo.items.iter().rev().find(|item| item.verb != Verb::Drop);
+    if let Some(item) = first
+        && matches!(item.verb, Verb::Squash | Verb::Fixup)
+    {
+        return Err(Error::from_str(&format!(
+            "nothing to {} {} into",
+            item.verb.name(),
+            short(item.oid)
+        )));
+    }
+
+    let signature = repo.signature()?;
+    let mut tip = repo.find_commit(todo.onto)?;
+    for item in todo.items.iter().rev() {
+        if item.verb == Verb::Drop {
+            continue;
+        }
+        let commit = repo.find_commit(item.oid)?;
+        // Commits that stay where they were are kept as they are, like git fast-forwards them.
+        if item.verb == Verb::Pick && commit.parent_id(0)? == tip.id() {
+            tip = commit;
+            continue;
+        }
+        let mut index = repo.cherrypick_commit(&commit, &tip, 0, None)?;
+        if index.has_conflicts() {
+            return Err(Error::from_str(&format!(
+                "{} doesn't apply cleanly there, nothing was changed",
+                short(item.oid)
+            )));
+        }
+        let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+        let message = lossy(commit.message_bytes()).0;
+
+        let oid = match item.verb {
+            Verb::Squash | Verb::Fixup => {
+                let previous = lossy(tip.message_bytes()).0;
+                // The `squash! ` summary only said where the commit goes.
+                let message = match message.strip_prefix("squash! ") {
+                    Some(rest) => rest.split_once("\n\n").map_or("", |(_, body)| body).into(),
+                    None => message,
+                };
+                let message = match item.verb {
+                    Verb::Squash if !message.trim().is_empty() => {
+                        format!("{}\n\n{message}", previous.trim_end())
+                    }
+                    _ => previous,
+                };
+                let parents: Vec<Commit> = tip.parents().collect();
+                let parents: Vec<&Commit> = parents.iter().collect();
+                repo.commit(None, &tip.author(), &signature, &message, &tree, &parents)?
+            }
+            _ => {
+                let message = match &item.reword {
+                    Some(summary) if item.verb == Verb::Reword => reword(&message, summary),
+                    _ => message,
+                };
+                repo.commit(None, &commit.author(), &signature, &message, &tree, &[&tip])?
+            }
+        };
+        tip = repo.find_commit(oid)?;
+    }
+
+    repo.checkout_tree(tip.as_object(), Some(CheckoutBuilder::new().safe()))?;
+    let message = format!("rebase -i (finish): onto {}", todo.onto);
+    repo.head()?.set_target(tip.id(), &message)?;
+    Ok(tip.id())
+}
+
+/// Message of commit `oid` with its summary replaced by `summary`.
+pub fn reworded_message(repo: &Repository, oid: Oid, summary: &str) -> Result<String, Error> {
+    let commit = repo.find_commit(oid)?;
+    Ok(reword(&lossy(commit.message_bytes()).0, summary))
+}
+
+/// Gives commit `oid` of the current branch the message `message`, see [`reworded_message`],
+/// and recreates the commits after it on top. Their trees stay as they are, so neither the
+/// index nor the working tree change. Returns the new HEAD commit.
+pub fn reword_commit(repo: &Repository, oid: Oid, message: &str) -> Result<Oid, Error> {
+    let mut head = repo.head()?;
+    let head_oid = head
+        .target()
+        .ok_or_else(|| Error::from_str("HEAD has no commit"))?;
+    if head_oid != oid && !repo.graph_descendant_of(head_oid, oid)? {
+        return Err(Error::from_str("the commit isn't on the current branch"));
+    }
+
+    let target = repo.find_commit(oid)?;
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(head_oid)?;
+    for parent in target.parent_ids() {
+        revwalk.hide(parent)?;
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+
+    let signature = repo.signature()?;
+    // Old ids of the rewritten commits to their new ones.
+    let mut rewritten: HashMap<Oid, Oid> = HashMap::new();
+    for id in revwalk {
+        let commit = repo.find_commit(id?)?;
+        let moved = commit.parent_ids().any(|p| rewritten.contains_key(&p));
+        if commit.id() != oid && !moved {
+            continue;
+        }
+        let message = if commit.id() == oid {
+            message.to_string()
+        } else {
+            lossy(commit.message_bytes()).0
+        };
+        let parents = commit
+            .parent_ids()
+            .map(|p| repo.find_commit(*rewritten.get(&p).unwrap_or(&p)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parents: Vec<&Commit> = parents.iter().collect();
+        let new = repo.commit(
+            None,
+            &commit.author(),
+            &signature,
+            &message,
+            &commit.tree()?,
+            &parents,
+        )?;
+        rewritten.insert(commit.id(), new);
+    }
+
+    let new_head = rewritten[&head_oid];
+    head.set_target(new_head, &format!("reword: {}", short(oid)))?;
+    Ok(new_head)
+}
+
+/// Message of a `fixup! ` commit of commit `target` of the current branch, for autosquash to
+/// fold the staged changes into it later. Fails when nothing is staged.
+pub fn fixup_message(repo: &Repository, target: Oid) -> Result<String, Error> {
+    let head = repo.head()?.peel_to_commit()?;
+    if head.id() != target && !repo.graph_descendant_of(head.id(), target)? {
+        return Err(Error::from_str("the commit isn't on the current branch"));
+    }
+    if repo.index()?.write_tree()? == head.tree_id() {
+        return Err(Error::from_str("nothing staged"));
+    }
+    let target = repo.find_commit(target)?;
+    Ok(format!(
+        "fixup! {}",
+        lossy(target.summary_bytes().unwrap_or_default()).0
+    ))
+}
+
+/// Commits the staged changes with `message`, the [`fixup_message`] once the commit hooks ran.
+/// Returns the new commit.
+pub fn fixup_commit(repo: &Repository, message: &str) -> Result<Oid, Error> {
+    let head = repo.head()?.peel_to_commit()?;
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let signature = repo.signature()?;
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&head],
+    )
+}
+
+/// A remote branch that already has commit `oid`, which rewriting would diverge from.
+pub fn pushed_to(repo: &Repository, oid: Oid) -> Option<String> {
+    let branches = repo.branches(Some(BranchType::Remote)).ok()?;
+    branches.flatten().find_map(|(branch, _)| {
+        let tip = branch.get().target()?;
+        if tip != oid && !repo.graph_descendant_of(tip, oid).unwrap_or(false) {
+            return None;
+        }
+        Some(lossy(branch.name_bytes().ok()?).0)
+    })
+}
+
+/// How a commit with `summary` is folded by autosquash and the name of the commit it is folded
+/// into. Nested prefixes such as `fixup! fixup! ` name the same commit. A prefix without a name
+/// folds nothing, as it would otherwise match every commit.
+fn fold_target(summary: &str) -> Option<(Verb, &str)> {
+    let (verb, mut name) = if let Some(name) = summary.strip_prefix("fixup! ") {
+        (Verb::Fixup, name)
+    } else {
+        (Verb::Squash, summary.strip_prefix("squash! ")?)
+    };
+    while let Some(rest) = name
+        .strip_prefix("fixup! ")
+        .or_else(|| name.strip_prefix("squash! "))
+    {
+        name = rest;
+    }
+    (!name.trim().is_empty()).then_some((verb, name))
+}
+
+/// `message` with its summary, the first paragraph, replaced.
+fn reword(message: &str, summary: &str) -> String {
+    match message.split_once("\n\n") {
+        Some((_, body)) => format!("{summary}\n\n{body}"),
+        None => format!("{summary}\n"),
+    }
+}
+
+fn short(oid: Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{commit_file, fixture};
+    use std::fs;
+
+    /// Fixture with commits adding `a.txt` to `d.txt` on `main` after the initial commit, which
+    /// is returned.
+    fn four_commits(name: &str) -> (Repository, Oid) {
+        let repo = fixture(name);
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Tester").unwrap();
+        config.set_str("user.email", "tester@example.com").unwrap();
+        let initial = repo.head().unwrap().target().unwrap();
+        for name in ["a", "b", "c", "d"] {
+            commit_file(&repo, &format!("{name}.txt"), name, &format!("Add {name}"));
+        }
+        (repo, initial)
+    }
+
+    /// Messages of the commits from HEAD down to the initial commit.
+    fn log(repo: &Repository) -> Vec<String> {
+        let mut commit = repo.head().unwrap().peel_to_commit().unwrap();
+        let mut messages = vec![commit.message().unwrap().to_string()];
+        while let Ok(parent) = commit.parent(0) {
+            messages.push(parent.message().unwrap().to_string());
+            commit = parent;
+        }
+        messages
+    }
+
+    fn head_has(repo: &Repository, path: &str) -> bool {
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        tree.get_path(path.as_ref()).is_ok()
+    }
+
+    /// Todo of commits with `summaries`, newest first, with ids `1111…`, `2222…` and so on from
+    /// the oldest.
+    fn todo(summaries: &[&str]) -> Todo {
+        let count = summaries.len();
+        let items = summaries
+            .iter()
+            .enumerate()
+            .map(|(i, summary)| TodoItem {
+                verb: Verb::Pick,
+                oid: Oid::from_str(&(count - i).to_string().repeat(40)).unwrap(),
+                summary: summary.to_string(),
+                reword: None,
+            })
+            .collect();
+        Todo {
+            onto: Oid::zero(),
+            items,
+        }
+    }
+
+    fn verbs_and_summaries(todo: &Todo) -> Vec<(&'static str, &str)> {
+        let items = todo.items.iter();
+        items.map(|i| (i.verb.name(), i.summary.as_str())).collect()
+    }
+
+    #[test]
+    fn autosquash_moves_folds_after_their_targets() {
+        let mut todo = todo(&[
+            "fixup! 1111",
+            "fixup! fixup! Add a",
+            "fixup! ",
+            "squash! Add b",
+            "fixup! Add a",
+            "Add b",
+            "Add a",
+        ]);
+        let id = |n: u32| Oid::from_str(&n.to_string().repeat(40)).unwrap();
+        assert_eq!(
+            todo.autosquash(),
+            [
+                (id(3), id(1)),
+                (id(4), id(2)),
+                (id(6), id(1)),
+                (id(7), id(1))
+            ]
+        );
+        // Newest first, each fold applied after the target and the folds before it.
+        assert_eq!(
+            verbs_and_summaries(&todo),
+            [
+                ("pick", "fixup! "),
+                ("squash", "squash! Add b"),
+                ("pick", "Add b"),
+                ("fixup", "fixup! 1111"),
+                ("fixup", "fixup! fixup! Add a"),
+                ("fixup", "fixup! Add a"),
+                ("pick", "Add a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn autosquash_ignores_folds_without_a_target() {
+        // The fold names a commit that comes after it, or none at all.
+        let mut todo = todo(&["Add a", "fixup! Add a", "squash!  ", "fixup! Add z"]);
+        assert!(!todo.items[2].is_fold());
+        assert_eq!(todo.autosquash(), []);
+        assert_eq!(
+            verbs_and_summaries(&todo),
+            [
+                ("pick", "Add a"),
+                ("pick", "fixup! Add a"),
+                ("pick", "squash!  "),
+                ("pick", "fixup! Add z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn run_todo_picks_drops_and_reorders() {
+        let (repo, initial) = four_commits("todo-reorder");
+        let a = repo.revparse_single("HEAD~3").unwrap().id();
+        let mut todo = Todo::after(&repo, initial).unwrap();
+        let summaries: Vec<&str> = todo.items.iter().map(|i| i.summary.as_str()).collect();
+        assert_eq!(summaries, ["Add d", "Add c", "Add b", "Add a"]);
+        todo.items[2].verb = Verb::Drop;
+        // Apply `Add d` before `Add c`.
+        assert_eq!(todo.move_item(0, false), Some(1));
+
+        let head = run_todo(&repo, &todo).unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(head));
+        assert_eq!(repo.head().unwrap().shorthand(), Some("main"));
+        assert_eq!(log(&repo), ["Add c", "Add d", "Add a", "Initial commit"]);
+        // `Add a` stays where it was, so it is kept as it is.
+        assert_eq!(repo.revparse_single("HEAD~2").unwrap().id(), a);
+        assert!(!head_has(&repo, "b.txt"));
+        assert!(!repo.workdir().unwrap().join("b.txt").exists());
+        assert!(head_has(&repo, "c.txt") && head_has(&repo, "d.txt"));
+    }
+
+    #[test]
+    fn run_todo_squashes_fixes_up_and_rewords() {
+        let (repo, initial) = four_commits("todo-squash");
+        let mut todo = Todo::after(&repo, initial).unwrap();
+        todo.items[0].verb = Verb::Fixup;
+        todo.items[1].verb = Verb::Squash;
+        todo.items[3].verb = Verb::Reword;
+        todo.items[3].reword = Some("Add the letter a".to_string());
+
+        run_todo(&repo, &todo).unwrap();
+        assert_eq!(
+            log(&repo),
+            ["Add b\n\nAdd c", "Add the letter a\n", "Initial commit"]
+        );
+        for path in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+            assert!(head_has(&repo, path), "{path}");
+        }
+        assert!(!is_dirty(&repo));
+    }
+
+    #[test]
+    fn run_todo_changes_nothing_when_a_commit_does_not_apply() {
+        let (repo, initial) = four_commits("todo-conflict");
+        commit_file(&repo, "a.txt", "changed", "Change a");
+        let head = repo.head().unwrap().target().unwrap();
+
+        // `Change a` can't apply without `Add a`.
+        let mut todo = Todo::after(&repo, initial).unwrap();
+        todo.items[4].verb = Verb::Drop;
+        let error = run_todo(&repo, &todo).unwrap_err();
+        let change = &todo.items[0];
+        assert_eq!(change.summary, "Change a");
+        assert_eq!(
+            error.message(),
+            format!(
+                "{} doesn't apply cleanly there, nothing was changed",
+                short(change.oid)
+            )
+        );
+        assert_eq!(repo.head().unwrap().target(), Some(head));
+        assert!(!is_dirty(&repo));
+
+        fs::write(repo.workdir().unwrap().join("a.txt"), "local").unwrap();
+        let todo = Todo::after(&repo, initial).unwrap();
+        assert_eq!(
+            run_todo(&repo, &todo).unwrap_err().message(),
+            "working tree has uncommitted changes, commit or stash them first"
+        );
+        assert_eq!(repo.head().unwrap().target(), Some(head));
+    }
+
+    #[test]
+    fn fixup_commit_commits_the_staged_changes() {
+        let (repo, initial) = four_commits("fixup");
+        let b = repo.revparse_single("HEAD~2").unwrap().id();
+        assert_eq!(
+            fixup_message(&repo, b).unwrap_err().message(),
+            "nothing staged"
+        );
+
+        fs::write(repo.workdir().unwrap().join("b.txt"), "b, fixed").unwrap();
+        fs::write(repo.workdir().unwrap().join("c.txt"), "c, not staged").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path("b.txt".as_ref()).unwrap();
+        index.write().unwrap();
+        let message = fixup_message(&repo, b).unwrap();
+        assert_eq!(message, "fixup! Add b");
+        let oid = fixup_commit(&repo, &message).unwrap();
+
+        let commit = repo.find_commit(oid).unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(oid));
+        assert_eq!(commit.message(), Some("fixup! Add b"));
+        assert_eq!(log(&repo)[1], "Add d");
+        let tree = commit.tree().unwrap();
+        let content = |path: &str| {
+            let entry = tree.get_path(path.as_ref()).unwrap();
+            let blob = repo.find_blob(entry.id()).unwrap();
+            String::from_utf8(blob.content().to_vec()).unwrap()
+        };
+        assert_eq!(content("b.txt"), "b, fixed");
+        assert_eq!(content("c.txt"), "c");
+
+        // Autosquash folds it into `Add b`.
+        let mut todo = Todo::after(&repo, initial).unwrap();
+        assert_eq!(todo.autosquash(), [(oid, b)]);
+    }
+
+    #[test]
+    fn reword_commit_keeps_the_trees() {
+        let (repo, _) = four_commits("reword");
+        let before: Vec<Oid> = (0..4)
+            .map(|n| {
+                let commit = repo.revparse_single(&format!("HEAD~{n}")).unwrap();
+                commit.peel_to_commit().unwrap().tree_id()
+            })
+            .collect();
+        let b = repo.revparse_single("HEAD~2").unwrap().id();
+        let a = repo.revparse_single("HEAD~3").unwrap().id();
+
+        let message = reworded_message(&repo, b, "Add the letter b").unwrap();
+        let head = reword_commit(&repo, b, &message).unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(head));
+        assert_eq!(
+            log(&repo),
+            [
+                "Add d",
+                "Add c",
+                "Add the letter b\n",
+                "Add a",
+                "Initial commit"
+            ]
+        );
+        for (n, tree) in before.iter().enumerate() {
+            let commit = repo.revparse_single(&format!("HEAD~{n}")).unwrap();
+            assert_eq!(commit.peel_to_commit().unwrap().tree_id(), *tree);
+        }
+        assert_eq!(repo.revparse_single("HEAD~3").unwrap().id(), a);
+        assert!(!is_dirty(&repo));
+
+        let initial = repo
+            .revparse_single("HEAD~4")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        let sig = repo.signature().unwrap();
+        let elsewhere = repo
+            .commit(
+                None,
+                &sig,
+                &sig,
+                "Elsewhere",
+                &initial.tree().unwrap(),
+                &[&initial],
+            )
+            .unwrap();
+        assert_eq!(
+            reword_commit(&repo, elsewhere, "Nope")
+                .unwrap_err()
+                .message(),
+            "the commit isn't on the current branch"
+        );
+        assert_eq!(repo.head().unwrap().target(), Some(head));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::lossy::path_to_bytes;
+use git2::{Oid, Repository};
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// Symbolic refs are followed this many levels at most, like git does.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// A ref read straight from the ref files.
+pub struct RawRef {
+    /// Full name such as `refs/heads/main`, byte for byte.
+    pub name: Vec<u8>,
+    /// Commit the ref points to after following symbolic refs, `None` if it dangles.
+    pub target: Option<Oid>,
+}
+
+/// Refs whose name starts with one of `prefixes` (e.g. `refs/heads/`), read from `packed-refs`
+/// and the loose ref files directly, sorted by name. Nothing is peeled, validated or looked up
+/// in the object database, which keeps listing tens of thousands of refs in the milliseconds
+/// where going through libgit2 one ref at a time takes seconds. Loose refs win over packed ones
+/// like in git.
+///
+/// `None` when the refs aren't stored in files this understands, such as a reftable.
+pub fn read_refs(repo: &Repository, prefixes: &[&str]) -> Option<Vec<RawRef>> {
+    let common = repo.commondir();
+    if common.join("reftable").exists() {
+        return None;
+    }
+
+    let mut targets = BTreeMap::new();
+    match fs::read(common.join("packed-refs")) {
+        Ok(packed) => parse_packed(&packed, &mut targets)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(_) => return None,
+    }
+    for prefix in prefixes {
+        let dir = common.join(prefix.trim_end_matches('/'));
+        read_loose(&dir, prefix.as_bytes(), &mut targets).ok()?;
+    }
+
+    let refs = targets
+        .iter()
+        .filter(|(name, _)| prefixes.iter().any(|p| name.starts_with(p.as_bytes())))
+        .map(|(name, target)| RawRef {
+            name: name.clone(),
+            target: resolve(&targets, target, 0),
+        })
+        .collect();
+    Some(refs)
+}
+
+/// Full name of the branch checked out in `repo`, `None` when HEAD is detached.
+pub fn head_ref(repo: &Repository) -> Option<Vec<u8>> {
+    let head = fs::read(repo.path().join("HEAD")).ok()?;
+    head.strip_prefix(b"ref: ")
+        .map(|name| name.trim_ascii_end().to_vec())
+}
+
+/// What a ref file says: an object id or the name of another ref.
+enum Target {
+    Direct(Oid),
+    Symbolic(Vec<u8>),
+}
+
+fn parse_target(content: &[u8]) -> Option<Target> {
+    let content = content.trim_ascii_end();
+    match content.strip_prefix(b"ref: ") {
+        Some(name) => Some(Target::Symbolic(name.to_vec())),
+        None => Oid::from_str(std::str::from_utf8(content).ok()?)
+            .ok()
+            .map(Target::Direct),
+    }
+}
+
+/// Lines of `<oid> <name>`, each optionally followed by a `^<oid>` line with the peeled id of
+/// an annotated tag, after a `#` header line.
+fn parse_packed(packed: &[u8], targets: &mut BTreeMap<Vec<u8>, Target>) -> Option<()> {
+    for line in packed.split(|&b| b == b'\n') {
+        if line.is_empty() || line.starts_with(b"#") || line.starts_with(b"^") {
+            continue;
+        }
+        let space = line.iter().position(|&b| b == b' ')?;
+        let oid = Oid::from_str(std::str::from_utf8(&line[..space]).ok()?).ok()?;
+        targets.insert(line[space + 1..].to_vec(), Target::Direct(oid));
+    }
+    Some(())
+}
+
+/// Adds the refs in `dir` and its subdirectories, named `prefix` followed by their path.
+fn read_loose(
+    dir: &Path,
+    prefix: &[u8],
+    targets: &mut BTreeMap<Vec<u8>, Target>,
+) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_name = path_to_bytes(Path::new(&entry.file_name()));
+        let mut name = prefix.to_vec();
+        name.extend_from_slice(&file_name);
+        if entry.file_type()?.is_dir() {
+            name.push(b'/');
+            read_loose(&entry.path(), &name, targets)?;
+        } else if !file_name.ends_with(b".lock")
+            && let Some(target) = parse_target(&fs::read(entry.path())?)
+        {
+            targets.insert(name, target);
+        }
+    }
+    Ok(())
+}
+
+fn resolve(targets: &BTreeMap<Vec<u8>, Target>, target: &Target, depth: usize) -> Option<Oid> {
+    match target {
+        Target::Direct(oid) => Some(*oid),
+        Target::Symbolic(name) if depth < MAX_SYMREF_DEPTH => {
+            resolve(targets, targets.get(name)?, depth + 1)
+        }
+        Target::Symbolic(_) => None,
+    }
+}
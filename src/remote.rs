@@ -0,0 +1,207 @@
+use git2::{
+    BranchType, Buf, Cred, CredentialType, Direction, Error, FetchOptions, FetchPrune, PushOptions,
+    RemoteCallbacks, Repository,
+};
+
+pub struct RemoteItem {
+    pub name: String,
+    pub url: String,
+}
+
+pub fn query_remotes(repo: &Repository) -> Vec<RemoteItem> {
+    let Ok(names) = repo.remotes() else {
+        return Vec::new();
+    };
+
+    names
+        .iter()
+        .flatten()
+        .map(|name| RemoteItem {
+            name: name.to_string(),
+            url: repo
+                .find_remote(name)
+                .ok()
+                .and_then(|r| r.url().map(|u| u.to_string()))
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Callbacks trying the ssh agent, then git's credential helpers, then default credentials.
+pub fn remote_callbacks<'a>(repo: &Repository) -> RemoteCallbacks<'a> {
+    let config = repo.config().ok();
+    let mut tried_ssh = false;
+    let mut tried_helper = false;
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(CredentialType::SSH_KEY) && !tried_ssh {
+            tried_ssh = true;
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && !tried_helper {
+            tried_helper = true;
+            if let Some(config) = &config {
+                return Cred::credential_helper(config, url, username);
+            }
+        }
+        if allowed.contains(CredentialType::DEFAULT) {
+            return Cred::default();
+        }
+        Err(Error::from_str("no usable credentials"))
+    });
+    callbacks
+}
+
+/// Fetches every configured remote with pruning. Returns the names of remotes that failed.
+pub fn fetch_all(repo: &Repository) -> Vec<(String, Error)> {
+    let mut failed = Vec::new();
+    let Ok(names) = repo.remotes() else {
+        return failed;
+    };
+
+    for name in names.iter().flatten() {
+        let result = repo.find_remote(name).and_then(|mut remote| {
+            let mut opts = FetchOptions::new();
+            opts.prune(FetchPrune::On)
+                .remote_callbacks(remote_callbacks(repo));
+            remote.fetch::<&str>(&[], Some(&mut opts), None)
+        });
+        if let Err(e) = result {
+            failed.push((name.to_string(), e));
+        }
+    }
+    failed
+}
+
+/// Pushes local branch `refname` to its upstream, or to a branch of the same name on `origin`
+/// when it has none. Only fast-forwards are pushed. Returns the remote pushed to.
+pub fn push_branch(repo: &Repository, refname: &str) -> Result<String, Error> {
+    let remote_name = match repo.branch_upstream_remote(refname) {
+        Ok(name) => buf_string(name)?,
+        Err(_) => "origin".to_string(),
+    };
+    let target = match repo.branch_upstream_merge(refname) {
+        Ok(name) => buf_string(name)?,
+        Err(_) => refname.to_string(),
+    };
+
+    let mut remote = repo.find_remote(&remote_name)?;
+    let mut rejected = None;
+    {
+        let mut callbacks = remote_callbacks(repo);
+        callbacks.push_update_reference(|_, status| {
+            rejected = status.map(|s| s.to_string());
+            Ok(())
+        });
+        let mut opts = PushOptions::new();
+        opts.remote_callbacks(callbacks);
+        remote.push(&[format!("{refname}:{target}")], Some(&mut opts))?;
+    }
+    match rejected {
+        Some(reason) => Err(Error::from_str(&format!(
+            "{remote_name} rejected it: {reason}"
+        ))),
+        None => Ok(remote_name),
+    }
+}
+
+/// Fetches the remote branch `refname` tracks: the upstream of a local branch or the branch a
+/// remote-tracking branch is a copy of. Returns the remote fetched from.
+pub fn fetch_branch(repo: &Repository, refname: &str) -> Result<String, Error> {
+    let no_upstream = |_| Error::from_str("it has no upstream");
+    let (remote_name, tracking) = if refname.starts_with("refs/remotes/") {
+        (
+            buf_string(repo.branch_remote_name(refname)?)?,
+            refname.to_string(),
+        )
+    } else {
+        (
+            buf_string(repo.branch_upstream_remote(refname).map_err(no_upstream)?)?,
+            buf_string(repo.branch_upstream_name(refname).map_err(no_upstream)?)?,
+        )
+    };
+
+    let mut remote = repo.find_remote(&remote_name)?;
+    let source = remote
+        .refspecs()
+        .filter(|spec| spec.direction() == Direction::Fetch && spec.dst_matches(&tracking))
+        .find_map(|spec| spec.rtransform(&tracking).ok())
+        .ok_or_else(|| Error::from_str(&format!("{remote_name} doesn't fetch {tracking}")))?;
+    let source = buf_string(source)?;
+
+    let mut opts = FetchOptions::new();
+    opts.remote_callbacks(remote_callbacks(repo));
+    remote.fetch(&[format!("+{source}:{tracking}")], Some(&mut opts), None)?;
+    Ok(remote_name)
+}
+
+fn buf_string(buf: Buf) -> Result<String, Error> {
+    buf.as_str()
+        .map(str::to_string)
+        .ok_or_else(|| Error::from_str("name isn't valid UTF-8"))
+}
+
+/// Result of [`backup_push`] for one ref.
+pub struct PushedRef {
+    pub refname: String,
+    pub deleted: bool,
+    /// Rejection reason reported by the remote.
+    pub error: Option<String>,
+}
+
+/// Makes the branches and tags of remote `name` match the local ones: force-pushes every local
+/// branch and tag and deletes those that only exist on the remote.
+pub fn backup_push(repo: &Repository, name: &str) -> Result<Vec<PushedRef>, Error> {
+    let mut local = Vec::new();
+    for (branch, _) in repo.branches(Some(BranchType::Local))?.flatten() {
+        if let Some(refname) = branch.get().name() {
+            local.push(refname.to_string());
+        }
+    }
+    for tag in repo.tag_names(None)?.iter().flatten() {
+        local.push(format!("refs/tags/{tag}"));
+    }
+
+    let mut remote = repo.find_remote(name)?;
+    let stale: Vec<String> = {
+        let connection =
+            remote.connect_auth(Direction::Push, Some(remote_callbacks(repo)), None)?;
+        connection
+            .list()?
+            .iter()
+            .map(|head| head.name().to_string())
+            .filter(|refname| {
+                (refname.starts_with("refs/heads/") || refname.starts_with("refs/tags/"))
+                    && !refname.ends_with("^{}")
+                    && !local.contains(refname)
+            })
+            .collect()
+    };
+
+    let refspecs: Vec<String> = local
+        .iter()
+        .map(|refname| format!("+{refname}:{refname}"))
+        .chain(stale.iter().map(|refname| format!(":{refname}")))
+        .collect();
+    if refspecs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut results = Vec::new();
+    {
+        let mut callbacks = remote_callbacks(repo);
+        callbacks.push_update_reference(|refname, status| {
+            results.push(PushedRef {
+                refname: refname.to_string(),
+                deleted: stale.iter().any(|s| s == refname),
+                error: status.map(|s| s.to_string()),
+            });
+            Ok(())
+        });
+        let mut opts = PushOptions::new();
+        opts.remote_callbacks(callbacks);
+        remote.push(&refspecs, Some(&mut opts))?;
+    }
+    Ok(results)
+}
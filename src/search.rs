@@ -0,0 +1,250 @@
+//! Matching branch names against the search, fzf-style by default: the characters of the
+//! search have to appear in the name in order but not next to each other, and names where they
+//! line up at word starts or in runs rank higher, so `fauth` finds `feature/oauth-login`.
+//!
+//! The search is split at spaces into terms that all have to match, and terms starting with `!`
+//! must not match: `release !rc` finds `release/1.0` but not `release/1.0-rc1`.
+
+/// How the search matches, set with `gix.search`.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    #[default]
+    Fuzzy,
+    /// The name contains the search as is, ignoring case.
+    Substring,
+}
+
+impl SearchMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "fuzzy" => Some(SearchMode::Fuzzy),
+            "substring" => Some(SearchMode::Substring),
+            _ => None,
+        }
+    }
+}
+
+/// How well a name matched and which of its characters did.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Higher is better, always 0 for substring matches.
+    pub score: i32,
+    /// Indices of the matched characters, counted in chars.
+    pub positions: Vec<usize>,
+}
+
+/// A search split into its terms.
+pub struct Query<'a> {
+    include: Vec<&'a str>,
+    exclude: Vec<&'a str>,
+}
+
+impl<'a> Query<'a> {
+    pub fn parse(search: &'a str) -> Self {
+        let mut query = Query {
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+        for term in search.split_whitespace() {
+            match term.strip_prefix('!') {
+                Some("") => {}
+                Some(term) => query.exclude.push(term),
+                None => query.include.push(term),
+            }
+        }
+        query
+    }
+
+    /// Score of the best match of every term in any of `fields` added up, `None` when a term
+    /// matches none of them or an excluded term matches one.
+    pub fn score(&self, mode: SearchMode, fields: &[&str]) -> Option<i32> {
+        let matches = |term: &str, field: &str| find(mode, term, field);
+        if self
+            .exclude
+            .iter()
+            .any(|term| fields.iter().any(|field| matches(term, field).is_some()))
+        {
+            return None;
+        }
+        self.include.iter().try_fold(0, |total, term| {
+            let best = fields
+                .iter()
+                .filter_map(|field| matches(term, field).map(|m| m.score))
+                .max()?;
+            Some(total + best)
+        })
+    }
+
+    /// Characters of `text` matched by any of the terms, counted in chars and sorted.
+    pub fn positions(&self, mode: SearchMode, text: &str) -> Vec<usize> {
+        let mut positions: Vec<usize> = self
+            .include
+            .iter()
+            .filter_map(|term| find(mode, term, text))
+            .flat_map(|m| m.positions)
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+        positions
+    }
+}
+
+const SCORE_MATCH: i32 = 16;
+const GAP_START: i32 = 3;
+const GAP_EXTENSION: i32 = 1;
+/// After a separator such as `/` or `-`, or at the start.
+const BONUS_BOUNDARY: i32 = 8;
+/// An uppercase letter after a lowercase one.
+const BONUS_CAMEL: i32 = 7;
+const BONUS_CONSECUTIVE: i32 = 4;
+/// The bonus of the first character of the search counts this many times.
+const FIRST_MULTIPLIER: i32 = 2;
+
+/// Matches `text` against `pattern`, `None` when it doesn't match. An empty pattern matches
+/// everything. Case is ignored unless the pattern has uppercase letters.
+pub fn find(mode: SearchMode, pattern: &str, text: &str) -> Option<Match> {
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    match mode {
+        SearchMode::Fuzzy => fuzzy(pattern, text),
+        SearchMode::Substring => {
+            let lowercase = text.to_lowercase();
+            let pattern = pattern.to_lowercase();
+            let start = lowercase.find(&pattern)?;
+            // Lowercasing can change byte lengths, so count chars in the lowercased text.
+            let first = lowercase[..start].chars().count();
+            Some(Match {
+                score: 0,
+                positions: (first..first + pattern.chars().count()).collect(),
+            })
+        }
+    }
+}
+
+fn fuzzy(pattern: &str, text: &str) -> Option<Match> {
+    let ignore_case = !pattern.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if ignore_case {
+            c.to_lowercase().next().unwrap_or(c)
+        } else {
+            c
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let chars: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = chars.iter().map(|c| fold(*c)).collect();
+    let bonus: Vec<i32> = (0..chars.len())
+        .map(|j| match j.checked_sub(1).map(|k| chars[k]) {
+            None => BONUS_BOUNDARY,
+            Some(prev) if "/-_. ".contains(prev) => BONUS_BOUNDARY,
+            Some(prev) if prev.is_lowercase() && chars[j].is_uppercase() => BONUS_CAMEL,
+            Some(_) => 0,
+        })
+        .collect();
+
+    // scores[i][j]: best score with pattern[i] matched at text[j], and where pattern[i - 1]
+    // was matched for that score.
+    let mut scores: Vec<Vec<Option<i32>>> = Vec::with_capacity(pattern.len());
+    let mut from: Vec<Vec<usize>> = Vec::with_capacity(pattern.len());
+    for (i, &p) in pattern.iter().enumerate() {
+        let mut row = vec![None; chars.len()];
+        let mut row_from = vec![0; chars.len()];
+        // Best score of the previous row followed by a gap of at least one character.
+        let mut gap: Option<(i32, usize)> = None;
+        for j in 0..chars.len() {
+            if i > 0 && j >= 2 {
+                let extended = gap.map(|(score, k)| (score - GAP_EXTENSION, k));
+                let started = scores[i - 1][j - 2].map(|score| (score - GAP_START, j - 2));
+                gap = extended.max(started);
+            }
+            if folded[j] != p {
+                continue;
+            }
+            if i == 0 {
+                row[j] = Some(SCORE_MATCH + bonus[j] * FIRST_MULTIPLIER);
+                continue;
+            }
+            let consecutive = j
+                .checked_sub(1)
+                .and_then(|k| scores[i - 1][k].map(|score| (score + BONUS_CONSECUTIVE, k)));
+            if let Some((score, k)) = consecutive.max(gap) {
+                row[j] = Some(score + SCORE_MATCH + bonus[j]);
+                row_from[j] = k;
+            }
+        }
+        scores.push(row);
+        from.push(row_from);
+    }
+
+    let last = pattern.len() - 1;
+    let (score, mut j) = scores[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| Some(((*score)?, j)))
+        .max_by_key(|(score, j)| (*score, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![0; pattern.len()];
+    for i in (0..=last).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(Match { score, positions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matches_rank_word_starts_higher() {
+        let login = find(SearchMode::Fuzzy, "fauth", "feature/oauth-login").unwrap();
+        assert_eq!(login.positions, [0, 9, 10, 11, 12]);
+        assert_eq!(find(SearchMode::Fuzzy, "fauth", "auth/fix"), None);
+
+        let prefix = find(SearchMode::Fuzzy, "fix", "fix/typo").unwrap();
+        let scattered = find(SearchMode::Fuzzy, "fix", "feature/index").unwrap();
+        assert!(prefix.score > scattered.score);
+
+        // Uppercase in the search makes it case-sensitive.
+        assert!(find(SearchMode::Fuzzy, "Fix", "fix/typo").is_none());
+        assert_eq!(
+            find(SearchMode::Substring, "OAUTH", "feature/oauth-login").map(|m| m.positions),
+            Some(vec![8, 9, 10, 11, 12])
+        );
+    }
+
+    #[test]
+    fn every_term_has_to_match_and_none_of_the_excluded() {
+        let query = Query::parse("release !rc");
+        assert!(query.score(SearchMode::Fuzzy, &["release/1.0"]).is_some());
+        assert!(
+            query
+                .score(SearchMode::Fuzzy, &["release/1.0-rc1"])
+                .is_none()
+        );
+        assert!(
+            query
+                .score(SearchMode::Fuzzy, &["release/1.0", "rc fixes"])
+                .is_none()
+        );
+
+        let query = Query::parse("feat login");
+        assert!(
+            query
+                .score(SearchMode::Substring, &["feature/oauth"])
+                .is_none()
+        );
+        assert!(
+            query
+                .score(SearchMode::Substring, &["feature/oauth", "login"])
+                .is_some()
+        );
+        assert_eq!(
+            query.positions(SearchMode::Substring, "feature/oauth-login"),
+            [0, 1, 2, 3, 14, 15, 16, 17, 18]
+        );
+    }
+}
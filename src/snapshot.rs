@@ -0,0 +1,206 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const FILE_NAME: &str = "gix-snapshot";
+
+/// Input typed into gix but not submitted yet. It is written to the git directory whenever it
+/// changes, so a crash or an accidental quit doesn't lose it and the next launch can offer to
+/// restore it.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// View the input was typed in.
+    pub view: Option<String>,
+    /// Kind and text of the open prompt.
+    pub prompt: Option<(String, String)>,
+    /// Search pattern that was being typed.
+    pub search: String,
+    /// Interactive rebase being edited, the commits still to be cherry-picked.
+    pub rebase: Option<SavedTodo>,
+}
+
+/// Todo list of the rebase view, by commit id so it can be checked against the repository
+/// before it is restored.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SavedTodo {
+    pub onto: String,
+    /// Verb, commit id and new summary of each item, newest first like the rebase view.
+    pub items: Vec<(String, String, Option<String>)>,
+}
+
+impl Snapshot {
+    pub fn is_empty(&self) -> bool {
+        self.prompt.is_none() && self.search.is_empty() && self.rebase.is_none()
+    }
+
+    /// Snapshot left in `git_dir` by a previous session, if any.
+    pub fn load(git_dir: &Path) -> Option<Self> {
+        Some(Self::parse(&fs::read_to_string(path(git_dir)).ok()?))
+    }
+
+    /// Reads the lines [`Snapshot::save`] writes, a key and an escaped value each.
+    fn parse(content: &str) -> Self {
+        let mut snapshot = Self::default();
+        let mut kind = None;
+        for line in content.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "view" => snapshot.view = Some(unescape(value)),
+                "prompt" => kind = Some(unescape(value)),
+                "input" => {
+                    if let Some(kind) = kind.take() {
+                        snapshot.prompt = Some((kind, unescape(value)));
+                    }
+                }
+                "search" => snapshot.search = unescape(value),
+                "rebase" => {
+                    snapshot.rebase = Some(SavedTodo {
+                        onto: unescape(value),
+                        items: Vec::new(),
+                    })
+                }
+                "todo" => {
+                    let mut fields = value.splitn(3, ' ').map(unescape);
+                    if let (Some(todo), Some(verb), Some(oid)) =
+                        (&mut snapshot.rebase, fields.next(), fields.next())
+                    {
+                        todo.items.push((verb, oid, fields.next()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        snapshot
+    }
+
+    /// Replaces the snapshot in `git_dir`, removing it when there is nothing to keep. It is
+    /// written to a temporary file that is renamed over the old one, so a crash while writing
+    /// never leaves half a snapshot behind.
+    pub fn save(&self, git_dir: &Path) -> io::Result<()> {
+        let path = path(git_dir);
+        if self.is_empty() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.serialize())?;
+        fs::rename(&tmp, &path)
+    }
+
+    /// A line per field, the key and the value escaped so it stays on that line.
+    fn serialize(&self) -> String {
+        let mut content = String::new();
+        if let Some(view) = &self.view {
+            content.push_str(&format!("view {}\n", escape(view)));
+        }
+        if let Some((kind, input)) = &self.prompt {
+            content.push_str(&format!(
+                "prompt {}\ninput {}\n",
+                escape(kind),
+                escape(input)
+            ));
+        }
+        if !self.search.is_empty() {
+            content.push_str(&format!("search {}\n", escape(&self.search)));
+        }
+        if let Some(todo) = &self.rebase {
+            content.push_str(&format!("rebase {}\n", escape(&todo.onto)));
+            for (verb, oid, reword) in &todo.items {
+                content.push_str(&format!("todo {} {}", escape(verb), escape(oid)));
+                if let Some(reword) = reword {
+                    content.push_str(&format!(" {}", escape(reword)));
+                }
+                content.push('\n');
+            }
+        }
+        content
+    }
+
+    /// Short description of what would be restored.
+    pub fn describe(&self) -> String {
+        match (&self.prompt, &self.rebase) {
+            (Some((kind, input)), _) => format!("{kind} '{input}'"),
+            (None, Some(todo)) => format!("rebase of {} commits", todo.items.len()),
+            (None, None) => format!("search '{}'", self.search),
+        }
+    }
+}
+
+/// `value` with backslashes, line breaks and spaces escaped, so it takes up one field of a line.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ' ' => escaped.push_str("\\s"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('s') => unescaped.push(' '),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+fn path(git_dir: &Path) -> PathBuf {
+    git_dir.join(FILE_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_multi_line_input_and_the_rebase() {
+        let snapshot = Snapshot {
+            view: Some("log".to_string()),
+            prompt: Some((
+                "command".to_string(),
+                "first line\nsecond \\n line\r\n".to_string(),
+            )),
+            search: "  spaced  ".to_string(),
+            rebase: Some(SavedTodo {
+                onto: "1234".to_string(),
+                items: vec![
+                    ("pick".to_string(), "abcd".to_string(), None),
+                    (
+                        "reword".to_string(),
+                        "ef01".to_string(),
+                        Some("New\nsummary with spaces".to_string()),
+                    ),
+                ],
+            }),
+        };
+        let content = snapshot.serialize();
+        assert_eq!(content.lines().count(), 7);
+        assert!(Snapshot::parse(&content) == snapshot);
+
+        let dir = std::env::temp_dir().join(format!("gix-test-snapshot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        snapshot.save(&dir).unwrap();
+        assert!(Snapshot::load(&dir) == Some(snapshot));
+        Snapshot::default().save(&dir).unwrap();
+        assert!(Snapshot::load(&dir).is_none());
+    }
+}
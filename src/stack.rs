@@ -0,0 +1,263 @@
+use crate::{
+    branch::{BranchItem, checkout_branch},
+    status::is_dirty,
+    worktree::WorktreeHeads,
+};
+use git2::{BranchType, Error, ErrorCode, Oid, Repository};
+use std::collections::HashMap;
+
+/// Parent of a stacked branch.
+pub struct StackParent {
+    pub name: String,
+    pub tip: Oid,
+    /// Parent commit the child is currently based on. Differs from `tip` once the parent has
+    /// moved on and the child needs a restack.
+    pub fork_point: Oid,
+}
+
+impl StackParent {
+    pub fn needs_restack(&self) -> bool {
+        self.tip != self.fork_point
+    }
+}
+
+/// Child branch name to its parent.
+pub type Stacks = HashMap<String, StackParent>;
+
+pub enum RestackOutcome {
+    Done(Vec<String>),
+    /// A rebase stopped on conflicts and was left in progress for the user to resolve.
+    Paused {
+        branch: String,
+        done: Vec<String>,
+    },
+}
+
+/// Detects parent/child relationships between local branches.
+///
+/// A branch tracking another local branch (`branch.<name>.remote = .`) is stacked on it.
+/// Otherwise the parent is the local branch closest below it, where "below" also accepts
+/// former tips of the parent found in its reflog so children stay attached after the parent
+/// was amended or rebased.
+pub fn detect_stacks(repo: &Repository) -> Stacks {
+    let mut tips: Vec<(String, Oid)> = Vec::new();
+    if let Ok(branches) = repo.branches(Some(BranchType::Local)) {
+        for (branch, _) in branches.flatten() {
+            if let (Ok(Some(name)), Some(tip)) = (branch.name(), branch.get().target()) {
+                tips.push((name.to_string(), tip));
+            }
+        }
+    }
+    let cfg = repo.config().ok();
+
+    let mut stacks = Stacks::new();
+    for (name, tip) in &tips {
+        let configured = cfg.as_ref().and_then(|cfg| {
+            let remote = cfg.get_string(&format!("branch.{name}.remote")).ok()?;
+            let merge = cfg.get_string(&format!("branch.{name}.merge")).ok()?;
+            let parent = merge.strip_prefix("refs/heads/")?;
+            if remote != "." || parent == name {
+                return None;
+            }
+            tips.iter().find(|(n, _)| n == parent)
+        });
+
+        let parent = match configured {
+            Some((parent, parent_tip)) => fork_point(repo, parent, *parent_tip, *tip)
+                .or_else(|| repo.merge_base(*tip, *parent_tip).ok())
+                .map(|fork| (parent, *parent_tip, fork)),
+            None => tips
+                .iter()
+                .filter(|(n, t)| n != name && t != tip)
+                .filter_map(|(n, t)| {
+                    let fork = fork_point(repo, n, *t, *tip)?;
+                    let (distance, _) = repo.graph_ahead_behind(*tip, fork).ok()?;
+                    Some(((distance, fork != *t, n), (n, *t, fork)))
+                })
+                .min_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, parent)| parent),
+        };
+
+        if let Some((parent, parent_tip, fork_point)) = parent {
+            stacks.insert(
+                name.clone(),
+                StackParent {
+                    name: parent.clone(),
+                    tip: parent_tip,
+                    fork_point,
+                },
+            );
+        }
+    }
+
+    // Amended parents can make two branches look stacked on each other. Break such cycles.
+    let names: Vec<String> = stacks.keys().cloned().collect();
+    for name in names {
+        let mut current = name.as_str();
+        let mut steps = 0;
+        while let Some(parent) = stacks.get(current) {
+            current = &parent.name;
+            steps += 1;
+            if current == name || steps > stacks.len() {
+                stacks.remove(&name);
+                break;
+            }
+        }
+    }
+
+    stacks
+}
+
+/// Latest commit of `parent` (its tip or a former tip from the reflog) that `child` is based on.
+fn fork_point(repo: &Repository, parent: &str, parent_tip: Oid, child: Oid) -> Option<Oid> {
+    let is_base = |oid: Oid| oid != child && repo.graph_descendant_of(child, oid).unwrap_or(false);
+    if is_base(parent_tip) {
+        return Some(parent_tip);
+    }
+
+    // The oldest entry records where the parent itself was created, which is not its own work.
+    let reflog = repo.reflog(&format!("refs/heads/{parent}")).ok()?;
+    reflog
+        .iter()
+        .take(reflog.len().saturating_sub(1))
+        .map(|entry| entry.id_new())
+        .find(|oid| is_base(*oid))
+}
+
+/// Reorders `branches` so each child follows its parent, setting `stack_depth` accordingly.
+/// Branches keep their relative order otherwise.
+pub fn order_by_stack(branches: Vec<BranchItem>, stacks: &Stacks) -> Vec<BranchItem> {
+    let listed = |name: &str| branches.iter().any(|b| b.name == name);
+    let is_root = |b: &BranchItem| {
+        b.is_remote
+            || stacks
+                .get(&b.name)
+                .is_none_or(|parent| !listed(&parent.name))
+    };
+
+    let mut order = Vec::with_capacity(branches.len());
+    let mut pending: Vec<(usize, usize)> = branches
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| is_root(b))
+        .map(|(i, _)| (i, 0))
+        .rev()
+        .collect();
+    while let Some((i, depth)) = pending.pop() {
+        order.push((i, depth));
+        let name = &branches[i].name;
+        pending.extend(
+            branches
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| {
+                    !b.is_remote && stacks.get(&b.name).is_some_and(|p| &p.name == name)
+                })
+                .map(|(c, _)| (c, depth + 1))
+                .rev(),
+        );
+    }
+
+    let mut slots: Vec<Option<BranchItem>> = branches.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|(i, depth)| {
+            let mut branch = slots[i].take()?;
+            branch.stack_depth = depth;
+            Some(branch)
+        })
+        .collect()
+}
+
+/// `root` (if it is stacked on something) and all branches stacked on top of it, parents first.
+fn restack_order(stacks: &Stacks, root: &str) -> Vec<String> {
+    let mut order = Vec::new();
+    if stacks.contains_key(root) {
+        order.push(root.to_string());
+    }
+
+    let mut pending = vec![root.to_string()];
+    while let Some(parent) = pending.pop() {
+        let mut children: Vec<&String> = stacks
+            .iter()
+            .filter(|(_, p)| p.name == parent)
+            .map(|(child, _)| child)
+            .collect();
+        children.sort();
+        for child in children.into_iter().rev() {
+            order.push(child.clone());
+            pending.push(child.clone());
+        }
+    }
+    order
+}
+
+/// Rebases `root` and every branch stacked on it onto the current tip of its parent, parents
+/// first. Stops with the rebase in progress when a commit doesn't apply cleanly, so it can be
+/// finished with `git rebase --continue`. `progress` is called before each branch.
+pub fn restack(
+    repo: &Repository,
+    stacks: &Stacks,
+    root: &str,
+    mut progress: impl FnMut(usize, usize, &str),
+) -> Result<RestackOutcome, Error> {
+    if is_dirty(repo) {
+        return Err(Error::from_str(
+            "working tree has uncommitted changes, commit or stash them first",
+        ));
+    }
+    let head = repo
+        .head()
+        .ok()
+        .and_then(|h| h.shorthand().map(|s| s.to_string()));
+    let signature = repo.signature()?;
+
+    let order = restack_order(stacks, root);
+    let mut done = Vec::new();
+    for (i, name) in order.iter().enumerate() {
+        progress(i + 1, order.len(), name);
+
+        let parent = &stacks[name];
+        let onto_oid = repo
+            .find_branch(&parent.name, BranchType::Local)?
+            .get()
+            .target()
+            .ok_or_else(|| Error::from_str("parent branch has no target"))?;
+        if onto_oid == parent.fork_point {
+            continue;
+        }
+
+        let branch = repo.find_branch(name, BranchType::Local)?;
+        let branch = repo.reference_to_annotated_commit(branch.get())?;
+        let upstream = repo.find_annotated_commit(parent.fork_point)?;
+        let onto = repo.find_annotated_commit(onto_oid)?;
+
+        let mut rebase = repo.rebase(Some(&branch), Some(&upstream), Some(&onto), None)?;
+        while let Some(operation) = rebase.next() {
+            operation?;
+            if repo.index()?.has_conflicts() {
+                return Ok(RestackOutcome::Paused {
+                    branch: name.clone(),
+                    done,
+                });
+            }
+            match rebase.commit(None, &signature, None) {
+                Err(e) if e.code() != ErrorCode::Applied => {
+                    rebase.abort()?;
+                    return Err(e);
+                }
+                _ => {}
+            }
+        }
+        rebase.finish(Some(&signature))?;
+        done.push(name.clone());
+    }
+
+    if let Some(head) = head
+        && !done.is_empty()
+    {
+        let worktrees = WorktreeHeads::read(repo);
+        checkout_branch(repo, head.as_bytes(), &worktrees, false, |_, _| true)?;
+    }
+    Ok(RestackOutcome::Done(done))
+}
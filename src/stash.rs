@@ -0,0 +1,35 @@
+use git2::{Error, Oid, Repository, StashApplyOptions};
+
+pub struct StashItem {
+    pub index: usize,
+    pub oid: Oid,
+    pub message: String,
+}
+
+/// Lists stashes. Needs a mutable repository since libgit2 walks the stash reflog.
+pub fn query_stashes(repo: &mut Repository) -> Vec<StashItem> {
+    let mut items = Vec::new();
+    let _ = repo.stash_foreach(|index, message, oid| {
+        items.push(StashItem {
+            index,
+            oid: *oid,
+            message: message.to_string(),
+        });
+        true
+    });
+    items
+}
+
+/// Stashes the changes to tracked files like `git stash` does, as "WIP on <branch>: ...".
+pub fn stash_changes(repo: &mut Repository) -> Result<Oid, Error> {
+    let signature = repo.signature()?;
+    repo.stash_save2(&signature, None, None)
+}
+
+/// Applies the newest stash, staged changes staged again, and drops it, like
+/// `git stash pop --index` does.
+pub fn pop_stash(repo: &mut Repository) -> Result<(), Error> {
+    let mut options = StashApplyOptions::new();
+    options.reinstantiate_index();
+    repo.stash_pop(0, Some(&mut options))
+}
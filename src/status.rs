@@ -0,0 +1,211 @@
+use crate::lossy::{bytes_to_path, lossy};
+use git2::{Error, IndexEntryExtendedFlag, IndexEntryFlag, Repository, Status, StatusOptions};
+use std::path::{Path, PathBuf};
+
+pub struct StatusItem {
+    /// Path for display, with replacement characters if it isn't valid UTF-8.
+    pub path: String,
+    /// Path as stored, only kept when it isn't valid UTF-8.
+    pub raw_path: Option<Vec<u8>>,
+    pub status: Status,
+    /// Git doesn't check the file for changes (`git update-index --assume-unchanged`).
+    pub assume_unchanged: bool,
+    /// Git leaves the file alone in the working tree (`git update-index --skip-worktree`).
+    pub skip_worktree: bool,
+}
+
+impl StatusItem {
+    /// Two letter index/worktree code, as printed by `git status --short`.
+    pub fn code(&self) -> String {
+        let s = self.status;
+        if s.is_conflicted() {
+            return "UU".to_string();
+        }
+        if s.is_wt_new() {
+            return "??".to_string();
+        }
+
+        let index = if s.is_index_new() {
+            'A'
+        } else if s.is_index_modified() {
+            'M'
+        } else if s.is_index_deleted() {
+            'D'
+        } else if s.is_index_renamed() {
+            'R'
+        } else if s.is_index_typechange() {
+            'T'
+        } else {
+            ' '
+        };
+        let worktree = if s.is_wt_modified() {
+            'M'
+        } else if s.is_wt_deleted() {
+            'D'
+        } else if s.is_wt_renamed() {
+            'R'
+        } else if s.is_wt_typechange() {
+            'T'
+        } else {
+            ' '
+        };
+        format!("{index}{worktree}")
+    }
+
+    /// Path of the file relative to the working tree, byte for byte.
+    pub fn fs_path(&self) -> PathBuf {
+        match &self.raw_path {
+            Some(raw) => bytes_to_path(raw),
+            None => PathBuf::from(&self.path),
+        }
+    }
+}
+
+fn status_item(path_bytes: &[u8], status: Status) -> StatusItem {
+    let (path, invalid) = lossy(path_bytes);
+    StatusItem {
+        path,
+        raw_path: invalid.then(|| path_bytes.to_vec()),
+        status,
+        assume_unchanged: false,
+        skip_worktree: false,
+    }
+}
+
+/// Changed and untracked files followed by files marked assume-unchanged or skip-worktree,
+/// only those under `path` if given.
+pub fn query_status(repo: &Repository, path: Option<&Path>) -> Vec<StatusItem> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true).recurse_untracked_dirs(true);
+    if let Some(path) = path {
+        opts.pathspec(path);
+    }
+
+    let Ok(statuses) = repo.statuses(Some(&mut opts)) else {
+        return Vec::new();
+    };
+
+    let mut items: Vec<StatusItem> = statuses
+        .iter()
+        .filter(|entry| !entry.status().is_ignored())
+        .map(|entry| status_item(entry.path_bytes(), entry.status()))
+        .collect();
+
+    let Ok(index) = repo.index() else {
+        return items;
+    };
+    for entry in index.iter() {
+        let assume_unchanged = entry.flags & IndexEntryFlag::VALID.bits() != 0;
+        let skip_worktree =
+            entry.flags_extended & IndexEntryExtendedFlag::SKIP_WORKTREE.bits() != 0;
+        if !assume_unchanged && !skip_worktree {
+            continue;
+        }
+        if path.is_some_and(|path| !bytes_to_path(&entry.path).starts_with(path)) {
+            continue;
+        }
+        let item = match items
+            .iter()
+            .position(|item| item.fs_path() == bytes_to_path(&entry.path))
+        {
+            Some(i) => &mut items[i],
+            None => {
+                items.push(status_item(&entry.path, Status::CURRENT));
+                items.last_mut().unwrap()
+            }
+        };
+        item.assume_unchanged = assume_unchanged;
+        item.skip_worktree = skip_worktree;
+    }
+    items
+}
+
+/// Index flags that can be toggled per file.
+#[derive(Clone, Copy)]
+pub enum IndexFlag {
+    AssumeUnchanged,
+    SkipWorktree,
+}
+
+/// Flips `flag` on the index entry of `path`. Returns whether it is now set.
+pub fn toggle_index_flag(repo: &Repository, path: &Path, flag: IndexFlag) -> Result<bool, Error> {
+    let mut index = repo.index()?;
+    let mut entry = index
+        .get_path(path, 0)
+        .ok_or_else(|| Error::from_str("file is not tracked"))?;
+
+    let set = match flag {
+        IndexFlag::AssumeUnchanged => {
+            entry.flags ^= IndexEntryFlag::VALID.bits();
+            entry.flags & IndexEntryFlag::VALID.bits() != 0
+        }
+        IndexFlag::SkipWorktree => {
+            entry.flags_extended ^= IndexEntryExtendedFlag::SKIP_WORKTREE.bits();
+            entry.flags_extended & IndexEntryExtendedFlag::SKIP_WORKTREE.bits() != 0
+        }
+    };
+    if entry.flags_extended == 0 {
+        entry.flags &= !IndexEntryFlag::EXTENDED.bits();
+    } else {
+        entry.flags |= IndexEntryFlag::EXTENDED.bits();
+    }
+
+    index.add(&entry)?;
+    index.write()?;
+    Ok(set)
+}
+
+/// Whether tracked files have staged or unstaged changes. Untracked files don't count.
+pub fn is_dirty(repo: &Repository) -> bool {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+
+    repo.statuses(Some(&mut opts))
+        .map(|statuses| !statuses.is_empty())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{commit_file, fixture};
+    use std::process::Command;
+
+    #[test]
+    fn toggles_index_flags() {
+        let repo = fixture("index-flags");
+        commit_file(&repo, "a.txt", "a\n", "Add a");
+        let path = Path::new("a.txt");
+        let entry = |repo: &Repository| repo.index().unwrap().get_path(path, 0).unwrap();
+        // `git ls-files -v` tags skip-worktree files with S, lowercase when they are also
+        // assume-unchanged.
+        let tag = || {
+            let output = Command::new("git")
+                .args(["ls-files", "-v", "a.txt"])
+                .current_dir(repo.workdir().unwrap())
+                .output()
+                .unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        assert!(toggle_index_flag(&repo, path, IndexFlag::AssumeUnchanged).unwrap());
+        assert_ne!(entry(&repo).flags & IndexEntryFlag::VALID.bits(), 0);
+        assert_eq!(tag(), "h a.txt\n");
+
+        assert!(toggle_index_flag(&repo, path, IndexFlag::SkipWorktree).unwrap());
+        let flags = entry(&repo).flags_extended;
+        assert_ne!(flags & IndexEntryExtendedFlag::SKIP_WORKTREE.bits(), 0);
+        assert_eq!(tag(), "s a.txt\n");
+
+        assert!(!toggle_index_flag(&repo, path, IndexFlag::AssumeUnchanged).unwrap());
+        assert!(!toggle_index_flag(&repo, path, IndexFlag::SkipWorktree).unwrap());
+        let entry = entry(&repo);
+        assert_eq!(entry.flags & IndexEntryFlag::VALID.bits(), 0);
+        assert_eq!(entry.flags & IndexEntryFlag::EXTENDED.bits(), 0);
+        assert_eq!(entry.flags_extended, 0);
+        assert_eq!(tag(), "H a.txt\n");
+
+        let untracked = toggle_index_flag(&repo, Path::new("b.txt"), IndexFlag::SkipWorktree);
+        assert!(untracked.is_err());
+    }
+}
@@ -3,101 +3,1247 @@
 /// NOTE:
 ///  - Term runs in raw mode, meaning all key combinations need to be captured to work. For
 ///    example Ctrl+C or Ctrl+Z.
+///  - Drawing happens between `begin_frame` and `end_frame`. Writes only go into a buffer and
+///    end_frame sends what changed to the terminal in one go.
 ///
-use crossterm::ExecutableCommand;
-use crossterm::cursor::MoveTo;
-use crossterm::event::read;
-use crossterm::event::{self, Event};
+use crate::doctor::{Background, ColorSupport};
+use crate::event_loop::{EventLoop, LoopEvent};
+use crossterm::cursor::{self, MoveTo};
+use crossterm::event::{
+    DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event,
+    KeyCode, KeyEvent, KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+    PushKeyboardEnhancementFlags,
+};
+use crossterm::queue;
 use crossterm::style::{
-    Attribute, Color, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+    Attribute, Attributes, Color, Print, ResetColor, SetAttribute, SetAttributes,
+    SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{
+    Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen, LeaveAlternateScreen,
+    SetTitle, disable_raw_mode, enable_raw_mode,
 };
-use crossterm::terminal::{ClearType, disable_raw_mode, enable_raw_mode};
 use std::fmt::Display;
-use std::io::{Stdout, Write, stdout};
-use std::ops::{Add, Div, Mul, Sub};
-use std::time::Duration;
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, Write, stderr, stdout};
+use std::ops::{Add, Div, Mul, Range, Sub};
+use std::rc::Rc;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Save and restore the window title on the xterm title stack, ignored by terminals without one.
+const PUSH_TITLE: &str = "\x1b[22;0t";
+const POP_TITLE: &str = "\x1b[23;0t";
 
 pub struct Term {
-    stdout: Stdout,
+    /// Where the UI is drawn. Normally stdout, but the controlling terminal when stdout is
+    /// redirected (e.g. `git rebase $(gix --pick)`) so the output stays clean.
+    stdout: Box<dyn Write>,
+    backend: Backend,
+    /// Colors the terminal can show. Colors beyond them are mapped to the closest one it has,
+    /// with `None` no colors or attributes are emitted at all (`--no-color`, `NO_COLOR`).
+    colors: ColorSupport,
+    /// Text on the terminal's own background is drawn in colors readable on it.
+    background: Background,
+    /// Characters borders and indicators are drawn with.
+    glyphs: &'static Glyphs,
+    /// When false, links are written as plain text, see [`Term::write_link`].
+    hyperlinks: bool,
+    /// Whether clicks are reported as events, which takes text selection from the terminal.
+    mouse: bool,
+    /// Whether the terminal speaks the kitty keyboard protocol, which tells apart keys the
+    /// legacy encoding sends the same bytes for, e.g. ctrl+j and enter or shift+enter and enter.
+    keyboard_enhancement: bool,
+    /// Window title set with `set_title`.
+    title: Option<String>,
+    /// Frame being drawn. Nothing reaches the terminal until `end_frame`.
+    buffer: Buffer,
+    /// Frame currently on screen, `None` when it is unknown and has to be redrawn in full.
+    previous: Option<Buffer>,
+    /// Style applied to text written from now on.
+    style: Style,
+    /// Target of the link text written from now on is part of.
+    link: Option<Rc<str>>,
+    /// Where the terminal cursor is shown at the end of the frame, hidden when `None`.
+    cursor: Option<Vec2>,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    attributes: Attributes,
+}
+
+/// Text with its own colors and attributes, written with [`Term::write_spans`].
+#[derive(Clone, Default)]
+pub struct Span {
+    pub text: String,
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attributes: Attributes,
+    /// URL the text links to, see [`Term::write_link`].
+    pub link: Option<String>,
+}
+
+#[allow(unused)]
+impl Span {
+    pub fn raw(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+    pub fn fg(mut self, color: Color) -> Self {
+        self.fg = Some(color);
+        self
+    }
+    pub fn bg(mut self, color: Color) -> Self {
+        self.bg = Some(color);
+        self
+    }
+    pub fn attribute(mut self, attribute: Attribute) -> Self {
+        self.attributes.set(attribute);
+        self
+    }
+    pub fn link(mut self, url: impl Into<String>) -> Self {
+        self.link = Some(url.into());
+        self
+    }
+    /// Grey and dim, for hints and annotations next to the actual content.
+    pub fn dim(self) -> Self {
+        self.fg(Color::Grey).attribute(Attribute::Dim)
+    }
+    /// Number of cells the text takes up.
+    pub fn width(&self) -> usize {
+        display_width(&self.text)
+    }
+}
+
+/// Spans making up one row.
+#[derive(Clone, Default)]
+pub struct Line {
+    pub spans: Vec<Span>,
+    /// Rows drawn below this one, for lines given more than one row with
+    /// [`Viewport::set_heights`].
+    pub continued: Vec<Vec<Span>>,
+}
+
+#[allow(unused)]
+impl Line {
+    pub fn push(&mut self, span: Span) {
+        self.spans.push(span);
+    }
+    pub fn width(&self) -> usize {
+        self.spans.iter().map(Span::width).sum()
+    }
+}
+
+impl From<String> for Line {
+    fn from(text: String) -> Self {
+        Self {
+            spans: vec![Span::raw(text)],
+            continued: Vec::new(),
+        }
+    }
+}
+
+impl From<Vec<Span>> for Line {
+    fn from(spans: Vec<Span>) -> Self {
+        Self {
+            spans,
+            continued: Vec::new(),
+        }
+    }
+}
+
+/// Scrolling window onto more lines than fit into the area it is drawn in.
+#[derive(Default)]
+pub struct Viewport {
+    /// First row in view. Rows are lines unless some are taller, see [`Viewport::set_heights`].
+    offset: usize,
+    /// Row each line starts at and, last, the row after them, empty while every line is one row.
+    starts: Vec<usize>,
+    /// Line 0 is drawn at the bottom, the way the lists in gix grow upwards.
+    pub bottom_up: bool,
+    /// Draw a scrollbar in the rightmost column when the content doesn't fit.
+    pub scrollbar: bool,
+}
+
+#[allow(unused)]
+impl Viewport {
+    pub fn new(bottom_up: bool, scrollbar: bool) -> Self {
+        Self {
+            offset: 0,
+            starts: Vec::new(),
+            bottom_up,
+            scrollbar,
+        }
+    }
+
+    /// Rows each line takes up, every line one row when empty. Rows past the first are drawn
+    /// from [`Line::continued`].
+    pub fn set_heights(&mut self, heights: &[usize]) {
+        self.starts.clear();
+        if heights.iter().all(|height| *height == 1) {
+            return;
+        }
+        let mut row = 0;
+        self.starts.push(row);
+        for height in heights {
+            row += height;
+            self.starts.push(row);
+        }
+    }
+
+    /// Row `line` starts at.
+    fn start(&self, line: usize) -> usize {
+        match self.starts.last() {
+            Some(last) if line >= self.starts.len() => last + line + 1 - self.starts.len(),
+            Some(_) => self.starts[line],
+            None => line,
+        }
+    }
+
+    /// Line drawn in `row`.
+    fn line_of_row(&self, row: usize) -> usize {
+        match self.starts.last() {
+            Some(last) if row >= *last => self.starts.len() - 1 + row - last,
+            Some(_) => self.starts.partition_point(|start| *start <= row) - 1,
+            None => row,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Line at the top of the view as last drawn.
+    pub fn first_line(&self) -> usize {
+        self.line_of_row(self.offset)
+    }
+
+    /// Scrolls back to the start, with every line one row again.
+    pub fn reset(&mut self) {
+        self.offset = 0;
+        self.starts.clear();
+    }
+
+    /// Scrolls by `lines`, towards the end of the content when positive. Clamped when drawn.
+    pub fn scroll_by(&mut self, lines: isize) {
+        self.offset = self.offset.saturating_add_signed(lines);
+    }
+
+    /// Scrolls as little as possible to bring `line` into a view `height` rows high.
+    pub fn scroll_to(&mut self, line: usize, height: usize) {
+        let (start, end) = (self.start(line), self.start(line + 1));
+        if start < self.offset {
+            self.offset = start;
+        } else if end > self.offset + height {
+            self.offset = end.saturating_sub(height).min(start);
+        }
+    }
+
+    /// Line drawn at terminal row `y` by the last `render` into `size` cells at `at`, `None`
+    /// for rows outside of it or past the end of the content.
+    pub fn line_at(&self, at: Vec2, size: Vec2, len: usize, y: u16) -> Option<usize> {
+        let row = y.checked_sub(at.y).filter(|row| *row < size.y)?;
+        let row = if self.bottom_up {
+            size.y - 1 - row
+        } else {
+            row
+        };
+        let line = self.line_of_row(self.offset + row as usize);
+        (line < len).then_some(line)
+    }
+
+    /// Lines in view for `len` lines of content, never scrolled past the last one.
+    pub fn visible(&mut self, height: usize, len: usize) -> Range<usize> {
+        self.offset = self.offset.min(len.saturating_sub(height));
+        self.offset..(self.offset + height).min(len)
+    }
+
+    /// Draws the lines in view into `size` cells at `at`, clipped to its width. `line` is only
+    /// called for lines in view. The `selected` line is scrolled into view and highlighted.
+    pub fn render(
+        &mut self,
+        term: &mut Term,
+        at: Vec2,
+        size: Vec2,
+        len: usize,
+        selected: Option<usize>,
+        mut line: impl FnMut(usize) -> Line,
+    ) {
+        let height = size.y as usize;
+        if let Some(selected) = selected {
+            self.scroll_to(selected, height);
+        }
+        let rows = self.start(len);
+        let visible = self.visible(height, rows);
+        let has_scrollbar = self.scrollbar && rows > height;
+        let width = size.x.saturating_sub(has_scrollbar as u16) as usize;
+
+        let lines = if visible.is_empty() {
+            0..0
+        } else {
+            self.line_of_row(visible.start)..self.line_of_row(visible.end - 1) + 1
+        };
+        for i in lines {
+            let line = line(i);
+            let (start, end) = (self.start(i), self.start(i + 1));
+            let rows = std::iter::once(line.spans).chain(line.continued);
+            for (k, spans) in rows.take(end - start).enumerate() {
+                // Bottom up, the rows of a line still read downwards from its first row.
+                let row = if self.bottom_up {
+                    end - 1 - k
+                } else {
+                    start + k
+                };
+                if !visible.contains(&row) {
+                    continue;
+                }
+                let row = (row - self.offset) as u16;
+                let y = if self.bottom_up {
+                    at.y + size.y - 1 - row
+                } else {
+                    at.y + row
+                };
+                let mut remaining = width;
+                let spans: Vec<Span> = spans
+                    .into_iter()
+                    .map(|mut span| {
+                        span.text = truncate(&span.text, remaining);
+                        remaining -= span.width();
+                        span
+                    })
+                    .collect();
+                if selected == Some(i) {
+                    term.set_selected();
+                }
+                term.write_spans(Vec2::new(at.x, y), &spans);
+                term.reset_attributes();
+            }
+        }
+
+        if has_scrollbar {
+            let thumb = (height * height / rows).max(1);
+            let mut start = self.offset * (height - thumb) / (rows - height);
+            if self.bottom_up {
+                start = height - thumb - start;
+            }
+            let x = at.x + size.x - 1;
+            let glyphs = term.glyphs();
+            for row in 0..height {
+                let symbol = if (start..start + thumb).contains(&row) {
+                    glyphs.scrollbar_thumb
+                } else {
+                    glyphs.scrollbar_track
+                };
+                term.write_spans(Vec2::new(x, at.y + row as u16), &[Span::raw(symbol).dim()]);
+            }
+        }
+    }
+}
+
+/// Selected row of a list whose length can change under it, e.g. when a filter is applied.
+/// Movement wraps around at both ends.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct ListState {
+    selected: usize,
+    len: usize,
+}
+
+#[allow(unused)]
+impl ListState {
+    /// Selected row, `None` when the list is empty.
+    pub fn selected(&self) -> Option<usize> {
+        (self.selected < self.len).then_some(self.selected)
+    }
+
+    /// Selected row, 0 when the list is empty. Only index with it after checking the list
+    /// isn't empty.
+    pub fn index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Selects `row`, or the last row when it is past the end.
+    pub fn select(&mut self, row: usize) {
+        self.selected = row.min(self.len.saturating_sub(1));
+    }
+
+    /// Updates the length after the rows changed, keeping the selection on the last row
+    /// when the list shrank past it.
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len;
+        self.select(self.selected);
+    }
+
+    /// Moves the selection `rows` towards the last row, or the first when negative. Stops at
+    /// either end instead of wrapping around, e.g. when moving a page at a time.
+    pub fn move_by(&mut self, rows: isize) {
+        self.select(self.selected.saturating_add_signed(rows));
+    }
+
+    pub fn select_first(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn select_last(&mut self) {
+        self.select(usize::MAX);
+    }
+
+    /// Selects the next row, wrapping around to the first.
+    pub fn select_next(&mut self) {
+        if self.len != 0 {
+            self.selected = (self.selected + 1) % self.len;
+        }
+    }
+
+    /// Selects the previous row, wrapping around to the last.
+    pub fn select_previous(&mut self) {
+        if self.len != 0 {
+            self.selected = self.selected.checked_sub(1).unwrap_or(self.len - 1);
+        }
+    }
+}
+
+/// List of rows drawn through a [`Viewport`] with a `>` in front of the selected row, or a
+/// dimmed message when it is empty.
+pub struct ListWidget {
+    empty: String,
+    numbered: bool,
+}
+
+impl ListWidget {
+    pub fn new(empty: impl Into<String>) -> Self {
+        Self {
+            empty: empty.into(),
+            numbered: false,
+        }
+    }
+
+    /// Numbers the rows from 1 in a dimmed column after the selection marker.
+    pub fn numbered(mut self, numbered: bool) -> Self {
+        self.numbered = numbered;
+        self
+    }
+
+    /// Draws `state.len()` rows into `size` cells at `at`, see [`Viewport::render`].
+    pub fn render(
+        &self,
+        term: &mut Term,
+        at: Vec2,
+        size: Vec2,
+        state: &ListState,
+        viewport: &mut Viewport,
+        mut line: impl FnMut(usize) -> Line,
+    ) {
+        let Some(selected) = state.selected() else {
+            let y = if viewport.bottom_up {
+                at.y + size.y.saturating_sub(1)
+            } else {
+                at.y
+            };
+            term.write_spans(Vec2::new(at.x, y), &[Span::raw(&self.empty).dim()]);
+            return;
+        };
+        let digits = state.len().to_string().len();
+        viewport.render(term, at, size, state.len(), Some(selected), |i| {
+            let marker = if i == selected { "> " } else { "  " };
+            let mut row = line(i);
+            if self.numbered {
+                row.spans
+                    .insert(0, Span::raw(format!("{:>digits$} ", i + 1)).dim());
+            }
+            row.spans.insert(0, Span::raw(marker));
+            // Rows below line up with the first one.
+            let indent = 2 + if self.numbered { digits + 1 } else { 0 };
+            for spans in &mut row.continued {
+                spans.insert(0, Span::raw(" ".repeat(indent)));
+            }
+            row
+        });
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+#[allow(unused)]
+pub enum BorderStyle {
+    Rounded,
+    #[default]
+    Heavy,
+    /// For terminals and fonts without box drawing characters.
+    Ascii,
+}
+
+impl BorderStyle {
+    /// Top left, top right, bottom left and bottom right corner, horizontal and vertical edge.
+    fn symbols(self) -> [&'static str; 6] {
+        match self {
+            BorderStyle::Rounded => ["╭", "╮", "╰", "╯", "─", "│"],
+            BorderStyle::Heavy => ["┏", "┓", "┗", "┛", "━", "┃"],
+            BorderStyle::Ascii => ["+", "+", "+", "+", "-", "|"],
+        }
+    }
+}
+
+/// Characters borders and indicators are drawn with. The ASCII set is for serial consoles,
+/// fonts without box drawing characters and locales that aren't UTF-8.
+pub struct Glyphs {
+    /// Overrides the border style of boxes when set.
+    pub border: Option<BorderStyle>,
+    pub scrollbar_thumb: &'static str,
+    pub scrollbar_track: &'static str,
+    /// Sparkline bars from lowest to highest.
+    pub bars: [char; 8],
+    /// Shades from empty to full, e.g. of the log minimap.
+    pub shades: [&'static str; 5],
+    pub up: char,
+    pub down: char,
+}
+
+impl Glyphs {
+    pub const UNICODE: Glyphs = Glyphs {
+        border: None,
+        scrollbar_thumb: "┃",
+        scrollbar_track: "│",
+        bars: ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'],
+        shades: [" ", "░", "▒", "▓", "█"],
+        up: '↑',
+        down: '↓',
+    };
+    pub const ASCII: Glyphs = Glyphs {
+        border: Some(BorderStyle::Ascii),
+        scrollbar_thumb: "#",
+        scrollbar_track: "|",
+        bars: ['_', '.', ',', '-', '~', '=', '*', '#'],
+        shades: [" ", ".", ":", "+", "#"],
+        up: '^',
+        down: 'v',
+    };
+}
+
+/// Bordered box with an optional title in the top border, for overlays such as the debug and
+/// profiling panels.
+pub struct BoxWidget {
+    title: Option<String>,
+    border: BorderStyle,
+    border_color: Option<Color>,
+    /// Empty cells between the border and the content on every side.
+    padding: u16,
+    /// Background of the inside, which is cleared either way.
+    fill: Option<Color>,
+}
+
+impl Default for BoxWidget {
+    fn default() -> Self {
+        Self {
+            title: None,
+            border: BorderStyle::default(),
+            border_color: Some(Color::AnsiValue(22)),
+            padding: 0,
+            fill: None,
+        }
+    }
+}
+
+#[allow(unused)]
+impl BoxWidget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+    pub fn border(mut self, border: BorderStyle) -> Self {
+        self.border = border;
+        self
+    }
+    pub fn border_color(mut self, color: Option<Color>) -> Self {
+        self.border_color = color;
+        self
+    }
+    pub fn padding(mut self, padding: u16) -> Self {
+        self.padding = padding;
+        self
+    }
+    pub fn fill(mut self, color: Color) -> Self {
+        self.fill = Some(color);
+        self
+    }
+
+    /// Outer size of a box around `content` cells, wide enough for the title.
+    pub fn size_for(&self, content: Vec2) -> Vec2 {
+        let title = self
+            .title
+            .as_deref()
+            .map_or(0, |t| display_width(t) as u16 + 2);
+        let inset = 2 + self.padding * 2;
+        Vec2::new(
+            content.x.max(title).saturating_add(inset),
+            content.y.saturating_add(inset),
+        )
+    }
+
+    /// Draws the border and clears the inside of the `size` cells at `at`. Returns the position
+    /// and size of the inside, which content should be clipped to.
+    pub fn render(&self, term: &mut Term, at: Vec2, size: Vec2) -> (Vec2, Vec2) {
+        if size.x < 2 || size.y < 2 {
+            return (at, Vec2::empty());
+        }
+        let [
+            top_left,
+            top_right,
+            bottom_left,
+            bottom_right,
+            horizontal,
+            vertical,
+        ] = term.glyphs().border.unwrap_or(self.border).symbols();
+        let color = self.border_color;
+        let (right, bottom) = (size.x - 1, size.y - 1);
+
+        term.set_pixel(at, None, color, Some(top_left));
+        term.set_pixel(at + Vec2::new(right, 0), None, color, Some(top_right));
+        term.set_pixel(at + Vec2::new(0, bottom), None, color, Some(bottom_left));
+        term.set_pixel(
+            at + Vec2::new(right, bottom),
+            None,
+            color,
+            Some(bottom_right),
+        );
+        for x in 1..right {
+            term.set_pixel(at + Vec2::new(x, 0), None, color, Some(horizontal));
+            term.set_pixel(at + Vec2::new(x, bottom), None, color, Some(horizontal));
+        }
+        for y in 1..bottom {
+            term.set_pixel(at + Vec2::new(0, y), None, color, Some(vertical));
+            term.set_pixel(at + Vec2::new(right, y), None, color, Some(vertical));
+            for x in 1..right {
+                term.set_pixel(at + Vec2::new(x, y), self.fill, None, None);
+            }
+        }
+        if let Some(title) = &self.title {
+            let title = truncate(&format!(" {title} "), right.saturating_sub(2) as usize);
+            term.write_bold_text(at + Vec2::new(2, 0), title);
+        }
+
+        let inset = 1 + self.padding;
+        let inner = Vec2::new(
+            size.x.saturating_sub(inset * 2),
+            size.y.saturating_sub(inset * 2),
+        );
+        (at + Vec2::new(inset, inset), inner)
+    }
+
+    /// Draws the box sized to fit `lines` at `at`, with the lines in bold inside.
+    pub fn render_lines(&self, term: &mut Term, at: Vec2, lines: &[String]) {
+        let width = lines.iter().map(|l| display_width(l)).max().unwrap_or(0);
+        let size = self.size_for(Vec2::new(width as u16, lines.len() as u16));
+        let (inner_at, inner) = self.render(term, at, size);
+        if let Some(fill) = self.fill {
+            term.set_bg_color(fill);
+        }
+        for (i, line) in lines.iter().take(inner.y as usize).enumerate() {
+            let text = truncate(line, inner.x as usize);
+            term.write_bold_text(inner_at + Vec2::new(0, i as u16), text);
+        }
+        term.reset_colors();
+    }
+}
+
+/// Single line text input with a cursor that can be moved and edited at.
+#[derive(Clone, Default)]
+pub struct Input {
+    text: String,
+    /// Position in graphemes, `0..=len`.
+    cursor: usize,
+}
+
+#[allow(unused)]
+impl Input {
+    /// Input holding `text` with the cursor at its end.
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let cursor = text.graphemes(true).count();
+        Self { text, cursor }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.cursor = 0;
+    }
+
+    /// Applies an editing key: characters insert at the cursor, left/right, home/end (also
+    /// ctrl+a/ctrl+e) move it, backspace/delete remove around it, ctrl+w removes the word
+    /// before it and ctrl+u everything before it. Returns whether the key was one of these.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let len = self.text.graphemes(true).count();
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = len,
+            KeyCode::Char('w') if ctrl => {
+                let graphemes: Vec<&str> = self.text.graphemes(true).take(self.cursor).collect();
+                let is_space = |g: &&&str| g.chars().all(char::is_whitespace);
+                let spaces = graphemes.iter().rev().take_while(is_space).count();
+                let word = graphemes
+                    .iter()
+                    .rev()
+                    .skip(spaces)
+                    .take_while(|g| !is_space(g))
+                    .count();
+                self.remove(self.cursor - spaces - word, self.cursor);
+            }
+            KeyCode::Char('u') if ctrl => self.remove(0, self.cursor),
+            KeyCode::Char(_) if ctrl => return false,
+            KeyCode::Char(c) => {
+                let at = self.byte_offset(self.cursor);
+                self.text.insert(at, c);
+                self.cursor = self.text[..at + c.len_utf8()].graphemes(true).count();
+            }
+            KeyCode::Backspace if self.cursor > 0 => self.remove(self.cursor - 1, self.cursor),
+            KeyCode::Delete if self.cursor < len => self.remove(self.cursor, self.cursor + 1),
+            KeyCode::Backspace | KeyCode::Delete => {}
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(len),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = len,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Inserts pasted `text` at the cursor. Line breaks and tabs become spaces and a trailing
+    /// line break is dropped, as the input is a single line.
+    pub fn insert_str(&mut self, text: &str) {
+        let text: String = text
+            .trim_end_matches(['\r', '\n'])
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        let at = self.byte_offset(self.cursor);
+        self.text.insert_str(at, &text);
+        self.cursor = self.text[..at + text.len()].graphemes(true).count();
+    }
+
+    /// Writes the text at `at`. With `focused` the terminal cursor is shown at the cursor.
+    pub fn render(&self, term: &mut Term, at: Vec2, focused: bool) {
+        term.write_text(at, &self.text);
+        if focused {
+            let before = &self.text[..self.byte_offset(self.cursor)];
+            term.set_cursor(Vec2::new(
+                at.x.saturating_add(display_width(before) as u16),
+                at.y,
+            ));
+        }
+    }
+
+    /// Removes the graphemes `from..to`, leaving the cursor at `from`.
+    fn remove(&mut self, from: usize, to: usize) {
+        let range = self.byte_offset(from)..self.byte_offset(to);
+        self.text.replace_range(range, "");
+        self.cursor = from;
+    }
+
+    fn byte_offset(&self, graphemes: usize) -> usize {
+        self.text
+            .grapheme_indices(true)
+            .nth(graphemes)
+            .map_or(self.text.len(), |(i, _)| i)
+    }
+}
+
+/// What a `Term` draws on and reads events from.
+enum Backend {
+    /// The terminal gix runs in, with the threads reading from it.
+    Terminal(EventLoop),
+    /// Grid of a fixed size fed with synthetic events, for tests. Frames only go to the buffer,
+    /// see `Term::lines`.
+    #[cfg(test)]
+    Test {
+        size: Vec2,
+        events: std::collections::VecDeque<Event>,
+    },
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Cell {
+    /// A single grapheme. Empty in the cell after a double-width grapheme, which covers it.
+    symbol: String,
+    style: Style,
+    link: Option<Rc<str>>,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: " ".to_string(),
+            style: Style::default(),
+            link: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Buffer {
+    size: Vec2,
+    cells: Vec<Cell>,
+}
+
+impl Buffer {
+    fn new(size: Vec2) -> Self {
+        Self {
+            size,
+            cells: vec![Cell::default(); size.x as usize * size.y as usize],
+        }
+    }
+
+    fn get_mut(&mut self, x: u16, y: u16) -> Option<&mut Cell> {
+        if x >= self.size.x || y >= self.size.y {
+            return None;
+        }
+        self.cells
+            .get_mut(y as usize * self.size.x as usize + x as usize)
+    }
+
+    /// Blanks the other half of double-width graphemes that writing to cells `from..to` of row
+    /// `y` partly overwrites, so no half of one is left on screen.
+    fn clear_overlapping(&mut self, from: u16, to: u16, y: u16) {
+        let is_covered = |buffer: &mut Self, x| {
+            buffer
+                .get_mut(x, y)
+                .is_some_and(|cell| cell.symbol.is_empty())
+        };
+        if is_covered(self, from)
+            && let Some(cell) = from.checked_sub(1).and_then(|x| self.get_mut(x, y))
+        {
+            cell.symbol = " ".to_string();
+        }
+        if is_covered(self, to)
+            && let Some(cell) = self.get_mut(to, y)
+        {
+            cell.symbol = " ".to_string();
+        }
+    }
 }
 
 #[allow(unused)]
 impl Term {
-    pub fn new() -> Term {
-        enable_raw_mode().unwrap();
-        let mut stdout = stdout();
-        stdout
-            .execute(crossterm::terminal::EnterAlternateScreen)
-            .unwrap();
-        stdout.execute(crossterm::cursor::Hide).unwrap();
-        stdout
-            .execute(crossterm::terminal::DisableLineWrap)
-            .unwrap();
-        Term { stdout }
+    pub fn new(
+        colors: ColorSupport,
+        background: Background,
+        ascii: bool,
+        hyperlinks: bool,
+        mouse: bool,
+        keyboard_enhancement: bool,
+    ) -> io::Result<Term> {
+        let stdout: Box<dyn Write> = if stdout().is_terminal() {
+            Box::new(stdout())
+        } else {
+            match OpenOptions::new().write(true).open("/dev/tty") {
+                Ok(tty) => Box::new(tty),
+                Err(_) => Box::new(stderr()),
+            }
+        };
+        let mut term = Term {
+            stdout,
+            backend: Backend::Terminal(EventLoop::start()),
+            colors,
+            background,
+            glyphs: if ascii {
+                &Glyphs::ASCII
+            } else {
+                &Glyphs::UNICODE
+            },
+            hyperlinks,
+            mouse,
+            keyboard_enhancement,
+            title: None,
+            buffer: Buffer::new(Vec2::empty()),
+            previous: None,
+            style: Style::default(),
+            link: None,
+            cursor: None,
+        };
+        term.buffer = Buffer::new(term.size());
+        term.resume()?;
+        Ok(term)
+    }
+
+    /// In-memory terminal of `size` cells for tests, it never touches the real terminal.
+    #[cfg(test)]
+    pub fn test(size: Vec2) -> Term {
+        Term {
+            stdout: Box::new(io::sink()),
+            backend: Backend::Test {
+                size,
+                events: Default::default(),
+            },
+            colors: ColorSupport::TrueColor,
+            background: Background::Dark,
+            glyphs: &Glyphs::UNICODE,
+            hyperlinks: false,
+            mouse: false,
+            keyboard_enhancement: false,
+            title: None,
+            buffer: Buffer::new(size),
+            previous: None,
+            style: Style::default(),
+            link: None,
+            cursor: None,
+        }
+    }
+
+    /// Queues `event` to be returned by `next_event` of a test terminal.
+    #[cfg(test)]
+    pub fn push_event(&mut self, event: Event) {
+        if let Backend::Test { events, .. } = &mut self.backend {
+            events.push_back(event);
+        }
+    }
+
+    /// Rows of the last drawn frame, without trailing spaces.
+    #[cfg(test)]
+    pub fn lines(&self) -> Vec<String> {
+        let buffer = self.previous.as_ref().unwrap_or(&self.buffer);
+        buffer
+            .cells
+            .chunks(buffer.size.x as usize)
+            .map(|row| {
+                let line: String = row.iter().map(|cell| cell.symbol.as_str()).collect();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+    /// Takes over the terminal again after `close`, e.g. when an external program exits.
+    pub fn resume(&mut self) -> io::Result<()> {
+        let Some(events) = self.event_loop() else {
+            return Ok(());
+        };
+        enable_raw_mode()?;
+        events.resume();
+        queue!(
+            self.stdout,
+            EnterAlternateScreen,
+            cursor::Hide,
+            DisableLineWrap,
+            EnableBracketedPaste
+        )?;
+        // Saves the title the shell had, `close` puts it back.
+        queue!(self.stdout, Print(PUSH_TITLE))?;
+        if let Some(title) = &self.title {
+            queue!(self.stdout, SetTitle(title))?;
+        }
+        if self.mouse {
+            queue!(self.stdout, EnableMouseCapture)?;
+        }
+        if self.keyboard_enhancement {
+            // Only disambiguation: reporting event types would send held keys as repeats, which
+            // the key handlers don't tell apart from presses.
+            queue!(
+                self.stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            )?;
+        }
+        self.stdout.flush()?;
+        self.previous = None;
+        Ok(())
     }
 
     pub fn close(&mut self) {
-        self.clear_all();
-        self.stdout.execute(crossterm::cursor::Show).unwrap();
-        self.stdout
-            .execute(crossterm::terminal::LeaveAlternateScreen)
-            .unwrap();
-        self.stdout
-            .execute(crossterm::terminal::EnableLineWrap)
-            .unwrap();
+        let Some(events) = self.event_loop() else {
+            return;
+        };
+        events.pause();
+        if self.keyboard_enhancement {
+            let _ = queue!(self.stdout, PopKeyboardEnhancementFlags);
+        }
+        if self.mouse {
+            queue!(self.stdout, DisableMouseCapture).unwrap();
+        }
+        queue!(
+            self.stdout,
+            Clear(ClearType::All),
+            cursor::Show,
+            LeaveAlternateScreen,
+            EnableLineWrap,
+            DisableBracketedPaste,
+            Print(POP_TITLE)
+        )
+        .unwrap();
+        self.stdout.flush().unwrap();
         disable_raw_mode().unwrap();
     }
 
-    pub fn size() -> Vec2 {
-        match crossterm::terminal::size() {
-            Ok(value) => Vec2::from(value),
-            _ => Vec2::empty(),
+    /// Gives the terminal back to the shell and stops gix, like ctrl+z does outside of raw mode.
+    /// Returns once the shell continues gix (`fg`), with the terminal taken over again and the
+    /// next frame drawn in full.
+    #[cfg(unix)]
+    pub fn suspend(&mut self) -> io::Result<()> {
+        if self.event_loop().is_none() {
+            return Ok(());
+        }
+        self.close();
+        // SAFETY: raise only sends a signal to the calling thread.
+        let stopped = match unsafe { libc::raise(libc::SIGTSTP) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+        self.resume()?;
+        stopped
+    }
+
+    /// Job control is a unix thing, elsewhere there is nothing to suspend to.
+    #[cfg(not(unix))]
+    pub fn suspend(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Sets the window title, sent with the next frame when it changed. The title from before
+    /// gix is restored by `close`.
+    pub fn set_title(&mut self, title: &str) {
+        let title: String = title.chars().filter(|c| !c.is_control()).collect();
+        if self.title.as_ref() == Some(&title) {
+            return;
+        }
+        if self.event_loop().is_some() {
+            queue!(self.stdout, SetTitle(&title)).unwrap();
+        }
+        self.title = Some(title);
+    }
+
+    /// Threads reading the terminal gix runs in, a test terminal has none.
+    fn event_loop(&self) -> Option<&EventLoop> {
+        match &self.backend {
+            Backend::Terminal(events) => Some(events),
+            #[cfg(test)]
+            Backend::Test { .. } => None,
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        match &self.backend {
+            Backend::Terminal(_) => match crossterm::terminal::size() {
+                Ok(value) => Vec2::from(value),
+                _ => Vec2::empty(),
+            },
+            #[cfg(test)]
+            Backend::Test { size, .. } => *size,
         }
     }
 
     pub fn reset_cursor(&mut self) {
-        self.stdout.execute(MoveTo(0, 0)).unwrap();
+        queue!(self.stdout, MoveTo(0, 0)).unwrap();
+        self.stdout.flush().unwrap();
+    }
+
+    /// Starts a new frame: empties the buffer, resized to the current terminal size. Drawing
+    /// only changes the buffer until `end_frame`.
+    pub fn begin_frame(&mut self) {
+        self.buffer = Buffer::new(self.size());
+        self.cursor = None;
     }
 
-    pub fn clear_all(&mut self) {
-        self.stdout
-            .execute(crossterm::terminal::Clear(ClearType::All))
+    /// Shows the terminal cursor at `at` once the frame ends, e.g. in a text input.
+    pub fn set_cursor(&mut self, at: Vec2) {
+        self.cursor = Some(at);
+    }
+
+    /// Draws the buffer, emitting only the cells that changed since the last frame, with a
+    /// single flush. Can also be called mid-frame to show progress.
+    pub fn end_frame(&mut self) {
+        let full = self
+            .previous
+            .as_ref()
+            .is_none_or(|previous| previous.size != self.buffer.size);
+        if full {
+            queue!(
+                self.stdout,
+                SetAttribute(Attribute::Reset),
+                ResetColor,
+                Clear(ClearType::All)
+            )
             .unwrap();
-        self.stdout.execute(MoveTo(0, 0)).unwrap();
+        }
+
+        let width = self.buffer.size.x as usize;
+        let mut cursor = None;
+        let mut current = Style::default();
+        let mut current_link: Option<&Rc<str>> = None;
+        for (i, cell) in self.buffer.cells.iter().enumerate() {
+            let unchanged = match &self.previous {
+                Some(previous) if !full => previous.cells[i] == *cell,
+                _ => *cell == Cell::default(),
+            };
+            // Cells covered by a double-width grapheme are drawn along with it.
+            if unchanged || cell.symbol.is_empty() {
+                continue;
+            }
+
+            let at = ((i % width) as u16, (i / width) as u16);
+            if cursor != Some(at) {
+                queue!(self.stdout, MoveTo(at.0, at.1)).unwrap();
+            }
+            if cell.style != current {
+                queue!(self.stdout, SetAttribute(Attribute::Reset), ResetColor).unwrap();
+                if let Some(fg) = cell.style.fg {
+                    let fg = match cell.style.bg {
+                        Some(_) => fg,
+                        None => readable_on(fg, self.background),
+                    };
+                    queue_color(&mut self.stdout, fit_color(fg, self.colors), false);
+                }
+                if let Some(bg) = cell.style.bg {
+                    queue_color(&mut self.stdout, fit_color(bg, self.colors), true);
+                }
+                if !cell.style.attributes.is_empty() {
+                    queue!(self.stdout, SetAttributes(cell.style.attributes)).unwrap();
+                }
+                current = cell.style;
+            }
+            if cell.link.as_ref() != current_link {
+                let url = cell.link.as_deref().map(sanitize_url).unwrap_or_default();
+                queue!(self.stdout, Print(format!("\x1b]8;;{url}\x1b\\"))).unwrap();
+                current_link = cell.link.as_ref();
+            }
+            queue!(self.stdout, Print(&cell.symbol)).unwrap();
+            cursor = Some((at.0 + display_width(&cell.symbol) as u16, at.1));
+        }
+        if current_link.is_some() {
+            queue!(self.stdout, Print("\x1b]8;;\x1b\\")).unwrap();
+        }
+        queue!(self.stdout, SetAttribute(Attribute::Reset), ResetColor).unwrap();
+        match self.cursor {
+            Some(at) => queue!(self.stdout, MoveTo(at.x, at.y), cursor::Show).unwrap(),
+            None => queue!(self.stdout, cursor::Hide).unwrap(),
+        }
+        self.stdout.flush().unwrap();
+        self.previous = Some(self.buffer.clone());
+    }
+
+    pub fn glyphs(&self) -> &'static Glyphs {
+        self.glyphs
+    }
+
+    /// Whether colors and attributes are emitted at all.
+    fn color(&self) -> bool {
+        self.colors != ColorSupport::None
     }
 
     /// Sets background color for following text until reset_colors is called.
     pub fn set_bg_color(&mut self, color: Color) {
-        self.stdout.execute(SetBackgroundColor(color)).unwrap();
+        if !self.color() {
+            return;
+        }
+        self.style.bg = Some(color);
     }
     /// Sets foreground color for following text until reset_colors is called.
     pub fn set_fg_color(&mut self, color: Color) {
-        self.stdout.execute(SetForegroundColor(color)).unwrap();
+        if !self.color() {
+            return;
+        }
+        self.style.fg = Some(color);
     }
     pub fn reset_colors(&mut self) {
-        self.stdout.execute(ResetColor).unwrap();
+        self.style.fg = None;
+        self.style.bg = None;
     }
 
     /// Sets attribute for following text until reset_attributes is called.
     pub fn set_attribute(&mut self, attribute: Attribute) {
-        self.stdout.execute(SetAttribute(attribute)).unwrap();
+        if !self.color() {
+            return;
+        }
+        self.add_attribute(attribute);
+    }
+    /// Marks following text as selected until reset_attributes is called. Bold, or reverse
+    /// video when colors are disabled.
+    pub fn set_selected(&mut self) {
+        let attribute = if self.color() {
+            Attribute::Bold
+        } else {
+            Attribute::Reverse
+        };
+        self.add_attribute(attribute);
     }
     pub fn reset_attributes(&mut self) {
-        self.stdout.execute(SetAttribute(Attribute::Reset)).unwrap();
+        self.style.attributes = Attributes::default();
+    }
+    fn add_attribute(&mut self, attribute: Attribute) {
+        if attribute == Attribute::Reset {
+            self.reset_attributes();
+        } else {
+            self.style.attributes.set(attribute);
+        }
     }
 
     pub fn write_text(&mut self, at: Vec2, text: impl std::fmt::Display) {
-        self.stdout.execute(MoveTo(at.x, at.y)).unwrap();
-        write!(self.stdout, "{}", text).unwrap();
-        self.stdout.flush().unwrap();
+        let style = self.style;
+        self.put(at, &text.to_string(), style);
+    }
+
+    /// Writes `text` wrapped to the width of `rect`, see [`wrap`], leaving out lines that
+    /// don't fit its height. Returns the number of lines written.
+    pub fn write_wrapped(&mut self, rect: Rect, text: &str) -> usize {
+        let lines = wrap(text, rect.size.x as usize);
+        let n = lines.len().min(rect.size.y as usize);
+        for (y, line) in lines.iter().take(n).enumerate() {
+            self.write_text(rect.at + Vec2::new(0, y as u16), line);
+        }
+        n
+    }
+    /// Writes `spans` one after another. Each span is drawn over the current style, so for
+    /// example `set_selected` still applies to the whole row, and leaves that style as it was.
+    pub fn write_spans(&mut self, at: Vec2, spans: &[Span]) {
+        let mut x = at.x;
+        for span in spans {
+            let mut style = self.style;
+            if self.color() {
+                style.fg = span.fg.or(style.fg);
+                style.bg = span.bg.or(style.bg);
+                style.attributes.extend(span.attributes);
+            }
+            self.link = span
+                .link
+                .as_deref()
+                .filter(|_| self.hyperlinks)
+                .map(Rc::from);
+            self.put(Vec2::new(x, at.y), &span.text, style);
+            self.link = None;
+            x = x.saturating_add(span.width() as u16);
+        }
+    }
+    pub fn write_line(&mut self, at: Vec2, line: &Line) {
+        self.write_spans(at, &line.spans);
+    }
+    /// Writes `text` as a link to `url` (OSC 8), which terminals that support it let the user
+    /// open. Other terminals get the plain text.
+    pub fn write_link(&mut self, at: Vec2, text: impl std::fmt::Display, url: &str) {
+        self.link = self.hyperlinks.then(|| Rc::from(url));
+        self.write_text(at, text);
+        self.link = None;
     }
     pub fn write_bold_text(&mut self, at: Vec2, text: impl std::fmt::Display) {
-        self.stdout.execute(MoveTo(at.x, at.y)).unwrap();
         self.set_attribute(Attribute::Bold);
-        write!(self.stdout, "{}", text).unwrap();
+        self.write_text(at, text);
         self.reset_attributes();
-        self.stdout.flush().unwrap();
     }
 
     pub fn set_pixel(
@@ -107,55 +1253,16 @@ impl Term {
         fg_color: Option<Color>,
         ch: Option<&str>,
     ) {
-        self.stdout.execute(MoveTo(at.x, at.y)).unwrap();
-        if let Some(bg) = bg_color {
-            self.stdout.execute(SetBackgroundColor(bg)).unwrap();
-        }
-        if let Some(fg) = fg_color {
-            self.stdout.execute(SetForegroundColor(fg)).unwrap();
-        }
-        write!(self.stdout, "{}", ch.unwrap_or(" ")).unwrap();
-        self.stdout.execute(ResetColor).unwrap();
-        self.stdout.flush().unwrap();
-    }
-
-    pub fn draw_text_bubble(&mut self, at: Vec2, text: impl std::fmt::Display) {
-        let string = text.to_string();
-        let lines: Vec<&str> = string.lines().collect();
-        let max_len = string.lines().map(|l| l.len()).max().unwrap_or(0);
-        let padding: u16 = 0;
-        let outline_color = Some(Color::AnsiValue(22));
-
-        let size = Vec2::new(
-            max_len as u16 + (padding * 2) + 2,
-            lines.len() as u16 + (padding * 2) + 1,
-        );
-
-        self.set_pixel(at, None, outline_color, Some("┏"));
-        self.set_pixel(at + Vec2::new(size.x, 0), None, outline_color, Some("┓"));
-        self.set_pixel(at + Vec2::new(0, size.y), None, outline_color, Some("┗"));
-        self.set_pixel(at + size, None, outline_color, Some("┛"));
-
-        for x in 1..size.x {
-            self.set_pixel(at + Vec2::new(x, 0), None, outline_color, Some("━"));
-            self.set_pixel(at + Vec2::new(x, size.y), None, outline_color, Some("━"));
-        }
-
-        for y in 1..size.y {
-            self.set_pixel(at + Vec2::new(0, y), None, outline_color, Some("┃"));
-            self.set_pixel(at + Vec2::new(size.x, y), None, outline_color, Some("┃"));
-        }
-
-        for (i, line) in lines.iter().enumerate() {
-            self.write_bold_text(at + Vec2::new(1, i as u16 + 1), *line);
+        let mut style = Style::default();
+        if self.color() {
+            style.fg = fg_color;
+            style.bg = bg_color;
         }
+        self.put(at, ch.unwrap_or(" "), style);
     }
 
     pub fn set_pixel_bg(&mut self, at: Vec2, color: Color) {
-        self.stdout.execute(MoveTo(at.x, at.y)).unwrap();
-        self.stdout.execute(SetBackgroundColor(color)).unwrap();
-        write!(self.stdout, " ").unwrap();
-        self.stdout.execute(ResetColor).unwrap();
+        self.set_pixel(at, Some(color), None, None);
     }
 
     pub fn draw(&mut self, at: Vec2, graphic: &str, color: Color) {
@@ -175,16 +1282,283 @@ impl Term {
         }
     }
 
-    pub fn read_event(&self, timeout_ms: u64) -> Option<Event> {
-        if event::poll(Duration::from_millis(timeout_ms)).ok()? {
-            Some(read().unwrap())
-        } else {
-            None
+    /// Writes `text` into the buffer, clipped to the buffer. Graphemes take up as many cells as
+    /// they are wide; one that doesn't fit at the right edge is left out.
+    fn put(&mut self, at: Vec2, text: &str, style: Style) {
+        let mut x = at.x;
+        for grapheme in text.graphemes(true) {
+            let symbol = if grapheme.chars().any(char::is_control) {
+                " "
+            } else {
+                grapheme
+            };
+            let width = display_width(symbol).clamp(1, 2) as u16;
+            if x.saturating_add(width) > self.buffer.size.x || at.y >= self.buffer.size.y {
+                break;
+            }
+            self.buffer.clear_overlapping(x, x + width, at.y);
+            for i in 0..width {
+                if let Some(cell) = self.buffer.get_mut(x + i, at.y) {
+                    cell.symbol = if i == 0 { symbol } else { "" }.to_string();
+                    cell.style = style;
+                    cell.link = self.link.clone();
+                }
+            }
+            x += width;
+        }
+    }
+
+    /// Input that is already waiting, skipping ticks, so long operations can check for a key
+    /// that cancels them without blocking. A test terminal never has any.
+    pub fn poll_input(&mut self) -> Option<Event> {
+        let events = self.event_loop()?;
+        while let Some(event) = events.try_next() {
+            if let LoopEvent::Input(event) = event {
+                return Some(event);
+            }
+        }
+        None
+    }
+
+    /// Next input or tick, see [`EventLoop`]. A test terminal returns its queued events instead
+    /// and panics once they run out, as nothing else could end the event loop.
+    pub fn next_event(&mut self) -> LoopEvent {
+        match &mut self.backend {
+            Backend::Terminal(events) => events.next(),
+            #[cfg(test)]
+            Backend::Test { events, .. } => {
+                LoopEvent::Input(events.pop_front().expect("test terminal ran out of events"))
+            }
+        }
+    }
+}
+
+/// The 16 ANSI colors in the order of their 256-color indices, with their xterm defaults.
+const ANSI_16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel levels of the 6x6x6 color cube at 256-color indices 16 to 231.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Text `color` as drawn on `background`. The UI is made for dark backgrounds, light ones get
+/// the darker variant of the light colors, which would hardly show up on them.
+fn readable_on(color: Color, background: Background) -> Color {
+    if background == Background::Dark {
+        return color;
+    }
+    match color {
+        Color::Grey => Color::DarkGrey,
+        Color::White => Color::Black,
+        Color::Yellow => Color::DarkYellow,
+        Color::Green => Color::DarkGreen,
+        Color::Cyan => Color::DarkCyan,
+        _ => color,
+    }
+}
+
+/// `color`, or the closest color to it a terminal with `colors` can show.
+fn fit_color(color: Color, colors: ColorSupport) -> Color {
+    match (color, colors) {
+        (Color::Rgb { r, g, b }, ColorSupport::Ansi256) => Color::AnsiValue(nearest_256((r, g, b))),
+        (Color::Rgb { r, g, b }, ColorSupport::Ansi16) => nearest_16((r, g, b)),
+        (Color::AnsiValue(i), ColorSupport::Ansi16) => match ANSI_16.get(i as usize) {
+            Some((color, _)) => *color,
+            None => nearest_16(ansi_rgb(i)),
+        },
+        _ => color,
+    }
+}
+
+/// Sets the foreground or `background` color. The 16 basic colors get their own SGR codes
+/// rather than the 256-color form crossterm uses for them, which 16-color consoles don't know.
+fn queue_color(out: &mut impl Write, color: Color, background: bool) {
+    let basic = ANSI_16.iter().position(|(c, _)| *c == color);
+    match basic {
+        Some(i) => {
+            let code = if i < 8 { 30 + i } else { 90 + i - 8 };
+            let code = if background { code + 10 } else { code };
+            queue!(out, Print(format!("\x1b[{code}m"))).unwrap();
+        }
+        None if background => queue!(out, SetBackgroundColor(color)).unwrap(),
+        None => queue!(out, SetForegroundColor(color)).unwrap(),
+    }
+}
+
+/// Index of the 256-color palette color closest to `rgb`, from the color cube or the gray ramp.
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(c))
+            .unwrap() as u8
+    };
+    let cube = 16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2);
+    let average = (rgb.0 as u16 + rgb.1 as u16 + rgb.2 as u16) / 3;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+    if distance(ansi_rgb(gray), rgb) < distance(ansi_rgb(cube), rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+/// Basic color with the hue of `rgb`. Closest by distance would turn dark colors black and
+/// invisible, so saturated colors keep their channels and only grays go by brightness.
+fn nearest_16((r, g, b): (u8, u8, u8)) -> Color {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    if max - min < 48 {
+        return match (r as u16 + g as u16 + b as u16) / 3 {
+            0..48 => Color::Black,
+            48..144 => Color::DarkGrey,
+            144..224 => Color::Grey,
+            _ => Color::White,
+        };
+    }
+    let on = |c: u8| usize::from(c as u16 * 2 >= max as u16);
+    let i = on(r) | on(g) << 1 | on(b) << 2;
+    ANSI_16[if max > 200 { i + 8 } else { i }].0
+}
+
+/// Color of 256-color palette index `i` as xterm shows it by default.
+fn ansi_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..16 => ANSI_16[i as usize].1,
+        16..232 => {
+            let i = i - 16;
+            let level = |c: u8| CUBE_LEVELS[c as usize];
+            (level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + 10 * (i - 232);
+            (v, v, v)
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x.abs_diff(y) as u32).pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+/// `url` without the control characters that would end the OSC 8 sequence early.
+fn sanitize_url(url: &str) -> String {
+    url.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Number of terminal cells `text` takes up, two for wide characters such as CJK and most
+/// emoji, none for combining characters.
+pub fn display_width(text: &str) -> usize {
+    text.width()
+}
+
+/// Longest prefix of `text` that fits into `width` cells, cut between graphemes.
+pub fn truncate(text: &str, width: usize) -> String {
+    let mut remaining = width;
+    text.graphemes(true)
+        .take_while(|g| match remaining.checked_sub(display_width(g)) {
+            Some(rest) => {
+                remaining = rest;
+                true
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// `text` padded with spaces to `width` cells, like `{:<width$}` does for characters.
+pub fn pad(text: &str, width: usize) -> String {
+    let fill = width.saturating_sub(display_width(text));
+    format!("{text}{}", " ".repeat(fill))
+}
+
+/// `text` broken into lines of at most `width` cells. Lines break between words, words wider
+/// than a line between graphemes, and every newline in `text` starts a new line.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split(' ').filter(|w| !w.is_empty()) {
+            let gap = usize::from(!line.is_empty());
+            if display_width(&line) + gap + display_width(word) <= width {
+                if gap == 1 {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            let mut rest = word;
+            while display_width(rest) > width {
+                // At least one grapheme, even one wider than `width`.
+                let head = match truncate(rest, width) {
+                    head if head.is_empty() => rest.graphemes(true).next().unwrap_or_default(),
+                    head => &rest[..head.len()],
+                };
+                lines.push(head.to_string());
+                rest = &rest[head.len()..];
+            }
+            line.push_str(rest);
         }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Renders `values` as a row of `bars` scaled to the largest value.
+pub fn sparkline(values: &[usize], bars: &[char]) -> String {
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&v| match v {
+            0 => ' ',
+            _ => bars[v * (bars.len() - 1) / max],
+        })
+        .collect()
+}
+
+/// `done` out of `total` as a bar `width` cells wide, e.g. `[#####-----]`.
+pub fn progress_bar(done: usize, total: usize, width: usize) -> String {
+    let filled = (done * width)
+        .checked_div(total)
+        .unwrap_or(width)
+        .min(width);
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+/// Area of the terminal, `size` cells from `at`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub at: Vec2,
+    pub size: Vec2,
+}
+
+#[allow(unused)]
+impl Rect {
+    pub fn new(at: Vec2, size: Vec2) -> Self {
+        Self { at, size }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Vec2 {
     pub x: u16,
     pub y: u16,
@@ -192,7 +1566,7 @@ pub struct Vec2 {
 
 #[allow(unused)]
 impl Vec2 {
-    pub fn new(x: u16, y: u16) -> Self {
+    pub const fn new(x: u16, y: u16) -> Self {
         Self { x, y }
     }
     pub fn empty() -> Self {
@@ -266,3 +1640,187 @@ impl Display for Vec2 {
         write!(f, "({}, {})", self.x, self.y)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(len: usize, selected: usize) -> ListState {
+        let mut state = ListState::default();
+        state.set_len(len);
+        state.select(selected);
+        state
+    }
+
+    #[test]
+    fn wide_characters_take_two_cells() {
+        assert_eq!(display_width("功能/登录"), 9);
+        assert_eq!(display_width("café"), 4);
+        assert_eq!(truncate("功能/登录", 3), "功");
+        assert_eq!(pad("功能", 6), "功能  ");
+
+        let mut term = Term::test(Vec2::new(8, 1));
+        term.begin_frame();
+        term.write_text(Vec2::new(0, 0), "a功能b");
+        term.write_text(Vec2::new(2, 0), "x");
+        term.end_frame();
+        assert_eq!(term.lines(), ["a x能b"]);
+    }
+
+    #[test]
+    fn colors_fit_the_palette() {
+        let orange = Color::Rgb {
+            r: 255,
+            g: 135,
+            b: 0,
+        };
+        assert_eq!(fit_color(orange, ColorSupport::TrueColor), orange);
+        assert_eq!(
+            fit_color(orange, ColorSupport::Ansi256),
+            Color::AnsiValue(208)
+        );
+        assert_eq!(fit_color(orange, ColorSupport::Ansi16), Color::Yellow);
+        let gray = Color::Rgb {
+            r: 100,
+            g: 100,
+            b: 100,
+        };
+        assert_eq!(
+            fit_color(gray, ColorSupport::Ansi256),
+            Color::AnsiValue(241)
+        );
+        assert_eq!(
+            fit_color(Color::AnsiValue(22), ColorSupport::Ansi16),
+            Color::DarkGreen
+        );
+        assert_eq!(
+            fit_color(Color::AnsiValue(9), ColorSupport::Ansi16),
+            Color::Red
+        );
+        assert_eq!(fit_color(Color::Blue, ColorSupport::Ansi16), Color::Blue);
+    }
+
+    #[test]
+    fn wrap_breaks_between_words() {
+        assert_eq!(wrap("the quick brown fox", 10), ["the quick", "brown fox"]);
+        assert_eq!(wrap("a\n\nb", 10), ["a", "", "b"]);
+        assert_eq!(wrap("abcdefghij k", 4), ["abcd", "efgh", "ij k"]);
+        assert_eq!(wrap("功能登录", 3), ["功", "能", "登", "录"]);
+
+        let mut term = Term::test(Vec2::new(6, 3));
+        term.begin_frame();
+        let rect = Rect::new(Vec2::new(1, 1), Vec2::new(5, 2));
+        assert_eq!(term.write_wrapped(rect, "one two three"), 2);
+        term.end_frame();
+        assert_eq!(term.lines(), ["", " one", " two"]);
+    }
+
+    #[test]
+    fn empty_list_has_no_selection() {
+        let mut state = ListState::default();
+        assert_eq!(state.selected(), None);
+        state.select_next();
+        state.select_previous();
+        assert_eq!(state.selected(), None);
+        assert_eq!(state.index(), 0);
+    }
+
+    #[test]
+    fn movement_wraps_around() {
+        let mut state = list(3, 2);
+        state.select_next();
+        assert_eq!(state.selected(), Some(0));
+        state.select_previous();
+        assert_eq!(state.selected(), Some(2));
+    }
+
+    #[test]
+    fn single_row_stays_selected() {
+        let mut state = list(1, 0);
+        state.select_next();
+        assert_eq!(state.selected(), Some(0));
+        state.select_previous();
+        assert_eq!(state.selected(), Some(0));
+    }
+
+    #[test]
+    fn paging_stops_at_the_ends() {
+        let mut state = list(10, 2);
+        state.move_by(4);
+        assert_eq!(state.selected(), Some(6));
+        state.move_by(4);
+        assert_eq!(state.selected(), Some(9));
+        state.move_by(-20);
+        assert_eq!(state.selected(), Some(0));
+        state.select_last();
+        assert_eq!(state.selected(), Some(9));
+        state.select_first();
+        assert_eq!(state.selected(), Some(0));
+    }
+
+    #[test]
+    fn select_clamps_to_last_row() {
+        let state = list(3, 10);
+        assert_eq!(state.selected(), Some(2));
+    }
+
+    #[test]
+    fn shrinking_keeps_selection_in_bounds() {
+        let mut state = list(10, 7);
+        state.set_len(4);
+        assert_eq!(state.selected(), Some(3));
+        state.set_len(8);
+        assert_eq!(state.selected(), Some(3));
+    }
+
+    #[test]
+    fn filter_emptying_the_list_then_matching_again() {
+        let mut state = list(10, 7);
+        state.set_len(0);
+        assert_eq!(state.selected(), None);
+        assert_eq!(state.index(), 0);
+        // Moving in the empty list must not leave the selection past the end.
+        state.select_previous();
+        state.set_len(2);
+        assert_eq!(state.selected(), Some(0));
+        state.select_previous();
+        assert_eq!(state.selected(), Some(1));
+    }
+
+    #[test]
+    fn taller_lines_scroll_by_rows() {
+        let mut viewport = Viewport::default();
+        viewport.set_heights(&[1, 2, 1, 3]);
+        let mut term = Term::test(Vec2::new(6, 3));
+        let (at, size) = (Vec2::new(0, 0), Vec2::new(6, 3));
+        let line = |i: usize| Line {
+            spans: vec![Span::raw(format!("{i}"))],
+            continued: (1..[1, 2, 1, 3][i])
+                .map(|k| vec![Span::raw(format!("{i}+{k}"))])
+                .collect(),
+        };
+        term.begin_frame();
+        viewport.render(&mut term, at, size, 4, Some(2), line);
+        term.end_frame();
+        assert_eq!(term.lines(), ["1", "1+1", "2"]);
+        assert_eq!(viewport.line_at(at, size, 4, 1), Some(1));
+        assert_eq!(viewport.line_at(at, size, 4, 2), Some(2));
+
+        term.begin_frame();
+        viewport.render(&mut term, at, size, 4, Some(3), line);
+        term.end_frame();
+        assert_eq!(term.lines(), ["3", "3+1", "3+2"]);
+
+        let mut viewport = Viewport::new(true, false);
+        viewport.set_heights(&[1, 2, 1, 3]);
+        let mut term = Term::test(Vec2::new(6, 7));
+        let size = Vec2::new(6, 7);
+        term.begin_frame();
+        viewport.render(&mut term, at, size, 4, Some(0), line);
+        term.end_frame();
+        assert_eq!(term.lines(), ["3", "3+1", "3+2", "2", "1", "1+1", "0"]);
+        assert_eq!(viewport.line_at(at, size, 4, 4), Some(1));
+        assert_eq!(viewport.line_at(at, size, 4, 5), Some(1));
+        assert_eq!(viewport.line_at(at, size, 4, 3), Some(2));
+    }
+}
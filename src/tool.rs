@@ -0,0 +1,51 @@
+use git2::{Oid, Repository};
+use std::{path::Path, process::Command};
+
+/// Opens `path` in the configured `diff.tool` through `git difftool`, which takes care of the
+/// temporary files. `cached` compares the index to HEAD instead of the working tree to the index.
+pub fn difftool(repo: &Repository, path: &Path, cached: bool) -> Result<(), String> {
+    let mut args = vec!["difftool", "--no-prompt"];
+    if cached {
+        args.push("--cached");
+    }
+    run_git(repo, &args, &[path])
+}
+
+/// Compares a file between two commits in the configured `diff.tool`. The file may have had
+/// another name in `from` if it was renamed since.
+pub fn difftool_revisions(
+    repo: &Repository,
+    from: (Oid, &str),
+    to: (Oid, &str),
+) -> Result<(), String> {
+    let (from_oid, to_oid) = (from.0.to_string(), to.0.to_string());
+    let args = ["difftool", "--no-prompt", "-M", &from_oid, &to_oid];
+    let mut paths = vec![Path::new(from.1)];
+    if to.1 != from.1 {
+        paths.push(Path::new(to.1));
+    }
+    run_git(repo, &args, &paths)
+}
+
+/// Resolves the conflict in `path` with the configured `merge.tool` through `git mergetool`.
+pub fn mergetool(repo: &Repository, path: &Path) -> Result<(), String> {
+    run_git(repo, &["mergetool", "--no-prompt"], &[path])
+}
+
+/// Runs git in the working tree with the terminal handed over to it. `paths` are passed after
+/// `--` as they are, so names that aren't valid UTF-8 reach git intact.
+fn run_git(repo: &Repository, args: &[&str], paths: &[&Path]) -> Result<(), String> {
+    let workdir = repo.workdir().unwrap_or(Path::new("."));
+    let status = Command::new("git")
+        .args(args)
+        .arg("--")
+        .args(paths)
+        .current_dir(workdir)
+        .status()
+        .map_err(|e| format!("could not run git: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("git {} exited with {status}", args[0]))
+    }
+}
@@ -0,0 +1,197 @@
+use crate::lossy::lossy;
+use git2::{Repository, WorktreeLockStatus};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+pub struct WorktreeItem {
+    /// `main` for the main worktree, otherwise the name git gave the linked worktree.
+    pub name: String,
+    pub path: PathBuf,
+    /// Checked out branch, `None` when HEAD is detached.
+    pub branch: Option<String>,
+    /// The worktree gix is running in.
+    pub is_current: bool,
+    pub is_locked: bool,
+    /// The worktree directory is gone and `git worktree prune` would remove it.
+    pub is_missing: bool,
+}
+
+/// The main worktree followed by all linked worktrees of the repository.
+pub fn query_worktrees(repo: &Repository) -> Vec<WorktreeItem> {
+    let current = repo.workdir().and_then(|w| w.canonicalize().ok());
+    let mut items = Vec::new();
+
+    if let Ok(main) = Repository::open(repo.commondir())
+        && let Some(workdir) = main.workdir()
+    {
+        items.push(worktree_item(
+            "main".to_string(),
+            workdir,
+            Some(&main),
+            current.as_deref(),
+            false,
+        ));
+    }
+
+    let Ok(names) = repo.worktrees() else {
+        return items;
+    };
+    for name in names.iter().flatten() {
+        let Ok(worktree) = repo.find_worktree(name) else {
+            continue;
+        };
+        let linked = Repository::open_from_worktree(&worktree).ok();
+        let is_locked = matches!(worktree.is_locked(), Ok(WorktreeLockStatus::Locked(_)));
+        items.push(worktree_item(
+            name.to_string(),
+            worktree.path(),
+            linked.as_ref(),
+            current.as_deref(),
+            is_locked,
+        ));
+    }
+    items
+}
+
+/// Branches checked out in the worktrees other than the one gix runs in, and where. Read once
+/// per refresh of the branch list, finding them opens every worktree.
+#[derive(Default)]
+pub struct WorktreeHeads(HashMap<String, PathBuf>);
+
+impl WorktreeHeads {
+    pub fn read(repo: &Repository) -> Self {
+        Self(
+            query_worktrees(repo)
+                .into_iter()
+                .filter(|w| !w.is_current)
+                .filter_map(|w| Some((w.branch?, w.path)))
+                .collect(),
+        )
+    }
+
+    /// Path of another worktree that has `branch` checked out. A branch can only be checked
+    /// out in one worktree at a time.
+    pub fn checked_out_elsewhere(&self, branch: &str) -> Option<&Path> {
+        self.0.get(branch).map(PathBuf::as_path)
+    }
+}
+
+fn worktree_item(
+    name: String,
+    path: &Path,
+    repo: Option<&Repository>,
+    current: Option<&Path>,
+    is_locked: bool,
+) -> WorktreeItem {
+    let canonical = path.canonicalize().ok();
+    WorktreeItem {
+        name,
+        path: path.components().collect(),
+        branch: repo
+            .and_then(|repo| repo.head().ok())
+            .filter(|head| head.is_branch())
+            .map(|head| lossy(head.shorthand_bytes()).0),
+        is_current: canonical.is_some() && canonical.as_deref() == current,
+        is_locked,
+        is_missing: canonical.is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        branch::{BranchQuery, checkout_branch, query_branches},
+        config::Config,
+        fixture::fixture,
+        status::query_status,
+    };
+    use git2::WorktreeAddOptions;
+    use std::{env, fs};
+
+    /// `repo` and a worktree linked to it with `feature` checked out, opened on its own.
+    fn linked(name: &str) -> (Repository, Repository) {
+        let repo = fixture(name);
+        let path = env::temp_dir().join(format!("gix-test-{name}-linked-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let feature = repo.find_reference("refs/heads/feature").unwrap();
+        let mut opts = WorktreeAddOptions::new();
+        opts.reference(Some(&feature));
+        repo.worktree("linked", &path, Some(&opts)).unwrap();
+        let linked = Repository::open(&path).unwrap();
+        drop(feature);
+        (repo, linked)
+    }
+
+    #[test]
+    fn linked_worktree_has_its_own_head_status_and_checkout() {
+        let (repo, linked) = linked("linked-worktree");
+        let heads = |repo: &Repository| -> Vec<String> {
+            query_branches(repo, &BranchQuery::Local)
+                .into_iter()
+                .filter(|b| b.is_head)
+                .map(|b| b.name)
+                .collect()
+        };
+        assert_eq!(heads(&repo), ["main"]);
+        assert_eq!(heads(&linked), ["feature"]);
+
+        fs::write(linked.workdir().unwrap().join("new.txt"), "new").unwrap();
+        let paths = |repo: &Repository| -> Vec<String> {
+            query_status(repo, None)
+                .into_iter()
+                .map(|s| s.path)
+                .collect()
+        };
+        assert_eq!(paths(&linked), ["new.txt"]);
+        assert!(paths(&repo).is_empty());
+
+        let worktrees = WorktreeHeads::read(&linked);
+        let main_path = worktrees.checked_out_elsewhere("main").unwrap();
+        assert_eq!(
+            main_path.canonicalize().unwrap(),
+            repo.workdir().unwrap().canonicalize().unwrap()
+        );
+        assert_eq!(worktrees.checked_out_elsewhere("feature"), None);
+        assert!(checkout_branch(&linked, b"main", &worktrees, false, |_, _| true).is_err());
+
+        let head = linked.head().unwrap().peel_to_commit().unwrap();
+        linked.branch("other", &head, false).unwrap();
+        assert!(checkout_branch(&linked, b"other", &worktrees, false, |_, _| true).unwrap());
+        assert_eq!(heads(&linked), ["other"]);
+        assert_eq!(heads(&repo), ["main"]);
+        assert!(
+            WorktreeHeads::read(&repo)
+                .checked_out_elsewhere("other")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn worktree_config_overrides_the_shared_config() {
+        let (repo, linked) = linked("worktree-config");
+        let mut shared = repo
+            .config()
+            .unwrap()
+            .open_level(git2::ConfigLevel::Local)
+            .unwrap();
+        shared.set_i32("core.repositoryformatversion", 1).unwrap();
+        shared.set_bool("extensions.worktreeConfig", true).unwrap();
+        shared.set_str("gix.view", "log").unwrap();
+        fs::write(
+            linked.path().join("config.worktree"),
+            "[gix]\n\treadOnly = true\n\tview = status\n",
+        )
+        .unwrap();
+
+        let linked = Repository::open(linked.workdir().unwrap()).unwrap();
+        let config = Config::load(&linked);
+        assert!(config.read_only);
+        assert_eq!(config.view, Some(crate::View::Status));
+        let config = Config::load(&repo);
+        assert!(!config.read_only);
+        assert_eq!(config.view, Some(crate::View::Log));
+    }
+}